@@ -3,11 +3,11 @@
 
 use std::default::Default;
 use std::string::ToString;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{cell::RefCell, fmt::Debug, str::FromStr};
 
 use anyhow::{self, Context};
-use rocksdb::{Options, DB};
+use rocksdb::{Direction, IteratorMode, Options, DB};
 use std::path::{Path, PathBuf};
 use tracing::{debug, error};
 
@@ -30,6 +30,7 @@ pub trait Cache: Debug {
 pub struct RocksDBCache {
     db: RefCell<Option<DB>>,
     ttl: Duration,
+    entry_ttl: Option<Duration>,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -107,6 +108,61 @@ impl FromStr for CompositeKey {
     }
 }
 
+/// Prefix of the keys under which the time each cache entry was last written is stored.
+const WRITTEN_AT_PREFIX: &[u8] = b"written-at:";
+
+fn written_at_key(composite_key: &CompositeKey) -> Vec<u8> {
+    let mut key = WRITTEN_AT_PREFIX.to_vec();
+    key.extend_from_slice(&composite_key.to_bytes());
+    key
+}
+
+/// Prefix of the keys under which access statistics for cache entries are stored. These are kept apart from the
+/// entries themselves and their write times.
+const ACCESS_STATS_PREFIX: &[u8] = b"access-stats:";
+const ACCESS_STATS_BYTE_LENGTH: usize = 16;
+
+/// How often and how recently a cache entry has been read. Used to decide which entries to evict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessStats {
+    /// The number of times the entry has been read.
+    pub hits: u64,
+    /// When the entry was last read.
+    pub last_accessed: SystemTime,
+}
+
+impl AccessStats {
+    fn to_bytes(self) -> [u8; ACCESS_STATS_BYTE_LENGTH] {
+        let seconds = self
+            .last_accessed
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut bytes = [0; ACCESS_STATS_BYTE_LENGTH];
+        bytes[..8].copy_from_slice(&self.hits.to_be_bytes());
+        bytes[8..].copy_from_slice(&seconds.to_be_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != ACCESS_STATS_BYTE_LENGTH {
+            return None;
+        }
+        let hits = u64::from_be_bytes(bytes[..8].try_into().ok()?);
+        let seconds = u64::from_be_bytes(bytes[8..].try_into().ok()?);
+        Some(Self {
+            hits,
+            last_accessed: UNIX_EPOCH + Duration::from_secs(seconds),
+        })
+    }
+}
+
+fn access_stats_key(composite_key: &CompositeKey) -> Vec<u8> {
+    let mut key = ACCESS_STATS_PREFIX.to_vec();
+    key.extend_from_slice(&composite_key.to_bytes());
+    key
+}
+
 impl RocksDBCache {
     fn make_db(path: &Path, ttl: Duration) -> DB {
         let mut opts = Options::default();
@@ -121,11 +177,121 @@ impl RocksDBCache {
         Self {
             db: RefCell::new(Some(Self::make_db(path.as_ref(), ttl))),
             ttl,
+            entry_ttl: None,
         }
     }
     pub fn open(path: PathBuf) -> Self {
         Self::open_with_ttl(path, Duration::from_secs(0))
     }
+
+    /// Treat entries written longer than `entry_ttl` ago as missing, so that they are recomputed and written again.
+    /// Unlike the database TTL, which only drops entries during compaction, this is enforced on every read. Entries
+    /// with no recorded write time are treated as expired.
+    pub fn with_entry_ttl(mut self, entry_ttl: Option<Duration>) -> Self {
+        self.entry_ttl = entry_ttl;
+        self
+    }
+
+    /// When an entry was last written, if that was recorded.
+    pub fn written_at(
+        &self,
+        kind: CacheKeyKind,
+        key: CacheKey,
+    ) -> anyhow::Result<Option<SystemTime>> {
+        let written_at_key = written_at_key(&CompositeKey { kind, key });
+        let value = self
+            .db
+            .borrow()
+            .as_ref()
+            .unwrap()
+            .get(&written_at_key)
+            .with_context(|| format!("Getting write time for {:?} failed", written_at_key))?;
+        Ok(value.and_then(|bytes| {
+            let seconds = u64::from_be_bytes(bytes[..].try_into().ok()?);
+            Some(UNIX_EPOCH + Duration::from_secs(seconds))
+        }))
+    }
+
+    fn is_expired(&self, composite_key: &CompositeKey) -> anyhow::Result<bool> {
+        let entry_ttl = match self.entry_ttl {
+            Some(entry_ttl) => entry_ttl,
+            None => return Ok(false),
+        };
+        let expired = match self.written_at(composite_key.kind, composite_key.key)? {
+            Some(written_at) => written_at
+                .elapsed()
+                .map(|age| age > entry_ttl)
+                .unwrap_or(false),
+            None => true,
+        };
+        Ok(expired)
+    }
+
+    /// Read the access statistics for an entry, if it has ever been read.
+    pub fn access_stats(
+        &self,
+        kind: CacheKeyKind,
+        key: CacheKey,
+    ) -> anyhow::Result<Option<AccessStats>> {
+        let stats_key = access_stats_key(&CompositeKey { kind, key });
+        let value = self
+            .db
+            .borrow()
+            .as_ref()
+            .unwrap()
+            .get(&stats_key)
+            .with_context(|| format!("Getting access stats for {:?} failed", stats_key))?;
+        Ok(value.and_then(|bytes| AccessStats::from_bytes(&bytes)))
+    }
+
+    /// List the access statistics of all entries that have been read, hottest (most frequently, then most recently
+    /// read) first.
+    pub fn entries_by_access(&self) -> anyhow::Result<Vec<(CompositeKey, AccessStats)>> {
+        let db = self.db.borrow();
+        let db = db.as_ref().unwrap();
+        let mut entries = Vec::new();
+        for item in db.iterator(IteratorMode::From(ACCESS_STATS_PREFIX, Direction::Forward)) {
+            let (key, value) = item.context("Iterating over access stats failed")?;
+            let composite_key_bytes = match key.strip_prefix(ACCESS_STATS_PREFIX) {
+                Some(bytes) => bytes,
+                None => break,
+            };
+            let composite_key = match composite_key_bytes
+                .try_into()
+                .ok()
+                .and_then(|bytes| CompositeKey::from_bytes(bytes).ok())
+            {
+                Some(composite_key) => composite_key,
+                None => continue,
+            };
+            if let Some(stats) = AccessStats::from_bytes(&value) {
+                entries.push((composite_key, stats));
+            }
+        }
+        entries.sort_by(|(_, a), (_, b)| {
+            b.hits
+                .cmp(&a.hits)
+                .then_with(|| b.last_accessed.cmp(&a.last_accessed))
+        });
+        Ok(entries)
+    }
+
+    fn record_access(&self, composite_key: &CompositeKey) -> anyhow::Result<()> {
+        let stats_key = access_stats_key(composite_key);
+        let db = self.db.borrow();
+        let db = db.as_ref().unwrap();
+        let hits = db
+            .get(&stats_key)?
+            .and_then(|bytes| AccessStats::from_bytes(&bytes))
+            .map(|stats| stats.hits)
+            .unwrap_or(0);
+        let stats = AccessStats {
+            hits: hits.saturating_add(1),
+            last_accessed: SystemTime::now(),
+        };
+        db.put(&stats_key, stats.to_bytes())?;
+        Ok(())
+    }
 }
 
 impl Drop for RocksDBCache {
@@ -146,23 +312,40 @@ impl Drop for RocksDBCache {
 
 impl Cache for RocksDBCache {
     fn put(&self, kind: CacheKeyKind, key: CacheKey, value: &[u8]) -> anyhow::Result<()> {
-        let key: &[u8] = &CompositeKey { kind, key }.to_bytes()[..];
-        self.db
-            .borrow()
-            .as_ref()
-            .unwrap()
-            .put(key, value)
-            .with_context(|| format!("Putting {:?} failed", key))
+        let composite_key = CompositeKey { kind, key };
+        let key: &[u8] = &composite_key.to_bytes()[..];
+        let db = self.db.borrow();
+        let db = db.as_ref().unwrap();
+        db.put(key, value)
+            .with_context(|| format!("Putting {:?} failed", key))?;
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        db.put(written_at_key(&composite_key), seconds.to_be_bytes())
+            .with_context(|| format!("Recording write time for {:?} failed", key))
     }
 
     fn get(&self, kind: CacheKeyKind, key: CacheKey) -> anyhow::Result<Option<Vec<u8>>> {
-        let key: &[u8] = &CompositeKey { kind, key }.to_bytes()[..];
-        self.db
+        let composite_key = CompositeKey { kind, key };
+        let key: &[u8] = &composite_key.to_bytes()[..];
+        let value = self
+            .db
             .borrow()
             .as_ref()
             .unwrap()
             .get(key)
-            .with_context(|| format!("Getting {:?} failed", key))
+            .with_context(|| format!("Getting {:?} failed", key))?;
+        if value.is_some() && self.is_expired(&composite_key)? {
+            debug!(?composite_key, "Cache entry expired");
+            return Ok(None);
+        }
+        if value.is_some() {
+            if let Err(err) = self.record_access(&composite_key) {
+                error!(?err, "Could not record cache entry access");
+            }
+        }
+        Ok(value)
     }
 
     fn clear(&self) -> anyhow::Result<()> {
@@ -274,6 +457,51 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_entry_ttl_expires_old_entries() -> anyhow::Result<()> {
+        let (_temp_dir, file_path) = create_test_repo();
+        let key = CacheKey::from_str(KEY).unwrap();
+        {
+            let cache = RocksDBCache::open(file_path.clone());
+            assert!(cache.written_at(kind(), key)?.is_some());
+        }
+
+        let cache = RocksDBCache::open(file_path).with_entry_ttl(Some(Duration::from_secs(1)));
+        assert_eq!(cache.get(kind(), key)?.unwrap(), b"abcd".to_vec());
+        std::thread::sleep(Duration::from_secs(2));
+        assert_eq!(cache.get(kind(), key)?, None);
+
+        // Writing the entry again makes it fresh.
+        cache.put(kind(), key, b"efgh")?;
+        assert_eq!(cache.get(kind(), key)?.unwrap(), b"efgh".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn test_access_stats_are_recorded_on_read() -> anyhow::Result<()> {
+        let (_temp_dir, file_path) = create_test_repo();
+        let cache = RocksDBCache::open(file_path);
+        let key = CacheKey::from_str(KEY).unwrap();
+        let other_key = CacheKey::from_str(HEX_KEY).unwrap();
+        cache.put(kind(), other_key, b"efgh")?;
+
+        assert_eq!(cache.access_stats(kind(), key)?, None);
+        cache.get(kind(), key)?;
+        cache.get(kind(), key)?;
+        cache.get(kind(), other_key)?;
+        // Misses are not counted.
+        cache.get(kind(), CacheKey::from_str(BAD_OID).unwrap())?;
+
+        assert_eq!(cache.access_stats(kind(), key)?.unwrap().hits, 2);
+        let entries = cache.entries_by_access()?;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].0, CompositeKey { kind: kind(), key });
+        assert_eq!(entries[0].1.hits, 2);
+        assert_eq!(entries[1].0.key, other_key);
+        assert_eq!(entries[1].1.hits, 1);
+        Ok(())
+    }
+
     #[test]
     fn test_compositekey() {
         let oid_bytes = CompositeKey {
@@ -7,34 +7,37 @@ use std::{
     path::{Path, PathBuf},
     sync::Arc,
     thread,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use anyhow::{bail, Context, Result};
-use clap::Parser;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, ValueSource};
 use focus_migrations::production::perform_pending_migrations;
 use focus_testing::GitBinary;
 use git2::Repository;
 
 use focus_util::{
-    app::{App, ExitCode},
+    app::{App, AppOptions, ExitCode},
     git_helper::{self, GitVersion},
     lock_file::LockFile,
-    paths, sandbox,
+    paths,
+    profile::{self, Profile},
+    sandbox,
     time::FocusTime,
 };
 
-use focus_internals::{target::TargetTypes, tracker::Tracker};
+use focus_internals::{locking, target::TargetTypes, tracker::Tracker};
 use focus_operations::{
     clone::{CloneArgs, ClonedRepoTemplate},
     maintenance::{self, ScheduleOpts},
     project::lint,
     selection::save,
-    sync::{SyncMode, SyncRequest},
+    sync::{SyncMode, SyncRequest, SyncStatus},
+    util::Removal,
 };
 use strum::VariantNames;
 use termion::{color, style};
-use tracing::{debug, debug_span, error, info};
+use tracing::{debug, debug_span, error, info, warn};
 
 #[derive(Parser, Clone, Debug)]
 struct NewArgs {
@@ -46,9 +49,10 @@ struct NewArgs {
     #[clap(parse(from_os_str))]
     sparse_repo: PathBuf,
 
-    /// The name of the branch to clone.
-    #[clap(short, long, default_value = "master")]
-    branch: String,
+    /// The name of the branch to clone. Defaults to the branch checked out in a local dense repo, or "master" when
+    /// cloning from a URL.
+    #[clap(short, long)]
+    branch: Option<String>,
 
     /// Days of history to maintain in the sparse repo. If greater than zero, the repository will be shallow. If set to zero, the repository will have the entire history.
     #[clap(long, default_value = "90")]
@@ -63,6 +67,20 @@ struct NewArgs {
 
     #[clap(long, possible_values = ClonedRepoTemplate::VARIANTS)]
     template: Option<ClonedRepoTemplate>,
+
+    /// Write the sparse profile without checking out the files it selects, e.g. for CI jobs that only consume the
+    /// patterns.
+    #[clap(long)]
+    no_checkout: bool,
+
+    /// Record the commit, selection, resolved targets and sparse profile of the clone in a lockfile at this path.
+    #[clap(long, parse(from_os_str))]
+    write_lock: Option<PathBuf>,
+
+    /// Recreate the clone recorded in this lockfile, checking out its commit and applying its sparse profile without
+    /// resolving any targets.
+    #[clap(long, parse(from_os_str), conflicts_with_all(&["projects_and_targets", "template"]))]
+    from_lock: Option<PathBuf>,
 }
 
 #[derive(Parser, Clone, Debug)]
@@ -83,6 +101,67 @@ enum Subcommand {
         /// Sync in one-shot, skipping the cache and invoking the underlying resolver once.
         #[clap(long = "one-shot")]
         one_shot: bool,
+
+        /// Sync exactly the newline-delimited targets in this file instead of computing them from the selection.
+        #[clap(long, parse(from_os_str))]
+        targets_file: Option<PathBuf>,
+
+        /// Explain why the sync was skipped, if it was.
+        #[clap(short = 'v', long)]
+        verbose: bool,
+
+        /// After syncing, check a sample of the working tree against the sparse checkout patterns.
+        #[clap(long)]
+        verify: bool,
+
+        /// Sync to the prefetched commit of the primary branch instead of HEAD, preparing for the next pull.
+        #[clap(long)]
+        to_prefetch: bool,
+
+        /// Retry Bazel queries that fail transiently (e.g. because the server restarted) this many times, overriding
+        /// `focus.bazel.query-retries`.
+        #[clap(long)]
+        query_retries: Option<usize>,
+
+        /// Where to write the file recording the synced commit once the sync completes. Defaults to
+        /// `.git/focus/last_sync_ok`.
+        #[clap(long, parse(from_os_str))]
+        completion_marker: Option<PathBuf>,
+
+        /// Write the sparse profile and sync point without checking out the working tree.
+        #[clap(long)]
+        no_checkout: bool,
+
+        /// Only write the sparse profile and sync point, leaving the working tree for a separate step to update. The
+        /// working tree is out of sync until you run `git checkout`.
+        #[clap(long, conflicts_with_all(&["no_checkout", "verify"]))]
+        write_only: bool,
+
+        /// Write a canonical copy of the selection to this file so that it can be committed and shared.
+        #[clap(long, parse(from_os_str))]
+        write_selection_to: Option<PathBuf>,
+
+        /// Write a canonical copy of the selection to the shared selection file, `focus.selection-mirror` or
+        /// `.focus-selection.json`, so that it can be committed and shared.
+        #[clap(long, conflicts_with = "write_selection_to")]
+        publish_selection: bool,
+
+        /// Fail if the selection includes projects that are no longer defined instead of warning.
+        #[clap(long)]
+        strict: bool,
+
+        /// Check sparse checkout patterns found in the index against a fresh resolution, in case they were resolved
+        /// at an older commit, and replace any that disagree.
+        #[clap(long)]
+        strict_cache: bool,
+
+        /// Fail if Bazel reports warnings or errors while resolving targets, which can mean it skipped some of them.
+        #[clap(long)]
+        strict_resolution: bool,
+
+        /// Skip the commands configured in `focus.hooks.pre-sync`, which can otherwise refuse the sync.
+        #[clap(long)]
+        no_hooks: bool,
     },
 
     /// Interact with repos configured on this system. Run `focus repo help` for more information.
@@ -91,6 +170,17 @@ enum Subcommand {
         subcommand: RepoSubcommand,
     },
 
+    /// Summarize each registered repository: its selection, when it was last synced, whether it needs a sync, whether
+    /// its working tree is clean, and how many sparse patterns it has.
+    Overview {
+        /// Only summarize repositories whose path matches this glob pattern
+        pattern: Option<String>,
+
+        /// Output the summary as JSON
+        #[clap(long)]
+        json: bool,
+    },
+
     /// Add projects and targets to the selection.
     Add {
         /// Project and targets to add to the selection.
@@ -108,6 +198,79 @@ enum Subcommand {
         /// Add the immediate targets and projects of projects to the selection, not the projects themselves.
         #[clap(long = "unroll")]
         unroll: bool,
+
+        /// Print how the selection would change without changing it or syncing
+        #[clap(long = "dry-run", conflicts_with("interactive"))]
+        dry_run: bool,
+
+        /// Add the projects and targets that can be added, skipping and reporting the rest, instead of leaving the
+        /// selection unchanged. Exits with status 2 if any were skipped.
+        #[clap(long, conflicts_with_all(&["interactive", "dry_run"]))]
+        keep_going: bool,
+
+        /// Replace a coordinate wherever the selection resolves to it, given as `FROM=TO` (e.g.
+        /// `//a:b=//a:b_local`). The override is stored in the selection rather than the project catalog.
+        #[clap(
+            long = "override",
+            value_name = "FROM=TO",
+            conflicts_with_all(&["interactive", "dry_run", "keep_going"])
+        )]
+        overrides: Vec<String>,
+    },
+
+    /// Add individual files to the selection. The other files in their directories are checked out too, but not
+    /// their subdirectories.
+    AddFile {
+        /// Files to add, relative to the root of the repository.
+        #[clap(parse(from_os_str), required = true)]
+        paths: Vec<PathBuf>,
+    },
+
+    /// Add the targets covering a list of changed files to the selection: the Bazel package owning each file, or the
+    /// file itself if no package does.
+    SelectForPaths {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+
+        /// A file listing the changed paths relative to the root of the repository, one per line, or `-` to read them
+        /// from standard input.
+        #[clap(parse(from_os_str))]
+        paths_file: PathBuf,
+    },
+
+    /// Add the Bazel targets of a rule kind (e.g. scala_test) under a directory to the selection.
+    AddByKind {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+
+        /// The rule kind to select, matched exactly (e.g. scala_test).
+        kind: String,
+
+        /// The directory to search, including the packages beneath it (e.g. //search or search/...).
+        scope: String,
+    },
+
+    /// Find the projects that include a target, with the one adding the least to the selection first.
+    CoverTarget {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+
+        /// The target to cover (e.g. bazel://search/common:lib or directory:search/common).
+        target: String,
+    },
+
+    /// List coordinates that are declared by more than one selected project, to help deduplicate project definitions.
+    CoordinateOverlap {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+
+        /// Consider every project in the catalog rather than only the selected ones.
+        #[clap(long)]
+        all: bool,
     },
 
     /// Remove projects and targets from the selection.
@@ -119,6 +282,211 @@ enum Subcommand {
         /// Remove all targets and projects from the selection
         #[clap(short = 'a', long = "all")]
         all: bool,
+
+        /// Print how the selection would change without changing it or syncing
+        #[clap(long = "dry-run")]
+        dry_run: bool,
+
+        /// Remove the projects and targets that can be removed, skipping and reporting the rest, instead of leaving
+        /// the selection unchanged. Exits with status 2 if any were skipped.
+        #[clap(long, conflicts_with("dry_run"))]
+        keep_going: bool,
+
+        /// Stop overriding a coordinate that was overridden with `focus add --override`.
+        #[clap(
+            long = "override",
+            value_name = "FROM",
+            conflicts_with_all(&["dry_run", "keep_going"])
+        )]
+        overrides: Vec<String>,
+    },
+
+    /// Exit successfully if the directory is within a focused repo, and unsuccessfully otherwise. Intended for prompts
+    /// and other integrations, so nothing is printed unless `--verbose` is given.
+    Check {
+        /// Path to the directory to check.
+        #[clap(parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+
+        /// Explain the result.
+        #[clap(long)]
+        verbose: bool,
+    },
+
+    /// Guess from cheap signals whether the repo needs to be synced, for prompts and hooks: exit with 0 if it is up to
+    /// date, 1 if a sync is needed, and 2 if it is unknown or this is not a focused repo. Nothing is resolved, so the
+    /// guess is approximate; `drift-status` compares the selected targets themselves.
+    NeedsSync {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+
+        /// Explain the result.
+        #[clap(long)]
+        verbose: bool,
+    },
+
+    /// Check that the working tree has no changed or untracked files, as sync requires, listing any that do. Exits with
+    /// 0 if the working tree is clean and 2 if it is not.
+    EnsureClean {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+
+        /// Output the result as JSON.
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Rewrite the selection file in a sorted, canonical form without changing what is selected.
+    CompactSelection {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+    },
+
+    /// Serve JSON-RPC requests (status, selected-layers, add, remove, sync) read line by line from standard input, writing
+    /// each response as a line to standard output. Intended for editor integrations.
+    Serve {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+    },
+
+    /// Check that focus works on this machine by cloning and syncing a small repo created in a temporary directory
+    SelfTest {},
+
+    /// Print every setting focus uses in the repo, its value, and where the value came from (default, git-config, env,
+    /// or flag).
+    EffectiveConfig {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+
+        /// Output the settings as JSON.
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Print everything focus knows about the repo as JSON: its configuration, selection, sync point, and project
+    /// catalog. Useful to attach to support requests.
+    Dump {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+    },
+
+    /// Replace a project in the selection with another, e.g. after the project was renamed.
+    RenameProject {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+
+        /// The name of the project in the selection.
+        from: String,
+
+        /// The name of the project to select instead.
+        to: String,
+    },
+
+    /// Report whether HEAD or the selection changed since the last sync, and whether a sync is recommended.
+    DriftStatus {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+    },
+
+    /// Check that the sync point refs name commits that still exist, clearing any that do not so that the next sync
+    /// starts over.
+    VerifySyncPoint {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+    },
+
+    /// Point the sync point at a commit without syncing, to recover a repository whose sync point refs are wrong. The
+    /// working tree is not changed, so it may no longer match what focus believes was synced.
+    SetSyncPoint {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+
+        /// The commit to record as synced.
+        commit: String,
+
+        /// Acknowledge that this bypasses syncing.
+        #[clap(long)]
+        force: bool,
+    },
+
+    /// Fetch a branch into the prefetch ref that `status` and preemptive sync use, instead of waiting for background
+    /// prefetching.
+    Prefetch {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+
+        /// The remote to fetch from.
+        #[clap(long, default_value = "origin")]
+        remote: String,
+
+        /// The branch to fetch; defaults to the primary branch.
+        #[clap(long)]
+        branch: Option<String>,
+    },
+
+    /// Show how many targets each selected project contributes to the complete target set, and which it pulls in
+    /// transitively.
+    ExpansionReport {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+    },
+
+    /// Report how many of the selection's dependency keys are in the index and estimate the Bazel queries a sync
+    /// would run. Exits unsuccessfully if anything would miss, so CI can decide whether to warm the index first.
+    CachePlan {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+    },
+
+    /// Resolve the targets of projects and the mandatory projects at HEAD into the index without changing the
+    /// selection or the working tree, so that a later sync selecting them finds them cached.
+    WarmCache {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+
+        /// The projects to resolve.
+        #[clap(required = true)]
+        layers: Vec<String>,
+    },
+
+    /// Run the resolver's Bazel query for a single coordinate at HEAD, bypassing the index, and show Bazel's raw
+    /// output and the directories derived from it.
+    QueryCoordinate {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+
+        /// The Bazel coordinate to query, e.g. `bazel://library_a/...`.
+        coordinate: String,
+    },
+
+    /// Compare the targets and directories the selection resolves to at two commits, using the projects defined at
+    /// each.
+    TargetSetDiff {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+
+        /// The earlier commit.
+        from: String,
+
+        /// The later commit.
+        #[clap(default_value = "HEAD")]
+        to: String,
     },
 
     /// Display which projects and targets are selected.
@@ -130,10 +498,61 @@ enum Subcommand {
         //Include only the types of targets specified
         #[clap(short = 't', long = "types", arg_enum)]
         target_types: Vec<TargetTypes>,
+
+        /// Skip reporting how far HEAD is behind the prefetched upstream commit, which walks the commit graph
+        #[clap(long = "no-upstream")]
+        no_upstream: bool,
+
+        /// Only check that the prefetch ref exists and is reasonably fresh, exiting unsuccessfully if not
+        #[clap(long = "check-prefetch")]
+        check_prefetch: bool,
+
+        /// Count the files materialized in the working tree and their total size; this walks the whole working tree
+        #[clap(long = "count-files")]
+        count_files: bool,
+
+        /// How to show the upstream commit time and the time of the last sync: `relative`, `relative-short`, or
+        /// `absolute` (RFC 3339)
+        #[clap(long = "time-format", default_value = "relative")]
+        time_format: focus_operations::status::TimeFormat,
+
+        /// Give up waiting for a running sync to finish after this long (for example "30s"). By default, status waits
+        /// as long as it takes.
+        #[clap(long, parse(try_from_str = humantime::parse_duration))]
+        lock_timeout: Option<Duration>,
+
+        /// Also list the coordinates the selection overrides
+        #[clap(short, long)]
+        verbose: bool,
+
+        /// Print a single token for a shell prompt, like `focus:12L±!`: the number of selected projects, `±` if HEAD
+        /// is behind the prefetched upstream commit, and `!` if a sync appears to be needed. Prints nothing and exits
+        /// unsuccessfully outside a focused repo.
+        #[clap(long, conflicts_with_all(&["targets", "check_prefetch", "count_files"]))]
+        prompt: bool,
     },
 
     /// List available projects.
-    Projects {},
+    Projects {
+        /// Output format: `text` lists optional projects; `json` and `csv` include mandatory projects too.
+        #[clap(long, default_value = "text")]
+        format: focus_operations::selection::ProjectListFormat,
+
+        /// List the presets defined in the project catalog instead of the projects.
+        #[clap(long)]
+        presets: bool,
+    },
+
+    /// Replace the selection with the projects of a preset from the project catalog and sync. Presets are curated
+    /// starting points, e.g. for a new team member; see `focus projects --presets`.
+    ApplyPreset {
+        /// Name of the preset to apply.
+        name: String,
+
+        /// Change the selection without syncing.
+        #[clap(long = "no-sync")]
+        no_sync: bool,
+    },
 
     /// Interact with project definitions
     Project {
@@ -151,6 +570,14 @@ enum Subcommand {
         #[clap(long, default_value = "true")]
         advisory: bool,
 
+        /// Print the changed paths that affect the build graph as JSON instead of notifying.
+        #[clap(long)]
+        json: bool,
+
+        /// With --json, also list the selected targets naming the packages that contain changed paths.
+        #[clap(long, requires = "json")]
+        affected_targets: bool,
+
         /// Arguments passed by the wrapper (a wrapper of `bazel` or otherwise)
         args: Vec<String>,
     },
@@ -201,7 +628,8 @@ enum Subcommand {
     /// Then open chrome://tracing in your browser and load the /tmp/chrome-trace.json flie.
     GitTrace { input: PathBuf, output: PathBuf },
 
-    /// Upgrade the repository by running outstanding migration steps.
+    /// Upgrade the repository's focus metadata format by running outstanding migration steps.
+    #[clap(visible_alias("migrate"))]
     Upgrade {
         #[clap(long, parse(from_os_str), default_value = ".")]
         repo: PathBuf,
@@ -239,6 +667,57 @@ enum Subcommand {
         #[clap(subcommand)]
         subcommand: SelectionSubcommand,
     },
+
+    /// Inspect and clean up preserved sandboxes
+    Sandbox {
+        #[clap(subcommand)]
+        subcommand: SandboxSubcommand,
+    },
+    #[clap(hide = true)]
+    /// Time the target set and sparse profile computation for the current selection without changing the working tree
+    Bench {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+
+        /// Number of times to run the computation.
+        #[clap(long, default_value = "5")]
+        iterations: usize,
+    },
+
+    /// Find the selected projects and targets responsible for a failure by syncing subsets of the selection and
+    /// running a test command against each, e.g. `focus bisect-selection -- bazel build //...`. The whole selection is
+    /// synced again afterward.
+    BisectSelection {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+
+        /// The command to run in the working tree, which fails if the problem is present.
+        #[clap(last = true, required = true)]
+        test_command: Vec<String>,
+    },
+
+    /// Show how much of the working tree each selected project or target accounts for on its own, counting only the
+    /// directories no other layer includes, largest first.
+    LayerSizes {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+    },
+    #[clap(hide = true)]
+    /// Repeatedly print whether the machine is idle as preemptive sync sees it, to check that idle detection works
+    /// before enabling it.
+    IdleMonitor {
+        /// How often to check (e.g. "1s").
+        #[clap(long, parse(try_from_str = humantime::parse_duration), default_value = "1s")]
+        interval: std::time::Duration,
+
+        /// How long the machine must go untouched to count as idle (e.g. "15s").
+        #[clap(long, parse(try_from_str = humantime::parse_duration), default_value = "15s")]
+        idle_threshold: std::time::Duration,
+    },
+
     #[clap(hide = true)]
     /// Update the sparse checkout to only contain selections
     On {
@@ -262,12 +741,42 @@ fn feature_name_for(subcommand: &Subcommand) -> String {
             RepoSubcommand::Repair { .. } => "repo-repair".to_string(),
             RepoSubcommand::Register { .. } => "repo-register".to_string(),
         },
+        Subcommand::Overview { .. } => "overview".to_string(),
         Subcommand::Add { .. } => "add".to_string(),
+        Subcommand::AddFile { .. } => "add-file".to_string(),
+        Subcommand::SelectForPaths { .. } => "select-for-paths".to_string(),
+        Subcommand::AddByKind { .. } => "add-by-kind".to_string(),
+        Subcommand::CoverTarget { .. } => "cover-target".to_string(),
+        Subcommand::CoordinateOverlap { .. } => "coordinate-overlap".to_string(),
         Subcommand::Remove { .. } => "remove".to_string(),
+        Subcommand::Check { .. } => "check".to_string(),
+        Subcommand::NeedsSync { .. } => "needs-sync".to_string(),
+        Subcommand::EnsureClean { .. } => "ensure-clean".to_string(),
+        Subcommand::CompactSelection { .. } => "compact-selection".to_string(),
+        Subcommand::RenameProject { .. } => "rename-project".to_string(),
+        Subcommand::Serve { .. } => "serve".to_string(),
+        Subcommand::EffectiveConfig { .. } => "effective-config".to_string(),
+        Subcommand::Dump { .. } => "dump".to_string(),
+        Subcommand::SelfTest {} => "self-test".to_string(),
+        Subcommand::Bench { .. } => "bench".to_string(),
+        Subcommand::BisectSelection { .. } => "bisect-selection".to_string(),
+        Subcommand::LayerSizes { .. } => "layer-sizes".to_string(),
+        Subcommand::DriftStatus { .. } => "drift-status".to_string(),
+        Subcommand::VerifySyncPoint { .. } => "verify-sync-point".to_string(),
+        Subcommand::SetSyncPoint { .. } => "set-sync-point".to_string(),
+        Subcommand::Prefetch { .. } => "prefetch".to_string(),
+        Subcommand::ExpansionReport { .. } => "expansion-report".to_string(),
+        Subcommand::CachePlan { .. } => "cache-plan".to_string(),
+        Subcommand::WarmCache { .. } => "warm-cache".to_string(),
+        Subcommand::QueryCoordinate { .. } => "query-coordinate".to_string(),
+        Subcommand::TargetSetDiff { .. } => "target-set-diff".to_string(),
         Subcommand::Status { .. } => "status".to_string(),
         Subcommand::Projects { .. } => "projects".to_string(),
+        Subcommand::ApplyPreset { .. } => "apply-preset".to_string(),
         Subcommand::Project { subcommand } => match subcommand {
             ProjectSubcommand::Lint { .. } => "project-lint".to_string(),
+            ProjectSubcommand::Coordinates { .. } => "project-coordinates".to_string(),
+            ProjectSubcommand::Tree { .. } => "project-tree".to_string(),
         },
         Subcommand::DetectBuildGraphChanges { .. } => "detect-build-graph-changes".to_string(),
         Subcommand::Refs { subcommand, .. } => match subcommand {
@@ -302,6 +811,7 @@ fn feature_name_for(subcommand: &Subcommand) -> String {
         Subcommand::Upgrade { .. } => "upgrade".to_string(),
         Subcommand::Index { subcommand } => match subcommand {
             IndexSubcommand::Clear { .. } => "index-clear".to_string(),
+            IndexSubcommand::CacheStats { .. } => "index-cache-stats".to_string(),
             IndexSubcommand::CalculateChurn { .. } => "index-calculate-churn".to_string(),
             IndexSubcommand::Fetch { .. } => "index-fetch".to_string(),
             IndexSubcommand::Get { .. } => "index-get".to_string(),
@@ -327,7 +837,17 @@ fn feature_name_for(subcommand: &Subcommand) -> String {
         Subcommand::Pull => "pull".to_string(),
         Subcommand::Selection { subcommand } => match subcommand {
             SelectionSubcommand::Save { .. } => "selection-save".to_string(),
+            SelectionSubcommand::Use { .. } => "selection-use".to_string(),
+            SelectionSubcommand::Targets { .. } => "selection-targets".to_string(),
+            SelectionSubcommand::History { .. } => "selection-history".to_string(),
+            SelectionSubcommand::Undo { .. } => "selection-undo".to_string(),
+        },
+        Subcommand::Sandbox { subcommand } => match subcommand {
+            SandboxSubcommand::List {} => "sandbox-list".to_string(),
+            SandboxSubcommand::Clean { .. } => "sandbox-clean".to_string(),
+            SandboxSubcommand::Audit { .. } => "sandbox-audit".to_string(),
         },
+        Subcommand::IdleMonitor { .. } => "idle-monitor".to_string(),
         Subcommand::On { .. } => "filter-on".to_string(),
         Subcommand::Off {} => "filter-off".to_string(),
     }
@@ -443,7 +963,18 @@ enum MaintenanceScheduleSubcommand {
 #[derive(Parser, Clone, Debug)]
 enum RepoSubcommand {
     /// List registered repositories
-    List {},
+    List {
+        /// Only list repositories whose path matches this glob pattern
+        pattern: Option<String>,
+
+        /// Output the list as JSON
+        #[clap(long)]
+        json: bool,
+
+        /// Also show the projects and targets each repository was cloned with
+        #[clap(short, long)]
+        verbose: bool,
+    },
 
     /// Attempt to repair the registry of repositories
     Repair {},
@@ -483,6 +1014,71 @@ enum BranchSubcommand {
 enum ProjectSubcommand {
     /// Load projects and then try to parse targets
     Lint {},
+
+    /// List the coordinates a project declares, and optionally the targets they resolve to
+    Coordinates {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+
+        /// Name of the project.
+        name: String,
+
+        /// Also list the targets the project resolves to, including those of the projects it includes
+        #[clap(long)]
+        resolve: bool,
+
+        /// Output the coordinates as JSON
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Show the directories a project selects as a tree
+    Tree {
+        /// Path to the repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+
+        /// Name of the project.
+        name: String,
+
+        /// Only show this many levels of the tree
+        #[clap(long)]
+        depth: Option<usize>,
+    },
+}
+
+#[derive(Parser, Clone, Debug)]
+enum SandboxSubcommand {
+    /// List preserved sandboxes and when they were created
+    List {},
+
+    /// Delete old sandboxes that are no longer in use. Asks before deleting them unless --yes is given.
+    Clean {
+        /// Delete sandboxes older than this (for example "12h" or "3days"). Defaults to 7 days.
+        #[clap(long, parse(try_from_str = humantime::parse_duration))]
+        max_age: Option<std::time::Duration>,
+
+        /// Only show what would be deleted
+        #[clap(long = "dry-run")]
+        dry_run: bool,
+
+        /// Delete without asking
+        #[clap(long, conflicts_with("dry_run"))]
+        yes: bool,
+    },
+
+    /// Find sandboxes left behind by focus processes that did not exit cleanly, report the space they use, and delete
+    /// them. They are neither in use nor deliberately preserved. Asks before deleting them unless --yes is given.
+    Audit {
+        /// Only show what would be deleted
+        #[clap(long = "dry-run")]
+        dry_run: bool,
+
+        /// Delete without asking
+        #[clap(long, conflicts_with("dry_run"))]
+        yes: bool,
+    },
 }
 
 #[derive(Parser, Clone, Debug)]
@@ -499,6 +1095,33 @@ enum SelectionSubcommand {
         #[clap(long, short = 'd')]
         project_description: Option<String>,
     },
+
+    /// Switch the active selection set. `add`, `remove`, and `sync` operate on the active set.
+    Use {
+        /// Name of the selection set to switch to; it is created empty if it does not exist. The original set is named `default`.
+        name: String,
+
+        /// Switch without syncing the working tree to the new selection set
+        #[clap(long = "no-sync")]
+        no_sync: bool,
+    },
+
+    /// Print the complete set of targets the selection resolves to, including those of mandatory projects
+    Targets {
+        /// Print the Bazel targets as one query expression joined with `+`, e.g. for `bazel query "$(...)"`
+        #[clap(long)]
+        emit_query: bool,
+    },
+
+    /// List the previous versions of the selection, most recent first
+    History {},
+
+    /// Go back to the most recent previous version of the selection
+    Undo {
+        /// Restore the selection without syncing the working tree
+        #[clap(long = "no-sync")]
+        no_sync: bool,
+    },
 }
 
 #[derive(Parser, Clone, Debug)]
@@ -516,6 +1139,14 @@ enum RefsSubcommand {
         /// included in master
         #[clap(short = 'm', long = "check-merge-base")]
         check_merge_base: bool,
+
+        /// Only show which refs would be deleted
+        #[clap(long = "dry-run")]
+        dry_run: bool,
+
+        /// Delete without asking
+        #[clap(long, conflicts_with("dry_run"))]
+        yes: bool,
     },
 
     ListExpired {
@@ -551,6 +1182,17 @@ enum IndexSubcommand {
         sparse_repo: PathBuf,
     },
 
+    /// Show the most and least frequently read entries of the on-disk cache.
+    CacheStats {
+        /// Path to the sparse repository.
+        #[clap(parse(from_os_str), default_value = ".")]
+        sparse_repo: PathBuf,
+
+        /// The number of hottest and coldest entries to show.
+        #[clap(long, default_value = "10")]
+        count: usize,
+    },
+
     /// Calculate statistics on cache invalidation for the projects in the
     /// repository.
     CalculateChurn {
@@ -690,6 +1332,20 @@ enum BackgroundSubcommand {
         /// Path to the sparse repository.
         #[clap(parse(from_os_str), default_value = ".")]
         sparse_repo: PathBuf,
+
+        /// Run even if the machine is in use, but only if preemptive sync is enabled. Without this, a manual sync
+        /// skips both checks.
+        #[clap(long, conflicts_with("idle_threshold"))]
+        ignore_activity: bool,
+
+        /// Wait for the machine to be idle this long (e.g. "10s") before syncing. Without this, a manual sync does
+        /// not check whether the machine is idle.
+        #[clap(long, parse(try_from_str = humantime::parse_duration))]
+        idle_threshold: Option<std::time::Duration>,
+
+        /// Explain why the sync was skipped, if it was.
+        #[clap(short = 'v', long)]
+        verbose: bool,
     },
 }
 
@@ -718,6 +1374,84 @@ struct FocusOpts {
     #[clap(long, global = true, env = "NO_COLOR")]
     no_color: bool,
 
+    /// Create sandboxes in this directory instead of the default location.
+    #[clap(
+        long = "sandbox-dir",
+        parse(from_os_str),
+        global = true,
+        env = "FOCUS_SANDBOX_DIR"
+    )]
+    sandbox_dir_override: Option<PathBuf>,
+
+    /// Keep the registry of repos and the resolution cache in this directory instead of the focus config and cache
+    /// directories, e.g. in CI where the home directory does not persist between stages.
+    #[clap(
+        long = "data-dir",
+        parse(from_os_str),
+        global = true,
+        env = "FOCUS_DATA_DIR"
+    )]
+    data_dir_override: Option<PathBuf>,
+
+    /// Do not record cloned repos in the list of tracked repos, e.g. for throwaway clones made by CI.
+    #[clap(long, global = true, env = "FOCUS_NO_TRACKER")]
+    no_tracker: bool,
+
+    /// Keep the sandbox directory after the command finishes, even if it succeeds.
+    #[clap(long, global = true, env = "FOCUS_PRESERVE_SANDBOX")]
+    preserve_sandbox: bool,
+
+    /// Keep the sandbox directory and print its path if the command fails.
+    #[clap(
+        long,
+        global = true,
+        parse(try_from_str),
+        default_value = "true",
+        env = "FOCUS_PRESERVE_SANDBOX_ON_FAILURE"
+    )]
+    preserve_sandbox_on_failure: bool,
+
+    /// Print stable, tab-separated output from `status`, `projects`, and `repo list` for use in scripts.
+    #[clap(long, global = true, env = "FOCUS_PORCELAIN")]
+    porcelain: bool,
+
+    /// Disable network access. Operations that need the network fail immediately, the project cache is skipped, and
+    /// Bazel queries do not fetch external repositories.
+    #[clap(long, global = true, env = "FOCUS_OFFLINE")]
+    offline: bool,
+
+    /// Abort the command if it runs longer than this (for example "30m" or "2h").
+    #[clap(long, global = true, parse(try_from_str = humantime::parse_duration))]
+    timeout: Option<Duration>,
+
+    /// Do not warn when selecting deprecated projects.
+    #[clap(long, global = true, env = "FOCUS_NO_DEPRECATED_WARNINGS")]
+    no_deprecated_warnings: bool,
+
+    /// Run this Git binary instead of the first `git` on the `PATH`.
+    #[clap(long = "git", parse(from_os_str), global = true, env = "FOCUS_GIT")]
+    git_binary: Option<PathBuf>,
+
+    /// Write metrics about the invocation to this file in the Prometheus text exposition format, e.g. for
+    /// node-exporter's textfile collector. Counters accumulate across invocations writing the same file.
+    #[clap(long, parse(from_os_str), global = true, env = "FOCUS_METRICS_FILE")]
+    metrics_file: Option<PathBuf>,
+
+    /// Use the named set of global options from the `profiles` in `config.json` in the focus config directory.
+    /// Options given as flags or environment variables take precedence over the profile.
+    #[clap(long, global = true, env = "FOCUS_PROFILE")]
+    profile: Option<String>,
+
+    /// Log with this filter in the syntax of `RUST_LOG` (e.g. `focus_internals::coordinate_resolver=debug`) instead
+    /// of the one in `RUST_LOG`.
+    #[clap(long = "log", global = true, env = "FOCUS_LOG")]
+    log_filter: Option<String>,
+
+    /// Log more verbosely than the default info level: `-D` logs at the debug level and `-DD` at the trace level.
+    /// Spelled apart from the `-v/--verbose` flags of subcommands. `--log` takes precedence.
+    #[clap(short = 'D', long = "debug", global = true, parse(from_occurrences))]
+    verbosity: u64,
+
     #[clap(subcommand)]
     cmd: Subcommand,
 }
@@ -730,11 +1464,6 @@ fn ensure_directories_exist(tracker: &Tracker) -> Result<()> {
     Ok(())
 }
 
-fn hold_lock_file(repo: &Path) -> Result<LockFile> {
-    let path = repo.join(".focus").join("focus.lock");
-    LockFile::new(&path)
-}
-
 fn check_compatible_git_version(git_binary: &GitBinary) -> Result<bool> {
     let passed = match GitVersion::current(git_binary)? {
         GitVersion { major, minor, .. } if major >= 2 && minor >= 35 => true,
@@ -781,6 +1510,7 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
         );
     }
 
+    let porcelain = options.porcelain;
     match options.cmd {
         Subcommand::New(NewArgs {
             dense_repo,
@@ -790,6 +1520,9 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
             copy_branches,
             projects_and_targets,
             template,
+            no_checkout,
+            write_lock,
+            from_lock,
         })
         | Subcommand::Clone(NewArgs {
             dense_repo,
@@ -799,16 +1532,35 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
             copy_branches,
             projects_and_targets,
             template,
+            no_checkout,
+            write_lock,
+            from_lock,
         }) => {
             let origin = focus_operations::clone::Origin::try_from(dense_repo.as_str())?;
+            let branch = match (branch, &origin) {
+                (Some(branch), _) => branch,
+                (None, focus_operations::clone::Origin::Local(dense_repo_path)) => {
+                    focus_operations::clone::branch_checked_out_in(dense_repo_path)?
+                }
+                (None, focus_operations::clone::Origin::Remote(_)) => CloneArgs::default().branch,
+            };
             let sparse_repo = {
                 let current_dir =
                     std::env::current_dir().context("Failed to obtain current directory")?;
-                let expanded = paths::expand_tilde(sparse_repo)
-                    .context("Failed to expand sparse repo path")?;
+                let expanded =
+                    paths::expand_path(sparse_repo).context("Failed to expand sparse repo path")?;
                 current_dir.join(expanded)
             };
 
+            let current_dir =
+                std::env::current_dir().context("Failed to obtain current directory")?;
+            let write_lock = write_lock
+                .map(|path| paths::expand_path(path).map(|path| current_dir.join(path)))
+                .transpose()?;
+            let from_lock = from_lock
+                .map(|path| paths::expand_path(path).map(|path| current_dir.join(path)))
+                .transpose()?;
+
             info!("Cloning {:?} into {}", dense_repo, sparse_repo.display());
 
             // Add targets length to TI custom map.
@@ -823,6 +1575,9 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
                 days_of_history,
                 copy_branches,
                 projects_and_targets,
+                no_checkout,
+                write_lock,
+                from_lock,
                 ..Default::default()
             };
 
@@ -834,27 +1589,114 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
                 app.clone(),
             )?;
 
-            perform_pending_migrations(&sparse_repo, app)
+            perform_pending_migrations(&sparse_repo, app.clone())
                 .context("Performing initial migrations after clone")?;
 
+            if focus_operations::selection::offer_selection_mirror(&sparse_repo, app.clone())? {
+                let mut request = SyncRequest::new(&sparse_repo, SyncMode::Incremental);
+                if no_checkout {
+                    request = request.with_no_checkout();
+                }
+                focus_operations::sync::run(&request, app)
+                    .context("Syncing the adopted selection")?;
+            }
+
             Ok(ExitCode(0))
         }
         Subcommand::Sync {
             sparse_repo,
             one_shot,
+            targets_file,
+            verbose,
+            verify,
+            to_prefetch,
+            query_retries,
+            completion_marker,
+            no_checkout,
+            write_only,
+            write_selection_to,
+            publish_selection,
+            strict,
+            strict_cache,
+            strict_resolution,
+            no_hooks,
         } => {
             // TODO: Add total number of paths in repo to TI.
             let sparse_repo =
-                paths::find_repo_root_from(app.clone(), paths::expand_tilde(sparse_repo)?)?;
+                paths::find_repo_root_from(app.clone(), paths::expand_path(sparse_repo)?)?;
             ensure_repo_compatibility(&sparse_repo, app.clone())?;
 
-            let _lock_file = hold_lock_file(&sparse_repo)?;
+            let _lock_file = locking::hold_repo_lock(&sparse_repo)?;
+            focus_operations::selection::offer_selection_mirror(&sparse_repo, app.clone())?;
             let mode = if one_shot {
                 SyncMode::OneShot
             } else {
                 SyncMode::Incremental
             };
-            focus_operations::sync::run(&SyncRequest::new(&sparse_repo, mode), app)?;
+            let mut request = SyncRequest::new(&sparse_repo, mode);
+            if let Some(targets_file) = targets_file {
+                let targets =
+                    focus_operations::sync::read_targets_file(&paths::expand_path(targets_file)?)?;
+                request = request.with_targets(targets);
+            }
+            if to_prefetch {
+                request = request.with_prefetch_commit();
+            }
+            if let Some(query_retries) = query_retries {
+                request = request.with_query_retries(query_retries);
+            }
+            if no_checkout {
+                request = request.with_no_checkout();
+            }
+            if write_only {
+                request = request.with_write_only();
+            }
+            if let Some(write_selection_to) = write_selection_to {
+                request =
+                    request.with_selection_mirror_path(paths::expand_path(write_selection_to)?);
+            } else if publish_selection {
+                let repo = focus_internals::model::repo::Repo::open(&sparse_repo, app.clone())?;
+                request = request.with_selection_mirror_path(
+                    focus_operations::selection::selection_mirror_path(&repo)?,
+                );
+            }
+            if strict {
+                request = request.with_strict();
+            }
+            if strict_cache {
+                request = request.with_strict_cache();
+            }
+            if strict_resolution {
+                request = request.with_strict_resolution();
+            }
+            if no_hooks {
+                request = request.with_no_hooks();
+            }
+            if let Some(completion_marker) = completion_marker {
+                request =
+                    request.with_completion_marker_path(paths::expand_path(completion_marker)?);
+            }
+            let result = focus_operations::sync::run(&request, app.clone())?;
+            // Always say that the working tree was left out of sync, since the caller has to act on it.
+            if verbose || result.status == SyncStatus::WrittenWithoutCheckout {
+                if let Some(explanation) = result.status.explanation() {
+                    eprintln!("{}", explanation);
+                }
+            }
+            if let Some(stats) = result.stats {
+                if verbose {
+                    eprintln!(
+                        "Optimized the sparse profile from {} to {} patterns.",
+                        stats.pattern_counts.computed, stats.pattern_counts.optimized
+                    );
+                }
+                if stats.checked_out {
+                    eprintln!("{}", focus_operations::sync::checkout_summary(&stats));
+                }
+            }
+            if verify && !focus_operations::sync::verify_checkout(&sparse_repo, app)? {
+                return Ok(ExitCode(1));
+            }
             Ok(ExitCode(0))
         }
 
@@ -869,6 +1711,8 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
                     cutoff_date,
                     use_transaction,
                     check_merge_base,
+                    dry_run,
+                    yes,
                 } => {
                     let cutoff = FocusTime::parse_date(cutoff_date)?;
                     focus_operations::refs::expire_old_refs(
@@ -876,6 +1720,7 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
                         cutoff,
                         check_merge_base,
                         use_transaction,
+                        Removal::from_flags(dry_run, yes),
                         app,
                     )?;
                     Ok(ExitCode(0))
@@ -943,8 +1788,18 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
         }
 
         Subcommand::Repo { subcommand } => match subcommand {
-            RepoSubcommand::List {} => {
-                focus_operations::repo::list(tracker)?;
+            RepoSubcommand::List {
+                pattern,
+                json,
+                verbose,
+            } => {
+                focus_operations::repo::list(
+                    tracker,
+                    pattern.as_deref(),
+                    json,
+                    porcelain,
+                    verbose,
+                )?;
                 Ok(ExitCode(0))
             }
             RepoSubcommand::Repair {} => {
@@ -958,15 +1813,29 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
             }
         },
 
+        Subcommand::Overview { pattern, json } => {
+            focus_operations::repo::overview(tracker, pattern.as_deref(), json, app)?;
+            Ok(ExitCode(0))
+        }
+
         Subcommand::DetectBuildGraphChanges {
             repo,
             advisory,
+            json,
+            affected_targets,
             args,
         } => {
-            let repo = paths::find_repo_root_from(app.clone(), paths::expand_tilde(repo)?)?;
+            let repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
             let repo = git_helper::find_top_level(app.clone(), &repo)
                 .context("Failed to canonicalize repo path")?;
-            focus_operations::detect_build_graph_changes::run(&repo, advisory, args, app)
+            focus_operations::detect_build_graph_changes::run(
+                &repo,
+                advisory,
+                json,
+                affected_targets,
+                args,
+                app,
+            )
         }
 
         Subcommand::Add {
@@ -974,10 +1843,22 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
             interactive,
             search_all_targets,
             unroll,
+            dry_run,
+            keep_going,
+            overrides,
         } => {
             let sparse_repo = paths::find_repo_root_from(app.clone(), std::env::current_dir()?)?;
             paths::assert_focused_repo(&sparse_repo)?;
-            let _lock_file = hold_lock_file(&sparse_repo)?;
+            if dry_run {
+                focus_operations::selection::add_dry_run(
+                    &sparse_repo,
+                    projects_and_targets,
+                    unroll,
+                    app,
+                )?;
+                return Ok(ExitCode(0));
+            }
+            let _lock_file = locking::hold_repo_lock(&sparse_repo)?;
             if interactive {
                 focus_operations::selection::add_interactive(
                     &sparse_repo,
@@ -985,6 +1866,28 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
                     search_all_targets,
                     unroll,
                 )?;
+            } else if keep_going {
+                let outcome = focus_operations::selection::add_keep_going(
+                    &sparse_repo,
+                    true,
+                    projects_and_targets,
+                    unroll,
+                    app,
+                )?;
+                return Ok(report_partial_mutation(&outcome));
+            } else if !overrides.is_empty() {
+                let override_changes = overrides
+                    .iter()
+                    .map(|spec| focus_operations::selection::parse_override(spec))
+                    .collect::<Result<Vec<_>>>()?;
+                focus_operations::selection::add_with_overrides(
+                    &sparse_repo,
+                    true,
+                    projects_and_targets,
+                    unroll,
+                    &override_changes,
+                    app,
+                )?;
             } else {
                 focus_operations::selection::add(
                     &sparse_repo,
@@ -997,12 +1900,95 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
             Ok(ExitCode(0))
         }
 
+        Subcommand::AddFile { paths: files } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), std::env::current_dir()?)?;
+            paths::assert_focused_repo(&sparse_repo)?;
+            let _lock_file = locking::hold_repo_lock(&sparse_repo)?;
+            focus_operations::selection::add_files(&sparse_repo, true, files, app)?;
+            Ok(ExitCode(0))
+        }
+
+        Subcommand::SelectForPaths { repo, paths_file } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+            let _lock_file = locking::hold_repo_lock(&sparse_repo)?;
+            let targets = focus_operations::selection::select_for_paths(
+                &sparse_repo,
+                true,
+                &paths_file,
+                app,
+            )?;
+            for target in targets {
+                println!("{}", target);
+            }
+            Ok(ExitCode(0))
+        }
+
+        Subcommand::AddByKind { repo, kind, scope } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+            let _lock_file = locking::hold_repo_lock(&sparse_repo)?;
+            let targets =
+                focus_operations::selection::add_by_kind(&sparse_repo, true, &kind, &scope, app)?;
+            for target in targets {
+                println!("{}", target);
+            }
+            Ok(ExitCode(0))
+        }
+
+        Subcommand::CoverTarget { repo, target } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+            focus_operations::project::cover_target(&sparse_repo, &target, app)
+        }
+
+        Subcommand::CoordinateOverlap { repo, all } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+            focus_operations::project::coordinate_overlap(&sparse_repo, all, app)
+        }
+
         Subcommand::Remove {
             projects_and_targets,
             all,
+            dry_run,
+            keep_going,
+            overrides,
         } => {
             let sparse_repo = paths::find_repo_root_from(app.clone(), std::env::current_dir()?)?;
-            let _lock_file = hold_lock_file(&sparse_repo)?;
+            if dry_run {
+                focus_operations::selection::remove_dry_run(
+                    &sparse_repo,
+                    projects_and_targets,
+                    all,
+                    app,
+                )?;
+                return Ok(ExitCode(0));
+            }
+            let _lock_file = locking::hold_repo_lock(&sparse_repo)?;
+            if keep_going {
+                let outcome = focus_operations::selection::remove_keep_going(
+                    &sparse_repo,
+                    true,
+                    projects_and_targets,
+                    all,
+                    app,
+                )?;
+                return Ok(report_partial_mutation(&outcome));
+            }
+            if !overrides.is_empty() {
+                let override_changes = overrides
+                    .iter()
+                    .map(|coordinate| {
+                        focus_operations::selection::parse_override_removal(coordinate)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                focus_operations::selection::remove_with_overrides(
+                    &sparse_repo,
+                    true,
+                    projects_and_targets,
+                    all,
+                    &override_changes,
+                    app,
+                )?;
+                return Ok(ExitCode(0));
+            }
             focus_operations::selection::remove(
                 &sparse_repo,
                 true,
@@ -1013,18 +1999,206 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
             Ok(ExitCode(0))
         }
 
+        Subcommand::Check { .. } | Subcommand::NeedsSync { .. } => {
+            unreachable!("Checks are run before the app is set up")
+        }
+
+        Subcommand::EnsureClean { repo, json } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+            focus_operations::ensure_clean::check(&sparse_repo, json, app)
+        }
+
+        Subcommand::CompactSelection { repo } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+            let _lock_file = locking::hold_repo_lock(&sparse_repo)?;
+            focus_operations::selection::compact(&sparse_repo, app)?;
+            Ok(ExitCode(0))
+        }
+
+        Subcommand::Bench { repo, iterations } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+            let report = focus_operations::bench::run(&sparse_repo, iterations, app)?;
+            print!("{}", report);
+            Ok(ExitCode(0))
+        }
+        Subcommand::BisectSelection { repo, test_command } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+            ensure_repo_compatibility(&sparse_repo, app.clone())?;
+            let _lock_file = locking::hold_repo_lock(&sparse_repo)?;
+            let report = focus_operations::bisect::run(&sparse_repo, &test_command, app)?;
+            print!("{}", report);
+            Ok(ExitCode(0))
+        }
+
+        Subcommand::LayerSizes { repo } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+            let _lock_file = locking::hold_repo_lock(&sparse_repo)?;
+            focus_operations::layer_sizes::run(&sparse_repo, app)
+        }
+
+        Subcommand::Serve { repo } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+            focus_operations::serve::run(&sparse_repo, app)?;
+            Ok(ExitCode(0))
+        }
+
+        Subcommand::SelfTest {} => {
+            let report = focus_operations::self_test::run(app)?;
+            print!("{}", report);
+            Ok(ExitCode(if report.passed() { 0 } else { 1 }))
+        }
+
+        Subcommand::EffectiveConfig { repo, json } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+            focus_operations::effective_config::run(&sparse_repo, json, app)?;
+            Ok(ExitCode(0))
+        }
+
+        Subcommand::Dump { repo } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+            focus_operations::dump::run(&sparse_repo, app)?;
+            Ok(ExitCode(0))
+        }
+
+        Subcommand::RenameProject { repo, from, to } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+            let _lock_file = locking::hold_repo_lock(&sparse_repo)?;
+            focus_operations::selection::rename_project(&sparse_repo, &from, &to, app)?;
+            Ok(ExitCode(0))
+        }
+
+        Subcommand::DriftStatus { repo } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+            focus_operations::status::drift(&sparse_repo, app)
+        }
+
+        Subcommand::VerifySyncPoint { repo } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+            let _lock_file = locking::hold_repo_lock(&sparse_repo)?;
+            let cleared = focus_operations::sync::verify_sync_point(&sparse_repo, app)?;
+            if cleared.is_empty() {
+                eprintln!("The sync point refs are intact.");
+            }
+            for name in cleared {
+                eprintln!(
+                    "Cleared {}, which named a missing commit; the next sync will start over.",
+                    name
+                );
+            }
+            Ok(ExitCode(0))
+        }
+
+        Subcommand::SetSyncPoint {
+            repo,
+            commit,
+            force,
+        } => {
+            if !force {
+                bail!("Setting the sync point bypasses syncing and may leave the working tree out of step; pass --force to do it anyway");
+            }
+            let sparse_repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+            let _lock_file = locking::hold_repo_lock(&sparse_repo)?;
+            let commit_id = focus_operations::sync::set_sync_point(&sparse_repo, &commit, app)?;
+            eprintln!("The sync point is now {}.", commit_id);
+            Ok(ExitCode(0))
+        }
+
+        Subcommand::Prefetch {
+            repo,
+            remote,
+            branch,
+        } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+            let commit_id = focus_operations::prefetch::run(&sparse_repo, &remote, branch, app)?;
+            eprintln!("Prefetched {}", commit_id);
+            Ok(ExitCode(0))
+        }
+
+        Subcommand::ExpansionReport { repo } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+            focus_operations::selection::print_expansion_report(&sparse_repo, app)?;
+            Ok(ExitCode(0))
+        }
+
+        Subcommand::CachePlan { repo } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+            let plan = focus_operations::index::plan(app, &sparse_repo)?;
+            print!("{}", plan);
+            Ok(ExitCode(if plan.fully_cached() { 0 } else { 1 }))
+        }
+
+        Subcommand::WarmCache { repo, layers } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+            let _lock_file = locking::hold_repo_lock(&sparse_repo)?;
+            let result = focus_operations::index::warm_cache(app, &sparse_repo, &layers)?;
+            println!(
+                "Warmed {} dependency keys at {} ({} were already cached)",
+                result.warmed, result.commit_id, result.already_cached
+            );
+            Ok(ExitCode(0))
+        }
+
+        Subcommand::QueryCoordinate { repo, coordinate } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+            let _lock_file = locking::hold_repo_lock(&sparse_repo)?;
+            let result = focus_operations::query::coordinate(&sparse_repo, &coordinate, app)?;
+            focus_operations::query::print_coordinate_query(&result);
+            Ok(ExitCode(0))
+        }
+
+        Subcommand::TargetSetDiff { repo, from, to } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+            let _lock_file = locking::hold_repo_lock(&sparse_repo)?;
+            let diff = focus_operations::query::target_set_diff(&sparse_repo, &from, &to, app)?;
+            focus_operations::query::print_target_set_diff(&diff);
+            Ok(ExitCode(0))
+        }
+
         Subcommand::Status {
             targets,
             target_types,
+            no_upstream,
+            check_prefetch,
+            count_files,
+            time_format,
+            lock_timeout,
+            verbose,
+            ..
         } => {
             let sparse_repo = paths::find_repo_root_from(app.clone(), std::env::current_dir()?)?;
-            focus_operations::status::run(&sparse_repo, app, targets, target_types)
+            if check_prefetch {
+                return focus_operations::status::check_prefetch(&sparse_repo, app);
+            }
+            focus_operations::status::run(
+                &sparse_repo,
+                app,
+                targets,
+                target_types,
+                !no_upstream,
+                count_files,
+                porcelain,
+                time_format,
+                lock_timeout,
+                verbose,
+            )
         }
 
-        Subcommand::Projects {} => {
+        Subcommand::Projects { format, presets } => {
             let repo = git_helper::find_top_level(app.clone(), std::env::current_dir()?)
                 .context("Finding the top level of the repo")?;
-            focus_operations::selection::list_projects(&repo, app)?;
+            if presets {
+                focus_operations::selection::list_presets(&repo, app, format, porcelain)?;
+            } else {
+                focus_operations::selection::list_projects(&repo, app, format, porcelain)?;
+            }
+            Ok(ExitCode(0))
+        }
+
+        Subcommand::ApplyPreset { name, no_sync } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), std::env::current_dir()?)?;
+            ensure_repo_compatibility(&sparse_repo, app.clone())?;
+            let _lock_file = locking::hold_repo_lock(&sparse_repo)?;
+            focus_operations::selection::apply_preset(&sparse_repo, &name, !no_sync, app)?;
             Ok(ExitCode(0))
         }
 
@@ -1055,7 +2229,7 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
                     app,
                 )?;
 
-                sandbox::cleanup::run_with_default()?;
+                sandbox::cleanup::run_with_default(&app.sandbox_dir())?;
 
                 Ok(ExitCode(0))
             }
@@ -1124,6 +2298,7 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
                         .unwrap_or(sandbox::cleanup::Config::DEFAULT_HOURS),
                     max_num_sandboxes: max_num_sandboxes
                         .unwrap_or(sandbox::cleanup::Config::DEFAULT_MAX_NUM_SANDBOXES),
+                    sandbox_root: Some(app.sandbox_dir()),
                     ..sandbox::cleanup::Config::try_from_git_default()?
                 };
 
@@ -1139,7 +2314,7 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
         }
 
         Subcommand::Upgrade { repo } => {
-            focus_migrations::production::perform_pending_migrations(
+            focus_migrations::production::migrate(
                 paths::find_repo_root_from(app.clone(), repo)?.as_path(),
                 app,
             )
@@ -1155,6 +2330,12 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
                 Ok(ExitCode(0))
             }
 
+            IndexSubcommand::CacheStats { sparse_repo, count } => {
+                let sparse_repo = paths::find_repo_root_from(app, sparse_repo)?;
+                focus_operations::index::print_cache_stats(sparse_repo, count)?;
+                Ok(ExitCode(0))
+            }
+
             IndexSubcommand::CalculateChurn {
                 sparse_repo,
                 num_commits,
@@ -1253,6 +2434,21 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
                     .context("Finding the top level of the repo")?;
                 lint(&repo, app)
             }
+            ProjectSubcommand::Coordinates {
+                repo,
+                name,
+                resolve,
+                json,
+            } => {
+                let sparse_repo =
+                    paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+                focus_operations::project::coordinates(&sparse_repo, &name, resolve, json, app)
+            }
+            ProjectSubcommand::Tree { repo, name, depth } => {
+                let sparse_repo =
+                    paths::find_repo_root_from(app.clone(), paths::expand_path(repo)?)?;
+                focus_operations::project::tree(&sparse_repo, &name, depth, app)
+            }
         },
 
         Subcommand::Event { args: _ } => Ok(ExitCode(0)),
@@ -1284,9 +2480,20 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
                 let sparse_repo = paths::find_repo_root_from(app.clone(), sparse_repo)?;
                 focus_operations::background::disable(app, sparse_repo)
             }
-            BackgroundSubcommand::Sync { sparse_repo } => {
+            BackgroundSubcommand::Sync {
+                sparse_repo,
+                ignore_activity,
+                idle_threshold,
+                verbose,
+            } => {
                 let sparse_repo = paths::find_repo_root_from(app.clone(), sparse_repo)?;
-                focus_operations::background::sync(app, sparse_repo)
+                focus_operations::background::sync(
+                    app,
+                    sparse_repo,
+                    ignore_activity,
+                    idle_threshold,
+                    verbose,
+                )
             }
         },
         Subcommand::Pull => {
@@ -1310,20 +2517,146 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
                 )?;
                 Ok(ExitCode(0))
             }
+            SelectionSubcommand::Use { name, no_sync } => {
+                let sparse_repo =
+                    paths::find_repo_root_from(app.clone(), std::env::current_dir()?)?;
+                ensure_repo_compatibility(&sparse_repo, app.clone())?;
+                let _lock_file = locking::hold_repo_lock(&sparse_repo)?;
+                focus_operations::selection::use_selection(&sparse_repo, &name, !no_sync, app)?;
+                Ok(ExitCode(0))
+            }
+            SelectionSubcommand::Targets { emit_query } => {
+                let sparse_repo =
+                    paths::find_repo_root_from(app.clone(), std::env::current_dir()?)?;
+                focus_operations::selection::print_targets(&sparse_repo, emit_query, app)?;
+                Ok(ExitCode(0))
+            }
+            SelectionSubcommand::History {} => {
+                let sparse_repo =
+                    paths::find_repo_root_from(app.clone(), std::env::current_dir()?)?;
+                focus_operations::selection::history(&sparse_repo, app)?;
+                Ok(ExitCode(0))
+            }
+            SelectionSubcommand::Undo { no_sync } => {
+                let sparse_repo =
+                    paths::find_repo_root_from(app.clone(), std::env::current_dir()?)?;
+                ensure_repo_compatibility(&sparse_repo, app.clone())?;
+                let _lock_file = locking::hold_repo_lock(&sparse_repo)?;
+                focus_operations::selection::undo(&sparse_repo, !no_sync, app)?;
+                Ok(ExitCode(0))
+            }
+        },
+        Subcommand::Sandbox { subcommand } => match subcommand {
+            SandboxSubcommand::List {} => {
+                focus_operations::sandbox::list(&app.sandbox_dir())?;
+                Ok(ExitCode(0))
+            }
+            SandboxSubcommand::Clean {
+                max_age,
+                dry_run,
+                yes,
+            } => {
+                focus_operations::sandbox::clean(
+                    &app.sandbox_dir(),
+                    max_age,
+                    Removal::from_flags(dry_run, yes),
+                )?;
+                Ok(ExitCode(0))
+            }
+            SandboxSubcommand::Audit { dry_run, yes } => {
+                focus_operations::sandbox::audit(
+                    &app.sandbox_dir(),
+                    Removal::from_flags(dry_run, yes),
+                )?;
+                Ok(ExitCode(0))
+            }
         },
+        Subcommand::IdleMonitor {
+            interval,
+            idle_threshold,
+        } => focus_operations::background::idle_monitor(interval, idle_threshold),
+
         Subcommand::On { run_sync } => {
             let sparse_repo = paths::find_repo_root_from(app.clone(), std::env::current_dir()?)?;
-            let _lock_file = hold_lock_file(&sparse_repo)?;
+            let _lock_file = locking::hold_repo_lock(&sparse_repo)?;
             focus_operations::filter::run(sparse_repo, app, true, run_sync)
         }
         Subcommand::Off {} => {
             let sparse_repo = paths::find_repo_root_from(app.clone(), std::env::current_dir()?)?;
-            let _lock_file = hold_lock_file(&sparse_repo)?;
+            let _lock_file = locking::hold_repo_lock(&sparse_repo)?;
             focus_operations::filter::run(sparse_repo, app, false, false)
         }
     }
 }
 
+/// Report what a `--keep-going` selection change skipped. Exits with status 2 if anything was skipped.
+fn report_partial_mutation(outcome: &focus_operations::selection::PartialMutation) -> ExitCode {
+    if outcome.is_complete() {
+        return ExitCode(0);
+    }
+    for item in outcome.skipped.iter() {
+        eprintln!("Skipped: {}", item);
+    }
+    ExitCode(2)
+}
+
+/// Report whether `repo` is within a focused repo through the exit code, printing an explanation if `verbose` is set.
+fn check_focused_repo(repo: &Path, verbose: bool) -> ExitCode {
+    match focus_internals::model::repo::Repo::check_focused(repo) {
+        Ok(()) => {
+            if verbose {
+                eprintln!("{} is a focused repo", repo.display());
+            }
+            ExitCode(0)
+        }
+        Err(e) => {
+            if verbose {
+                eprintln!("{} is not a focused repo: {:#}", repo.display(), e);
+            }
+            ExitCode(1)
+        }
+    }
+}
+
+/// Print the compact status of the focused repo containing `repo` for a shell prompt, printing nothing if it is not in
+/// one so that prompts degrade quietly.
+fn print_prompt_status(repo: &Path) -> ExitCode {
+    match focus_operations::status::prompt_status(repo) {
+        Ok(status) => {
+            println!("{}", status);
+            ExitCode(0)
+        }
+        Err(_) => ExitCode(1),
+    }
+}
+
+fn check_sync_needed(repo: &Path, verbose: bool) -> ExitCode {
+    match focus_internals::model::repo::Repo::check_sync_needed(repo) {
+        Ok(false) => {
+            if verbose {
+                eprintln!("{} appears to be up to date", repo.display());
+            }
+            ExitCode(0)
+        }
+        Ok(true) => {
+            if verbose {
+                eprintln!("{} appears to need a sync", repo.display());
+            }
+            ExitCode(1)
+        }
+        Err(e) => {
+            if verbose {
+                eprintln!(
+                    "Could not tell whether {} needs a sync: {:#}",
+                    repo.display(),
+                    e
+                );
+            }
+            ExitCode(2)
+        }
+    }
+}
+
 fn ensure_repo_compatibility(sparse_repo: &Path, app: Arc<App>) -> Result<()> {
     if focus_migrations::production::is_upgrade_required(sparse_repo, app)
         .context("Failed to determine whether an upgrade is required")?
@@ -1337,6 +2670,65 @@ fn ensure_repo_compatibility(sparse_repo: &Path, app: Arc<App>) -> Result<()> {
     Ok(())
 }
 
+/// Take the global options that were given neither as flags nor through their environment variables from `profile`.
+fn apply_profile(options: &mut FocusOpts, matches: &ArgMatches, profile: Profile) {
+    let unset = |id: &str| {
+        matches!(
+            matches.value_source(id),
+            None | Some(ValueSource::DefaultValue)
+        )
+    };
+    let Profile {
+        resolution_threads,
+        sandbox_dir,
+        data_dir,
+        preserve_sandbox,
+        preserve_sandbox_on_failure,
+        offline,
+        porcelain,
+        no_color,
+        no_deprecated_warnings,
+        git,
+    } = profile;
+
+    if let (Some(threads), true) = (resolution_threads, unset("resolution_threads")) {
+        options.resolution_threads = threads;
+    }
+    if unset("sandbox_dir_override") {
+        options.sandbox_dir_override = options.sandbox_dir_override.take().or(sandbox_dir);
+    }
+    if unset("data_dir_override") {
+        options.data_dir_override = options.data_dir_override.take().or(data_dir);
+    }
+    if unset("git_binary") {
+        options.git_binary = options.git_binary.take().or(git);
+    }
+    for (value, id, option) in [
+        (
+            preserve_sandbox,
+            "preserve_sandbox",
+            &mut options.preserve_sandbox,
+        ),
+        (
+            preserve_sandbox_on_failure,
+            "preserve_sandbox_on_failure",
+            &mut options.preserve_sandbox_on_failure,
+        ),
+        (offline, "offline", &mut options.offline),
+        (porcelain, "porcelain", &mut options.porcelain),
+        (no_color, "no_color", &mut options.no_color),
+        (
+            no_deprecated_warnings,
+            "no_deprecated_warnings",
+            &mut options.no_deprecated_warnings,
+        ),
+    ] {
+        if let (Some(value), true) = (value, unset(id)) {
+            *option = value;
+        }
+    }
+}
+
 fn setup_thread_pool(resolution_threads: usize) -> Result<()> {
     if resolution_threads > 0 {
         rayon::ThreadPoolBuilder::new()
@@ -1366,18 +2758,99 @@ fn setup_maintenance_scheduler(opts: &FocusOpts) -> Result<()> {
     }
 }
 
+#[derive(Debug, thiserror::Error)]
+#[error("Timed out after {}", humantime::format_duration(*.0))]
+struct TimedOut(Duration);
+
+/// How long to wait for the commands of a timed out subcommand to exit after killing them.
+const TIMED_OUT_COMMANDS_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// Run the subcommand, giving up if it does not finish within `timeout`. After a timeout the commands the subcommand
+/// started are killed and the subcommand is abandoned on its thread, so the caller must exit promptly with
+/// `exit_after_timeout`.
+fn run_subcommand_with_timeout(
+    app: Arc<App>,
+    tracker: Tracker,
+    options: FocusOpts,
+    timeout: Option<Duration>,
+) -> Result<ExitCode> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return run_subcommand(app, &tracker, options),
+    };
+
+    let running_commands = app.running_commands();
+    let (sender, receiver) = std::sync::mpsc::channel();
+    thread::Builder::new()
+        .name("subcommand".to_owned())
+        .spawn(move || {
+            // The receiver is gone if the subcommand timed out, in which case there is nobody to report to.
+            let _ = sender.send(run_subcommand(app, &tracker, options));
+        })
+        .context("Spawning the subcommand thread")?;
+
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+            error!(?timeout, "The command did not finish in time");
+            // Stop the commands before the sandbox they are using is removed.
+            running_commands.kill_all(TIMED_OUT_COMMANDS_GRACE_PERIOD);
+            Err(TimedOut(timeout).into())
+        }
+        Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+            bail!("The subcommand thread exited without a result")
+        }
+    }
+}
+
+/// Exit after the subcommand timed out. Its thread is still running, so rather than returning while it keeps holding
+/// locks and writing to the sandbox, release the locks, remove the sandbox unless it is to be preserved regardless of
+/// the outcome, and exit the process right away. This is the only place besides `main` that exits the process: returning
+/// would leave the abandoned subcommand running until `main` exits.
+fn exit_after_timeout(app: &App, error: anyhow::Error, preserve_sandbox: bool) -> ! {
+    LockFile::release_all();
+    if preserve_sandbox {
+        eprintln!("Sandbox preserved at {}", app.sandbox().path().display());
+    } else if let Err(e) = app.sandbox().discard() {
+        warn!(?e, "Failed to remove the sandbox");
+    }
+    eprintln!("Error: {:?}", error);
+    std::process::exit(1);
+}
+
 /// Run the main and any destructors. Local variables are not guaranteed to be
 /// dropped if `std::process::exit` is called, so make sure to bubble up the
 /// return code to the top level, which is the only place in the code that's
-/// allowed to call `std::process::exit`.
+/// allowed to call `std::process::exit` (besides `exit_after_timeout`).
 fn main_and_drop_locals() -> Result<ExitCode> {
     let started_at = Instant::now();
-    let options = FocusOpts::parse();
+    let matches = FocusOpts::command().get_matches();
+    let mut options = FocusOpts::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if let Some(name) = options.profile.clone() {
+        let config_file = paths::focus_config_dir().join(profile::CONFIG_FILE_NAME);
+        let profile = Profile::load(&config_file, &name)
+            .with_context(|| format!("Loading profile '{}'", name))?;
+        apply_profile(&mut options, &matches, profile);
+    }
 
     let FocusOpts {
         resolution_threads,
         working_directory,
         no_color,
+        sandbox_dir_override,
+        data_dir_override,
+        no_tracker,
+        preserve_sandbox,
+        preserve_sandbox_on_failure,
+        offline,
+        timeout,
+        no_deprecated_warnings,
+        git_binary,
+        porcelain: _,
+        profile: _,
+        metrics_file,
+        log_filter,
+        verbosity,
         cmd: _,
     } = &options;
 
@@ -1385,13 +2858,62 @@ fn main_and_drop_locals() -> Result<ExitCode> {
         std::env::set_current_dir(working_directory).context("Switching working directory")?;
     }
 
-    let preserve_sandbox = true;
+    // Checks and prompt status are run frequently (e.g. by shell prompts), so skip creating a sandbox and initializing
+    // tracing.
+    match &options.cmd {
+        Subcommand::Check { repo, verbose } => {
+            return Ok(check_focused_repo(&paths::expand_path(repo)?, *verbose))
+        }
+        Subcommand::NeedsSync { repo, verbose } => {
+            return Ok(check_sync_needed(&paths::expand_path(repo)?, *verbose))
+        }
+        Subcommand::Status { prompt: true, .. } => {
+            return Ok(print_prompt_status(&std::env::current_dir()?))
+        }
+        _ => {}
+    }
+
+    let sandbox_dir = match sandbox_dir_override {
+        Some(dir) if !dir.as_os_str().is_empty() => {
+            let dir = std::env::current_dir()
+                .context("Failed to obtain current directory")?
+                .join(paths::expand_path(dir)?);
+            paths::ensure_writable_directory(&dir).context("Validating the sandbox directory")?;
+            Some(dir)
+        }
+        _ => None,
+    };
+
+    let data_dir = match data_dir_override {
+        Some(dir) if !dir.as_os_str().is_empty() => {
+            let dir = std::env::current_dir()
+                .context("Failed to obtain current directory")?
+                .join(paths::expand_path(dir)?);
+            paths::ensure_writable_directory(&dir).context("Validating the data directory")?;
+            Some(dir)
+        }
+        _ => None,
+    };
+
+    let git_binary = git_binary.as_ref().map(paths::expand_path).transpose()?;
+    let preserve_sandbox = *preserve_sandbox;
+    let preserve_sandbox_on_failure = *preserve_sandbox_on_failure;
+    let timeout = *timeout;
+    let metrics_file = metrics_file.as_ref().map(paths::expand_path).transpose()?;
+    let command = feature_name_for(&options.cmd);
 
     let app = Arc::from(App::new(
         preserve_sandbox,
-        Some(&feature_name_for(&options.cmd)),
+        Some(&command),
         Some(env!("CARGO_PKG_NAME").to_owned()),
         Some(env!("CARGO_PKG_VERSION").to_owned()),
+        AppOptions {
+            git_binary,
+            sandbox_dir,
+            data_dir,
+            offline: *offline,
+            no_deprecated_warnings: *no_deprecated_warnings,
+        },
     )?);
     let ti_context = app.tool_insights_client();
 
@@ -1400,15 +2922,26 @@ fn main_and_drop_locals() -> Result<ExitCode> {
     let is_tty = termion::is_tty(&std::io::stdout());
 
     let sandbox_dir = app.sandbox().path().to_owned();
-    let tracker = Tracker::from_config_dir()?;
+    let tracker = if *no_tracker {
+        Tracker::disabled()
+    } else {
+        Tracker::from_config_dir(app.options().data_dir.as_deref())?
+    };
 
     let _guard = focus_tracing::init_tracing(focus_tracing::TracingOpts {
         is_tty,
         no_color: *no_color,
         log_dir: Some(sandbox_dir.to_owned()),
+        log_filter: log_filter
+            .clone()
+            .or_else(|| focus_tracing::log_filter_for_verbosity(*verbosity).map(String::from)),
     })?;
 
-    info!(path = ?sandbox_dir, "Created sandbox");
+    if preserve_sandbox {
+        info!(path = ?sandbox_dir, "Created sandbox, which will be preserved");
+    } else {
+        info!(path = ?sandbox_dir, "Created sandbox");
+    }
 
     ensure_directories_exist(&tracker).context("Failed to create necessary directories")?;
     let setup_maintenance_task = thread::spawn({
@@ -1418,7 +2951,15 @@ fn main_and_drop_locals() -> Result<ExitCode> {
         }
     });
 
-    let exit_code = match run_subcommand(app.clone(), &tracker, options) {
+    let result = run_subcommand_with_timeout(app.clone(), tracker, options, timeout);
+    if let Some(metrics_file) = &metrics_file {
+        record_command_metrics(&app, &command, started_at.elapsed(), result.is_ok());
+        if let Err(e) = app.metrics().write_to_file(metrics_file) {
+            warn!(?e, path = ?metrics_file, "Failed to write metrics");
+        }
+    }
+
+    let exit_code = match result {
         Ok(exit_code) => {
             ti_context
                 .get_inner()
@@ -1429,13 +2970,23 @@ fn main_and_drop_locals() -> Result<ExitCode> {
             ti_context
                 .get_inner()
                 .write_invocation_message(Some(1), None);
+            if e.is::<TimedOut>() {
+                exit_after_timeout(&app, e, preserve_sandbox);
+            }
+            if preserve_sandbox || (preserve_sandbox_on_failure && app.sandbox().preserve()) {
+                eprintln!("Sandbox preserved at {}", sandbox_dir.display());
+            }
             return Err(e);
         }
     };
 
-    sandbox::cleanup::run_with_default()?;
+    sandbox::cleanup::run_with_default(&app.sandbox_dir())?;
     setup_maintenance_task.join().unwrap()?;
 
+    if preserve_sandbox {
+        eprintln!("Sandbox preserved at {}", sandbox_dir.display());
+    }
+
     let total_runtime = started_at.elapsed();
     debug!(
         total_runtime_secs = total_runtime.as_secs_f32(),
@@ -1445,6 +2996,28 @@ fn main_and_drop_locals() -> Result<ExitCode> {
     Ok(exit_code)
 }
 
+fn record_command_metrics(app: &App, command: &str, duration: Duration, succeeded: bool) {
+    let metrics = app.metrics();
+    let labels = [("command", command)];
+    metrics.set(
+        &focus_util::metrics::COMMAND_DURATION_SECONDS,
+        &labels,
+        duration.as_secs_f64(),
+    );
+    metrics.set(
+        &focus_util::metrics::COMMAND_SUCCESS,
+        &labels,
+        if succeeded { 1.0 } else { 0.0 },
+    );
+    metrics.increment(
+        &focus_util::metrics::COMMAND_RUNS_TOTAL,
+        &[
+            ("command", command),
+            ("result", if succeeded { "success" } else { "failure" }),
+        ],
+    );
+}
+
 fn main() -> Result<()> {
     let ExitCode(exit_code) = main_and_drop_locals()?;
     std::process::exit(exit_code);
@@ -1470,4 +3043,37 @@ mod tests {
         assert_eq!(feature_name, "event-this-is-an-event-subcommand-teehee");
         Ok(())
     }
+
+    #[test]
+    fn profile_fills_in_options_that_were_not_given() -> Result<()> {
+        let matches = FocusOpts::command().try_get_matches_from([
+            "focus",
+            "--offline",
+            "--sandbox-dir",
+            "/tmp/explicit",
+            "check",
+        ])?;
+        let mut options = FocusOpts::from_arg_matches(&matches)?;
+        apply_profile(
+            &mut options,
+            &matches,
+            Profile {
+                resolution_threads: Some(8),
+                sandbox_dir: Some(PathBuf::from("/tmp/profile")),
+                offline: Some(false),
+                preserve_sandbox_on_failure: Some(false),
+                porcelain: Some(true),
+                ..Default::default()
+            },
+        );
+        assert_eq!(options.resolution_threads, 8);
+        assert_eq!(
+            options.sandbox_dir_override,
+            Some(PathBuf::from("/tmp/explicit"))
+        );
+        assert!(options.offline);
+        assert!(!options.preserve_sandbox_on_failure);
+        assert!(options.porcelain);
+        Ok(())
+    }
 }
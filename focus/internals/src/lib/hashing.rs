@@ -3,7 +3,9 @@
 
 use anyhow::{Context, Result};
 use sha2::{Digest, Sha256};
-use std::path::Path;
+use std::{collections::HashSet, path::Path};
+
+use crate::target::Target;
 
 type Hasher = Sha256;
 
@@ -17,3 +19,25 @@ pub fn hash_file(path: impl AsRef<Path>) -> Result<Vec<u8>> {
     digest.update(&contents);
     Ok(digest.finalize().to_vec())
 }
+
+/// Hash a set of targets independently of their iteration order, returning a hex digest.
+pub fn hash_target_set(targets: &HashSet<Target>) -> String {
+    let mut names: Vec<String> = targets.iter().map(|t| t.to_string()).collect();
+    names.sort();
+
+    let mut digest = Hasher::new();
+    for name in names {
+        digest.update(name.as_bytes());
+        digest.update(b"\n");
+    }
+    hex::encode(digest.finalize())
+}
+
+/// Hash the name of a selection set together with the stored contents of its file, returning a hex digest.
+pub fn hash_selection(name: &str, contents: &[u8]) -> String {
+    let mut digest = Hasher::new();
+    digest.update(name.as_bytes());
+    digest.update(b"\n");
+    digest.update(contents);
+    hex::encode(digest.finalize())
+}
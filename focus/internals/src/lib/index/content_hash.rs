@@ -74,6 +74,10 @@ pub struct Caches {
 
     /// Cache of dependencies loaded from the `prelude_bazel` file.
     prelude_deps_cache: Option<BTreeSet<Label>>,
+
+    /// Cache of the files at the root of the repository which are relevant to
+    /// the build graph (other than `WORKSPACE` itself).
+    top_level_build_files_cache: Option<BTreeSet<PathBuf>>,
 }
 
 /// Context used to compute a content hash.
@@ -253,6 +257,16 @@ fn get_dependencies<'a>(
                 target_name: TargetName::Name("WORKSPACE".to_string()),
             }));
 
+            // Global Bazel configuration in the root of the repository (such
+            // as a root `BUILD` file or `.bzl` files that are not explicitly
+            // loaded) can change the meaning of any package, so mix in the
+            // textual hashes of those files as well.
+            dep_keys.extend(
+                get_top_level_build_files(ctx)?
+                    .into_iter()
+                    .map(DependencyKey::Path),
+            );
+
             Ok((
                 "BazelPackage",
                 Some(label),
@@ -379,6 +393,28 @@ pub fn get_prelude_deps(ctx: &HashContext) -> Result<BTreeSet<Label>> {
     Ok(result)
 }
 
+/// Get the files at the root of the repository which are relevant to the build
+/// graph, such as a root `BUILD` file, `.bzl` files, or `WORKSPACE` variants.
+/// The `WORKSPACE` file itself is excluded since it is already an explicit
+/// dependency of every package.
+fn get_top_level_build_files(ctx: &HashContext) -> Result<BTreeSet<PathBuf>> {
+    if let Some(top_level_build_files) = &ctx.caches.borrow().top_level_build_files_cache {
+        return Ok(top_level_build_files.clone());
+    }
+
+    let result: BTreeSet<PathBuf> = ctx
+        .head_tree
+        .iter()
+        .filter(|tree_entry| tree_entry.kind() == Some(git2::ObjectType::Blob))
+        .filter(|tree_entry| tree_entry.name() != Some("WORKSPACE"))
+        .filter(is_tree_entry_relevant_to_build_graph)
+        .filter_map(|tree_entry| tree_entry.name().map(PathBuf::from))
+        .collect();
+
+    ctx.caches.borrow_mut().top_level_build_files_cache = Some(result.clone());
+    Ok(result)
+}
+
 fn content_hash_tree_path(ctx: &HashContext, path: &Path) -> Result<ContentHash> {
     if let Some(hash) = ctx.caches.borrow().tree_path_cache.get(path) {
         return Ok(hash.clone());
@@ -553,8 +589,38 @@ load
 
 #[cfg(test)]
 mod tests {
+    use focus_testing::ScratchGitRepo;
+
     use super::*;
 
+    #[test]
+    fn test_top_level_build_files_invalidate_package_hash() -> anyhow::Result<()> {
+        let temp = tempfile::tempdir()?;
+        let fix = ScratchGitRepo::new_static_fixture(temp.path())?;
+        fix.write_and_commit_file("WORKSPACE", "", "add WORKSPACE")?;
+        fix.write_and_commit_file("package1/BUILD", "", "add package")?;
+        let repo = fix.repo()?;
+        let key = DependencyKey::BazelPackage("//package1:foo".parse()?);
+
+        let hash_at = |commit_id: git2::Oid| -> anyhow::Result<ContentHash> {
+            let head_tree = repo.find_commit(commit_id)?.tree()?;
+            let ctx = HashContext::new(&repo, &head_tree)?;
+            Ok(content_hash(&ctx, &key)?)
+        };
+
+        let before = hash_at(fix.write_and_commit_file("README", "hello", "add README")?)?;
+        let unrelated = hash_at(fix.write_and_commit_file("README", "bye", "edit README")?)?;
+        assert_eq!(before, unrelated);
+
+        let added = hash_at(fix.write_and_commit_file("defs.bzl", "X = 1", "add defs.bzl")?)?;
+        assert_ne!(before, added);
+
+        let edited = hash_at(fix.write_and_commit_file("defs.bzl", "X = 2", "edit defs.bzl")?)?;
+        assert_ne!(added, edited);
+
+        Ok(())
+    }
+
     #[test]
     fn test_extract_load_statements() -> Result<()> {
         let content = r#"
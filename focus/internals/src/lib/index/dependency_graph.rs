@@ -84,7 +84,7 @@ impl From<Target> for DependencyKey {
     fn from(target: Target) -> Self {
         match target {
             Target::Bazel(label) => Self::BazelPackage(label),
-            Target::Directory(path) => Self::Path(PathBuf::from(path)),
+            Target::Directory(path) | Target::File(path) => Self::Path(PathBuf::from(path)),
         }
     }
 }
@@ -451,7 +451,7 @@ sh_binary(
         let request = ResolutionRequest {
             repo: fix.path().to_path_buf(),
             targets: target_set,
-            options: Default::default(),
+            ..Default::default()
         };
         let cache_options = CacheOptions::default();
         let resolve_result = resolver.resolve(&request, &cache_options, app)?;
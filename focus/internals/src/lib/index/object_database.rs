@@ -5,6 +5,7 @@ use std::time::Duration;
 
 use super::content_hash::HashContext;
 use super::{content_hash, ContentHash, DependencyKey, DependencyValue};
+use crate::model::repo::RESOLUTION_CACHE_TTL_CONFIG_KEY;
 use anyhow::Context;
 use content_addressed_cache::Cache;
 use tracing::{debug, error, info, info_span, warn};
@@ -101,12 +102,33 @@ pub trait RocksDBMemoizationCacheExt {
 
 const ROCKSDB_CACHE_TTL: Duration = Duration::from_secs(3600 * 24 * 14);
 
+/// Read the configured resolution cache TTL, ignoring (with a warning) values that can't be parsed.
+fn resolution_cache_ttl(repo: &git2::Repository) -> Option<Duration> {
+    let value = repo
+        .config()
+        .and_then(|config| config.get_string(RESOLUTION_CACHE_TTL_CONFIG_KEY))
+        .ok()?;
+    match humantime::parse_duration(&value) {
+        Ok(ttl) => Some(ttl),
+        Err(e) => {
+            warn!(
+                key = RESOLUTION_CACHE_TTL_CONFIG_KEY,
+                ?value,
+                ?e,
+                "Ignoring invalid resolution cache TTL"
+            );
+            None
+        }
+    }
+}
+
 impl RocksDBMemoizationCacheExt for RocksDBCache {
     fn new(repo: &git2::Repository) -> RocksDBCache {
         let rocksdb_path = repo.path().join("focus/focus-index-rocks-db");
         let span = info_span!("Opening index database");
         let _guard = span.enter();
         RocksDBCache::open_with_ttl(rocksdb_path, ROCKSDB_CACHE_TTL)
+            .with_entry_ttl(resolution_cache_ttl(repo))
     }
 }
 
@@ -354,4 +376,25 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_resolution_cache_ttl_config() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let fix = ScratchGitRepo::new_static_fixture(temp_dir.path())?;
+        let repo = fix.repo()?;
+        assert_eq!(resolution_cache_ttl(&repo), None);
+
+        repo.config()?
+            .set_str(RESOLUTION_CACHE_TTL_CONFIG_KEY, "12h")?;
+        assert_eq!(
+            resolution_cache_ttl(&repo),
+            Some(Duration::from_secs(12 * 3600))
+        );
+
+        repo.config()?
+            .set_str(RESOLUTION_CACHE_TTL_CONFIG_KEY, "potato")?;
+        assert_eq!(resolution_cache_ttl(&repo), None);
+
+        Ok(())
+    }
 }
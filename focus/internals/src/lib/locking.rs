@@ -3,14 +3,38 @@
 
 use anyhow::Result;
 
-use std::{path::Path, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
 use focus_util::{app::App, git_helper, lock_file::LockFile};
 
+/// Take the exclusive lock that commands changing the repo hold while they run.
+pub fn hold_repo_lock(repo_path: &Path) -> Result<LockFile> {
+    LockFile::new(&repo_path.join(".focus").join("focus.lock"))
+}
+
+/// Take an exclusive lock on the named lock file in the repo's git dir.
 pub fn hold_lock(repo_path: &Path, file_name: &Path, app: Arc<App>) -> Result<LockFile> {
+    LockFile::new(&lock_path(repo_path, file_name, app)?)
+}
+
+/// Take a shared lock on the named lock file in the repo's git dir. Readers holding it can proceed concurrently, but
+/// not while an exclusive lock is held, so this waits for the exclusive holder, giving up after `timeout` if given.
+pub fn hold_read_lock(
+    repo_path: &Path,
+    file_name: &Path,
+    timeout: Option<Duration>,
+    app: Arc<App>,
+) -> Result<LockFile> {
+    LockFile::wait_shared(&lock_path(repo_path, file_name, app)?, timeout)
+}
+
+fn lock_path(repo_path: &Path, file_name: &Path, app: Arc<App>) -> Result<PathBuf> {
     let git_dir = git_helper::git_dir(repo_path, app)?;
     let focus_dir = git_dir.join(".focus");
     std::fs::create_dir_all(&focus_dir)?;
-    let lock_path = focus_dir.join(file_name);
-    LockFile::new(&lock_path)
+    Ok(focus_dir.join(file_name))
 }
@@ -4,9 +4,15 @@
 use anyhow::{Context, Result};
 use tracing::warn;
 
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use crate::model::selection::WorkingTree;
+use crate::model::selection::{WorkingTree, DEFAULT_SELECTION_NAME};
+
+const SELECTION_FILE_NAME: &str = "user.selection.json";
+const SELECTIONS_DIR_NAME: &str = "selections";
 
 pub struct DataPaths {
     pub dot_focus_dir: PathBuf,
@@ -14,6 +20,7 @@ pub struct DataPaths {
     pub data_dir: PathBuf,
     pub project_dir: PathBuf,
     pub selection_file: PathBuf,
+    pub selections_dir: PathBuf,
 }
 
 impl DataPaths {
@@ -22,7 +29,8 @@ impl DataPaths {
         let focus_dir = working_tree.work_dir().join("focus");
         let data_dir = dot_focus_dir.join("focus");
         let project_dir = focus_dir.join("projects");
-        let selection_file = dot_focus_dir.join("user.selection.json");
+        let selection_file = dot_focus_dir.join(SELECTION_FILE_NAME);
+        let selections_dir = dot_focus_dir.join(SELECTIONS_DIR_NAME);
 
         let instance = Self {
             dot_focus_dir,
@@ -30,6 +38,7 @@ impl DataPaths {
             data_dir,
             project_dir,
             selection_file,
+            selections_dir,
         };
         instance
             .ensure_directories_are_set_up_correctly()
@@ -37,6 +46,22 @@ impl DataPaths {
         Ok(instance)
     }
 
+    /// The file storing the named selection set. The default set lives in `selection_file`.
+    pub fn selection_file_for(&self, name: &str) -> PathBuf {
+        Self::selection_file_in(&self.dot_focus_dir, name)
+    }
+
+    /// The file storing the named selection set within `dot_focus_dir`, without setting up any directories.
+    pub fn selection_file_in(dot_focus_dir: &Path, name: &str) -> PathBuf {
+        if name == DEFAULT_SELECTION_NAME {
+            dot_focus_dir.join(SELECTION_FILE_NAME)
+        } else {
+            dot_focus_dir
+                .join(SELECTIONS_DIR_NAME)
+                .join(format!("{}.selection.json", name))
+        }
+    }
+
     fn ensure_directories_are_set_up_correctly(&self) -> Result<()> {
         if !self.focus_dir.is_dir() {
             warn!(
@@ -50,7 +75,7 @@ impl DataPaths {
             );
         }
 
-        let dirs_to_create = vec![self.data_dir.as_path()];
+        let dirs_to_create = vec![self.data_dir.as_path(), self.selections_dir.as_path()];
         for dir in dirs_to_create {
             std::fs::create_dir_all(dir).with_context(|| {
                 format!("Failed to create directory hierarchy '{}'", &dir.display())
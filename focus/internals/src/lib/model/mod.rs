@@ -7,3 +7,4 @@ pub mod outlining;
 mod persistence;
 pub mod repo;
 pub mod selection;
+pub mod verification;
@@ -310,6 +310,48 @@ pub fn create_hierarchical_patterns(patterns: &PatternSet) -> PatternSet {
     resulting_patterns
 }
 
+/// Remove directory patterns that are redundant because the same directory or one of its ancestors is included
+/// recursively, along with duplicate patterns that differ only in precedence. In cone mode, the optimized patterns
+/// match exactly the same files as the originals.
+pub fn optimize_patterns(patterns: &PatternSet) -> PatternSet {
+    // Root patterns are always written non-recursively, so they never cover other patterns.
+    let recursive_paths: HashSet<&Path> = patterns
+        .iter()
+        .filter_map(|pattern| match pattern {
+            Pattern::Directory {
+                path,
+                recursive: true,
+                ..
+            } if !path.is_empty() && path.as_path() != ROOT_PATH.as_path() => Some(path.as_path()),
+            _ => None,
+        })
+        .collect();
+
+    let mut seen_directories = HashSet::<(&Path, bool)>::new();
+    let mut optimized_patterns = PatternSet::new();
+    for pattern in patterns {
+        if let Pattern::Directory {
+            path, recursive, ..
+        } = pattern
+        {
+            let covered_by_ancestor = path
+                .ancestors()
+                .skip(1)
+                .any(|ancestor| recursive_paths.contains(ancestor));
+            let covered_by_recursive_self = !*recursive && recursive_paths.contains(path.as_path());
+            if covered_by_ancestor
+                || covered_by_recursive_self
+                || !seen_directories.insert((path.as_path(), *recursive))
+            {
+                continue;
+            }
+        }
+        optimized_patterns.insert(pattern.clone());
+    }
+
+    optimized_patterns
+}
+
 lazy_static! {
     pub static ref DEFAULT_OUTLINING_PATTERNS: PatternSet = {
         let mut patterns = PatternSet::new();
@@ -585,4 +627,41 @@ mod testing {
         let hierarchical_patterns = create_hierarchical_patterns(&patterns);
         insta::assert_json_snapshot!(&hierarchical_patterns);
     }
+
+    #[test]
+    fn test_optimize_patterns() {
+        let directory = |precedence: usize, path: &str, recursive: bool| Pattern::Directory {
+            precedence,
+            path: PathBuf::from(path),
+            recursive,
+        };
+        let mut patterns = PatternSet::new();
+        patterns.insert(directory(0, "", true));
+        patterns.insert(Pattern::Verbatim {
+            precedence: 0,
+            fragment: String::from("/*"),
+        });
+        patterns.insert(directory(1, "a/b", true));
+        patterns.insert(directory(2, "a/b/c", true));
+        patterns.insert(directory(2, "a/b/d", false));
+        patterns.insert(directory(2, "a/b", false));
+        patterns.insert(directory(3, "a/b", true));
+        patterns.insert(directory(3, "a/bc", true));
+        patterns.insert(directory(3, "e", false));
+
+        let optimized_patterns = optimize_patterns(&patterns);
+        assert_eq!(
+            optimized_patterns.into_iter().collect::<Vec<_>>(),
+            vec![
+                directory(0, "", true),
+                directory(1, "a/b", true),
+                directory(3, "a/bc", true),
+                directory(3, "e", false),
+                Pattern::Verbatim {
+                    precedence: 0,
+                    fragment: String::from("/*"),
+                },
+            ]
+        );
+    }
 }
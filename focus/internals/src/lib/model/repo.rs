@@ -6,44 +6,48 @@ use focus_util::{
     app::App,
     git,
     git_helper::{self, get_head_commit, ConfigExt},
+    metrics::{SYNC_INDEX_HIT_RATIO, SYNC_INDEX_KEYS},
     paths::{self, is_build_definition},
     sandbox_command::SandboxCommandOutput,
 };
 
 use std::{
-    collections::HashSet,
+    collections::{BTreeSet, HashMap, HashSet},
     fs,
     io::BufWriter,
     io::Write,
     path::{Path, PathBuf},
     str::FromStr,
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 use url::Url;
 
 use crate::{
     hashing,
     index::{
-        get_files_to_materialize, update_object_database_from_resolution, DependencyKey,
-        HashContext, PathsToMaterializeResult,
+        content_hash, get_files_to_materialize, update_object_database_from_resolution,
+        DependencyKey, HashContext, PathsToMaterializeResult,
     },
     model::outlining::{create_hierarchical_patterns, Pattern},
     project_cache::{ProjectCache, Value},
-    target::TargetSet,
+    target::{kind_query, normalize_kind_query_scope, TargetSet},
     target_resolver::{
-        BazelResolutionStrategy, CacheOptions, ResolutionOptions, ResolutionRequest,
-        ResolutionResult, Resolver, RoutingResolver,
+        BazelResolutionStrategy, CacheOptions, OneShotBazelResolver, ResolutionOptions,
+        ResolutionRequest, ResolutionResult, Resolver, RoutingResolver,
     },
 };
 
 use super::{
     configuration::Configuration,
+    data_paths::DataPaths,
     outlining::{
-        pattern_default_precedence, PatternContainer, PatternSet, PatternSetWriter,
-        DEFAULT_OUTLINING_PATTERNS,
+        optimize_patterns, pattern_default_precedence, PatternContainer, PatternSet,
+        PatternSetWriter, DEFAULT_OUTLINING_PATTERNS,
+    },
+    selection::{
+        validate_selection_name, Selection, SelectionManager, Target, DEFAULT_SELECTION_NAME,
     },
-    selection::{Selection, SelectionManager, Target},
 };
 
 use anyhow::{bail, Context, Result};
@@ -51,25 +55,472 @@ use git2::{ObjectType, Oid, Repository, TreeWalkMode, TreeWalkResult};
 use tracing::{debug, info, info_span, trace, warn};
 use uuid::Uuid;
 
+/// Declares the Git config keys of the settings focus reads, along with `SETTING_CONFIG_KEYS` listing all of them, so
+/// that `Repo::effective_settings` reports every setting.
+macro_rules! setting_config_keys {
+    ($($(#[$attr:meta])* $vis:vis const $name:ident = $key:literal;)*) => {
+        $($(#[$attr])* $vis const $name: &str = $key;)*
+
+        /// The Git config keys of every setting focus reads.
+        pub const SETTING_CONFIG_KEYS: &[&str] = &[$($name),*];
+    };
+}
+
+setting_config_keys! {
+    const ACTIVE_SELECTION_CONFIG_KEY = "focus.selection.active";
+    const FILTER_VIEW = "focus.filter";
+    const PREEMPTIVE_SYNC_ENABLED_CONFIG_KEY = "focus.preemptive-sync.enabled";
+    const PREEMPTIVE_SYNC_USER_IDLE_MILLIS_THRESHOLD_CONFIG_KEY = "focus.preemptive-sync.user-idle-threshold";
+    const REMOTE_ORIGIN_URL_CONFIG_KEY = "remote.origin.url";
+    pub const CHECKOUT_BACKEND_CONFIG_KEY = "focus.checkout.backend";
+    pub const SPARSE_MODE_CONFIG_KEY = "focus.sparse-mode";
+    pub const MIN_VERSION_CONFIG_KEY = "focus.min-version";
+    pub const PROJECT_CACHE_ENDPOINT_CONFIG_KEY = "focus.project-cache.endpoint";
+    pub const PROJECT_CACHE_INCLUDE_HEADERS_FILE_CONFIG_KEY = "focus.project-cache.include-headers-from";
+    pub const BAZEL_ONE_SHOT_RESOLUTION_CONFIG_KEY = "focus.bazel.one-shot";
+    pub const RESOLVER_COMMAND_CONFIG_KEY = "focus.resolver.command";
+    pub const BAZEL_QUERY_RETRIES_CONFIG_KEY = "focus.bazel.query-retries";
+    /// Whether one-shot resolution queries Bazel once for all coordinates rather than once per coordinate. Defaults to
+    /// true.
+    pub const BAZEL_UNION_QUERY_CONFIG_KEY = "focus.bazel.union-query";
+    pub const SELECTION_HISTORY_DEPTH_CONFIG_KEY = "focus.selection-history-depth";
+    pub const SELECTION_MIRROR_CONFIG_KEY = "focus.selection-mirror";
+    /// Commands run before each sync, any of which can veto it by failing. May be set more than once.
+    pub const PRE_SYNC_HOOKS_CONFIG_KEY = "focus.hooks.pre-sync";
+    /// How long resolution results stay valid, e.g. `12h`. Results can depend on remote state that changes without a
+    /// commit (such as `http_archive` external repositories), which their keys cannot capture; entries older than this
+    /// are recomputed even though their key matches. Unset by default, in which case entries never expire early.
+    pub const RESOLUTION_CACHE_TTL_CONFIG_KEY = "focus.resolution-cache-ttl";
+}
+
 const SPARSE_SYNC_REF_NAME: &str = "refs/focus/sync";
 const PREEMPTIVE_SYNC_REF_NAME: &str = "refs/focus/presync";
 const UUID_CONFIG_KEY: &str = "focus.uuid";
-const PREEMPTIVE_SYNC_ENABLED_CONFIG_KEY: &str = "focus.preemptive-sync.enabled";
-const PREEMPTIVE_SYNC_USER_IDLE_MILLIS_THRESHOLD_CONFIG_KEY: &str =
-    "focus.preemptive-sync.user-idle-threshold";
 const PREEMPTIVE_SYNC_USER_IDLE_MILLIS_THRESHOLD_DEFAULT: i32 = 15000;
-const FILTER_VIEW: &str = "focus.filter";
 
 const INDEX_SPARSE_CONFIG_KEY: &str = "index.sparse";
 const CORE_UNTRACKED_CACHE_CONFIG_KEY: &str = "core.untrackedCache";
+const CORE_SPARSE_CHECKOUT_CONFIG_KEY: &str = "core.sparseCheckout";
+const CORE_SPARSE_CHECKOUT_CONE_CONFIG_KEY: &str = "core.sparseCheckoutCone";
+
+/// Fragments of Git's standard error output indicating that a checkout failed for a reason that may not recur, such
+/// as another process briefly holding the index lock.
+const CHECKOUT_TRANSIENT_FAILURE_SIGNATURES: &[&str] = &[
+    "index.lock': File exists",
+    "Resource temporarily unavailable",
+    "Interrupted system call",
+    "Device or resource busy",
+    "Stale file handle",
+];
+const CHECKOUT_RETRIES: usize = 2;
+/// How long to wait before retrying a checkout. The delay doubles after each attempt.
+const CHECKOUT_INITIAL_RETRY_BACKOFF: Duration = Duration::from_millis(500);
 
 const OUTLINING_PATTERN_FILE_NAME: &str = "focus/outlining.patterns.json";
+const SYNC_POINT_FINGERPRINT_FILE_NAME: &str = "sync-point-fingerprint";
+const SYNC_POINT_SELECTION_NAME_FILE_NAME: &str = "sync-point-selection-name";
+const SYNC_POINT_SELECTION_DIGEST_FILE_NAME: &str = "sync-point-selection-digest";
 const LAST: usize = usize::MAX;
 
-pub const PROJECT_CACHE_ENDPOINT_CONFIG_KEY: &str = "focus.project-cache.endpoint";
-pub const PROJECT_CACHE_INCLUDE_HEADERS_FILE_CONFIG_KEY: &str =
-    "focus.project-cache.include-headers-from";
-pub const BAZEL_ONE_SHOT_RESOLUTION_CONFIG_KEY: &str = "focus.bazel.one-shot";
+const BAZEL_QUERY_RETRIES_DEFAULT: usize = 2;
+const SELECTION_HISTORY_DEPTH_DEFAULT: usize = 10;
+/// Configured history depths are capped at this so that the history cannot grow without bound.
+const SELECTION_HISTORY_DEPTH_MAX: usize = 100;
+const SELECTION_HISTORY_DIR_NAME: &str = "selection_history";
+/// Where a shared selection is looked for when `focus.selection-mirror` is not set, relative to the working tree.
+pub const DEFAULT_SELECTION_MIRROR_PATH: &str = ".focus-selection.json";
+
+/// The number of patterns in a sparse profile before and after removing redundant patterns.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct PatternCounts {
+    /// The number of patterns computed for the selection.
+    pub computed: usize,
+
+    /// The number of patterns that remained after optimization and were applied.
+    pub optimized: usize,
+}
+
+/// What a sync did: the sparse profile it computed and, if the working tree was checked out, how the checkout changed
+/// it. Checkouts run Git itself, so the files added and removed are found by comparing the working tree before and
+/// after.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct SyncStats {
+    pub pattern_counts: PatternCounts,
+
+    /// Whether the working tree was checked out because the profile changed.
+    pub checked_out: bool,
+
+    /// The number of files the checkout added to the working tree.
+    pub files_added: usize,
+
+    /// The number of files the checkout removed from the working tree.
+    pub files_removed: usize,
+
+    /// The total size of the files the checkout added.
+    pub bytes_added: u64,
+
+    /// The total size of the files the checkout removed.
+    pub bytes_removed: u64,
+
+    /// How long the checkout took.
+    pub checkout_duration: Duration,
+}
+
+/// What Bazel returned when queried for a single coordinate, alongside the patterns a sync derives for it.
+#[derive(Clone, Debug)]
+pub struct CoordinateQuery {
+    /// The commit the outlining tree was at when queried.
+    pub commit_id: git2::Oid,
+
+    /// The query that was run.
+    pub query: String,
+
+    /// Bazel's output, unfiltered.
+    pub output: String,
+
+    /// The directories that would be checked out for the coordinate.
+    pub patterns: PatternSet,
+}
+
+/// Where the value of a setting came from.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SettingSource {
+    /// Nothing was configured, so the built-in default applies.
+    Default,
+
+    /// The value was read from Git config.
+    GitConfig,
+
+    /// The value was read from an environment variable.
+    Environment,
+
+    /// The value was given on the command line.
+    Flag,
+}
+
+impl std::fmt::Display for SettingSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SettingSource::Default => write!(f, "default"),
+            SettingSource::GitConfig => write!(f, "git-config"),
+            SettingSource::Environment => write!(f, "env"),
+            SettingSource::Flag => write!(f, "flag"),
+        }
+    }
+}
+
+/// The value a setting resolved to, and where that value came from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EffectiveSetting {
+    pub name: String,
+    pub value: String,
+    pub source: SettingSource,
+}
+
+impl EffectiveSetting {
+    pub fn new(name: impl Into<String>, value: impl ToString, source: SettingSource) -> Self {
+        Self {
+            name: name.into(),
+            value: value.to_string(),
+            source,
+        }
+    }
+}
+
+/// How the working tree is updated to match a new sparse profile. Both backends run the configured Git binary, since
+/// libgit2 cannot apply sparse checkout patterns.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CheckoutBackend {
+    /// Run `git sparse-checkout init` followed by `git checkout`.
+    SparseCheckout,
+
+    /// Run `git read-tree -mu HEAD`, which applies the profile without the porcelain `sparse-checkout` command. This
+    /// helps with Git versions whose `sparse-checkout` command misbehaves.
+    ReadTree,
+}
+
+impl std::fmt::Display for CheckoutBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckoutBackend::SparseCheckout => write!(f, "sparse-checkout"),
+            CheckoutBackend::ReadTree => write!(f, "read-tree"),
+        }
+    }
+}
+
+impl FromStr for CheckoutBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sparse-checkout" => Ok(CheckoutBackend::SparseCheckout),
+            "read-tree" => Ok(CheckoutBackend::ReadTree),
+            _ => bail!(
+                "Unknown checkout backend '{}'; expected 'sparse-checkout' or 'read-tree'",
+                s
+            ),
+        }
+    }
+}
+
+/// Which of Git's sparse checkout modes the sparse profile is written for.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SparseMode {
+    /// Use cone mode unless the profile contains verbatim patterns, which cone mode cannot express.
+    Auto,
+
+    /// Always use cone mode, which matches directories much faster than full pattern matching. Profiles containing
+    /// verbatim patterns are rejected.
+    Cone,
+
+    /// Always use full pattern matching.
+    Pattern,
+}
+
+impl SparseMode {
+    /// Whether a profile made of `patterns` should be written in cone mode. Fails if cone mode is required but the
+    /// profile contains verbatim patterns, since Git would reject the resulting profile.
+    pub fn uses_cone(&self, patterns: &PatternSet) -> Result<bool> {
+        let verbatim_fragment = patterns.iter().find_map(|pattern| match pattern {
+            Pattern::Verbatim { fragment, .. } => Some(fragment),
+            Pattern::Directory { .. } => None,
+        });
+        match (self, verbatim_fragment) {
+            (SparseMode::Auto, fragment) => Ok(fragment.is_none()),
+            (SparseMode::Cone, Some(fragment)) => bail!(
+                "Cone mode cannot express the pattern '{}'; set {} to 'auto' or 'pattern'",
+                fragment,
+                SPARSE_MODE_CONFIG_KEY
+            ),
+            (SparseMode::Cone, None) => Ok(true),
+            (SparseMode::Pattern, _) => Ok(false),
+        }
+    }
+}
+
+impl std::fmt::Display for SparseMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SparseMode::Auto => write!(f, "auto"),
+            SparseMode::Cone => write!(f, "cone"),
+            SparseMode::Pattern => write!(f, "pattern"),
+        }
+    }
+}
+
+impl FromStr for SparseMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(SparseMode::Auto),
+            "cone" => Ok(SparseMode::Cone),
+            "pattern" => Ok(SparseMode::Pattern),
+            _ => bail!(
+                "Unknown sparse mode '{}'; expected 'auto', 'cone', or 'pattern'",
+                s
+            ),
+        }
+    }
+}
+
+/// What `Repo::sync` does with the sparse profile it computes.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PatternApplication {
+    /// Write the sparse profile and check out the working tree to match it.
+    Checkout,
+
+    /// Write the sparse profile without updating the working tree, which will match it after the next checkout.
+    WriteOnly,
+
+    /// Only compute the sparse profile.
+    Skip,
+}
+
+/// Update the working tree's sparse profile as directed, returning what the sync did.
+fn apply_outline_patterns(
+    working_tree: &WorkingTree,
+    outline_patterns: PatternSet,
+    pattern_counts: PatternCounts,
+    pattern_application: PatternApplication,
+    app: Arc<App>,
+) -> Result<SyncStats> {
+    let sparse_mode = working_tree.get_sparse_mode()?;
+    let cone = sparse_mode.uses_cone(&outline_patterns)?;
+    debug!(%sparse_mode, cone, "Chose sparse checkout mode");
+    let stats = SyncStats {
+        pattern_counts,
+        ..Default::default()
+    };
+    match pattern_application {
+        PatternApplication::Checkout => {
+            let files_before = working_tree
+                .indexed_materialized_files()
+                .context("Reading the index before checking out")?;
+            let started_at = Instant::now();
+            let checked_out = working_tree
+                .apply_sparse_patterns(outline_patterns, cone, app)
+                .context("Failed to apply outlined patterns to working tree")?;
+            let checkout_duration = started_at.elapsed();
+            if !checked_out {
+                return Ok(stats);
+            }
+
+            let files_after = working_tree
+                .indexed_materialized_files()
+                .context("Reading the index after checking out")?;
+            let added: Vec<Oid> = files_after
+                .iter()
+                .filter(|(path, _)| !files_before.contains_key(*path))
+                .map(|(_, id)| *id)
+                .collect();
+            let removed: Vec<Oid> = files_before
+                .iter()
+                .filter(|(path, _)| !files_after.contains_key(*path))
+                .map(|(_, id)| *id)
+                .collect();
+            Ok(SyncStats {
+                checked_out,
+                files_added: added.len(),
+                files_removed: removed.len(),
+                bytes_added: working_tree.total_blob_size(&added)?,
+                bytes_removed: working_tree.total_blob_size(&removed)?,
+                checkout_duration,
+                ..stats
+            })
+        }
+        PatternApplication::WriteOnly => {
+            // The checkout stays pending so that the next sync checks out the profile even though it will not have
+            // changed by then.
+            working_tree
+                .stage_sparse_patterns(outline_patterns, cone)
+                .context("Failed to write outlined patterns to working tree")?;
+            Ok(stats)
+        }
+        PatternApplication::Skip => Ok(stats),
+    }
+}
+
+fn record_index_metrics(app: &App, hits: usize, misses: usize) {
+    let metrics = app.metrics();
+    metrics.set(&SYNC_INDEX_KEYS, &[("result", "hit")], hits as f64);
+    metrics.set(&SYNC_INDEX_KEYS, &[("result", "miss")], misses as f64);
+    if hits + misses > 0 {
+        metrics.set(
+            &SYNC_INDEX_HIT_RATIO,
+            &[],
+            hits as f64 / (hits + misses) as f64,
+        );
+    }
+}
+
+/// Whether Git's standard error output indicates that a checkout failed transiently.
+fn is_transient_checkout_failure(stderr: &str) -> bool {
+    CHECKOUT_TRANSIENT_FAILURE_SIGNATURES
+        .iter()
+        .any(|signature| stderr.contains(signature))
+}
+
+/// Exact-match patterns for the given file targets. Glob metacharacters in the paths are escaped so that each pattern
+/// matches only the named file. Since cone-mode sparse checkouts cannot include individual files, selecting any file
+/// switches the working tree to pattern mode.
+fn file_patterns(targets: &TargetSet) -> PatternSet {
+    targets
+        .iter()
+        .filter_map(|target| match target {
+            Target::File(path) => Some(Pattern::Verbatim {
+                precedence: LAST,
+                fragment: format!("/{}", escape_pattern(path.trim_start_matches('/'))),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+fn escape_pattern(path: &str) -> String {
+    let mut escaped = String::with_capacity(path.len());
+    for c in path.chars() {
+        if matches!(c, '\\' | '*' | '?' | '[') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Remove redundant patterns before they are applied to `working_tree`. Removing them only preserves what the profile
+/// matches in cone mode, so patterns that will be applied in pattern mode are left as they are. A profile that cone mode
+/// rejects is left as it is too, since it fails when it is applied.
+fn optimize_outline_patterns(
+    working_tree: &WorkingTree,
+    patterns: PatternSet,
+) -> Result<(PatternSet, PatternCounts)> {
+    let cone = working_tree
+        .get_sparse_mode()?
+        .uses_cone(&patterns)
+        .unwrap_or(false);
+    if !cone {
+        let pattern_counts = PatternCounts {
+            computed: patterns.len(),
+            optimized: patterns.len(),
+        };
+        return Ok((patterns, pattern_counts));
+    }
+
+    let optimized_patterns = optimize_patterns(&patterns);
+    let pattern_counts = PatternCounts {
+        computed: patterns.len(),
+        optimized: optimized_patterns.len(),
+    };
+    debug!(
+        computed = pattern_counts.computed,
+        optimized = pattern_counts.optimized,
+        "Optimized sparse patterns"
+    );
+    Ok((optimized_patterns, pattern_counts))
+}
+
+/// Parse a dotted version such as `0.7.1` into its numeric components, ignoring any pre-release or build suffix.
+fn parse_version(version: &str) -> Result<Vec<u64>> {
+    version
+        .split(|c| c == '-' || c == '+')
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .map(|component| {
+            component
+                .parse::<u64>()
+                .with_context(|| format!("Invalid version '{}'", version))
+        })
+        .collect()
+}
+
+/// Refuse to work with a repo whose `focus.min-version` is newer than this binary, since an older binary might write
+/// files in a format that newer ones do not expect.
+fn check_min_version(repo: &Repository) -> Result<()> {
+    let config_snapshot = repo.config()?.snapshot()?;
+    let min_version = match config_snapshot.get_str(MIN_VERSION_CONFIG_KEY) {
+        Ok(min_version) => min_version,
+        Err(_) => return Ok(()),
+    };
+    let mut required = parse_version(min_version)
+        .with_context(|| format!("Reading key '{}'", MIN_VERSION_CONFIG_KEY))?;
+    let running_version = env!("CARGO_PKG_VERSION");
+    let mut running = parse_version(running_version)?;
+    // Compare `1.2` and `1.2.0` as equal.
+    let len = running.len().max(required.len());
+    running.resize(len, 0);
+    required.resize(len, 0);
+    if running < required {
+        bail!(
+            "This repo requires focus {} or newer (set by '{}'), but this is focus {}; please upgrade focus",
+            min_version,
+            MIN_VERSION_CONFIG_KEY,
+            running_version
+        );
+    }
+    Ok(())
+}
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum WorkingTreeKind {
@@ -128,18 +579,105 @@ impl WorkingTree {
         self.repo.path().join("info")
     }
 
-    /// The location of the current sparse checkout file within the working tree.
+    /// The location of the current sparse checkout file. This is `info/sparse-checkout` in the working tree's own git
+    /// dir, which libgit2 already resolves to the per-worktree git dir for linked worktrees and to `$GIT_DIR` for
+    /// relocated ones, so this is the same file `git rev-parse --git-path info/sparse-checkout` names.
     pub fn sparse_checkout_path(&self) -> PathBuf {
         self.info_dir().join("sparse-checkout")
     }
 
-    /// Writes the given `patterns` to the working tree.
-    pub fn apply_sparse_patterns(
-        &self,
-        patterns: PatternSet,
-        cone: bool,
-        app: Arc<App>,
-    ) -> Result<bool> {
+    /// The file marking a checkout that was started but did not complete, which is kept next to the sparse profile.
+    pub fn pending_checkout_path(&self) -> PathBuf {
+        self.sparse_checkout_path().with_extension("pending")
+    }
+
+    /// Whether a checkout was started but did not complete, leaving the working tree partially updated.
+    pub fn checkout_pending(&self) -> bool {
+        self.pending_checkout_path().is_file()
+    }
+
+    /// The number of patterns in the current sparse checkout file, or zero if there is none.
+    pub fn sparse_pattern_count(&self) -> Result<usize> {
+        let path = self.sparse_checkout_path();
+        if !path.exists() {
+            return Ok(0);
+        }
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Reading {}", path.display()))?;
+        Ok(content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .count())
+    }
+
+    /// Count the files materialized in the working tree and their total size in bytes. This walks the whole working
+    /// tree, so it is slow for large checkouts.
+    pub fn count_materialized_files(&self) -> Result<(usize, u64)> {
+        let mut count = 0;
+        let mut total_size = 0;
+        self.walk_materialized_files(|_path, size| {
+            count += 1;
+            total_size += size;
+        })?;
+        Ok((count, total_size))
+    }
+
+    /// The files materialized in the working tree and their sizes in bytes. Like `count_materialized_files`, this walks
+    /// the whole working tree.
+    pub fn materialized_files(&self) -> Result<HashMap<PathBuf, u64>> {
+        let mut files = HashMap::new();
+        self.walk_materialized_files(|path, size| {
+            files.insert(path.to_owned(), size);
+        })?;
+        Ok(files)
+    }
+
+    /// The files the index has materialized in the working tree, meaning those without the skip-worktree bit, and
+    /// their blob IDs. This reads only the index, so it is much cheaper than `materialized_files`.
+    fn indexed_materialized_files(&self) -> Result<HashMap<Vec<u8>, Oid>> {
+        let mut index = self.repo.index().context("Opening the index")?;
+        index.read(true).context("Reading the index")?;
+        let skip_worktree = git2::IndexEntryExtendedFlag::SKIP_WORKTREE.bits();
+        Ok(index
+            .iter()
+            .filter(|entry| entry.flags_extended & skip_worktree == 0)
+            .map(|entry| (entry.path, entry.id))
+            .collect())
+    }
+
+    /// The total size of the given blobs, read from their object headers.
+    fn total_blob_size(&self, ids: &[Oid]) -> Result<u64> {
+        let odb = self.repo.odb().context("Opening the object database")?;
+        ids.iter().try_fold(0, |total, id| {
+            let (size, _kind) = odb
+                .read_header(*id)
+                .with_context(|| format!("Reading the header of blob {}", id))?;
+            Ok(total + size as u64)
+        })
+    }
+
+    fn walk_materialized_files(&self, mut visit: impl FnMut(&Path, u64)) -> Result<()> {
+        let walker = walkdir::WalkDir::new(self.work_dir())
+            .follow_links(false)
+            .into_iter()
+            .filter_entry(|entry| entry.file_name() != ".git");
+        for entry in walker {
+            let entry = entry.context("Walking the working tree")?;
+            if entry.file_type().is_file() {
+                let size = entry
+                    .metadata()
+                    .with_context(|| format!("Reading metadata for {}", entry.path().display()))?
+                    .len();
+                visit(entry.path(), size);
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the patterns to the sparse checkout file without updating the working tree, returning whether the file
+    /// changed.
+    pub fn write_sparse_patterns(&self, patterns: PatternSet, cone: bool) -> Result<bool> {
         // Make sure the patterns form a hierarchy
         let patterns = if cone {
             create_hierarchical_patterns(&patterns)
@@ -148,16 +686,16 @@ impl WorkingTree {
         };
 
         // Write the patterns
-        let info_dir = self.info_dir();
-        std::fs::create_dir_all(&info_dir)
-            .with_context(|| format!("In working tree {}", self.work_dir().display()))
-            .context("Failed to create leading directories for sparse profile")?;
+        let sparse_profile_path = self.sparse_checkout_path();
+        if let Some(parent_dir) = sparse_profile_path.parent() {
+            std::fs::create_dir_all(parent_dir)
+                .with_context(|| format!("In working tree {}", self.work_dir().display()))
+                .context("Failed to create leading directories for sparse profile")?;
+        }
 
         // Make sure Git doesn't try to do something cute with the sparse profile like merge existing contents.
-        let sparse_profile_path = self.sparse_checkout_path();
-        let candidate_sparse_profile_path = self
-            .sparse_checkout_path()
-            .with_extension(Path::new("candidate"));
+        let candidate_sparse_profile_path =
+            sparse_profile_path.with_extension(Path::new("candidate"));
         let new_content_hash = patterns.write_to_file(&candidate_sparse_profile_path)?;
 
         if sparse_profile_path.is_file() {
@@ -175,40 +713,167 @@ impl WorkingTree {
         }
         std::fs::rename(&candidate_sparse_profile_path, &sparse_profile_path)
             .context("Moving candidate sparse profile into place")?;
+        info!(profile = ?sparse_profile_path, count = %patterns.len(), "Wrote patterns");
+
+        Ok(true)
+    }
+
+    /// Write the patterns to the sparse checkout file and mark a checkout of them as pending, returning whether a
+    /// checkout is needed because the file changed or an earlier checkout did not complete. The marker is removed once
+    /// `apply_sparse_patterns` checks out the working tree.
+    fn stage_sparse_patterns(&self, patterns: PatternSet, cone: bool) -> Result<bool> {
+        // The marker is written before the profile so that a checkout which fails partway, even by the process being
+        // killed, is redone by the next application although the profile will not have changed by then.
+        let pending_checkout_path = self.pending_checkout_path();
+        let resuming = pending_checkout_path.is_file();
+        if !resuming {
+            if let Some(parent_dir) = pending_checkout_path.parent() {
+                std::fs::create_dir_all(parent_dir).context(
+                    "Failed to create leading directories for the pending checkout marker",
+                )?;
+            }
+            std::fs::write(&pending_checkout_path, b"")
+                .context("Writing the pending checkout marker")?;
+        }
+        if !self.write_sparse_patterns(patterns, cone)? {
+            if !resuming {
+                std::fs::remove_file(&pending_checkout_path)
+                    .context("Removing the pending checkout marker")?;
+                return Ok(false);
+            }
+            warn!("Resuming a checkout that did not complete");
+        }
+        Ok(true)
+    }
+
+    /// Writes the given `patterns` to the working tree.
+    pub fn apply_sparse_patterns(
+        &self,
+        patterns: PatternSet,
+        cone: bool,
+        app: Arc<App>,
+    ) -> Result<bool> {
+        if !self.stage_sparse_patterns(patterns, cone)? {
+            return Ok(false);
+        }
 
+        let sparse_profile_path = self.sparse_checkout_path();
+        let backend = self.get_checkout_backend()?;
         // Update the working tree to match
-        info!(profile = ?sparse_profile_path, count = %patterns.len(), "Applying patterns");
-        {
-            let args = vec![
-                "sparse-checkout",
-                "init",
-                if cone { "--cone" } else { "--no-cone" },
-            ];
-            let (mut cmd, scmd) = git_helper::git_command(app.clone())?;
-            scmd.ensure_success_or_log(
-                cmd.current_dir(self.work_dir()).args(args),
-                SandboxCommandOutput::Stderr,
+        info!(profile = ?sparse_profile_path, %backend, "Applying patterns");
+        if backend == CheckoutBackend::ReadTree {
+            self.apply_sparse_patterns_with_read_tree(cone, app)?;
+        } else {
+            self.run_checkout_command(
+                &[
+                    "sparse-checkout",
+                    "init",
+                    if cone { "--cone" } else { "--no-cone" },
+                ],
+                app.clone(),
             )
-            .with_context(|| format!("In working tree {}", self.work_dir().display()))
             .context("git sparse-checkout init failed")?;
+
+            // Newer versions of Git don't actually check out files when `sparse-checkout init` runs, so run `git checkout`. It might be worth making this behavior version-dependent.
+            info!("Checking out");
+            self.run_checkout_command(&["checkout"], app)
+                .context("git checkout failed")?;
         }
 
-        // Newer versions of Git don't actually check out files when `sparse-checkout init` runs, so run `git checkout`. It might be worth making this behavior version-dependent.
-        info!("Checking out");
-        {
-            let args = vec!["checkout"];
-            let (mut cmd, scmd) = git_helper::git_command(app)?;
-            scmd.ensure_success_or_log(
+        std::fs::remove_file(self.pending_checkout_path())
+            .context("Removing the pending checkout marker")?;
+        Ok(true)
+    }
+
+    /// Run a Git command that updates the working tree, retrying failures that look transient.
+    fn run_checkout_command(&self, args: &[&str], app: Arc<App>) -> Result<()> {
+        let mut backoff = CHECKOUT_INITIAL_RETRY_BACKOFF;
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let (mut cmd, scmd) = git_helper::git_command(app.clone())?;
+            let error = match scmd.ensure_success_or_log(
                 cmd.current_dir(self.work_dir()).args(args),
                 SandboxCommandOutput::Stderr,
-            )
-            .with_context(|| format!("In working tree {:?}", self.work_dir()))
-            .context("git checkout failed")?;
+            ) {
+                Ok(_) => return Ok(()),
+                Err(error) => {
+                    error.context(format!("In working tree {}", self.work_dir().display()))
+                }
+            };
+            let mut stderr = String::new();
+            let transient = scmd
+                .read_to_string(SandboxCommandOutput::Stderr, &mut stderr)
+                .map(|_| is_transient_checkout_failure(&stderr))
+                .unwrap_or(false);
+            if !transient || attempt > CHECKOUT_RETRIES {
+                return Err(error);
+            }
+            warn!(
+                attempt,
+                retries = CHECKOUT_RETRIES,
+                ?backoff,
+                ?error,
+                "Checkout failed transiently; retrying"
+            );
+            std::thread::sleep(backoff);
+            backoff *= 2;
         }
+    }
+
+    fn apply_sparse_patterns_with_read_tree(&self, cone: bool, app: Arc<App>) -> Result<bool> {
+        // `read-tree` only consults the profile when sparse checkout is enabled.
+        let mut config = self.repo.config().context("Reading config")?;
+        config
+            .set_bool(CORE_SPARSE_CHECKOUT_CONFIG_KEY, true)
+            .with_context(|| format!("Writing key '{}'", CORE_SPARSE_CHECKOUT_CONFIG_KEY))?;
+        config
+            .set_bool(CORE_SPARSE_CHECKOUT_CONE_CONFIG_KEY, cone)
+            .with_context(|| format!("Writing key '{}'", CORE_SPARSE_CHECKOUT_CONE_CONFIG_KEY))?;
+
+        info!("Checking out with read-tree");
+        self.run_checkout_command(&["read-tree", "-mu", "HEAD"], app)
+            .context("git read-tree failed")?;
 
         Ok(true)
     }
 
+    /// The configured way of updating the working tree to match the sparse profile.
+    pub fn get_checkout_backend(&self) -> Result<CheckoutBackend> {
+        let config_snapshot = self.repo.config()?.snapshot()?;
+        match config_snapshot.get_str(CHECKOUT_BACKEND_CONFIG_KEY) {
+            Ok(backend) => backend
+                .parse()
+                .with_context(|| format!("Reading key '{}'", CHECKOUT_BACKEND_CONFIG_KEY)),
+            Err(_) => Ok(CheckoutBackend::SparseCheckout),
+        }
+    }
+
+    pub fn set_checkout_backend(&self, backend: CheckoutBackend) -> Result<()> {
+        self.repo
+            .config()?
+            .set_str(CHECKOUT_BACKEND_CONFIG_KEY, &backend.to_string())
+            .with_context(|| format!("Writing key '{}'", CHECKOUT_BACKEND_CONFIG_KEY))
+    }
+
+    /// The configured sparse checkout mode.
+    pub fn get_sparse_mode(&self) -> Result<SparseMode> {
+        let config_snapshot = self.repo.config()?.snapshot()?;
+        match config_snapshot.get_str(SPARSE_MODE_CONFIG_KEY) {
+            Ok(mode) => mode
+                .parse()
+                .with_context(|| format!("Reading key '{}'", SPARSE_MODE_CONFIG_KEY)),
+            Err(_) => Ok(SparseMode::Auto),
+        }
+    }
+
+    pub fn set_sparse_mode(&self, mode: SparseMode) -> Result<()> {
+        self.repo
+            .config()?
+            .set_str(SPARSE_MODE_CONFIG_KEY, &mode.to_string())
+            .with_context(|| format!("Writing key '{}'", SPARSE_MODE_CONFIG_KEY))
+    }
+
     /// Switch to the given commit in this working tree.
     pub fn switch_to_commit(
         &self,
@@ -345,6 +1010,44 @@ impl WorkingTree {
         self.read_ref(PREEMPTIVE_SYNC_REF_NAME)
     }
 
+    /// Delete the sync point refs that exist but do not resolve to a commit in the object database, which happens
+    /// when the commit they name is garbage collected. Returns the names of the deleted refs.
+    pub fn clear_dangling_sync_point_refs(&self) -> Result<Vec<String>> {
+        let mut cleared = Vec::new();
+        for name in [SPARSE_SYNC_REF_NAME, PREEMPTIVE_SYNC_REF_NAME] {
+            let mut reference = match self.repo.find_reference(name) {
+                Ok(reference) => reference,
+                Err(e) if e.code() == git2::ErrorCode::NotFound => continue,
+                Err(e) => return Err(e).with_context(|| format!("Finding ref {}", name)),
+            };
+            if reference.peel_to_commit().is_ok() {
+                continue;
+            }
+            warn!(%name, target = ?reference.target(), "Sync point ref does not resolve to a commit");
+            reference
+                .delete()
+                .with_context(|| format!("Deleting dangling ref {}", name))?;
+            cleared.push(name.to_owned());
+        }
+        Ok(cleared)
+    }
+
+    /// The name of the active selection set, read directly from the Git config.
+    pub fn active_selection_name(&self) -> String {
+        self.repo
+            .config()
+            .and_then(|config| config.get_string(ACTIVE_SELECTION_CONFIG_KEY))
+            .unwrap_or_else(|_| DEFAULT_SELECTION_NAME.to_owned())
+    }
+
+    /// Where the active selection set is stored.
+    pub fn active_selection_path(&self) -> PathBuf {
+        DataPaths::selection_file_in(
+            &self.work_dir().join(".focus"),
+            &self.active_selection_name(),
+        )
+    }
+
     pub fn primary_branch_name(&self) -> Result<String> {
         if self.repo.find_reference("refs/heads/master").is_ok() {
             Ok(String::from("master"))
@@ -378,6 +1081,12 @@ impl WorkingTree {
             .context("Updating the sparse sync ref")
     }
 
+    /// Updates the sparse sync ref to the given commit rather than HEAD (named SYNC_REF_NAME)
+    pub fn write_sync_point_ref_to(&self, commit_id: git2::Oid) -> Result<()> {
+        self.write_sync_point_ref_internal(SPARSE_SYNC_REF_NAME, commit_id)
+            .context("Updating the sparse sync ref")
+    }
+
     /// Updates the sparse sync ref to the value of the HEAD ref (named SYNC_REF_NAME)
     pub fn write_preemptive_sync_point_ref(&self, commit_id: git2::Oid) -> Result<()> {
         self.write_sync_point_ref_internal(PREEMPTIVE_SYNC_REF_NAME, commit_id)
@@ -397,6 +1106,36 @@ impl WorkingTree {
         )
     }
 
+    /// The changed and untracked paths in the working tree, each with its two-letter short status code as shown by
+    /// `git status --short` (e.g. ` M` or `??`).
+    pub fn dirty_paths(&self, app: Arc<App>) -> Result<Vec<(String, String)>> {
+        // Version 2 entries start with their kind rather than a status code that may begin with a space, which the
+        // trimmed output would lose.
+        let output = git_helper::run_consuming_stdout(
+            self.work_dir(),
+            vec!["status", "--porcelain=v2", "-z", "--no-renames"],
+            app,
+        )?;
+        output
+            .split('\0')
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let fields: Vec<&str> = match entry.chars().next() {
+                    Some('1') => entry.splitn(9, ' ').collect(),
+                    Some('u') => entry.splitn(11, ' ').collect(),
+                    Some('?') => vec!["?", "??", entry.get(2..).unwrap_or_default()],
+                    _ => vec![],
+                };
+                match (fields.get(1), fields.last()) {
+                    (Some(status), Some(path)) if fields.len() > 2 && !path.is_empty() => {
+                        Ok((status.replace('.', " "), path.to_string()))
+                    }
+                    _ => Err(anyhow::anyhow!("Unexpected git status entry {:?}", entry)),
+                }
+            })
+            .collect()
+    }
+
     pub fn read_uuid(&self) -> Result<Option<Uuid>> {
         let config_snapshot = self.repo.config()?.snapshot()?;
         match config_snapshot.get_str(UUID_CONFIG_KEY) {
@@ -695,11 +1434,8 @@ impl Outliner for DenseRepoOutliner {
 
 impl DenseRepoOutliner {}
 
-fn make_routing_resolver() -> Result<RoutingResolver> {
-    let cache_dir = dirs::cache_dir()
-        .context("failed to determine cache dir")?
-        .join("focus")
-        .join("cache");
+fn make_routing_resolver(app: &App) -> Result<RoutingResolver> {
+    let cache_dir = paths::focus_cache_dir(app.options().data_dir.as_deref())?;
     Ok(RoutingResolver::new(cache_dir.as_path()))
 }
 
@@ -718,9 +1454,10 @@ fn outline_common(
         repo: repo_workdir.to_owned(),
         targets: target_set.clone(),
         options: resolution_options.clone(),
+        commit: Some(commit_id),
     };
     let mut patterns = PatternSet::new();
-    let resolver = make_routing_resolver()?;
+    let resolver = make_routing_resolver(&app)?;
     let result = resolver.resolve(&request, &cache_options, app)?;
     for path in result.paths.iter() {
         let qualified_path = repo_workdir.join(path);
@@ -809,6 +1546,10 @@ pub struct Repo {
     outliner: Option<Arc<dyn Outliner>>,
     repo: git2::Repository,
     config: Configuration,
+    bazel_query_retries_override: Option<usize>,
+    strict_cache: bool,
+    strict_resolution: bool,
+    expected_selection_digest: Option<String>,
     app: Arc<App>,
 }
 
@@ -820,6 +1561,7 @@ impl Repo {
         if repo.is_bare() {
             bail!("Bare repos are not supported");
         }
+        check_min_version(&repo)?;
         let git_dir = repo.path().to_owned();
         let working_tree: Option<Arc<WorkingTree>> = match repo.workdir() {
             Some(work_dir) => {
@@ -854,6 +1596,10 @@ impl Repo {
             outliner: outlining_tree,
             repo,
             config,
+            bazel_query_retries_override: None,
+            strict_cache: false,
+            strict_resolution: false,
+            expected_selection_digest: None,
             app,
         })
     }
@@ -862,6 +1608,77 @@ impl Repo {
         &self.repo
     }
 
+    /// Cheaply check whether `path` is within a focused repo: a non-bare repo with a sparse checkout file and a readable
+    /// sync point. Unlike `open`, this loads no configuration, selection, or outliner state.
+    pub fn check_focused(path: &Path) -> Result<()> {
+        let repo = git2::Repository::discover(path)
+            .with_context(|| format!("{} is not in a Git repo", path.display()))?;
+        if repo.is_bare() {
+            bail!("{} is a bare repo", repo.path().display());
+        }
+        let working_tree = WorkingTree::new(repo)?;
+        if working_tree.kind() != WorkingTreeKind::Sparse {
+            bail!(
+                "{} has no sparse checkout file",
+                working_tree.work_dir().display()
+            );
+        }
+        if working_tree.read_sparse_sync_point_ref()?.is_none() {
+            bail!("{} has no sync point", working_tree.work_dir().display());
+        }
+        Ok(())
+    }
+
+    /// Cheaply guess whether the focused repo containing `path` needs to be synced: HEAD has moved from the sync point,
+    /// or the active selection set or its contents differ from those recorded by the last sync. Nothing is resolved, so
+    /// this is approximate; `focus drift-status` compares the selected targets themselves. A sync point that was
+    /// recorded without the selection it was synced from needs a sync. Fails if `path` is not within a focused repo.
+    pub fn check_sync_needed(path: &Path) -> Result<bool> {
+        let repo = git2::Repository::discover(path)
+            .with_context(|| format!("{} is not in a Git repo", path.display()))?;
+        if repo.is_bare() {
+            bail!("{} is a bare repo", repo.path().display());
+        }
+        let working_tree = WorkingTree::new(repo)?;
+        if working_tree.kind() != WorkingTreeKind::Sparse {
+            bail!(
+                "{} has no sparse checkout file",
+                working_tree.work_dir().display()
+            );
+        }
+        let sync_point = match working_tree.read_sparse_sync_point_ref()? {
+            Some(sync_point) => sync_point,
+            None => bail!("{} has no sync point", working_tree.work_dir().display()),
+        };
+        if working_tree.get_head_commit()?.id() != sync_point {
+            return Ok(true);
+        }
+
+        let digest_path = Self::focus_git_dir_path(working_tree.git_dir())
+            .join(SYNC_POINT_SELECTION_DIGEST_FILE_NAME);
+        let synced_digest = match fs::read_to_string(&digest_path) {
+            Ok(digest) => digest,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(true),
+            Err(e) => return Err(e).with_context(|| format!("Reading {}", digest_path.display())),
+        };
+
+        let selection_path = working_tree.active_selection_path();
+        let contents = match fs::read(&selection_path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Reading {}", selection_path.display()))
+            }
+        };
+        let digest = hashing::hash_selection(&working_tree.active_selection_name(), &contents);
+        Ok(digest != synced_digest.trim())
+    }
+
+    /// Whether `path` is within a focused repo. See `check_focused`.
+    pub fn is_focused(path: &Path) -> bool {
+        Self::check_focused(path).is_ok()
+    }
+
     pub fn config(&self) -> &Configuration {
         &self.config
     }
@@ -870,6 +1687,11 @@ impl Repo {
         git_dir.join("focus")
     }
 
+    /// Where previous versions of the selection are kept.
+    pub fn selection_history_dir_path(git_dir: &Path) -> PathBuf {
+        Self::focus_git_dir_path(git_dir).join(SELECTION_HISTORY_DIR_NAME)
+    }
+
     pub fn outlining_tree_path(git_dir: &Path) -> PathBuf {
         Self::focus_git_dir_path(git_dir).join(OUTLINING_TREE_NAME)
     }
@@ -878,16 +1700,35 @@ impl Repo {
         &self.path
     }
 
-    /// Run a sync, returning the number of patterns that were applied and whether a checkout occured as a result of the profile changing.
+    /// Run a sync, returning the number of patterns that were applied and, if a checkout occured as a result of the profile changing, how it changed the working tree.
     pub fn sync(
         &self,
         commit_id: git2::Oid,
         targets: &TargetSet,
-        skip_pattern_application: bool,
+        pattern_application: PatternApplication,
         app: Arc<App>,
         cache: Option<&RocksDBCache>,
         snapshot: Option<PathBuf>,
-    ) -> Result<(usize, bool)> {
+    ) -> Result<SyncStats> {
+        let (outline_patterns, pattern_counts) =
+            self.compute_sparse_profile(commit_id, targets, app.clone(), cache, snapshot)?;
+        let stats = self.apply_sparse_profile(outline_patterns, pattern_application, app)?;
+        Ok(SyncStats {
+            pattern_counts,
+            ..stats
+        })
+    }
+
+    /// Compute the sparse profile selecting `targets` at `commit_id` without applying it, also returning how many
+    /// patterns remained after optimization.
+    pub fn compute_sparse_profile(
+        &self,
+        commit_id: git2::Oid,
+        targets: &TargetSet,
+        app: Arc<App>,
+        cache: Option<&RocksDBCache>,
+        snapshot: Option<PathBuf>,
+    ) -> Result<(PatternSet, PatternCounts)> {
         let (working_tree, outlining_tree) = match (&self.working_tree, &self.outliner) {
             (Some(working_tree), Some(outlining_tree)) => (working_tree, outlining_tree),
             _ => {
@@ -896,15 +1737,19 @@ impl Repo {
             }
         };
 
-        // Ensure that the trees are properly configured
-        working_tree
-            .configure(app.clone())
-            .context("Configuring the working tree")?;
+        // Ensure that the outlining tree is properly configured
         outlining_tree
             .underlying()
             .configure(app.clone())
             .context("Configuring the outlining tree")?;
 
+        // Files are included by pattern rather than resolved, since resolution would include their whole package.
+        let (file_targets, targets): (TargetSet, TargetSet) = targets
+            .iter()
+            .cloned()
+            .partition(|target| matches!(target, Target::File(_)));
+        let targets = &targets;
+
         let mut outline_patterns = if let Some(cache) = cache {
             self.sync_incremental(
                 commit_id,
@@ -925,16 +1770,35 @@ impl Repo {
         }?;
 
         outline_patterns.extend(working_tree.default_working_tree_patterns()?);
-        let pattern_count = outline_patterns.len();
-        let checked_out = if skip_pattern_application {
-            false
-        } else {
-            working_tree
-                .apply_sparse_patterns(outline_patterns, true, app)
-                .context("Failed to apply outlined patterns to working tree")?
-        };
+        outline_patterns.extend(file_patterns(&file_targets));
+        optimize_outline_patterns(working_tree, outline_patterns)
+    }
 
-        Ok((pattern_count, checked_out))
+    /// Apply a sparse profile computed earlier, e.g. one recorded in a clone lockfile, without resolving any targets.
+    pub fn apply_sparse_profile(
+        &self,
+        patterns: PatternSet,
+        pattern_application: PatternApplication,
+        app: Arc<App>,
+    ) -> Result<SyncStats> {
+        let working_tree = self.working_tree()?;
+        working_tree
+            .configure(app.clone())
+            .context("Configuring the working tree")?;
+        if pattern_application != PatternApplication::Skip {
+            self.verify_selection_unchanged()?;
+        }
+        let pattern_counts = PatternCounts {
+            computed: patterns.len(),
+            optimized: patterns.len(),
+        };
+        apply_outline_patterns(
+            &working_tree,
+            patterns,
+            pattern_counts,
+            pattern_application,
+            app,
+        )
     }
 
     /// Sync in one shot, not using the cache.
@@ -949,6 +1813,10 @@ impl Repo {
         info!("Running one-shot sync");
         let resolution_options = ResolutionOptions {
             bazel_resolution_strategy: BazelResolutionStrategy::OneShot,
+            external_resolver_command: self.get_resolver_command()?,
+            bazel_query_retries: self.get_bazel_query_retries()?,
+            strict_resolution: self.strict_resolution,
+            bazel_union_query: self.get_bazel_union_query()?,
         };
         let (outline_patterns, _resolution_result) = outliner
             .outline(commit_id, targets, &resolution_options, snapshot, app)
@@ -991,7 +1859,29 @@ impl Repo {
                 // );
                 // Query again now that the index is populated.
                 paths_to_materialize =
-                    get_files_to_materialize(&hash_context, cache, dependency_keys)?;
+                    get_files_to_materialize(&hash_context, cache, dependency_keys.clone())?;
+            }
+        }
+        // Under strict cache mode, the paths found in the index are compared with those of a fresh resolution. Strict
+        // resolution needs a fresh resolution to check as well.
+        let mut cached_paths = None;
+        if self.strict_cache || self.strict_resolution {
+            if let PathsToMaterializeResult::Ok { seen_keys, paths } = paths_to_materialize {
+                // Index entries do not record the commit they were resolved at, so they are resolved again to check.
+                info!(
+                    num_cached_keys = seen_keys.len(),
+                    %commit_id,
+                    "Validating cached sparse checkout patterns against a fresh resolution"
+                );
+                cached_paths = Some(paths);
+                let missing_keys = seen_keys
+                    .iter()
+                    .map(|key| Ok((key.clone(), content_hash(&hash_context, key)?)))
+                    .collect::<Result<_>>()?;
+                paths_to_materialize = PathsToMaterializeResult::MissingKeys {
+                    seen_keys,
+                    missing_keys,
+                };
             }
         }
         Ok(match paths_to_materialize {
@@ -1006,6 +1896,7 @@ impl Repo {
                 ti_client
                     .get_context()
                     .add_to_custom_map("index_hit_count", seen_keys.len().to_string());
+                record_index_metrics(&app, seen_keys.len(), 0);
                 paths
                     .into_iter()
                     .map(|path| Pattern::Directory {
@@ -1043,10 +1934,19 @@ impl Repo {
                 ti_client
                     .get_context()
                     .add_to_custom_map("index_hit_count", seen_keys.len().to_string());
+                record_index_metrics(
+                    &app,
+                    seen_keys.len().saturating_sub(missing_keys.len()),
+                    missing_keys.len(),
+                );
 
                 debug!(?missing_keys, "These are the missing keys");
                 let resolution_options = ResolutionOptions {
                     bazel_resolution_strategy: BazelResolutionStrategy::Incremental,
+                    external_resolver_command: self.get_resolver_command()?,
+                    bazel_query_retries: self.get_bazel_query_retries()?,
+                    strict_resolution: self.strict_resolution,
+                    bazel_union_query: self.get_bazel_union_query()?,
                 };
                 let (outline_patterns, resolution_result) = outliner
                     .outline(
@@ -1060,18 +1960,33 @@ impl Repo {
 
                 debug!(?resolution_result, ?outline_patterns, "Resolved patterns");
                 update_object_database_from_resolution(&hash_context, cache, &resolution_result)?;
+                if let Some(cached_paths) = cached_paths {
+                    if let PathsToMaterializeResult::Ok { paths, .. } =
+                        get_files_to_materialize(&hash_context, cache, dependency_keys)?
+                    {
+                        if paths != cached_paths {
+                            let stale: Vec<&PathBuf> =
+                                cached_paths.symmetric_difference(&paths).collect();
+                            warn!(
+                                ?stale,
+                                "The index disagreed with a fresh resolution; replaced its entries"
+                            );
+                        }
+                    }
+                }
                 outline_patterns
             }
         })
     }
 
-    /// Sync using the project cache returning an optional value of the number of patterns and whether a checkout occured. None is returned if the project cache could not be used.
+    /// Sync using the project cache returning an optional value of what the sync did. None is returned if the project cache could not be used.
     pub fn sync_using_project_cache(
         &self,
         commit_id: git2::Oid,
         selection: &Selection,
+        pattern_application: PatternApplication,
         snapshot: Option<PathBuf>,
-    ) -> Result<Option<(usize, bool)>> {
+    ) -> Result<Option<SyncStats>> {
         if !selection.targets.is_empty() {
             tracing::warn!("Skipping project cache because the selection contains ad-hoc targets");
             return Ok(None);
@@ -1175,12 +2090,20 @@ impl Repo {
         trace!(?outline_patterns);
 
         // TODO: Implement skipping application if the profile has not changed
-        let pattern_count = outline_patterns.len();
-        let checked_out = working_tree
-            .apply_sparse_patterns(outline_patterns, true, self.app.clone())
-            .context("Failed to apply outlined patterns to working tree")?;
+        let (outline_patterns, pattern_counts) =
+            optimize_outline_patterns(&working_tree, outline_patterns)?;
+        if pattern_application != PatternApplication::Skip {
+            self.verify_selection_unchanged()?;
+        }
+        let stats = apply_outline_patterns(
+            &working_tree,
+            outline_patterns,
+            pattern_counts,
+            pattern_application,
+            self.app.clone(),
+        )?;
         info!("Synced from project cache");
-        Ok(Some((pattern_count, checked_out)))
+        Ok(Some(stats))
     }
 
     /// Creates an outlining tree for the repository.
@@ -1248,6 +2171,129 @@ impl Repo {
         self.outliner.clone()
     }
 
+    /// Run the one-shot resolver's Bazel query for `target` in the outlining tree at HEAD, bypassing the index.
+    pub fn query_coordinate(&self, target: &Target, app: Arc<App>) -> Result<CoordinateQuery> {
+        if !matches!(target, Target::Bazel(_)) {
+            bail!("Only Bazel coordinates are resolved by querying Bazel");
+        }
+        let outliner = self
+            .outliner
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Querying requires an outlining tree"))?;
+        let commit_id = self.get_head_commit()?.id();
+        let targets: TargetSet = [target.clone()].into_iter().collect();
+        let resolution_options = self.one_shot_resolution_options()?;
+
+        // Outlining switches the outlining tree to the commit, so the raw query sees the same build graph.
+        let (patterns, _resolution_result) = outliner
+            .outline(commit_id, &targets, &resolution_options, None, app.clone())
+            .context("Failed to outline")?;
+        let request = ResolutionRequest {
+            repo: outliner.underlying().work_dir().to_owned(),
+            targets,
+            options: resolution_options,
+            commit: Some(commit_id),
+        };
+        let (query, output) =
+            OneShotBazelResolver::raw_package_query(&request, app).context("Querying Bazel")?;
+
+        Ok(CoordinateQuery {
+            commit_id,
+            query,
+            output,
+            patterns,
+        })
+    }
+
+    /// Resolve `targets` and their dependencies in the outlining tree at HEAD, bypassing the index, returning the
+    /// directories they materialize.
+    pub fn resolve_directories(
+        &self,
+        targets: &TargetSet,
+        app: Arc<App>,
+    ) -> Result<BTreeSet<PathBuf>> {
+        let outliner = self
+            .outliner
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Resolving requires an outlining tree"))?;
+        let commit_id = self.get_head_commit()?.id();
+        let (patterns, _resolution_result) = outliner
+            .outline(
+                commit_id,
+                targets,
+                &self.one_shot_resolution_options()?,
+                None,
+                app,
+            )
+            .context("Failed to outline")?;
+        Ok(patterns
+            .into_iter()
+            .filter_map(|pattern| match pattern {
+                Pattern::Directory { path, .. } => Some(path),
+                Pattern::Verbatim { .. } => None,
+            })
+            .collect())
+    }
+
+    fn one_shot_resolution_options(&self) -> Result<ResolutionOptions> {
+        Ok(ResolutionOptions {
+            bazel_resolution_strategy: BazelResolutionStrategy::OneShot,
+            external_resolver_command: None,
+            bazel_query_retries: self.get_bazel_query_retries()?,
+            strict_resolution: self.strict_resolution,
+            bazel_union_query: self.get_bazel_union_query()?,
+        })
+    }
+
+    /// Query Bazel in the outlining tree at HEAD for the rules of exactly `kind` in `scope` and the packages beneath it.
+    /// The arguments are validated, and `scope` must be a directory at HEAD, before Bazel is invoked.
+    pub fn query_targets_of_kind(
+        &self,
+        kind: &str,
+        scope: &str,
+        app: Arc<App>,
+    ) -> Result<Vec<Target>> {
+        let query = kind_query(kind, scope)?;
+        let scope_path = normalize_kind_query_scope(scope)?;
+        let head_commit = self.get_head_commit()?;
+        if !scope_path.is_empty() {
+            let tree = head_commit.tree().context("Resolving tree")?;
+            let is_directory = tree
+                .get_path(Path::new(&scope_path))
+                .map(|entry| entry.kind() == Some(ObjectType::Tree))
+                .unwrap_or(false);
+            if !is_directory {
+                bail!("Scope '{}' is not a directory at HEAD", scope);
+            }
+        }
+
+        let outliner = self
+            .outliner
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Querying requires an outlining tree"))?;
+        let outlining_tree = outliner.underlying();
+        outlining_tree
+            .switch_to_commit(head_commit.id(), true, true, app.clone())
+            .context("Failed to switch the outlining tree to HEAD")?;
+        let request = ResolutionRequest {
+            repo: outlining_tree.work_dir().to_owned(),
+            targets: TargetSet::new(),
+            options: self.one_shot_resolution_options()?,
+            commit: Some(head_commit.id()),
+        };
+        let labels = OneShotBazelResolver::label_query(&request, &query, app)
+            .with_context(|| format!("Querying Bazel for {}", query))?;
+        info!(%query, count = labels.len(), "Found targets of kind");
+
+        labels
+            .iter()
+            .map(|label| {
+                Target::try_from(format!("bazel:{}", label).as_str())
+                    .with_context(|| format!("Parsing label '{}' returned by Bazel", label))
+            })
+            .collect()
+    }
+
     pub fn dense_outlining_tree(&self) -> Result<OutliningTreeOutliner> {
         todo!("impl")
     }
@@ -1277,6 +2323,65 @@ impl Repo {
         self.git_focus_dir().join("project-cache")
     }
 
+    /// Reads the fingerprint (sync point commit and target set hash) recorded by the last sync, if any.
+    pub fn read_sync_point_fingerprint(&self) -> Result<Option<String>> {
+        let path = self.git_focus_dir().join(SYNC_POINT_FINGERPRINT_FILE_NAME);
+        match fs::read_to_string(&path) {
+            Ok(contents) => Ok(Some(contents.trim().to_owned())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Reading {}", path.display())),
+        }
+    }
+
+    /// Reads the name of the selection set that was active at the last sync, if any.
+    pub fn read_sync_point_selection_name(&self) -> Result<Option<String>> {
+        let path = self
+            .git_focus_dir()
+            .join(SYNC_POINT_SELECTION_NAME_FILE_NAME);
+        match fs::read_to_string(&path) {
+            Ok(contents) => Ok(Some(contents.trim().to_owned())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Reading {}", path.display())),
+        }
+    }
+
+    /// Records the name of the selection set that the sync point was computed from.
+    pub fn write_sync_point_selection_name(&self, name: &str) -> Result<()> {
+        let dir = self.git_focus_dir();
+        fs::create_dir_all(&dir).with_context(|| format!("Creating {}", dir.display()))?;
+        let path = dir.join(SYNC_POINT_SELECTION_NAME_FILE_NAME);
+        fs::write(&path, name).with_context(|| format!("Writing {}", path.display()))
+    }
+
+    /// Records the digest of the selection a completed sync was computed from. See `selection_digest`.
+    pub fn write_sync_point_selection_digest(&self, digest: &str) -> Result<()> {
+        let dir = self.git_focus_dir();
+        fs::create_dir_all(&dir).with_context(|| format!("Creating {}", dir.display()))?;
+        let path = dir.join(SYNC_POINT_SELECTION_DIGEST_FILE_NAME);
+        fs::write(&path, digest).with_context(|| format!("Writing {}", path.display()))
+    }
+
+    /// Forgets the digest of the selection recorded by the last sync, so that `check_sync_needed` reports that a sync is
+    /// needed. Used when a sync materialized targets other than those of the selection.
+    pub fn clear_sync_point_selection_digest(&self) -> Result<()> {
+        let path = self
+            .git_focus_dir()
+            .join(SYNC_POINT_SELECTION_DIGEST_FILE_NAME);
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).with_context(|| format!("Removing {}", path.display())),
+        }
+    }
+
+    /// Records the fingerprint (sync point commit and target set hash) of a completed sync.
+    pub fn write_sync_point_fingerprint(&self, fingerprint: &str) -> Result<()> {
+        let dir = self.git_focus_dir();
+        fs::create_dir_all(&dir).with_context(|| format!("Creating {}", dir.display()))?;
+        let path = dir.join(SYNC_POINT_FINGERPRINT_FILE_NAME);
+        fs::write(&path, fingerprint).with_context(|| format!("Writing {}", path.display()))
+    }
+
     /// Write git config to support gitstats instrumentation.
     /// This sets `focus.version` and `twitter.statsenabled`
     pub fn write_git_config_to_support_instrumentation(&self) -> Result<()> {
@@ -1301,12 +2406,17 @@ impl Repo {
         self.selection_manager()?.computed_selection()
     }
 
+    /// The ref that prefetching updates with the given remote branch.
+    pub fn prefetch_ref_name(remote_name: &str, branch_name: &str) -> String {
+        format!("refs/prefetch/remotes/{}/{}", remote_name, branch_name)
+    }
+
     pub fn get_prefetch_head_commit(
         &self,
         remote_name: &str,
         branch_name: &str,
     ) -> Result<Option<git2::Commit>> {
-        let ref_name = format!("refs/prefetch/remotes/{}/{}", remote_name, branch_name);
+        let ref_name = Self::prefetch_ref_name(remote_name, branch_name);
         match self.repo.find_reference(&ref_name) {
             Ok(prefetch_head_reference) => Ok(Some(
                 prefetch_head_reference
@@ -1448,6 +2558,61 @@ impl Repo {
         Ok(())
     }
 
+    /// Read the name of the active selection set.
+    pub fn get_active_selection_name(&self) -> Result<String> {
+        let config_snapshot = self.repo.config()?.snapshot()?;
+        Ok(config_snapshot
+            .get_str(ACTIVE_SELECTION_CONFIG_KEY)
+            .map(|s| s.to_owned())
+            .unwrap_or_else(|_| DEFAULT_SELECTION_NAME.to_owned()))
+    }
+
+    /// A digest of the active selection set's name and stored contents, which changes whenever the selection does.
+    pub fn selection_digest(&self) -> Result<String> {
+        let name = self.get_active_selection_name()?;
+        let path = self.selection_path()?;
+        let contents = match fs::read(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e).with_context(|| format!("Reading {}", path.display())),
+        };
+        Ok(hashing::hash_selection(&name, &contents))
+    }
+
+    /// Refuse to write a sparse profile unless the selection still has `digest`. Sync records the digest of the
+    /// selection it computes the profile from, so that a selection changed by another process in the meantime is not
+    /// overwritten with a stale profile.
+    pub fn expect_selection_digest(&mut self, digest: String) {
+        self.expected_selection_digest = Some(digest);
+    }
+
+    fn verify_selection_unchanged(&self) -> Result<()> {
+        if let Some(expected) = self.expected_selection_digest.as_ref() {
+            if &self.selection_digest()? != expected {
+                bail!("The selection changed while the sparse profile was being computed; re-run sync");
+            }
+        }
+        Ok(())
+    }
+
+    /// The location of the file storing the active selection set.
+    pub fn selection_path(&self) -> Result<PathBuf> {
+        let paths = DataPaths::from_working_tree(self.working_tree()?)?;
+        Ok(paths.selection_file_for(&self.get_active_selection_name()?))
+    }
+
+    /// Switch the active selection set. `add`, `remove`, and `sync` operate on the active set.
+    pub fn set_active_selection_name(&self, name: &str) -> Result<()> {
+        validate_selection_name(name)?;
+        git_helper::write_config(
+            &self.path,
+            ACTIVE_SELECTION_CONFIG_KEY,
+            name,
+            self.app.clone(),
+        )
+        .with_context(|| format!("Writing key '{}'", ACTIVE_SELECTION_CONFIG_KEY))
+    }
+
     pub fn get_bazel_oneshot_resolution(&self) -> Result<bool> {
         let mut config_snapshot = self.repo.config()?.snapshot()?;
         config_snapshot.get_bool_with_default(BAZEL_ONE_SHOT_RESOLUTION_CONFIG_KEY, false)
@@ -1461,4 +2626,178 @@ impl Repo {
             self.app.clone(),
         )
     }
+
+    /// The executable configured to resolve coordinates in place of Bazel, if any.
+    pub fn get_resolver_command(&self) -> Result<Option<PathBuf>> {
+        let config_snapshot = self.repo.config()?.snapshot()?;
+        Ok(config_snapshot.get_path(RESOLVER_COMMAND_CONFIG_KEY).ok())
+    }
+
+    pub fn set_resolver_command(&self, command: &Path) -> Result<()> {
+        git_helper::write_config(
+            &self.path,
+            RESOLVER_COMMAND_CONFIG_KEY,
+            command.to_string_lossy().as_ref(),
+            self.app.clone(),
+        )
+        .with_context(|| format!("Writing key '{}'", RESOLVER_COMMAND_CONFIG_KEY))
+    }
+
+    /// How many times Bazel queries that fail transiently are retried. An override takes precedence over the
+    /// configured value.
+    pub fn get_bazel_query_retries(&self) -> Result<usize> {
+        if let Some(retries) = self.bazel_query_retries_override {
+            return Ok(retries);
+        }
+        let config_snapshot = self.repo.config()?.snapshot()?;
+        Ok(config_snapshot
+            .get_i64(BAZEL_QUERY_RETRIES_CONFIG_KEY)
+            .ok()
+            .and_then(|retries| usize::try_from(retries).ok())
+            .unwrap_or(BAZEL_QUERY_RETRIES_DEFAULT))
+    }
+
+    /// Whether one-shot resolution queries Bazel once for all coordinates rather than once per coordinate.
+    pub fn get_bazel_union_query(&self) -> Result<bool> {
+        let mut config_snapshot = self.repo.config()?.snapshot()?;
+        config_snapshot.get_bool_with_default(BAZEL_UNION_QUERY_CONFIG_KEY, true)
+    }
+
+    /// The commands to run before syncing, in the order they are configured.
+    pub fn get_pre_sync_hooks(&self) -> Result<Vec<String>> {
+        let config_snapshot = self.repo.config()?.snapshot()?;
+        config_snapshot
+            .multivar_values(PRE_SYNC_HOOKS_CONFIG_KEY, None)
+            .with_context(|| format!("Reading key '{}'", PRE_SYNC_HOOKS_CONFIG_KEY))
+    }
+
+    /// How many previous versions of the selection to keep in `.git/focus/selection_history`. Each entry is a copy
+    /// of the selection file, so larger values use more disk space. Zero disables the history. Values above
+    /// `SELECTION_HISTORY_DEPTH_MAX` are capped.
+    pub fn get_selection_history_depth(&self) -> Result<usize> {
+        let config_snapshot = self.repo.config()?.snapshot()?;
+        let depth = config_snapshot
+            .get_i64(SELECTION_HISTORY_DEPTH_CONFIG_KEY)
+            .ok()
+            .and_then(|depth| usize::try_from(depth).ok())
+            .unwrap_or(SELECTION_HISTORY_DEPTH_DEFAULT);
+        if depth > SELECTION_HISTORY_DEPTH_MAX {
+            warn!(
+                depth,
+                max = SELECTION_HISTORY_DEPTH_MAX,
+                "The configured selection history depth exceeds the maximum; using the maximum"
+            );
+            return Ok(SELECTION_HISTORY_DEPTH_MAX);
+        }
+        Ok(depth)
+    }
+
+    /// Where to mirror the selection after it changes or is synced, from `focus.selection-mirror`, so that it can be
+    /// committed and shared. Relative paths are relative to the root of the working tree. `None` if not configured.
+    pub fn get_selection_mirror_path(&self) -> Result<Option<PathBuf>> {
+        let config_snapshot = self.repo.config()?.snapshot()?;
+        Ok(config_snapshot
+            .get_path(SELECTION_MIRROR_CONFIG_KEY)
+            .ok()
+            .map(|path| self.path.join(path)))
+    }
+
+    pub fn set_selection_mirror_path(&self, path: &Path) -> Result<()> {
+        git_helper::write_config(
+            &self.path,
+            SELECTION_MIRROR_CONFIG_KEY,
+            path.to_string_lossy().as_ref(),
+            self.app.clone(),
+        )
+        .with_context(|| format!("Writing key '{}'", SELECTION_MIRROR_CONFIG_KEY))
+    }
+
+    pub fn set_bazel_query_retries(&self, retries: usize) -> Result<()> {
+        git_helper::write_config(
+            &self.path,
+            BAZEL_QUERY_RETRIES_CONFIG_KEY,
+            retries.to_string().as_str(),
+            self.app.clone(),
+        )
+        .with_context(|| format!("Writing key '{}'", BAZEL_QUERY_RETRIES_CONFIG_KEY))
+    }
+
+    /// Resolve each setting read from Git config, as listed in `SETTING_CONFIG_KEYS`, to the value this repo uses,
+    /// noting whether it was configured or fell back to the default. Settings without a default are reported with their
+    /// configured values as is.
+    pub fn effective_settings(&self) -> Result<Vec<EffectiveSetting>> {
+        let config_snapshot = self
+            .repo
+            .config()
+            .context("Reading config")?
+            .snapshot()
+            .context("Snapshotting config")?;
+        let optional = |value: Option<String>| value.unwrap_or_else(|| String::from("(unset)"));
+
+        let mut settings = Vec::with_capacity(SETTING_CONFIG_KEYS.len());
+        for &key in SETTING_CONFIG_KEYS {
+            let value = match key {
+                ACTIVE_SELECTION_CONFIG_KEY => self.get_active_selection_name()?,
+                CHECKOUT_BACKEND_CONFIG_KEY => {
+                    self.working_tree()?.get_checkout_backend()?.to_string()
+                }
+                SPARSE_MODE_CONFIG_KEY => self.working_tree()?.get_sparse_mode()?.to_string(),
+                FILTER_VIEW => self.working_tree()?.get_filter_config()?.to_string(),
+                PREEMPTIVE_SYNC_ENABLED_CONFIG_KEY => {
+                    self.get_preemptive_sync_enabled()?.to_string()
+                }
+                PREEMPTIVE_SYNC_USER_IDLE_MILLIS_THRESHOLD_CONFIG_KEY => self
+                    .get_preemptive_sync_idle_threshold()?
+                    .as_millis()
+                    .to_string(),
+                BAZEL_ONE_SHOT_RESOLUTION_CONFIG_KEY => {
+                    self.get_bazel_oneshot_resolution()?.to_string()
+                }
+                BAZEL_QUERY_RETRIES_CONFIG_KEY => self.get_bazel_query_retries()?.to_string(),
+                BAZEL_UNION_QUERY_CONFIG_KEY => self.get_bazel_union_query()?.to_string(),
+                SELECTION_HISTORY_DEPTH_CONFIG_KEY => {
+                    self.get_selection_history_depth()?.to_string()
+                }
+                _ => {
+                    let values = config_snapshot
+                        .multivar_values(key, None)
+                        .with_context(|| format!("Reading key '{}'", key))?;
+                    optional((!values.is_empty()).then(|| values.join(", ")))
+                }
+            };
+            let source = if key == BAZEL_QUERY_RETRIES_CONFIG_KEY
+                && self.bazel_query_retries_override.is_some()
+            {
+                SettingSource::Flag
+            } else if config_snapshot.get_entry(key).is_ok() {
+                SettingSource::GitConfig
+            } else {
+                SettingSource::Default
+            };
+            settings.push(EffectiveSetting::new(key, value, source));
+        }
+        settings.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(settings)
+    }
+
+    /// Retry transiently failing Bazel queries this many times for the lifetime of this instance, ignoring the
+    /// configured value.
+    pub fn override_bazel_query_retries(&mut self, retries: usize) {
+        self.bazel_query_retries_override = Some(retries);
+    }
+
+    /// Validate the sparse checkout patterns that incremental syncs find in the index against a fresh resolution, for the
+    /// lifetime of this instance. Index entries are keyed by content hashes, which cover `WORKSPACE` but not the commit
+    /// the entry was resolved at, so a resolution made at an older commit can be reused. The fresh resolution is the one
+    /// applied, its entries replace the cached ones, and disagreements are reported.
+    pub fn require_strict_cache(&mut self) {
+        self.strict_cache = true;
+    }
+
+    /// Fail Bazel resolution if Bazel reports warnings or errors concerning the requested targets, rather than accepting
+    /// a result that may be missing targets it skipped, for the lifetime of this instance. Sparse checkout patterns found
+    /// in the index are resolved again so that they are checked too.
+    pub fn require_strict_resolution(&mut self) {
+        self.strict_resolution = true;
+    }
 }
@@ -0,0 +1,123 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use tracing::debug;
+
+/// Keeps copies of previous versions of the selection file, numbered in the order they were recorded. Only the most
+/// recent `depth` copies are kept; a depth of zero disables the history.
+#[derive(Clone, Debug)]
+pub(crate) struct SelectionHistory {
+    dir: PathBuf,
+    depth: usize,
+}
+
+impl SelectionHistory {
+    pub fn new(dir: impl AsRef<Path>, depth: usize) -> Self {
+        Self {
+            dir: dir.as_ref().to_owned(),
+            depth,
+        }
+    }
+
+    /// Paths of the recorded selections, oldest first.
+    pub fn entries(&self) -> Result<Vec<PathBuf>> {
+        if !self.dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)
+            .with_context(|| format!("Reading {}", self.dir.display()))?
+        {
+            let path = entry?.path();
+            if Self::serial_of(&path).is_some() {
+                entries.push(path);
+            }
+        }
+        entries.sort_unstable_by_key(|path| Self::serial_of(path));
+        Ok(entries)
+    }
+
+    /// Copy the selection file at `selection_path`, if there is one, into the history, then discard the oldest entries
+    /// beyond the configured depth.
+    pub fn record(&self, selection_path: &Path) -> Result<()> {
+        if self.depth > 0 && selection_path.is_file() {
+            let entries = self.entries()?;
+            let serial = entries
+                .last()
+                .and_then(|path| Self::serial_of(path))
+                .map_or(0, |serial| serial + 1);
+            std::fs::create_dir_all(&self.dir)
+                .with_context(|| format!("Creating {}", self.dir.display()))?;
+            let entry_path = self.dir.join(format!("{:010}.json", serial));
+            std::fs::copy(selection_path, &entry_path).with_context(|| {
+                format!(
+                    "Copying {} to {}",
+                    selection_path.display(),
+                    entry_path.display()
+                )
+            })?;
+            debug!(path = ?entry_path, "Recorded selection history entry");
+        }
+        self.trim()
+    }
+
+    /// Remove the entry at `path` from the history.
+    pub fn remove(&self, path: &Path) -> Result<()> {
+        std::fs::remove_file(path).with_context(|| format!("Removing {}", path.display()))?;
+        debug!(?path, "Removed selection history entry");
+        Ok(())
+    }
+
+    fn trim(&self) -> Result<()> {
+        let entries = self.entries()?;
+        let excess = entries.len().saturating_sub(self.depth);
+        for path in entries.iter().take(excess) {
+            std::fs::remove_file(path).with_context(|| format!("Removing {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    pub fn serial_of(path: &Path) -> Option<u64> {
+        if path.extension()? != "json" {
+            return None;
+        }
+        path.file_stem()?.to_str()?.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_is_trimmed_to_the_configured_depth() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let selection_path = dir.path().join("selection.json");
+        let history_dir = dir.path().join("selection_history");
+
+        let history = SelectionHistory::new(&history_dir, 3);
+        history.record(&selection_path)?;
+        assert!(history.entries()?.is_empty());
+
+        for version in 0..5 {
+            std::fs::write(&selection_path, version.to_string())?;
+            history.record(&selection_path)?;
+        }
+        let contents = history
+            .entries()?
+            .iter()
+            .map(std::fs::read_to_string)
+            .collect::<std::io::Result<Vec<_>>>()?;
+        assert_eq!(contents, vec!["2", "3", "4"]);
+
+        // A depth of zero disables the history and discards what was recorded.
+        let disabled = SelectionHistory::new(&history_dir, 0);
+        disabled.record(&selection_path)?;
+        assert!(disabled.entries()?.is_empty());
+
+        Ok(())
+    }
+}
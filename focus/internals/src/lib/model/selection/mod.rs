@@ -1,16 +1,19 @@
 // Copyright 2022 Twitter, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+mod history;
 mod project;
 pub use project::resolve_targets_for_project;
 pub use project::Project;
 pub(crate) use project::ProjectCatalog;
+pub use project::ProjectExpansion;
 use project::ProjectIndex;
 
 #[allow(clippy::module_inception)]
 mod selection;
-pub use selection::Selection;
-pub(crate) use selection::SelectionManager;
+pub use selection::SelectionHistoryEntry;
+pub use selection::SelectionManager;
+pub use selection::{validate_selection_name, Selection, DEFAULT_SELECTION_NAME};
 
 use super::data_paths::DataPaths;
 
@@ -20,7 +23,7 @@ pub use operations::Operation;
 pub use operations::OperationAction;
 pub use operations::OperationResult;
 pub use operations::RemoveOptions;
-pub(crate) use operations::Underlying;
+pub use operations::Underlying;
 
 #[cfg(test)]
 mod testing;
@@ -1,7 +1,7 @@
 // Copyright 2022 Twitter, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::collections::HashSet;
+use std::{collections::HashSet, fmt::Display};
 
 use super::*;
 
@@ -38,7 +38,16 @@ pub enum Underlying {
     Project(String),
 }
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+impl Display for Underlying {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Underlying::Target(target) => write!(f, "{}", target),
+            Underlying::Project(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Operation {
     pub action: OperationAction,
     pub underlying: Underlying,
@@ -3,7 +3,7 @@
 
 use anyhow::{bail, Context, Result};
 use std::{
-    collections::{BTreeSet, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     convert::TryFrom,
     ffi::OsString,
     fmt::Display,
@@ -42,6 +42,21 @@ pub struct Project {
     #[serde(skip_serializing_if = "BTreeSet::is_empty")]
     #[serde(default)]
     pub projects: BTreeSet<String>,
+
+    /// If set, the project is deprecated and this explains why. Users are warned when they select it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub deprecated: Option<String>,
+
+    /// The project that users of this deprecated project should select instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub replaced_by: Option<String>,
+
+    /// Former names of this project. Selections that use them refer to this project.
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    #[serde(default)]
+    pub aliases: BTreeSet<String>,
 }
 
 /// Resolves all targets for a set of projects, including sub-project definitions.
@@ -81,6 +96,49 @@ pub fn resolve_targets_for_project(
     Ok(target_set)
 }
 
+/// The targets one selected project contributes to the target set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProjectExpansion {
+    pub name: String,
+    pub mandatory: bool,
+
+    /// Targets listed by the project itself.
+    pub direct: BTreeSet<Target>,
+
+    /// Targets pulled in only through the projects it includes.
+    pub transitive: BTreeSet<Target>,
+}
+
+impl ProjectExpansion {
+    /// Attribute the targets of `project` to the project itself or to the projects it includes.
+    pub fn of(project: &Project, available_subprojects: &HashMap<String, Project>) -> Result<Self> {
+        let direct = project
+            .targets
+            .iter()
+            .map(|target| Target::try_from(target.as_str()))
+            .collect::<Result<BTreeSet<Target>, _>>()?;
+        let transitive = resolve_targets_for_project(vec![project.clone()], available_subprojects)?
+            .into_iter()
+            .filter(|target| !direct.contains(target))
+            .collect();
+        Ok(Self {
+            name: project.name.clone(),
+            mandatory: project.mandatory,
+            direct,
+            transitive,
+        })
+    }
+
+    /// The number of targets the project contributes.
+    pub fn len(&self) -> usize {
+        self.direct.len() + self.transitive.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
 impl Project {
     /// Returns whether this project is mandatory, meaning it is always present in the sparse outline and is not presented to users as being selectable.
     pub fn is_mandatory(&self) -> bool {
@@ -92,6 +150,24 @@ impl Project {
         !self.is_mandatory()
     }
 
+    /// Returns whether this project is deprecated.
+    pub fn is_deprecated(&self) -> bool {
+        self.deprecated.is_some()
+    }
+
+    /// Returns the warning to show when this project is selected, if it is deprecated.
+    pub fn deprecation_warning(&self) -> Option<String> {
+        let reason = self.deprecated.as_ref()?;
+        let mut warning = format!("Project '{}' is deprecated", self.name);
+        if !reason.trim().is_empty() {
+            warning.push_str(&format!(": {}", reason.trim()));
+        }
+        if let Some(replacement) = self.replaced_by.as_ref() {
+            warning.push_str(&format!(" (use '{}' instead)", replacement));
+        }
+        Some(warning)
+    }
+
     pub fn lint(&self) -> Result<()> {
         for target in &self.targets {
             Target::try_from(target.as_str()).with_context(|| {
@@ -137,6 +213,10 @@ impl TryFrom<&Project> for TargetSet {
 #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
 struct ProjectSet {
     pub projects: Vec<Project>,
+
+    /// Curated selections, mapping each preset's name to the projects it selects. Omitted when empty.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub presets: BTreeMap<String, Vec<String>>,
 }
 impl ProjectSet {
     #[allow(dead_code)]
@@ -207,6 +287,9 @@ pub struct ProjectIndex {
 
     /// Sources maps project names to name of the project set file they were defined in.
     pub sources: HashMap<String, String>,
+
+    /// Presets maps the names of curated selections to the projects they select.
+    pub presets: BTreeMap<String, Vec<String>>,
 }
 
 impl Display for ProjectIndex {
@@ -252,13 +335,18 @@ impl Display for ProjectIndex {
             let stats = stats.join(", ");
             writeln!(
                 f,
-                "{}   {}{}",
+                "{}   {}{}{}",
                 padded_project_name,
                 project.description,
                 if stats.is_empty() {
                     "".to_string()
                 } else {
                     format!(" ({})", stats)
+                },
+                if project.is_deprecated() {
+                    " [deprecated]"
+                } else {
+                    ""
                 }
             )?;
         }
@@ -268,6 +356,15 @@ impl Display for ProjectIndex {
 // TODO(wilhelm): Reduce duplication of the keys of these tables by introducing an intermediate token table.
 
 impl ProjectIndex {
+    /// Find the project with the given name, or failing that, the project that lists it as an alias.
+    pub fn resolve(&self, name: &str) -> Option<&Project> {
+        self.underlying.get(name).or_else(|| {
+            self.underlying
+                .values()
+                .find(|project| project.aliases.contains(name))
+        })
+    }
+
     fn new(manager: &ProjectSetStore) -> Result<Self> {
         let mut projects = Self::default();
         for (project_set_name, project_set) in manager.underlying().iter() {
@@ -299,6 +396,20 @@ impl ProjectIndex {
                 .insert(project_name, project_set_name.to_owned());
         }
 
+        for (preset_name, projects) in project_set.presets.iter() {
+            if self
+                .presets
+                .insert(preset_name.clone(), projects.clone())
+                .is_some()
+            {
+                bail!(
+                    "Duplicate preset '{}' encountered in set {}",
+                    preset_name,
+                    project_set_name
+                );
+            }
+        }
+
         Ok(())
     }
 }
@@ -331,10 +442,14 @@ pub enum ProjectCatalogError {
 
 impl ProjectCatalog {
     pub(crate) fn new(paths: &DataPaths) -> Result<Self> {
+        Self::from_directories(&paths.project_dir, &paths.focus_dir)
+    }
+
+    fn from_directories(project_dir: &Path, focus_dir: &Path) -> Result<Self> {
         let optional_project_set_store =
-            ProjectSetStore::new(&paths.project_dir).context("Loading optional projects")?;
+            ProjectSetStore::new(project_dir).context("Loading optional projects")?;
         let mandatory_project_set_store =
-            ProjectSetStore::new(&paths.focus_dir).context("Loading mandatory projects")?;
+            ProjectSetStore::new(focus_dir).context("Loading mandatory projects")?;
         let optional_projects = ProjectIndex::new(&optional_project_set_store)?;
         let mandatory_projects = ProjectIndex::new(&mandatory_project_set_store)?;
         Ok(Self {
@@ -344,6 +459,44 @@ impl ProjectCatalog {
         })
     }
 
+    /// Load the projects defined at `commit_id` rather than those in the working tree. The catalog is read-only in
+    /// spirit: saving it would write to a temporary directory that no longer exists.
+    pub(crate) fn at_commit(repo: &git2::Repository, commit_id: git2::Oid) -> Result<Self> {
+        let tree = repo
+            .find_commit(commit_id)
+            .and_then(|commit| commit.tree())
+            .with_context(|| format!("Resolving the tree of commit {}", commit_id))?;
+        let dir = tempfile::tempdir().context("Creating a temporary directory")?;
+        let focus_dir = dir.path().join("focus");
+        let project_dir = focus_dir.join("projects");
+        for (tree_path, target_dir) in [("focus", &focus_dir), ("focus/projects", &project_dir)] {
+            let subtree = match tree.get_path(Path::new(tree_path)) {
+                Ok(entry) => entry
+                    .to_object(repo)
+                    .and_then(|object| object.peel_to_tree())
+                    .with_context(|| format!("Resolving {} at {}", tree_path, commit_id))?,
+                Err(_) => continue,
+            };
+            std::fs::create_dir_all(target_dir)
+                .with_context(|| format!("Creating {}", target_dir.display()))?;
+            for entry in subtree.iter() {
+                let name = match entry.name() {
+                    Some(name) if name.ends_with(".projects.json") => name,
+                    _ => continue,
+                };
+                let blob = match entry.to_object(repo)?.into_blob() {
+                    Ok(blob) => blob,
+                    Err(_) => continue,
+                };
+                let path = target_dir.join(name);
+                std::fs::write(&path, blob.content())
+                    .with_context(|| format!("Writing {}", path.display()))?;
+            }
+        }
+        Self::from_directories(&project_dir, &focus_dir)
+            .with_context(|| format!("Loading the projects defined at {}", commit_id))
+    }
+
     pub fn save(&mut self) -> Result<()> {
         self.optional_project_set_store.0.save()
     }
@@ -393,6 +546,7 @@ impl ProjectCatalog {
                 None => {
                     let new_project_set = ProjectSet {
                         projects: vec![project],
+                        ..Default::default()
                     };
                     self.optional_project_set_store
                         .0
@@ -442,6 +596,9 @@ mod testing {
             mandatory: false,
             targets: btreeset![String::from(TARGET_STR),],
             projects: btreeset![String::from(PROJECT_NAME_STR_2)],
+            deprecated: None,
+            replaced_by: None,
+            aliases: btreeset![],
         }
     }
 
@@ -455,6 +612,9 @@ mod testing {
                 String::from(PROJECT_NAME_STR_2),
                 String::from(PROJECT_NAME_STR)
             ],
+            deprecated: None,
+            replaced_by: None,
+            aliases: btreeset![],
         }
     }
 
@@ -465,6 +625,9 @@ mod testing {
             mandatory: false,
             targets: btreeset!["non-compliant-scheme:thisdoesntmatter".to_string()],
             projects: btreeset![],
+            deprecated: None,
+            replaced_by: None,
+            aliases: btreeset![],
         }
     }
 
@@ -475,6 +638,9 @@ mod testing {
             mandatory: false,
             targets: btreeset!["bazel://something".to_string()],
             projects: btreeset![],
+            deprecated: None,
+            replaced_by: None,
+            aliases: btreeset![],
         }
     }
 
@@ -496,6 +662,23 @@ mod testing {
         Ok(())
     }
 
+    #[test]
+    fn expansion_separates_direct_and_transitive_targets() -> Result<()> {
+        let available_projects =
+            hashmap! { project2().name => project2(), project().name => project() };
+        let expansion = ProjectExpansion::of(&project(), &available_projects)?;
+        assert_eq!(expansion.name, PROJECT_NAME_STR);
+        assert_eq!(expansion.direct, btreeset![target()]);
+        assert_eq!(expansion.transitive, btreeset![target2()]);
+        assert_eq!(expansion.len(), 2);
+
+        let expansion = ProjectExpansion::of(&compliant_project(), &available_projects)?;
+        assert!(expansion.transitive.is_empty());
+        assert_eq!(expansion.len(), 1);
+
+        Ok(())
+    }
+
     #[test]
     fn test_get_all_targets_for_project_fails_with_invalid_subproject_name() -> Result<()> {
         let available_projects = hashmap! { project().name => project() };
@@ -557,4 +740,70 @@ mod testing {
         assert_eq!(project.projects, btreeset!["another_project".to_string()]);
         Ok(())
     }
+
+    #[test]
+    fn presets_are_indexed() -> Result<()> {
+        let project_set: ProjectSet = serde_json::from_str(
+            r#"
+            {
+                "projects": [],
+                "presets": {
+                    "mobile": ["team_a/ios", "team_a/android"]
+                }
+            }
+            "#,
+        )?;
+        let mut index = ProjectIndex::default();
+        index.extend("mobile.projects.json", &project_set)?;
+        assert_eq!(
+            index.presets.get("mobile"),
+            Some(&vec![
+                String::from("team_a/ios"),
+                String::from("team_a/android")
+            ])
+        );
+        assert!(index.extend("other.projects.json", &project_set).is_err());
+
+        let without_presets: ProjectSet = serde_json::from_str(r#"{ "projects": [] }"#)?;
+        assert!(without_presets.presets.is_empty());
+        assert!(!serde_json::to_string(&without_presets)?.contains("presets"));
+        Ok(())
+    }
+
+    #[test]
+    fn deprecated_project_warning() -> Result<()> {
+        let project: Project = serde_json::from_str(
+            r#"
+            {
+                "name": "old_project",
+                "description": "an old project",
+                "deprecated": "It was split up.",
+                "replaced_by": "new_project"
+            }
+            "#,
+        )?;
+        assert!(project.is_deprecated());
+        assert_eq!(
+            project.deprecation_warning().as_deref(),
+            Some(
+                "Project 'old_project' is deprecated: It was split up. (use 'new_project' instead)"
+            )
+        );
+        assert_eq!(compliant_project().deprecation_warning(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn resolving_projects_by_alias() {
+        let mut renamed_project = compliant_project();
+        renamed_project.aliases = btreeset!["old_name".to_string()];
+        let mut index = ProjectIndex::default();
+        index
+            .underlying
+            .insert(renamed_project.name.clone(), renamed_project.clone());
+
+        assert_eq!(index.resolve("compliant_project"), Some(&renamed_project));
+        assert_eq!(index.resolve("old_name"), Some(&renamed_project));
+        assert_eq!(index.resolve("unknown_name"), None);
+    }
 }
@@ -6,33 +6,80 @@ use anyhow::{bail, Context, Result};
 use focus_util::backed_up_file::BackedUpFile;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{BTreeSet, HashSet},
+    collections::{BTreeMap, BTreeSet, HashSet},
     fmt::Display,
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 use tracing::{debug, error, warn};
 
+use super::history::SelectionHistory;
 use super::*;
 
+/// The name of the selection set used when none has been chosen.
+pub const DEFAULT_SELECTION_NAME: &str = "default";
+
+/// Check that `name` can be used as the name of a selection set.
+pub fn validate_selection_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+    {
+        bail!(
+            "Invalid selection name '{}': names may only contain letters, digits, '-', '_', and '.', and may not start with '.'",
+            name
+        );
+    }
+    Ok(())
+}
+
 /// A structure representing the current selection in memory. Instead of serializing this structure, a PersistedSelection is stored to disk. In addition to that structure being simpler to serialize, the indirection allows for updates to the underlying project definitions.
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct Selection {
     pub projects: HashSet<Project>,
     pub targets: HashSet<Target>,
+
+    /// Coordinates that replace others wherever the selection's projects resolve to them, e.g. to try a local variant
+    /// of a target without editing the project catalog.
+    pub overrides: BTreeMap<Target, Target>,
 }
 
 impl Selection {
+    /// Create a selection from its persisted form, also returning the names of selected projects that are no longer
+    /// defined.
     fn from_persisted_selection(
         persisted_selection: PersistedSelection,
         projects: &ProjectIndex,
-    ) -> Result<Self> {
-        let mut selection = Selection::default();
+    ) -> Result<(Self, BTreeSet<String>)> {
+        let mut selection = Selection {
+            overrides: parse_overrides(&persisted_selection.overrides)?,
+            ..Default::default()
+        };
         let operations = Vec::<Operation>::try_from(persisted_selection)
             .context("Structuring a persisted selection as a set of operations")?;
-        selection
+        let result = selection
             .apply_operations(&operations, projects)
             .context("Creating a selection from its persisted form")?;
-        Ok(selection)
+        let missing_projects = result
+            .absent
+            .into_iter()
+            .filter_map(|underlying| match underlying {
+                Underlying::Project(name) => Some(name),
+                Underlying::Target(_) => None,
+            })
+            .collect();
+        Ok((selection, missing_projects))
+    }
+
+    /// Replace the targets in `target_set` that are overridden.
+    fn apply_overrides(&self, target_set: &mut TargetSet) {
+        for (from, to) in self.overrides.iter() {
+            if target_set.remove(from) {
+                target_set.insert(to.clone());
+            }
+        }
     }
 
     fn apply_operations(
@@ -104,8 +151,12 @@ impl Display for Selection {
         writeln!(f)?;
 
         writeln!(f, "--- Targets ---")?;
-        let sorted_targets =
-            BTreeSet::<String>::from_iter(self.targets.iter().map(|target| target.to_string()));
+        let sorted_targets = BTreeSet::<String>::from_iter(
+            self.targets
+                .iter()
+                .filter(|target| !matches!(target, Target::File(_)))
+                .map(|target| target.to_string()),
+        );
         if sorted_targets.is_empty() {
             writeln!(f, "None selected.")?;
         } else {
@@ -114,6 +165,19 @@ impl Display for Selection {
             }
         }
 
+        let sorted_files =
+            BTreeSet::<&str>::from_iter(self.targets.iter().filter_map(|target| match target {
+                Target::File(path) => Some(path.as_str()),
+                _ => None,
+            }));
+        if !sorted_files.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "--- Files ---")?;
+            for path in sorted_files.iter() {
+                writeln!(f, "{}", path)?;
+            }
+        }
+
         Ok(())
     }
 }
@@ -140,7 +204,7 @@ impl<'processor> SelectionOperationProcessor<'processor> {
                     }
                 }
                 (OperationAction::Add(_), Underlying::Project(name)) => {
-                    match self.projects.underlying.get(name.as_str()) {
+                    match self.projects.resolve(name) {
                         Some(project) => {
                             if self.selection.projects.insert(project.clone()) {
                                 result.added.insert(operation.underlying.clone());
@@ -166,7 +230,7 @@ impl<'processor> SelectionOperationProcessor<'processor> {
                     }
                 }
                 (OperationAction::Remove(_), Underlying::Project(name)) => {
-                    match self.projects.underlying.get(name) {
+                    match self.projects.resolve(name) {
                         Some(project) => {
                             if self.selection.projects.remove(project) {
                                 debug!(?project, "Project removed from selection");
@@ -196,54 +260,223 @@ pub struct SelectionManager {
     selection_path: PathBuf,
     /// The currently selected projects.
     selection: Selection,
+    /// Names of projects in the stored selection that are no longer defined. They are kept in the stored selection
+    /// until removed explicitly.
+    missing_projects: BTreeSet<String>,
     /// A catalog of defined projects.
     project_catalog: ProjectCatalog,
+    /// Previous versions of the stored selection.
+    history: SelectionHistory,
 }
 
 impl SelectionManager {
+    /// Count the projects in the selection stored at `path` without loading the project catalog, e.g. for shell
+    /// prompts.
+    pub fn count_persisted_projects(path: &Path) -> Result<usize> {
+        let persisted_selection: PersistedSelection = load_model(path)
+            .with_context(|| format!("Loading the selection {}", path.display()))?;
+        Ok(persisted_selection.projects.len())
+    }
+
     pub fn from_repo(repo: &Repo) -> Result<Self> {
         let working_tree = repo.working_tree()?;
         let paths = DataPaths::from_working_tree(working_tree)?;
         let project_catalog = ProjectCatalog::new(&paths)?;
-        Self::new(&paths.selection_file, project_catalog)
+        let selection_name = repo.get_active_selection_name()?;
+        let history = SelectionHistory::new(
+            Repo::selection_history_dir_path(repo.git_dir()),
+            repo.get_selection_history_depth()?,
+        );
+        Self::new(
+            &paths.selection_file_for(&selection_name),
+            project_catalog,
+            history,
+        )
     }
 
-    fn new(selection_path: impl AsRef<Path>, project_catalog: ProjectCatalog) -> Result<Self> {
+    fn new(
+        selection_path: impl AsRef<Path>,
+        project_catalog: ProjectCatalog,
+        history: SelectionHistory,
+    ) -> Result<Self> {
         let mut instance = Self {
             selection_path: selection_path.as_ref().to_owned(),
             selection: Default::default(),
+            missing_projects: Default::default(),
             project_catalog,
+            history,
         };
         instance.reload()?;
         Ok(instance)
     }
 
-    /// Load a selection from the given `path` using project definitions from `projects`.
-    fn load(path: impl AsRef<Path>, projects: &ProjectIndex) -> Result<Selection> {
+    /// Load a selection from the given `path` using project definitions from `projects`, also returning the names of
+    /// selected projects that are not defined.
+    fn load(
+        path: impl AsRef<Path>,
+        projects: &ProjectIndex,
+    ) -> Result<(Selection, BTreeSet<String>)> {
         let persisted_selection = load_model(path).context("Loading persisted selection")?;
         Selection::from_persisted_selection(persisted_selection, projects)
     }
 
     /// Load the selection from disk.
     pub fn reload(&mut self) -> Result<()> {
-        let selection: Selection = Self::load(
+        let (selection, missing_projects) = Self::load(
             &self.selection_path,
             &self.project_catalog.optional_projects,
         )?;
-        debug!(?selection, path = ?self.selection_path, "Reloaded selection");
+        debug!(?selection, ?missing_projects, path = ?self.selection_path, "Reloaded selection");
         self.selection = selection;
+        self.missing_projects = missing_projects;
         Ok(())
     }
 
-    /// Save the current selection to the configured `selection_path`.
+    /// Save the current selection to the configured `selection_path`, first recording the stored selection in the
+    /// history. A save that leaves the selection unchanged adds no history entry.
     pub fn save(&self) -> Result<()> {
-        let selection = self.selection.clone();
-        let persisted_selection = PersistedSelection::from(&selection);
+        let persisted_selection = self.persisted_selection();
+        let stored_selection = load_model::<PersistedSelection>(&self.selection_path).ok();
+        if stored_selection.as_ref() != Some(&persisted_selection) {
+            self.history
+                .record(&self.selection_path)
+                .context("Recording the selection history")?;
+        }
         store_model(&self.selection_path, &persisted_selection)?;
         debug!(?persisted_selection, path = ?self.selection_path, "Saved selection");
         Ok(())
     }
 
+    /// The selection as it is stored, including projects that are no longer defined.
+    fn persisted_selection(&self) -> PersistedSelection {
+        let mut persisted_selection = PersistedSelection::from(&self.selection);
+        persisted_selection
+            .projects
+            .extend(self.missing_projects.iter().cloned());
+        persisted_selection
+    }
+
+    /// Write a copy of the selection in canonical form to `path`, typically a file tracked in the repository so that a
+    /// team can share it. The file is left alone if it already holds this selection. Returns whether it was written.
+    pub fn write_mirror(&self, path: &Path) -> Result<bool> {
+        if path.is_file() && !self.differs_from_mirror(path)? {
+            return Ok(false);
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Creating {}", parent.display()))?;
+        }
+        store_model(path, &self.persisted_selection())
+            .with_context(|| format!("Writing the selection mirror {}", path.display()))?;
+        debug!(path = ?path, "Mirrored selection");
+        Ok(true)
+    }
+
+    /// Whether `path` holds a mirrored selection that differs from this one. A missing mirror does not differ.
+    pub fn differs_from_mirror(&self, path: &Path) -> Result<bool> {
+        if !path.is_file() {
+            return Ok(false);
+        }
+        let mirrored: PersistedSelection = load_model(path)
+            .with_context(|| format!("Loading the selection mirror {}", path.display()))?;
+        Ok(mirrored != self.persisted_selection())
+    }
+
+    /// Replace the selection with the one mirrored in `path`. Call `save` to store the result.
+    pub fn adopt_mirror(&mut self, path: &Path) -> Result<()> {
+        let (selection, missing_projects) =
+            Self::load(path, &self.project_catalog.optional_projects)
+                .with_context(|| format!("Loading the selection mirror {}", path.display()))?;
+        self.selection = selection;
+        self.missing_projects = missing_projects;
+        Ok(())
+    }
+
+    /// Rewrite the selection file in canonical form: sorted, without duplicates, and with targets normalized. The
+    /// selection itself is unchanged. Returns whether the file's content changed.
+    pub fn compact(&self) -> Result<bool> {
+        let original_content = std::fs::read(&self.selection_path).ok();
+        self.save().context("Saving the compacted selection")?;
+        let compacted_content = std::fs::read(&self.selection_path)
+            .with_context(|| format!("Reading {}", self.selection_path.display()))?;
+        Ok(original_content.as_deref() != Some(compacted_content.as_slice()))
+    }
+
+    /// Replace the project named `from` with the project named `to` in the selection. `from` need not be defined any
+    /// longer, which is the usual case after a project is renamed. Call `save` to store the result.
+    pub fn rename_project(&mut self, from: &str, to: &str) -> Result<()> {
+        let replacement = match self.project_catalog.optional_projects.resolve(to) {
+            Some(project) => project.clone(),
+            None => bail!("Project '{}' is not defined", to),
+        };
+        if !self.missing_projects.remove(from) {
+            // A project selected under a name that is now an alias was loaded under its current name.
+            let selected = self
+                .selection
+                .projects
+                .iter()
+                .find(|project| project.name == from || project.aliases.contains(from))
+                .cloned();
+            match selected {
+                Some(project) => {
+                    self.selection.projects.remove(&project);
+                }
+                None => bail!("Project '{}' is not in the selection", from),
+            }
+        }
+        self.selection.projects.insert(replacement);
+        Ok(())
+    }
+
+    /// Previous versions of the stored selection, oldest first.
+    pub fn history(&self) -> Result<Vec<SelectionHistoryEntry>> {
+        self.history
+            .entries()?
+            .into_iter()
+            .map(|path| {
+                let persisted_selection: PersistedSelection =
+                    load_model(&path).with_context(|| {
+                        format!("Loading the selection history entry {}", path.display())
+                    })?;
+                let recorded_at = std::fs::metadata(&path)
+                    .and_then(|metadata| metadata.modified())
+                    .with_context(|| format!("Reading the metadata of {}", path.display()))?;
+                Ok(SelectionHistoryEntry {
+                    serial: SelectionHistory::serial_of(&path).unwrap_or_default(),
+                    recorded_at,
+                    projects: persisted_selection.projects,
+                    targets: persisted_selection.targets,
+                    path,
+                })
+            })
+            .collect()
+    }
+
+    /// Replace the stored selection with the one recorded in the history `entry`. The replaced selection is not
+    /// recorded in the history; call `discard_history_entry` once the restored selection has been applied.
+    pub fn restore(&mut self, entry: &SelectionHistoryEntry) -> Result<()> {
+        let (selection, missing_projects) =
+            Self::load(&entry.path, &self.project_catalog.optional_projects).with_context(
+                || {
+                    format!(
+                        "Loading the selection history entry {}",
+                        entry.path.display()
+                    )
+                },
+            )?;
+        self.selection = selection;
+        self.missing_projects = missing_projects;
+        let persisted_selection = self.persisted_selection();
+        store_model(&self.selection_path, &persisted_selection)?;
+        debug!(?persisted_selection, serial = entry.serial, path = ?self.selection_path, "Restored selection");
+        Ok(())
+    }
+
+    /// Remove `entry` from the history.
+    pub fn discard_history_entry(&self, entry: &SelectionHistoryEntry) -> Result<()> {
+        self.history.remove(&entry.path)
+    }
+
     /// Returns a back up of the selection file.
     pub fn create_backup(&self) -> Result<BackedUpFile> {
         BackedUpFile::new(&self.selection_path)
@@ -283,10 +516,81 @@ impl SelectionManager {
             projects.into_iter().collect(),
             optional_projects,
         )?);
+        self.selection.apply_overrides(&mut target_set);
 
         Ok(target_set)
     }
 
+    /// Compute the complete target set of the stored selection using the projects defined at `commit_id` instead of
+    /// those in the working tree. Selected projects that are not defined at that commit are skipped with a warning.
+    pub fn compute_complete_target_set_at(
+        &self,
+        repo: &Repo,
+        commit_id: git2::Oid,
+    ) -> Result<TargetSet> {
+        let catalog = ProjectCatalog::at_commit(repo.underlying(), commit_id)?;
+        let optional_projects = &catalog.optional_projects.underlying;
+        let mut projects: Vec<Project> = catalog
+            .mandatory_projects
+            .underlying
+            .values()
+            .cloned()
+            .collect();
+        let selected_names = self
+            .selection
+            .projects
+            .iter()
+            .map(|project| &project.name)
+            .chain(self.missing_projects.iter());
+        for name in selected_names {
+            match optional_projects.get(name) {
+                Some(project) => projects.push(project.clone()),
+                None => {
+                    warn!(%name, %commit_id, "Skipping a selected project that is not defined at the commit")
+                }
+            }
+        }
+
+        let mut target_set = self.selection.targets.clone();
+        target_set.extend(resolve_targets_for_project(projects, optional_projects)?);
+        self.selection.apply_overrides(&mut target_set);
+        Ok(target_set)
+    }
+
+    /// Attribute the complete target set to the selected and mandatory projects, sorted by name. Targets selected
+    /// individually are not included.
+    pub fn expansion_report(&self) -> Result<Vec<ProjectExpansion>> {
+        let optional_projects = &self.project_catalog().optional_projects.underlying;
+        let mut expansions = self
+            .computed_selection()?
+            .projects
+            .iter()
+            .map(|project| {
+                ProjectExpansion::of(project, optional_projects)
+                    .with_context(|| format!("Expanding project {}", project.name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        expansions.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        Ok(expansions)
+    }
+
+    /// Returns the names of selected projects that are no longer defined in the repository, e.g. because their
+    /// definitions were deleted upstream.
+    pub fn missing_projects(&self) -> &BTreeSet<String> {
+        &self.missing_projects
+    }
+
+    /// Replace `from` with `to` wherever the selection resolves to `from`. Returns whether the overrides changed. Call
+    /// `save` to store the result.
+    pub fn set_override(&mut self, from: Target, to: Target) -> bool {
+        self.selection.overrides.insert(from, to.clone()) != Some(to)
+    }
+
+    /// Stop overriding `from`. Returns whether it was overridden. Call `save` to store the result.
+    pub fn remove_override(&mut self, from: &Target) -> bool {
+        self.selection.overrides.remove(from).is_some()
+    }
+
     /// Returns user-selected projects and targets.
     pub fn selection(&self) -> Result<Selection> {
         Ok(self.selection.clone())
@@ -297,22 +601,51 @@ impl SelectionManager {
         action: OperationAction,
         projects_and_targets: &[String],
     ) -> Result<bool> {
+        Ok(self
+            .mutate_with_result(action, projects_and_targets)?
+            .changed())
+    }
+
+    /// Like `mutate`, but returns the projects and targets that were added to or removed from the selection.
+    pub fn mutate_with_result(
+        &mut self,
+        action: OperationAction,
+        projects_and_targets: &[String],
+    ) -> Result<OperationResult> {
         let operations = projects_and_targets
             .iter()
             .map(|value| Operation::new(action, value.clone()))
             .collect::<Vec<Operation>>();
-        let result = self
-            .process(&operations)
-            .context("Processing updates to the selection")?;
-        Ok(result.changed())
+        self.process(&operations)
+            .context("Processing updates to the selection")
     }
 
     pub fn process(&mut self, operations: &[Operation]) -> Result<OperationResult> {
+        // Projects that are no longer defined can only be removed, which the processor does not know about.
+        let mut missing_projects = self.missing_projects.clone();
+        let mut removed_missing_projects = HashSet::new();
+        let operations: Vec<Operation> = operations
+            .iter()
+            .filter_map(
+                |operation| match (&operation.action, &operation.underlying) {
+                    (OperationAction::Remove(_), Underlying::Project(name))
+                        if missing_projects.remove(name) =>
+                    {
+                        removed_missing_projects.insert(operation.underlying.clone());
+                        None
+                    }
+                    _ => Some(operation.clone()),
+                },
+            )
+            .collect();
+
         let mut selection = self.selection.clone();
-        let result =
-            selection.apply_operations(operations, &self.project_catalog.optional_projects)?;
+        let mut result =
+            selection.apply_operations(&operations, &self.project_catalog.optional_projects)?;
+        result.removed.extend(removed_missing_projects);
         if result.is_success() {
             self.selection = selection;
+            self.missing_projects = missing_projects;
         } else {
             error!("The selection will not be updated because an error occured while applying the requested changes");
         }
@@ -329,11 +662,29 @@ impl SelectionManager {
     }
 }
 
-/// A structure to store the names of selected projects and targets. Converted from the fully-featured in-memory representation Selection.
+/// A previous version of the stored selection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SelectionHistoryEntry {
+    /// Position of the entry in the history; later entries have larger serials.
+    pub serial: u64,
+    /// When the entry was recorded.
+    pub recorded_at: SystemTime,
+    /// Names of the selected projects.
+    pub projects: BTreeSet<String>,
+    /// The selected targets.
+    pub targets: BTreeSet<String>,
+    path: PathBuf,
+}
+
+/// A structure to store the names of selected projects and targets. Converted from the fully-featured in-memory representation Selection. Names are kept sorted so that the stored file is stable.
 #[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 struct PersistedSelection {
-    pub projects: HashSet<String>,
-    pub targets: HashSet<String>,
+    pub projects: BTreeSet<String>,
+    pub targets: BTreeSet<String>,
+
+    /// Maps overridden coordinates to their replacements. Omitted when empty.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub overrides: BTreeMap<String, String>,
 }
 
 impl From<&Selection> for PersistedSelection {
@@ -343,16 +694,37 @@ impl From<&Selection> for PersistedSelection {
             .iter()
             .filter(|&project| project.is_selectable())
             .map(|project| project.name.clone())
-            .collect::<HashSet<String>>();
+            .collect::<BTreeSet<String>>();
         let targets = selection
             .targets
             .iter()
             .map(String::from)
-            .collect::<HashSet<String>>();
-        Self { projects, targets }
+            .collect::<BTreeSet<String>>();
+        let overrides = selection
+            .overrides
+            .iter()
+            .map(|(from, to)| (String::from(from), String::from(to)))
+            .collect();
+        Self {
+            projects,
+            targets,
+            overrides,
+        }
     }
 }
 
+fn parse_overrides(overrides: &BTreeMap<String, String>) -> Result<BTreeMap<Target, Target>> {
+    overrides
+        .iter()
+        .map(|(from, to)| {
+            let parse = |repr: &str| {
+                Target::try_from(repr).with_context(|| format!("Interpreting override '{}'", repr))
+            };
+            Ok((parse(from)?, parse(to)?))
+        })
+        .collect()
+}
+
 impl TryFrom<PersistedSelection> for Vec<Operation> {
     type Error = anyhow::Error;
 
@@ -391,7 +763,7 @@ impl TryFrom<PersistedSelection> for Vec<Operation> {
 mod testing {
     use super::*;
     use anyhow::Result;
-    use maplit::{btreeset, hashset};
+    use maplit::{btreemap, btreeset, hashset};
 
     const PROJECT_NAME_STR: &str = "a_project";
     const PROJECT_NAME_STR_2: &str = "b_project";
@@ -404,6 +776,9 @@ mod testing {
             mandatory: false,
             targets: btreeset![String::from("bazel://a:b"),],
             projects: btreeset![String::from(PROJECT_NAME_STR_2)],
+            deprecated: None,
+            replaced_by: None,
+            aliases: btreeset![],
         }
     }
 
@@ -415,6 +790,7 @@ mod testing {
         Selection {
             projects: hashset! {project()},
             targets: hashset! {target()},
+            overrides: Default::default(),
         }
     }
 
@@ -424,16 +800,56 @@ mod testing {
         let persisted_selection = PersistedSelection::from(&selection);
         assert_eq!(
             persisted_selection.projects,
-            hashset! {PROJECT_NAME_STR.to_owned()}
+            btreeset! {PROJECT_NAME_STR.to_owned()}
         );
         assert_eq!(
             persisted_selection.targets,
-            hashset! {TARGET_STR.to_owned()}
+            btreeset! {TARGET_STR.to_owned()}
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn overrides_are_persisted_and_applied() -> Result<()> {
+        let local_target = Target::try_from("bazel://c:d_local")?;
+        let mut selection = selection();
+        selection.overrides.insert(target(), local_target.clone());
+
+        let persisted_selection = PersistedSelection::from(&selection);
+        assert_eq!(
+            persisted_selection.overrides,
+            btreemap! {TARGET_STR.to_owned() => String::from("bazel://c:d_local")}
+        );
+        assert_eq!(
+            parse_overrides(&persisted_selection.overrides)?,
+            selection.overrides
         );
 
+        let mut target_set = hashset! {target(), Target::try_from("bazel://a:b")?};
+        selection.apply_overrides(&mut target_set);
+        assert_eq!(
+            target_set,
+            hashset! {local_target, Target::try_from("bazel://a:b")?}
+        );
+
+        assert!(
+            !serde_json::to_string(&PersistedSelection::from(&Selection::default()))?
+                .contains("overrides")
+        );
         Ok(())
     }
 
+    #[test]
+    fn selection_names_are_validated() {
+        assert!(validate_selection_name("default").is_ok());
+        assert!(validate_selection_name("release-2.1_x").is_ok());
+        assert!(validate_selection_name("").is_err());
+        assert!(validate_selection_name(".hidden").is_err());
+        assert!(validate_selection_name("../escape").is_err());
+        assert!(validate_selection_name("with space").is_err());
+    }
+
     #[test]
     fn operation_vec_try_from() -> Result<()> {
         let selection = selection();
@@ -191,3 +191,47 @@ fn duplicate_projects_are_ignored() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn saving_records_changes_in_the_history() -> Result<()> {
+    init_logging();
+
+    let fixture = Fixture::new()?;
+    let repo = fixture.repo()?;
+
+    let project_a = Underlying::Project(String::from("team_banzai/project_a"));
+    let project_b = Underlying::Project(String::from("team_zissou/project_b"));
+
+    let mut selection_manager = repo.selection_manager()?;
+    for underlying in [project_a, project_b] {
+        selection_manager.process(&[Operation {
+            action: OperationAction::default_add(),
+            underlying,
+        }])?;
+        selection_manager.save()?;
+    }
+    // The first save had no stored selection to record.
+    assert_eq!(selection_manager.history()?.len(), 1);
+
+    // Saving an unchanged selection does not add an entry.
+    selection_manager.save()?;
+    let history = selection_manager.history()?;
+    assert_eq!(history.len(), 1);
+    let entry = &history[0];
+    assert_eq!(
+        entry.projects,
+        maplit::btreeset! {String::from("team_banzai/project_a")}
+    );
+
+    // Restoring the entry brings back the previous selection without recording the replaced one.
+    selection_manager.restore(entry)?;
+    selection_manager.discard_history_entry(entry)?;
+    assert!(selection_manager.history()?.is_empty());
+    let selection_manager = repo.selection_manager()?;
+    assert_eq!(
+        project_names(&selection_manager.computed_selection()?),
+        hashset! {String::from("mandatory"), String::from("team_banzai/project_a")}
+    );
+
+    Ok(())
+}
@@ -0,0 +1,217 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{Context, Result};
+use git2::{ObjectType, Tree, TreeWalkMode, TreeWalkResult};
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use super::repo::WorkingTree;
+
+/// A mismatch between the sparse checkout patterns and the contents of the working tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Discrepancy {
+    /// A directory included by the patterns is missing from the working tree.
+    MissingIncludedDirectory(PathBuf),
+
+    /// A directory excluded by the patterns has files checked out in the working tree.
+    PresentExcludedDirectory(PathBuf),
+}
+
+impl Display for Discrepancy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Discrepancy::MissingIncludedDirectory(path) => {
+                write!(f, "{} is included but missing", path.display())
+            }
+            Discrepancy::PresentExcludedDirectory(path) => {
+                write!(f, "{} is excluded but checked out", path.display())
+            }
+        }
+    }
+}
+
+/// Directories named by a cone-mode sparse checkout file.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct ConePatterns {
+    /// Directories whose contents are included.
+    included: BTreeSet<PathBuf>,
+
+    /// Included directories whose subdirectories are excluded unless included themselves. The empty path is the root.
+    shallow: BTreeSet<PathBuf>,
+}
+
+impl ConePatterns {
+    fn parse(content: &str) -> Self {
+        let mut patterns = Self::default();
+        for line in content.lines().map(str::trim) {
+            if let Some(negated) = line.strip_prefix('!') {
+                if let Some(dir) = negated.strip_suffix("*/") {
+                    patterns
+                        .shallow
+                        .insert(PathBuf::from(dir.trim_matches('/')));
+                }
+            } else if line.len() > 1 && line.starts_with('/') && line.ends_with('/') {
+                patterns
+                    .included
+                    .insert(PathBuf::from(line.trim_matches('/')));
+            }
+        }
+        patterns
+    }
+
+    /// Whether `path` is included or is an ancestor of an included directory.
+    fn is_included(&self, path: &Path) -> bool {
+        self.included.iter().any(|included| {
+            included.starts_with(path)
+                || (path.starts_with(included) && !self.shallow.contains(included))
+        })
+    }
+}
+
+/// Check a sample of up to `sample_size` included and excluded directories against the working tree, returning any
+/// discrepancies found.
+pub fn verify_working_tree(
+    working_tree: &WorkingTree,
+    sample_size: usize,
+) -> Result<Vec<Discrepancy>> {
+    let sparse_checkout_path = working_tree.sparse_checkout_path();
+    let content = std::fs::read_to_string(&sparse_checkout_path)
+        .with_context(|| format!("Reading {}", sparse_checkout_path.display()))?;
+    let patterns = ConePatterns::parse(&content);
+    let head_tree = working_tree
+        .get_head_commit()?
+        .tree()
+        .context("Resolving the HEAD tree")?;
+    let repo = working_tree.git_repo();
+    let work_dir = working_tree.work_dir();
+
+    let subtree = |path: &Path| -> Result<Option<Tree>> {
+        if path.as_os_str().is_empty() {
+            return Ok(Some(head_tree.clone()));
+        }
+        match head_tree.get_path(path) {
+            Ok(entry) if entry.kind() == Some(ObjectType::Tree) => {
+                Ok(Some(entry.to_object(repo)?.peel_to_tree()?))
+            }
+            _ => Ok(None),
+        }
+    };
+
+    // Included directories are checked for their first committed file. Only files directly inside shallow
+    // directories are checked out.
+    let mut expected_present = Vec::new();
+    for dir in patterns.included.iter() {
+        if let Some(tree) = subtree(dir)? {
+            let recursive = !patterns.shallow.contains(dir);
+            expected_present.push((dir.clone(), tree, recursive));
+        }
+    }
+
+    // Subdirectories of shallow directories that are not themselves included should be absent.
+    let mut expected_absent = Vec::new();
+    for dir in patterns.shallow.iter() {
+        if let Some(tree) = subtree(dir)? {
+            for entry in tree.iter() {
+                if entry.kind() != Some(ObjectType::Tree) {
+                    continue;
+                }
+                let child = dir.join(String::from_utf8_lossy(entry.name_bytes()).as_ref());
+                if !patterns.is_included(&child) {
+                    expected_absent.push((child, entry.to_object(repo)?.peel_to_tree()?));
+                }
+            }
+        }
+    }
+
+    let mut discrepancies = Vec::new();
+    for (dir, tree, recursive) in sample(&expected_present, sample_size) {
+        let present = match first_file(tree, *recursive)? {
+            Some(file) => work_dir.join(dir).join(file).exists(),
+            None => work_dir.join(dir).is_dir(),
+        };
+        if !present {
+            discrepancies.push(Discrepancy::MissingIncludedDirectory(dir.clone()));
+        }
+    }
+    for (dir, tree) in sample(&expected_absent, sample_size) {
+        if let Some(file) = first_file(tree, true)? {
+            if work_dir.join(dir).join(file).exists() {
+                discrepancies.push(Discrepancy::PresentExcludedDirectory(dir.clone()));
+            }
+        }
+    }
+
+    Ok(discrepancies)
+}
+
+/// The path of the first file in the tree (or one of its subtrees if `recursive` is set), relative to it.
+fn first_file(tree: &Tree, recursive: bool) -> Result<Option<PathBuf>> {
+    let mut found = None;
+    tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+        if entry.kind() == Some(ObjectType::Blob) {
+            found = Some(
+                PathBuf::from(root).join(String::from_utf8_lossy(entry.name_bytes()).as_ref()),
+            );
+            TreeWalkResult::Abort
+        } else if recursive {
+            TreeWalkResult::Ok
+        } else {
+            TreeWalkResult::Skip
+        }
+    })
+    .or_else(|e| if found.is_some() { Ok(()) } else { Err(e) })
+    .context("Walking tree")?;
+    Ok(found)
+}
+
+/// Pick up to `size` items spread evenly across `items`.
+fn sample<T>(items: &[T], size: usize) -> impl Iterator<Item = &T> {
+    let stride = if size == 0 {
+        usize::MAX
+    } else {
+        ((items.len() + size - 1) / size).max(1)
+    };
+    items.iter().step_by(stride).take(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing_cone_patterns() {
+        let patterns = ConePatterns::parse("/*\n!/*/\n/focus/\n/mandatory_y/\n!/mandatory_y/*/\n/mandatory_y/very_important_info/\n");
+        assert_eq!(
+            patterns.included,
+            BTreeSet::from([
+                PathBuf::from("focus"),
+                PathBuf::from("mandatory_y"),
+                PathBuf::from("mandatory_y/very_important_info"),
+            ])
+        );
+        assert_eq!(
+            patterns.shallow,
+            BTreeSet::from([PathBuf::from(""), PathBuf::from("mandatory_y")])
+        );
+
+        assert!(patterns.is_included(Path::new("focus/projects")));
+        assert!(patterns.is_included(Path::new("mandatory_y/very_important_info")));
+        assert!(!patterns.is_included(Path::new("mandatory_y/other")));
+        assert!(!patterns.is_included(Path::new("library_a")));
+    }
+
+    #[test]
+    fn sampling_spreads_evenly() {
+        let items: Vec<usize> = (0..10).collect();
+        assert_eq!(
+            sample(&items, 5).copied().collect::<Vec<_>>(),
+            vec![0, 2, 4, 6, 8]
+        );
+        assert_eq!(sample(&items, 20).count(), 10);
+        assert_eq!(sample(&items, 0).count(), 0);
+    }
+}
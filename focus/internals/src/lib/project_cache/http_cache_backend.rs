@@ -13,12 +13,17 @@ use super::{remote::RequestOptions, *};
 pub struct HttpCacheBackend {
     endpoint: Url,
     client: Client,
+    app: Arc<App>,
 }
 
 impl HttpCacheBackend {
-    pub fn new(endpoint: Url) -> Result<Self> {
+    pub fn new(endpoint: Url, app: Arc<App>) -> Result<Self> {
         let client = Self::blocking_client().context("Creating HTTP client failed")?;
-        Ok(Self { endpoint, client })
+        Ok(Self {
+            endpoint,
+            client,
+            app,
+        })
     }
 
     fn blocking_client() -> Result<Client> {
@@ -40,6 +45,7 @@ impl ProjectCacheBackend for HttpCacheBackend {
 
     // Fetch a model from the given URL and decode it from its JSON representation.
     fn load_model(&self, url: Url) -> Result<Vec<u8>> {
+        focus_util::offline::ensure_online(&self.app, "fetch from the project cache")?;
         let span = tracing::info_span!("Fetching");
         let _guard = span.enter();
         tracing::debug!(url = ?url.as_str(), "GET");
@@ -61,6 +67,7 @@ impl ProjectCacheBackend for HttpCacheBackend {
 
     // Encode the given model as JSON and upload it using HTTP PUT to the given URL.
     fn store(&self, url: Url, options: &RequestOptions, value: Vec<u8>) -> Result<()> {
+        focus_util::offline::ensure_online(&self.app, "upload to the project cache")?;
         // TODO: Add an ETag to skip upload if the content is identical.
         let span = tracing::info_span!("Putting");
         let _guard = span.enter();
@@ -83,7 +83,7 @@ impl<'cache> ProjectCache<'cache> {
             debug!(?database_path, "Database is open");
             result
         };
-        let backend = Self::make_backend(&endpoint)?;
+        let backend = Self::make_backend(&endpoint, app.clone())?;
         Ok(Self {
             app,
             repo,
@@ -552,11 +552,14 @@ impl<'cache> ProjectCache<'cache> {
         Ok(self.database.get_pinned(&key_str)?.is_some())
     }
 
-    pub fn make_backend(endpoint: &Url) -> anyhow::Result<Box<dyn ProjectCacheBackend>> {
+    pub fn make_backend(
+        endpoint: &Url,
+        app: Arc<App>,
+    ) -> anyhow::Result<Box<dyn ProjectCacheBackend>> {
         if endpoint.scheme().eq_ignore_ascii_case("file") {
             Ok(Box::new(LocalCacheBackend::new(endpoint.clone())?))
         } else {
-            Ok(Box::new(HttpCacheBackend::new(endpoint.clone())?))
+            Ok(Box::new(HttpCacheBackend::new(endpoint.clone(), app)?))
         }
     }
 }
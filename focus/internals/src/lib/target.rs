@@ -4,6 +4,7 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::{collections::HashSet, convert::TryFrom, fmt::Display};
 
@@ -15,6 +16,7 @@ pub type TargetSet = HashSet<Target>;
 pub enum TargetTypes {
     Bazel,
     Directory,
+    File,
     Pants,
 }
 
@@ -25,6 +27,10 @@ pub enum Target {
 
     /// A specific directory within the repository.
     Directory(String),
+
+    /// A single file within the repository. It is included by an exact-match pattern rather than resolved, so selecting
+    /// files requires a pattern-mode (non-cone) sparse checkout.
+    File(String),
 }
 
 impl Display for Target {
@@ -32,6 +38,51 @@ impl Display for Target {
         match self {
             Target::Bazel(c) => write!(f, "bazel:{}", c),
             Target::Directory(c) => write!(f, "directory:{}", c),
+            Target::File(c) => write!(f, "file:{}", c),
+        }
+    }
+}
+
+impl Target {
+    /// Whether a change to the build-graph-relevant file at `path` (relative to the repository root) could change what
+    /// this target resolves to. This only considers the packages a target names directly, not their dependencies, so
+    /// it is a hint rather than a guarantee.
+    pub fn may_be_affected_by(&self, path: &Path) -> bool {
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        match self {
+            Target::Bazel(label) if label.external_repository.is_some() => false,
+            Target::Bazel(label) => {
+                let package: PathBuf = label.path_components.iter().collect();
+                match label.target_name {
+                    TargetName::Ellipsis => dir.starts_with(&package),
+                    TargetName::Name(_) => dir == package,
+                }
+            }
+            Target::Directory(directory) => path.starts_with(directory),
+            Target::File(_) => false,
+        }
+    }
+
+    /// Whether selecting this target also selects `other`: they are the same target, this is a directory containing
+    /// `other`, or this is a recursive Bazel pattern (like `//a/...`) whose packages include `other`'s.
+    pub fn covers(&self, other: &Target) -> bool {
+        if self == other {
+            return true;
+        }
+        match (self, other) {
+            (Target::Directory(directory), Target::Directory(path) | Target::File(path)) => {
+                Path::new(path).starts_with(directory)
+            }
+            (Target::Directory(directory), Target::Bazel(label)) => label
+                .package_directory()
+                .map(|package| package.starts_with(directory))
+                .unwrap_or(false),
+            (Target::Bazel(pattern), Target::Bazel(label)) => {
+                pattern.target_name == TargetName::Ellipsis
+                    && pattern.external_repository == label.external_repository
+                    && label.path_components.starts_with(&pattern.path_components)
+            }
+            _ => false,
         }
     }
 }
@@ -60,6 +111,8 @@ impl TryFrom<&str> for Target {
                     Ok(Target::Bazel(label))
                 } else if prefix.eq_ignore_ascii_case("directory") {
                     Ok(Target::Directory(rest))
+                } else if prefix.eq_ignore_ascii_case("file") {
+                    Ok(Target::File(rest))
                 } else {
                     Err(TargetError::UnsupportedScheme(prefix.to_owned()))
                 }
@@ -74,6 +127,7 @@ impl From<&Target> for String {
         match val {
             Target::Bazel(spec) => format!("bazel:{}", spec),
             Target::Directory(spec) => format!("directory:{}", spec),
+            Target::File(spec) => format!("file:{}", spec),
         }
     }
 }
@@ -134,6 +188,17 @@ impl Display for Label {
     }
 }
 
+impl Label {
+    /// The directory of the package this label refers to, relative to the repository root, or `None` if the label is
+    /// in an external repository.
+    pub fn package_directory(&self) -> Option<PathBuf> {
+        match self.external_repository {
+            Some(_) => None,
+            None => Some(self.path_components.iter().collect()),
+        }
+    }
+}
+
 impl Debug for Label {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let label_string: String = format!("{}", self);
@@ -149,6 +214,9 @@ pub enum LabelParseError {
 
     #[error("Empty label")]
     EmptyLabel,
+
+    #[error("Label contains both kinds of quote and cannot be used in a query: {0}")]
+    UnquotableQueryWord(String),
 }
 
 impl FromStr for Label {
@@ -195,6 +263,93 @@ impl FromStr for Label {
     }
 }
 
+/// Quote `word` so that the Bazel query lexer reads it as a single word. Words made only of letters, digits, and
+/// `*/@.-_:$~[]` are left as they are unless they begin with `-` or `*`; anything else is wrapped in whichever kind of
+/// quote it does not contain. The query language has no escape sequences, so a word containing both kinds of quote
+/// cannot be expressed.
+pub fn quote_bazel_query_word(word: &str) -> Result<String, LabelParseError> {
+    let is_plain = !word.is_empty()
+        && !word.starts_with(['-', '*'])
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "*/@.-_:$~[]".contains(c));
+    if is_plain {
+        Ok(word.to_string())
+    } else if !word.contains('\'') {
+        Ok(format!("'{}'", word))
+    } else if !word.contains('"') {
+        Ok(format!("\"{}\"", word))
+    } else {
+        Err(LabelParseError::UnquotableQueryWord(word.to_string()))
+    }
+}
+
+/// A Bazel query expression for the union of `labels`, sorted so that the output is stable.
+pub fn bazel_query_union<'a>(
+    labels: impl IntoIterator<Item = &'a Label>,
+) -> Result<String, LabelParseError> {
+    let mut words = labels
+        .into_iter()
+        .map(|label| quote_bazel_query_word(&label.to_string()))
+        .collect::<Result<Vec<String>, LabelParseError>>()?;
+    words.sort_unstable();
+    words.dedup();
+    Ok(words.join(" + "))
+}
+
+/// Why a rule kind or scope given to select targets by kind was rejected.
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum KindQueryError {
+    #[error("Invalid rule kind {0:?}: expected a rule class name such as scala_test")]
+    InvalidKind(String),
+
+    #[error("Invalid scope {0:?}: expected a package path such as //search")]
+    InvalidScope(String),
+}
+
+/// Normalize a scope given as `search`, `//search`, or `//search/...` to the package path `search`, rejecting anything
+/// that is not a plain path within the main repository.
+pub fn normalize_kind_query_scope(scope: &str) -> Result<String, KindQueryError> {
+    let invalid = || KindQueryError::InvalidScope(scope.to_owned());
+    let path = scope.trim();
+    let path = path.strip_prefix("//").unwrap_or(path);
+    let path = path.strip_suffix("...").unwrap_or(path);
+    let path = path.trim_end_matches('/');
+    if path.is_empty() {
+        return Ok(String::new());
+    }
+    for component in path.split('/') {
+        let valid = !component.is_empty()
+            && component != "."
+            && component != ".."
+            && component
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_-.+=,@~".contains(c));
+        if !valid {
+            return Err(invalid());
+        }
+    }
+    Ok(path.to_owned())
+}
+
+/// A Bazel query expression for the rules of exactly `kind` (e.g. `scala_test`) in the package path `scope` and the
+/// packages beneath it. `scope` is normalized with `normalize_kind_query_scope`.
+pub fn kind_query(kind: &str, scope: &str) -> Result<String, KindQueryError> {
+    let mut chars = kind.chars();
+    let valid_kind = matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid_kind {
+        return Err(KindQueryError::InvalidKind(kind.to_owned()));
+    }
+    let scope = normalize_kind_query_scope(scope)?;
+    let pattern = if scope.is_empty() {
+        String::from("//...")
+    } else {
+        format!("//{}/...", scope)
+    };
+    Ok(format!("kind('^{} rule$', {})", kind, pattern))
+}
+
 #[cfg(test)]
 mod tests {
     use std::convert::TryFrom;
@@ -213,6 +368,14 @@ mod tests {
                 target_name: TargetName::Name("b".to_string()),
             })
         );
+        assert_eq!(
+            Target::try_from("file:a/b.txt")?,
+            Target::File("a/b.txt".to_string())
+        );
+        assert_eq!(
+            Target::File("a/b.txt".to_string()).to_string(),
+            "file:a/b.txt"
+        );
         assert_eq!(
             Target::try_from("bazel://foo"),
             Ok(Target::Bazel(Label {
@@ -273,4 +436,104 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn targets_affected_by_changed_paths() -> Result<()> {
+        let recursive = Target::try_from("bazel://a/...")?;
+        let named = Target::try_from("bazel://a/b:c")?;
+        let directory = Target::try_from("directory:a/b")?;
+        let external = Target::try_from("bazel:@other//a/...")?;
+
+        let path = Path::new("a/b/BUILD");
+        assert!(recursive.may_be_affected_by(path));
+        assert!(named.may_be_affected_by(path));
+        assert!(directory.may_be_affected_by(path));
+        assert!(!external.may_be_affected_by(path));
+
+        let path = Path::new("a/BUILD");
+        assert!(recursive.may_be_affected_by(path));
+        assert!(!named.may_be_affected_by(path));
+        assert!(!directory.may_be_affected_by(path));
+
+        assert!(!recursive.may_be_affected_by(Path::new("z/BUILD")));
+        Ok(())
+    }
+
+    #[test]
+    fn quoting_labels_for_bazel_query() -> Result<()> {
+        let labels = vec![
+            Label::from_str("//b/c:d")?,
+            Label::from_str("@ext//a/...")?,
+            Label::from_str("//a:file with spaces")?,
+            Label::from_str("//a:it's")?,
+            Label::from_str("//b/c:d")?,
+        ];
+        assert_eq!(
+            bazel_query_union(labels.iter())?,
+            r#""//a:it's" + '//a:file with spaces' + //b/c:d + @ext//a/..."#
+        );
+
+        assert_eq!(quote_bazel_query_word("-//a:b")?, "'-//a:b'");
+        assert_eq!(
+            quote_bazel_query_word(r#"//a:"it's""#),
+            Err(LabelParseError::UnquotableQueryWord(
+                r#"//a:"it's""#.to_string()
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn kind_queries() -> Result<()> {
+        assert_eq!(
+            kind_query("scala_test", "//search/...")?,
+            "kind('^scala_test rule$', //search/...)"
+        );
+        assert_eq!(
+            kind_query("java_library", "search/core/")?,
+            "kind('^java_library rule$', //search/core/...)"
+        );
+        assert_eq!(
+            kind_query("sh_test", "//")?,
+            "kind('^sh_test rule$', //...)"
+        );
+
+        assert_eq!(
+            kind_query("scala_test)", "search"),
+            Err(KindQueryError::InvalidKind(String::from("scala_test)")))
+        );
+        assert!(kind_query("", "search").is_err());
+        assert!(kind_query("1test", "search").is_err());
+        assert_eq!(
+            kind_query("scala_test", "search/../etc"),
+            Err(KindQueryError::InvalidScope(String::from("search/../etc")))
+        );
+        assert!(kind_query("scala_test", "search', //other").is_err());
+        assert!(kind_query("scala_test", "@ext//search").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn target_covers() -> Result<()> {
+        let directory = Target::try_from("directory:search/common")?;
+        assert!(directory.covers(&directory));
+        assert!(directory.covers(&Target::try_from("directory:search/common/util")?));
+        assert!(directory.covers(&Target::try_from("file:search/common/BUILD")?));
+        assert!(directory.covers(&Target::try_from("bazel://search/common/util:lib")?));
+        assert!(!directory.covers(&Target::try_from("directory:search/commonplace")?));
+        assert!(!directory.covers(&Target::try_from("bazel:@ext//search/common:lib")?));
+
+        let recursive = Target::try_from("bazel://search/...")?;
+        assert!(recursive.covers(&Target::try_from("bazel://search/common:lib")?));
+        assert!(recursive.covers(&Target::try_from("bazel://search/common/...")?));
+        assert!(!recursive.covers(&Target::try_from("bazel://searchable:lib")?));
+        assert!(!recursive.covers(&Target::try_from("directory:search/common")?));
+
+        let label = Target::try_from("bazel://search/common:lib")?;
+        assert!(!label.covers(&Target::try_from("bazel://search/common:test")?));
+        assert!(!Target::try_from("file:search/BUILD")?.covers(&directory));
+
+        Ok(())
+    }
 }
@@ -1,9 +1,42 @@
 // Copyright 2022 Twitter, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::borrow::Borrow;
+use std::{borrow::Borrow, time::Duration};
 
-use crate::target::Label;
+use focus_util::{
+    app::App,
+    sandbox_command::{SandboxCommand, SandboxCommandOutput},
+};
+use tracing::warn;
+
+use crate::target::{Label, Target, TargetName};
+
+use super::ResolutionRequest;
+
+/// Fragments of Bazel's standard error output indicating a failure that may not recur if the command is run again,
+/// such as the server being restarted or another client holding the output base lock.
+const TRANSIENT_FAILURE_SIGNATURES: &[&str] = &[
+    "Server terminated abruptly",
+    "Server crashed during startup",
+    "could not connect to server",
+    "Another command holds the client lock",
+    "Another command is running",
+    "Connection reset by peer",
+    "Error reading server info",
+];
+
+/// How long to wait before the first retry. The delay doubles after each attempt.
+const INITIAL_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Arguments for `bazel query` that keep it from fetching external repositories in offline mode, so that a query
+/// needing a repository that has not been fetched fails instead of reaching for the network.
+pub fn offline_query_args(app: &App) -> &'static [&'static str] {
+    if app.options().offline {
+        &["--nofetch"]
+    } else {
+        &[]
+    }
+}
 
 pub fn make_set(labels: impl IntoIterator<Item = impl Borrow<Label>>) -> String {
     format!(
@@ -23,3 +56,264 @@ pub fn make_set(labels: impl IntoIterator<Item = impl Borrow<Label>>) -> String
 pub fn quote_target_name(target_name: &str) -> String {
     format!("\"{}\"", target_name)
 }
+
+/// A failed Bazel invocation, classified by whether retrying it might help.
+pub struct QueryFailure {
+    error: anyhow::Error,
+    transient: bool,
+}
+
+impl QueryFailure {
+    /// A failure that will recur if the query is retried, e.g. a syntax error in the query.
+    pub fn permanent(error: anyhow::Error) -> Self {
+        Self {
+            error,
+            transient: false,
+        }
+    }
+
+    /// Classify the failure of `scmd` by inspecting its standard error output.
+    pub fn from_command(error: anyhow::Error, scmd: &SandboxCommand) -> Self {
+        let mut stderr = String::new();
+        let transient = scmd
+            .read_to_string(SandboxCommandOutput::Stderr, &mut stderr)
+            .map(|_| is_transient_failure(&stderr))
+            .unwrap_or(false);
+        Self { error, transient }
+    }
+}
+
+/// Whether Bazel's standard error output indicates a transient failure.
+pub fn is_transient_failure(stderr: &str) -> bool {
+    TRANSIENT_FAILURE_SIGNATURES
+        .iter()
+        .any(|signature| stderr.contains(signature))
+}
+
+/// The warnings and errors in Bazel's standard error output. A query that succeeds can still report these when it
+/// skips targets, leaving them out of the result.
+pub fn query_diagnostics(stderr: &str) -> Vec<&str> {
+    stderr
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with("WARNING:") || line.starts_with("ERROR:"))
+        .collect()
+}
+
+/// Whether a line of Bazel's output names `label` or, for a label covering a package tree, a target within it.
+fn mentions_label(line: &str, label: &Label) -> bool {
+    let (needle, nested) = match label.target_name {
+        TargetName::Name(_) => (label.to_string(), false),
+        TargetName::Ellipsis => (
+            format!(
+                "{}//{}",
+                label.external_repository.as_deref().unwrap_or_default(),
+                label.path_components.join("/")
+            ),
+            true,
+        ),
+    };
+    line.match_indices(&needle).any(|(index, _)| {
+        match line[index + needle.len()..].chars().next() {
+            None | Some('\'') | Some('"') | Some(' ') | Some(',') => true,
+            Some(':') | Some('/') => nested,
+            Some(_) => false,
+        }
+    })
+}
+
+/// The warnings and errors among `diagnostics` that concern the Bazel targets of `request`, meaning Bazel may have
+/// dropped them or their dependencies from the result.
+pub fn diagnostics_concerning<'a>(
+    diagnostics: &[&'a str],
+    request: &ResolutionRequest,
+) -> Vec<&'a str> {
+    let labels: Vec<&Label> = request
+        .targets
+        .iter()
+        .filter_map(|target| match target {
+            Target::Bazel(label) => Some(label),
+            _ => None,
+        })
+        .collect();
+    diagnostics
+        .iter()
+        .copied()
+        .filter(|line| labels.iter().any(|label| mentions_label(line, label)))
+        .collect()
+}
+
+/// With `--strict-resolution`, fail if the query that `scmd` ran for `request` reported warnings or errors concerning
+/// the requested targets. Diagnostics about other targets are logged but accepted.
+pub fn check_strict_resolution(
+    scmd: &SandboxCommand,
+    request: &ResolutionRequest,
+) -> anyhow::Result<()> {
+    let mut stderr = String::new();
+    scmd.read_to_string(SandboxCommandOutput::Stderr, &mut stderr)?;
+    let diagnostics = query_diagnostics(&stderr);
+    let concerning = diagnostics_concerning(&diagnostics, request);
+    if !concerning.is_empty() {
+        anyhow::bail!(
+            "Bazel reported problems resolving the requested targets, so some may have been left out:\n{}",
+            concerning.join("\n")
+        );
+    }
+    if !diagnostics.is_empty() {
+        warn!(
+            ?diagnostics,
+            "Bazel reported problems unrelated to the requested targets"
+        );
+    }
+    Ok(())
+}
+
+/// Run `attempt`, retrying transient failures up to `retries` times with exponential backoff.
+pub fn run_with_retries<T>(
+    retries: usize,
+    attempt: impl FnMut() -> Result<T, QueryFailure>,
+) -> anyhow::Result<T> {
+    run_with_retries_and_backoff(retries, INITIAL_RETRY_BACKOFF, attempt)
+}
+
+fn run_with_retries_and_backoff<T>(
+    retries: usize,
+    initial_backoff: Duration,
+    mut attempt: impl FnMut() -> Result<T, QueryFailure>,
+) -> anyhow::Result<T> {
+    let mut backoff = initial_backoff;
+    let mut attempt_number = 0;
+    loop {
+        attempt_number += 1;
+        match attempt() {
+            Ok(result) => return Ok(result),
+            Err(QueryFailure { error, transient }) => {
+                if !transient || attempt_number > retries {
+                    return Err(error);
+                }
+                warn!(
+                    attempt = attempt_number,
+                    retries,
+                    ?backoff,
+                    ?error,
+                    "Bazel failed transiently; retrying"
+                );
+                std::thread::sleep(backoff);
+                backoff *= 2;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use anyhow::anyhow;
+
+    use super::*;
+
+    fn failing_attempts(
+        failures: usize,
+        transient: bool,
+        attempts: &Cell<usize>,
+    ) -> impl FnMut() -> Result<(), QueryFailure> + '_ {
+        move || {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() <= failures {
+                Err(QueryFailure {
+                    error: anyhow!("attempt {} failed", attempts.get()),
+                    transient,
+                })
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn transient_failures_are_retried() {
+        let attempts = Cell::new(0);
+        assert!(run_with_retries_and_backoff(
+            2,
+            Duration::ZERO,
+            failing_attempts(2, true, &attempts)
+        )
+        .is_ok());
+        assert_eq!(attempts.get(), 3);
+
+        let attempts = Cell::new(0);
+        assert!(run_with_retries_and_backoff(
+            2,
+            Duration::ZERO,
+            failing_attempts(3, true, &attempts)
+        )
+        .is_err());
+        assert_eq!(attempts.get(), 3);
+    }
+
+    #[test]
+    fn permanent_failures_are_not_retried() {
+        let attempts = Cell::new(0);
+        assert!(run_with_retries_and_backoff(
+            2,
+            Duration::ZERO,
+            failing_attempts(1, false, &attempts)
+        )
+        .is_err());
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[test]
+    fn recognizing_transient_failures() {
+        assert!(is_transient_failure(
+            "Server terminated abruptly (error code: 14, error message: 'Socket closed')"
+        ));
+        assert!(!is_transient_failure(
+            "ERROR: Skipping '//foo:': syntax error at ')'"
+        ));
+    }
+
+    #[test]
+    fn extracting_query_diagnostics() {
+        let stderr = "\
+Loading: 0 packages loaded
+WARNING: /repo/a/BUILD:3:1: target '//a:b' is not visible from '//c:d'
+INFO: Empty results
+ERROR: Skipping '//e:f': no such package 'e'
+";
+        assert_eq!(
+            query_diagnostics(stderr),
+            vec![
+                "WARNING: /repo/a/BUILD:3:1: target '//a:b' is not visible from '//c:d'",
+                "ERROR: Skipping '//e:f': no such package 'e'",
+            ]
+        );
+        assert!(query_diagnostics("Loading: 0 packages loaded\n").is_empty());
+    }
+
+    #[test]
+    fn finding_diagnostics_concerning_requested_targets() -> anyhow::Result<()> {
+        let request = ResolutionRequest {
+            repo: Default::default(),
+            targets: [
+                Target::try_from("bazel://a:b")?,
+                Target::try_from("bazel://e/...")?,
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        let diagnostics = [
+            "WARNING: /repo/a/BUILD:3:1: target '//a:b' is not visible from '//c:d'",
+            "WARNING: /repo/a/BUILD:4:1: target '//a:bc' is deprecated",
+            "ERROR: Skipping '//e/f:g': no such package 'e/f'",
+            "ERROR: Skipping '//ef:g': no such package 'ef'",
+        ];
+        assert_eq!(
+            diagnostics_concerning(&diagnostics, &request),
+            vec![diagnostics[0], diagnostics[2]]
+        );
+        Ok(())
+    }
+}
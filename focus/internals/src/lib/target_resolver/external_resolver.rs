@@ -0,0 +1,253 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use anyhow::bail;
+use focus_util::sandbox_command::{SandboxCommand, SandboxCommandOutput};
+use git2::Oid;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, warn};
+
+use super::*;
+
+/// The message written to the standard input of an external resolver.
+#[derive(Debug, Serialize)]
+struct ExternalResolverRequest<'a> {
+    coordinates: Vec<&'a str>,
+    commit: String,
+}
+
+/// The message an external resolver writes to its standard output. Cached responses are stored in the same form.
+#[derive(Debug, Serialize, Deserialize)]
+struct ExternalResolverResponse {
+    paths: Vec<PathBuf>,
+}
+
+/// Resolves coordinates by running a user-specified executable.
+///
+/// The executable is run in the repository with a single JSON object of the form `{"coordinates": [...], "commit":
+/// "<hex>"}` on its standard input. It must exit successfully after writing a JSON object of the form `{"paths":
+/// [...]}` to its standard output, where the paths are relative to the repository root.
+///
+/// If a cache directory is set, responses are cached there, keyed by the command, the commit, and the coordinates.
+pub struct ExternalCommandResolver {
+    command: PathBuf,
+    cache_dir: Option<PathBuf>,
+}
+
+impl ExternalCommandResolver {
+    pub fn new(command: impl Into<PathBuf>) -> Self {
+        Self {
+            command: command.into(),
+            cache_dir: None,
+        }
+    }
+
+    /// Cache responses in `cache_dir`.
+    pub fn with_cache_dir(mut self, cache_dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    /// Resolve `coordinates` at `commit` like `resolve_coordinates`, reusing and storing cached responses as
+    /// `cache_options` allow.
+    pub(crate) fn resolve_cached(
+        &self,
+        repo: &Path,
+        coordinates: &TargetSet,
+        commit: Oid,
+        cache_options: &CacheOptions,
+        app: Arc<App>,
+    ) -> Result<BTreeSet<PathBuf>> {
+        let cache_path = self
+            .cache_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.json", self.cache_key(coordinates, commit))));
+        if let (Some(cache_path), true) = (&cache_path, cache_options.accept_cached_response) {
+            if let Ok(raw_response) = std::fs::read_to_string(cache_path) {
+                match Self::parse_response(&raw_response) {
+                    Ok(paths) => {
+                        debug!(path = ?cache_path, "Using cached external resolver response");
+                        return Ok(paths);
+                    }
+                    Err(err) => {
+                        warn!(?err, path = ?cache_path, "Ignoring unreadable cached response")
+                    }
+                }
+            }
+        }
+
+        let paths = self.resolve_coordinates(repo, coordinates, commit, app)?;
+        if let (Some(cache_path), true) = (&cache_path, cache_options.store_response_in_cache) {
+            if let Err(err) = Self::store_response(cache_path, &paths) {
+                warn!(?err, path = ?cache_path, "Could not cache the external resolver response");
+            }
+        }
+        Ok(paths)
+    }
+
+    fn cache_key(&self, coordinates: &TargetSet, commit: Oid) -> String {
+        let mut coordinates: Vec<String> = coordinates.iter().map(|c| c.to_string()).collect();
+        coordinates.sort_unstable();
+
+        let mut digest = Sha256::new();
+        digest.update(self.command.to_string_lossy().as_bytes());
+        digest.update(b"\n");
+        digest.update(commit.as_bytes());
+        for coordinate in coordinates {
+            digest.update(b"\n");
+            digest.update(coordinate.as_bytes());
+        }
+        hex::encode(digest.finalize())
+    }
+
+    fn store_response(cache_path: &Path, paths: &BTreeSet<PathBuf>) -> Result<()> {
+        let dir = cache_path
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("Cache path has no parent"))?;
+        std::fs::create_dir_all(dir).with_context(|| format!("Creating {}", dir.display()))?;
+        let mut file = tempfile::NamedTempFile::new_in(dir)
+            .with_context(|| format!("Creating a temporary file in {}", dir.display()))?;
+        let response = ExternalResolverResponse {
+            paths: paths.iter().cloned().collect(),
+        };
+        serde_json::to_writer(&mut file, &response).context("Writing the cached response")?;
+        file.persist(cache_path)
+            .with_context(|| format!("Storing {}", cache_path.display()))?;
+        Ok(())
+    }
+
+    fn parse_response(raw_response: &str) -> Result<BTreeSet<PathBuf>> {
+        let response: ExternalResolverResponse = serde_json::from_str(raw_response)
+            .context("Parsing the response from the external resolver")?;
+        for path in response.paths.iter() {
+            if path.is_absolute() {
+                bail!(
+                    "The external resolver returned an absolute path ({}); paths must be relative to the repository",
+                    path.display()
+                );
+            }
+        }
+        Ok(response.paths.into_iter().collect())
+    }
+}
+
+impl CoordinateResolver for ExternalCommandResolver {
+    fn resolve_coordinates(
+        &self,
+        repo: &Path,
+        coordinates: &TargetSet,
+        commit: Oid,
+        app: Arc<App>,
+    ) -> Result<BTreeSet<PathBuf>> {
+        let mut coordinates: Vec<String> = coordinates.iter().map(|c| c.to_string()).collect();
+        coordinates.sort_unstable();
+        let request = ExternalResolverRequest {
+            coordinates: coordinates.iter().map(String::as_str).collect(),
+            commit: commit.to_string(),
+        };
+
+        let request_path = {
+            let (mut file, path, _serial) = app
+                .sandbox()
+                .create_file(Some("external_resolver_request"), Some("json"), None)
+                .context("Creating external resolver request file")?;
+            serde_json::to_writer(&mut file, &request)
+                .context("Writing external resolver request")?;
+            file.flush().context("Flushing external resolver request")?;
+            path
+        };
+        let stdin = File::open(&request_path).with_context(|| {
+            format!(
+                "Opening external resolver request {}",
+                request_path.display()
+            )
+        })?;
+
+        let (mut cmd, scmd) = SandboxCommand::new_with_handles(
+            &self.command,
+            Some(Stdio::from(stdin)),
+            None,
+            None,
+            app,
+        )?;
+        scmd.ensure_success_or_log(cmd.current_dir(repo), SandboxCommandOutput::Stderr)
+            .with_context(|| format!("Running external resolver {}", self.command.display()))?;
+
+        let mut raw_response = String::new();
+        scmd.read_to_string(SandboxCommandOutput::Stdout, &mut raw_response)?;
+        let paths = Self::parse_response(&raw_response)?;
+        info!(
+            command = ?self.command,
+            coordinates = coordinates.len(),
+            paths = paths.len(),
+            "External resolver finished"
+        );
+        Ok(paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing_responses() -> Result<()> {
+        assert_eq!(
+            ExternalCommandResolver::parse_response(r#"{"paths": ["b/c", "a"]}"#)?,
+            BTreeSet::from([PathBuf::from("a"), PathBuf::from("b/c")])
+        );
+        assert!(ExternalCommandResolver::parse_response(r#"{"paths": ["/etc"]}"#).is_err());
+        assert!(ExternalCommandResolver::parse_response("not json").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn responses_are_cached_by_commit() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let app = Arc::new(App::new_for_testing()?);
+        let dir = tempfile::tempdir()?;
+        let command = dir.path().join("resolver");
+        let invocations = dir.path().join("invocations");
+        std::fs::write(
+            &command,
+            format!(
+                "#!/bin/sh\ncat > /dev/null\necho >> {}\necho '{{\"paths\": [\"a\"]}}'\n",
+                invocations.display()
+            ),
+        )?;
+        std::fs::set_permissions(&command, std::fs::Permissions::from_mode(0o755))?;
+        let invocation_count =
+            || -> Result<usize> { Ok(std::fs::read_to_string(&invocations)?.lines().count()) };
+
+        let resolver =
+            ExternalCommandResolver::new(&command).with_cache_dir(dir.path().join("cache"));
+        let coordinates: TargetSet = [Target::try_from("bazel://a/...")?].into_iter().collect();
+        let commit = Oid::from_str("1111111111111111111111111111111111111111")?;
+        let other_commit = Oid::from_str("2222222222222222222222222222222222222222")?;
+        let resolve = |commit: Oid, cache_options: &CacheOptions| {
+            resolver.resolve_cached(dir.path(), &coordinates, commit, cache_options, app.clone())
+        };
+
+        let expected = BTreeSet::from([PathBuf::from("a")]);
+        assert_eq!(resolve(commit, &CacheOptions::default())?, expected);
+        assert_eq!(resolve(commit, &CacheOptions::default())?, expected);
+        assert_eq!(invocation_count()?, 1);
+
+        assert_eq!(resolve(other_commit, &CacheOptions::default())?, expected);
+        assert_eq!(invocation_count()?, 2);
+
+        resolve(commit, &CacheOptions::new(false, false))?;
+        assert_eq!(invocation_count()?, 3);
+
+        Ok(())
+    }
+}
@@ -14,7 +14,7 @@ use tracing::{debug, info};
 
 use crate::target::{Label, TargetName};
 
-use super::*;
+use super::{bazel_common::QueryFailure, *};
 
 const OUTLINING_BAZELRC_PATH: &str = "focus/outlining.bazelrc";
 
@@ -208,16 +208,28 @@ impl IncrementalBazelResolver {
             initial_bazel_args.push(String::from("--noworkspace_rc"));
             initial_bazel_args.push(format!("--bazelrc={}", OUTLINING_BAZELRC_PATH));
         }
-        let (mut cmd, scmd) = SandboxCommand::new(Self::locate_bazel_binary(request), app)?;
-        scmd.ensure_success_or_log(
-            cmd.args(initial_bazel_args)
-                .arg("query")
-                .arg("--query_file")
-                .arg(query_file_path)
-                .args(bazel_args)
-                .current_dir(&request.repo),
-            SandboxCommandOutput::Stderr,
-        )?;
+        let scmd = bazel_common::run_with_retries(request.options.bazel_query_retries, || {
+            let (mut cmd, scmd) =
+                SandboxCommand::new(Self::locate_bazel_binary(request), app.clone())
+                    .map_err(QueryFailure::permanent)?;
+            match scmd.ensure_success_or_log(
+                cmd.args(&initial_bazel_args)
+                    .arg("query")
+                    .args(bazel_common::offline_query_args(&app))
+                    .arg("--query_file")
+                    .arg(&query_file_path)
+                    .args(bazel_args)
+                    .current_dir(&request.repo),
+                SandboxCommandOutput::Stderr,
+            ) {
+                Ok(_) => Ok(scmd),
+                Err(e) => Err(QueryFailure::from_command(e, &scmd)),
+            }
+        })?;
+
+        if request.options.strict_resolution {
+            bazel_common::check_strict_resolution(&scmd, request)?;
+        }
 
         // Read to string so that we can print it if we need to debug.
         let raw_result = {
@@ -4,6 +4,7 @@
 mod bazel_common;
 mod bazel_de;
 mod directory_resolver;
+mod external_resolver;
 mod incremental_bazel_resolver;
 mod oneshot_bazel_resolver;
 
@@ -13,13 +14,18 @@ use crate::{
     index::{DependencyKey, DependencyValue},
     target::{Target, TargetSet},
 };
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use std::{
     collections::{BTreeMap, BTreeSet, HashSet},
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
 };
+use tracing::info;
 
+pub use self::external_resolver::ExternalCommandResolver;
 pub(crate) use self::{
     directory_resolver::DirectoryResolver, incremental_bazel_resolver::IncrementalBazelResolver,
     oneshot_bazel_resolver::OneShotBazelResolver,
@@ -39,13 +45,73 @@ pub enum BazelResolutionStrategy {
 #[derive(Clone, Debug)]
 pub struct ResolutionOptions {
     pub(crate) bazel_resolution_strategy: BazelResolutionStrategy,
+
+    /// An executable that resolves non-directory coordinates in place of Bazel. See `ExternalCommandResolver`.
+    pub(crate) external_resolver_command: Option<PathBuf>,
+
+    /// How many times to retry Bazel queries that fail transiently.
+    pub(crate) bazel_query_retries: usize,
+
+    /// Whether to fail if Bazel reports warnings or errors while resolving, since it may have skipped targets.
+    pub(crate) strict_resolution: bool,
+
+    /// Whether one-shot resolution queries Bazel once for all coordinates with a union expression, letting Bazel load
+    /// the packages they share once, rather than once per coordinate. Packages are attributed to the coordinates naming
+    /// them either way, but only separate queries can attribute the packages a coordinate depends on elsewhere.
+    /// Incremental resolution always makes a single query and attributes packages through the index.
+    pub(crate) bazel_union_query: bool,
 }
 
 impl Default for ResolutionOptions {
     fn default() -> Self {
         Self {
             bazel_resolution_strategy: BazelResolutionStrategy::Incremental,
+            external_resolver_command: None,
+            bazel_query_retries: 0,
+            strict_resolution: false,
+            bazel_union_query: true,
+        }
+    }
+}
+
+/// Counts the coordinates resolved so far and logs the percentage complete each time another tenth of them is done.
+/// Progress goes through the log rather than a status line redrawn on the terminal so that it interleaves with the rest
+/// of the output. Coordinates resolved together, such as the Bazel labels in one query, complete together.
+pub(crate) struct ResolutionProgress {
+    total: usize,
+    resolved: AtomicUsize,
+    reported_tenths: AtomicUsize,
+}
+
+impl ResolutionProgress {
+    pub fn new(total: usize) -> Self {
+        Self {
+            total,
+            resolved: AtomicUsize::new(0),
+            reported_tenths: AtomicUsize::new(0),
+        }
+    }
+
+    /// Record that `count` more coordinates were resolved. Returns the percentage complete if it was reported.
+    pub fn advance(&self, count: usize) -> Option<usize> {
+        if self.total == 0 || count == 0 {
+            return None;
+        }
+        let resolved = (self.resolved.fetch_add(count, Ordering::SeqCst) + count).min(self.total);
+        let percent = resolved * 100 / self.total;
+        let tenths = percent / 10;
+        if self.reported_tenths.fetch_max(tenths, Ordering::SeqCst) >= tenths {
+            return None;
         }
+        info!(
+            resolved,
+            total = self.total,
+            "Resolved {} of {} coordinates ({}%)",
+            resolved,
+            self.total,
+            percent
+        );
+        Some(percent)
     }
 }
 
@@ -55,6 +121,9 @@ pub struct ResolutionRequest {
     pub repo: PathBuf,
     pub targets: TargetSet,
     pub options: ResolutionOptions,
+
+    /// The commit checked out in `repo` that the targets are resolved at. Required by the external resolver.
+    pub commit: Option<git2::Oid>,
 }
 
 /// Result of resolving a set of targets; namely a set of paths.
@@ -94,9 +163,7 @@ impl From<BTreeSet<PathBuf>> for ResolutionResult {
 
 /// Dictates whether the resolver can retrieve or store responses to a cache.
 pub struct CacheOptions {
-    #[allow(dead_code)]
     accept_cached_response: bool,
-    #[allow(dead_code)]
     store_response_in_cache: bool,
 }
 
@@ -129,10 +196,23 @@ pub trait Resolver {
     ) -> Result<ResolutionResult>;
 }
 
+/// Resolves coordinates to the set of paths that must be materialized to build them at a particular commit. This is
+/// the extension point for build systems other than Bazel.
+pub trait CoordinateResolver {
+    fn resolve_coordinates(
+        &self,
+        repo: &Path,
+        coordinates: &TargetSet,
+        commit: git2::Oid,
+        app: Arc<App>,
+    ) -> Result<BTreeSet<PathBuf>>;
+}
+
 pub struct RoutingResolver {
     incremental_bazel_resolver: IncrementalBazelResolver,
     oneshot_bazel_resolver: OneShotBazelResolver,
     directory_resolver: DirectoryResolver,
+    external_resolver_cache_dir: PathBuf,
 }
 
 impl Resolver for RoutingResolver {
@@ -141,6 +221,7 @@ impl Resolver for RoutingResolver {
             incremental_bazel_resolver: IncrementalBazelResolver::new(cache_root),
             oneshot_bazel_resolver: OneShotBazelResolver::new(cache_root),
             directory_resolver: DirectoryResolver::new(cache_root),
+            external_resolver_cache_dir: cache_root.join("external"),
         }
     }
 
@@ -163,6 +244,12 @@ impl Resolver for RoutingResolver {
                     target @ Target::Directory(_) => {
                         directory_targets.insert(target);
                     }
+                    Target::File(path) => {
+                        bail!(
+                            "File targets are selected by pattern and cannot be resolved: {}",
+                            path
+                        )
+                    }
                 }
             }
 
@@ -177,28 +264,52 @@ impl Resolver for RoutingResolver {
             vec![bazel_subrequest, directory_subrequest]
         };
 
+        let progress = ResolutionProgress::new(request.targets.len());
         subrequests
             .par_iter()
             .map(|subrequest| {
                 let app_clone = app.clone();
 
-                match subrequest.targets.iter().next() {
+                let result = match subrequest.targets.iter().next() {
                     Some(Target::Bazel(_)) => {
-                        match subrequest.options.bazel_resolution_strategy {
-                            BazelResolutionStrategy::Incremental => self
-                                .incremental_bazel_resolver
-                                .resolve(subrequest, cache_options, app_clone),
-                            BazelResolutionStrategy::OneShot => self
-                                .oneshot_bazel_resolver
-                                .resolve(subrequest, cache_options, app_clone),
+                        if let Some(command) = &subrequest.options.external_resolver_command {
+                            let commit = subrequest.commit.ok_or_else(|| {
+                                anyhow::anyhow!(
+                                    "The external resolver requires the commit to resolve at"
+                                )
+                            })?;
+                            ExternalCommandResolver::new(command)
+                                .with_cache_dir(&self.external_resolver_cache_dir)
+                                .resolve_cached(
+                                    &subrequest.repo,
+                                    &subrequest.targets,
+                                    commit,
+                                    cache_options,
+                                    app_clone,
+                                )
+                                .map(ResolutionResult::from)
+                        } else {
+                            match subrequest.options.bazel_resolution_strategy {
+                                BazelResolutionStrategy::Incremental => self
+                                    .incremental_bazel_resolver
+                                    .resolve(subrequest, cache_options, app_clone),
+                                BazelResolutionStrategy::OneShot => self
+                                    .oneshot_bazel_resolver
+                                    .resolve(subrequest, cache_options, app_clone),
+                            }
                         }
                     }
                     Some(Target::Directory(_)) => {
                         self.directory_resolver
                             .resolve(subrequest, cache_options, app_clone)
                     }
+                    Some(Target::File(_)) => unreachable!("File targets are rejected above"),
                     None => Ok(Default::default()),
+                };
+                if result.is_ok() {
+                    progress.advance(subrequest.targets.len());
                 }
+                result
             })
             .try_reduce(ResolutionResult::new, |mut acc, result| {
                 acc.merge(result);
@@ -207,3 +318,21 @@ impl Resolver for RoutingResolver {
             .context("Resolving targets failed")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolution_progress_is_reported_by_tenths() {
+        let progress = ResolutionProgress::new(20);
+        assert_eq!(progress.advance(1), None);
+        assert_eq!(progress.advance(1), Some(10));
+        assert_eq!(progress.advance(1), None);
+        assert_eq!(progress.advance(12), Some(75));
+        assert_eq!(progress.advance(0), None);
+        assert_eq!(progress.advance(5), Some(100));
+
+        assert_eq!(ResolutionProgress::new(0).advance(1), None);
+    }
+}
@@ -12,9 +12,9 @@ use anyhow::{bail, Result};
 use focus_util::sandbox_command::{SandboxCommand, SandboxCommandOutput};
 use tracing::{debug, info};
 
-use crate::target::Label;
+use crate::target::{Label, TargetName};
 
-use super::*;
+use super::{bazel_common::QueryFailure, *};
 
 const OUTLINING_BAZELRC_PATH: &str = "focus/outlining.bazelrc";
 
@@ -57,12 +57,11 @@ impl Resolver for OneShotBazelResolver {
             bail!("Failed to lock mutex: {}", e);
         }
 
-        let mut paths = BTreeSet::new();
         let package_deps = BTreeMap::new();
         #[allow(clippy::redundant_clone)]
         let app = app.clone();
 
-        let labels: HashSet<&Label> = request
+        let labels: BTreeSet<&Label> = request
             .targets
             .iter()
             .filter_map(|target| {
@@ -74,31 +73,155 @@ impl Resolver for OneShotBazelResolver {
             })
             .collect();
 
-        let query = format!(
-            "deps({0}) union kind(rule, filter('^//', buildfiles(deps({0}))))",
-            bazel_common::make_set(labels.iter().copied())
-        );
-
-        let result = Self::run_bazel_package_query(app, request, &query)?;
-        for line in result {
-            paths.insert(PathBuf::from_str(line.as_str())?);
+        let packages = if request.options.bazel_union_query {
+            let query = Self::package_query(labels.iter().copied());
+            let required = Self::query_packages(app, request, &query)?;
+            info!("'{}' requires {} packages", &query, required.len());
+            Self::attribute_packages(labels.iter().copied(), required)
+        } else {
+            let mut packages = AttributedPackages::default();
+            for label in labels {
+                let query = Self::package_query([label]);
+                let required = Self::query_packages(app.clone(), request, &query)?;
+                info!("'{}' requires {} packages", &query, required.len());
+                packages.merged.extend(required.iter().cloned());
+                packages.by_label.insert(label.clone(), required);
+            }
+            packages
+        };
+        for (label, required) in packages.by_label.iter() {
+            debug!(%label, count = required.len(), "Coordinate requires packages");
         }
 
-        info!("'{}' requires {} packages", &query, paths.len());
-
         Ok(ResolutionResult {
-            paths,
+            paths: packages.merged,
             package_deps,
         })
     }
 }
 
+/// The packages required by a set of Bazel coordinates, attributed to the coordinates that required them where that is
+/// known.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct AttributedPackages {
+    /// The packages known to be required by each coordinate.
+    by_label: BTreeMap<Label, BTreeSet<PathBuf>>,
+
+    /// Every package required, including those that could not be attributed to a coordinate.
+    merged: BTreeSet<PathBuf>,
+}
+
 impl OneShotBazelResolver {
+    /// Build the query for the packages `labels` depend on. Under `bazel_union_query`, all of the labels of a request go
+    /// into a single `set(...)` expression, so Bazel loads the packages they share once per resolution rather than once
+    /// per label.
+    fn package_query<'a>(labels: impl IntoIterator<Item = &'a Label>) -> String {
+        format!(
+            "deps({0}) union kind(rule, filter('^//', buildfiles(deps({0}))))",
+            bazel_common::make_set(labels)
+        )
+    }
+
+    /// Attribute the packages that a union query over `labels` returned to the labels naming them: the package of a
+    /// label, or any package beneath a recursive `/...` label. The merged output does not say which label required
+    /// the other packages, so they are left unattributed.
+    fn attribute_packages<'a>(
+        labels: impl IntoIterator<Item = &'a Label>,
+        packages: BTreeSet<PathBuf>,
+    ) -> AttributedPackages {
+        let by_label = labels
+            .into_iter()
+            .filter(|label| label.external_repository.is_none())
+            .map(|label| {
+                let directory: PathBuf = label.path_components.iter().collect();
+                let named = packages
+                    .iter()
+                    .filter(|package| match label.target_name {
+                        TargetName::Ellipsis => package.starts_with(&directory),
+                        TargetName::Name(_) => **package == directory,
+                    })
+                    .cloned()
+                    .collect();
+                (label.clone(), named)
+            })
+            .collect();
+        AttributedPackages {
+            by_label,
+            merged: packages,
+        }
+    }
+
+    fn query_packages(
+        app: Arc<App>,
+        request: &ResolutionRequest,
+        query: &str,
+    ) -> Result<BTreeSet<PathBuf>> {
+        Self::run_bazel_package_query(app, request, query)?
+            .into_iter()
+            .map(|line| Ok(PathBuf::from_str(line.as_str())?))
+            .collect()
+    }
+
+    /// Run the query that `resolve` would for the Bazel targets in `request`, returning the query and Bazel's
+    /// unfiltered output, for debugging resolution.
+    pub(crate) fn raw_package_query(
+        request: &ResolutionRequest,
+        app: Arc<App>,
+    ) -> Result<(String, String)> {
+        let query = Self::package_query(request.targets.iter().filter_map(|target| match target {
+            Target::Bazel(label) => Some(label),
+            _ => None,
+        }));
+        let output = Self::run_raw_bazel_package_query(app, request, &query)?;
+        Ok((query, output))
+    }
+
+    /// Run `query` in the repo of `request`, returning the labels of the matching targets in the main repository.
+    pub(crate) fn label_query(
+        request: &ResolutionRequest,
+        query: &str,
+        app: Arc<App>,
+    ) -> Result<Vec<String>> {
+        let raw_result = Self::run_raw_bazel_query(app, request, query, "--output=label")?;
+        Ok(raw_result
+            .lines()
+            .filter(|s| !s.is_empty() && !s.starts_with('@'))
+            .map(|s| s.to_owned())
+            .collect())
+    }
+
     fn run_bazel_package_query(
         app: Arc<App>,
         request: &ResolutionRequest,
         query: &str,
     ) -> Result<Vec<String>> {
+        let raw_result = Self::run_raw_bazel_package_query(app, request, query)?;
+        Ok(raw_result
+            .lines()
+            .filter_map(|s| {
+                if !s.starts_with('@') {
+                    Some(s.to_owned())
+                } else {
+                    None
+                }
+            })
+            .collect())
+    }
+
+    fn run_raw_bazel_package_query(
+        app: Arc<App>,
+        request: &ResolutionRequest,
+        query: &str,
+    ) -> Result<String> {
+        Self::run_raw_bazel_query(app, request, query, "--output=package")
+    }
+
+    fn run_raw_bazel_query(
+        app: Arc<App>,
+        request: &ResolutionRequest,
+        query: &str,
+        output_arg: &str,
+    ) -> Result<String> {
         let query_file_path = {
             let (mut file, path, _serial) = app
                 .sandbox()
@@ -114,25 +237,43 @@ impl OneShotBazelResolver {
             initial_bazel_args.push(String::from("--noworkspace_rc"));
             initial_bazel_args.push(format!("--bazelrc={}", OUTLINING_BAZELRC_PATH));
         }
-        let (mut cmd, scmd) = SandboxCommand::new(Self::locate_bazel_binary(request), app)?;
-        scmd.ensure_exit_with_status_or_log(
-            cmd.args(initial_bazel_args)
-                .arg("query")
-                .arg("--output=package")
-                .arg("--order_output=no")
-                .arg("--noimplicit_deps")
-                .arg("--query_file")
-                .arg(query_file_path)
-                .current_dir(&request.repo),
-            SandboxCommandOutput::Stderr,
-            &[0, 3],
-            // TODO: Attempt to disable fetching to speed Bazel further.
-            //   --nofetch --experimental_repository_disable_download=true --keep_going
-            //
-            // We will have to allow Bazel PARTIAL_ANALYSIS_FAILURE because of --nofetch.
-            //
-            // See https://github.com/bazelbuild/bazel/blob/master/src/main/java/com/google/devtools/build/lib/util/ExitCode.java.
-        )?;
+        // Bazel exits with 3 when the query partially failed, which strict resolution does not accept.
+        let successful_status_codes: &[i32] = if request.options.strict_resolution {
+            &[0]
+        } else {
+            &[0, 3]
+        };
+        let scmd = bazel_common::run_with_retries(request.options.bazel_query_retries, || {
+            let (mut cmd, scmd) =
+                SandboxCommand::new(Self::locate_bazel_binary(request), app.clone())
+                    .map_err(QueryFailure::permanent)?;
+            match scmd.ensure_exit_with_status_or_log(
+                cmd.args(&initial_bazel_args)
+                    .arg("query")
+                    .args(bazel_common::offline_query_args(&app))
+                    .arg(output_arg)
+                    .arg("--order_output=no")
+                    .arg("--noimplicit_deps")
+                    .arg("--query_file")
+                    .arg(&query_file_path)
+                    .current_dir(&request.repo),
+                SandboxCommandOutput::Stderr,
+                successful_status_codes,
+                // TODO: Attempt to disable fetching to speed Bazel further.
+                //   --nofetch --experimental_repository_disable_download=true --keep_going
+                //
+                // We will have to allow Bazel PARTIAL_ANALYSIS_FAILURE because of --nofetch.
+                //
+                // See https://github.com/bazelbuild/bazel/blob/master/src/main/java/com/google/devtools/build/lib/util/ExitCode.java.
+            ) {
+                Ok(_) => Ok(scmd),
+                Err(e) => Err(QueryFailure::from_command(e, &scmd)),
+            }
+        })?;
+
+        if request.options.strict_resolution {
+            bazel_common::check_strict_resolution(&scmd, request)?;
+        }
 
         // Read to string so that we can print it if we need to debug.
         let raw_result = {
@@ -142,15 +283,45 @@ impl OneShotBazelResolver {
         };
 
         debug!(?query, ?raw_result, "Query returned with result");
-        Ok(raw_result
-            .lines()
-            .filter_map(|s| {
-                if !s.starts_with('@') {
-                    Some(s.to_owned())
-                } else {
-                    None
-                }
-            })
-            .collect())
+        Ok(raw_result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn package_query_batches_labels() -> Result<()> {
+        let a: Label = "//a:lib".parse()?;
+        let b: Label = "//b/...".parse()?;
+        assert_eq!(
+            OneShotBazelResolver::package_query([&a, &b]),
+            r#"deps(set("//a:lib" "//b/...")) union kind(rule, filter('^//', buildfiles(deps(set("//a:lib" "//b/...")))))"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn union_query_packages_are_attributed_to_the_labels_naming_them() -> Result<()> {
+        let a: Label = "//a:lib".parse()?;
+        let b: Label = "//b/...".parse()?;
+        let packages: BTreeSet<PathBuf> = ["a", "a/sub", "b", "b/c", "bc", "shared"]
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+        let attributed = OneShotBazelResolver::attribute_packages([&a, &b], packages.clone());
+        assert_eq!(
+            attributed.by_label[&a],
+            [PathBuf::from("a")].into_iter().collect()
+        );
+        assert_eq!(
+            attributed.by_label[&b],
+            [PathBuf::from("b"), PathBuf::from("b/c")]
+                .into_iter()
+                .collect()
+        );
+        assert_eq!(attributed.merged, packages);
+        Ok(())
     }
 }
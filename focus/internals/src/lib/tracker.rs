@@ -11,14 +11,19 @@ use std::{
 };
 
 use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
 use tracing::{debug, info, warn};
 use uuid::Uuid;
 
-use focus_util::{app::App, lock_file::LockFile, paths::focus_config_dir};
+use focus_util::{
+    app::App,
+    lock_file::LockFile,
+    paths::{self, focus_tracker_dir},
+};
 
 use crate::model::repo::Repo;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TrackedRepo {
     identifier: Uuid,
     location: PathBuf,
@@ -66,6 +71,22 @@ impl Display for TrackedRepo {
     }
 }
 
+/// The projects and targets a repo was cloned with, recorded in the tracker when the repo is cloned.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CloneSpec {
+    pub projects_and_targets: Vec<String>,
+}
+
+impl Display for CloneSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.projects_and_targets.is_empty() {
+            write!(f, "(nothing)")
+        } else {
+            write!(f, "{}", self.projects_and_targets.join(","))
+        }
+    }
+}
+
 pub struct Snapshot {
     pub(crate) repos: Vec<TrackedRepo>,
     pub(crate) index_by_identifier: HashMap<Vec<u8>, usize>,
@@ -104,29 +125,44 @@ impl Snapshot {
 pub struct Tracker {
     _tempdir: Option<tempfile::TempDir>,
     directory: PathBuf,
+    /// Whether repos are recorded at all. A disabled tracker records nothing and tracks no repos.
+    enabled: bool,
 }
 
 impl Tracker {
     pub fn new(directory: &Path) -> Result<Self> {
-        std::fs::create_dir_all(directory)
+        paths::create_dir_all_race_safe(directory)
             .with_context(|| format!("creating directory hierarchy '{}'", directory.display()))?;
 
         Ok(Self {
             _tempdir: None,
             directory: directory.to_owned(),
+            enabled: true,
         })
     }
 
-    pub fn from_config_dir() -> Result<Self> {
+    /// The tracker in the focus config directory, or in `data_dir` if given.
+    pub fn from_config_dir(data_dir: Option<&Path>) -> Result<Self> {
         if cfg!(test) {
             panic!("Should not be trying to construct a real Tracker object during testing, as it reads/writes from the user's config directory");
         }
         Ok(Self {
             _tempdir: None,
-            directory: focus_config_dir(),
+            directory: focus_tracker_dir(data_dir),
+            enabled: true,
         })
     }
 
+    /// A tracker that neither records repos nor touches the filesystem, for ephemeral clones that should not appear
+    /// among the tracked repos.
+    pub fn disabled() -> Self {
+        Self {
+            _tempdir: None,
+            directory: PathBuf::new(),
+            enabled: false,
+        }
+    }
+
     pub fn for_testing() -> Result<Self> {
         let tempdir = tempfile::tempdir()?;
         let directory = tempdir.path().to_path_buf();
@@ -134,15 +170,27 @@ impl Tracker {
         Ok(Self {
             _tempdir: Some(tempdir),
             directory,
+            enabled: true,
         })
     }
 
     pub fn ensure_directories_exist(&self) -> Result<()> {
-        std::fs::create_dir_all(self.repos_by_uuid_dir()).context("create by-uuid repo dir")?;
+        if !self.enabled {
+            return Ok(());
+        }
+        paths::create_dir_all_race_safe(&self.repos_by_uuid_dir())
+            .context("create by-uuid repo dir")?;
         Ok(())
     }
 
     pub fn ensure_registered(&self, repo_directory: &Path, app: Arc<App>) -> Result<()> {
+        if !self.enabled {
+            debug!(
+                ?repo_directory,
+                "Not registering the repo because tracking is disabled"
+            );
+            return Ok(());
+        }
         let uuid = TrackedRepo::get_or_generate_uuid(repo_directory, app)?;
         let link_path = self.repos_by_uuid_dir().join(uuid.to_string());
         if link_path.is_symlink() {
@@ -183,6 +231,9 @@ impl Tracker {
 
     // Repair the registry of tracked repositories by checking that symlinks point to canonicalizable destinations and that the configured UUIDs match the inbound link.
     pub fn repair(&self, app: Arc<App>) -> Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
         // Hold a repo repair lock.
         let _lock = LockFile::new(&self.repo_registry_lock_path());
 
@@ -261,6 +312,9 @@ impl Tracker {
 
     // Scan the directory containing repos labeled by UUID.
     pub fn scan(&self) -> Result<Snapshot> {
+        if !self.enabled {
+            return Ok(Snapshot::new(Vec::new()));
+        }
         let reader = self
             .repos_by_uuid_dir()
             .read_dir()
@@ -319,6 +373,55 @@ impl Tracker {
         Ok(Snapshot::new(repos))
     }
 
+    /// Record the projects and targets the repo in `repo_directory` was cloned with. Nothing is written to the repo or
+    /// the tracker when tracking is disabled, not even the UUID identifying the repo.
+    pub fn record_clone_spec(
+        &self,
+        repo_directory: &Path,
+        spec: &CloneSpec,
+        app: Arc<App>,
+    ) -> Result<()> {
+        if !self.enabled {
+            debug!(
+                ?repo_directory,
+                "Not recording the clone spec because tracking is disabled"
+            );
+            return Ok(());
+        }
+        let identifier = TrackedRepo::get_or_generate_uuid(repo_directory, app)?;
+        let dir = self.clone_specs_dir();
+        paths::create_dir_all_race_safe(&dir)
+            .with_context(|| format!("Creating {}", dir.display()))?;
+        let path = self.clone_spec_path(&identifier);
+        let contents = serde_json::to_vec_pretty(spec).context("Serializing clone spec")?;
+        std::fs::write(&path, contents).with_context(|| format!("Writing {}", path.display()))
+    }
+
+    /// The projects and targets the repo identified by `identifier` was cloned with, if they were recorded. Repos
+    /// cloned before these were recorded have none.
+    pub fn clone_spec(&self, identifier: &Uuid) -> Result<Option<CloneSpec>> {
+        if !self.enabled {
+            return Ok(None);
+        }
+        let path = self.clone_spec_path(identifier);
+        let contents = match std::fs::read(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Reading {}", path.display())),
+        };
+        serde_json::from_slice(&contents)
+            .with_context(|| format!("Parsing {}", path.display()))
+            .map(Some)
+    }
+
+    fn clone_spec_path(&self, identifier: &Uuid) -> PathBuf {
+        self.clone_specs_dir().join(format!("{}.json", identifier))
+    }
+
+    fn clone_specs_dir(&self) -> PathBuf {
+        self.repos_dir().join("clone-specs")
+    }
+
     fn repos_dir(&self) -> PathBuf {
         self.directory.join("repos")
     }
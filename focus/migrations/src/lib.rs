@@ -4,7 +4,7 @@
 pub mod production;
 
 use anyhow::{Context, Result};
-use focus_util::app::App;
+use focus_util::{app::App, backed_up_file::BackedUpFile};
 use serde::{Deserialize, Serialize};
 use std::{
     cell::{Cell, RefCell},
@@ -21,6 +21,12 @@ pub trait Migration {
     fn id(&self) -> Identifier;
     fn description(&self) -> &str;
     fn upgrade(&self, path: &Path, app: Arc<App>) -> Result<()>;
+
+    /// Files (relative to the repo) that the migration rewrites. Those that exist are backed up before upgrading and
+    /// restored if the upgrade fails.
+    fn backed_up_files(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
 }
 
 pub type Migrations = Vec<Box<dyn Migration>>;
@@ -106,6 +112,21 @@ impl Runner {
         self.migrations.last().map(|m| m.as_ref().id())
     }
 
+    /// The identifier of the last migration applied to the repo.
+    pub fn current_version(&self) -> Identifier {
+        self.manifest.borrow().version.get()
+    }
+
+    /// The migrations that have not yet been applied to the repo, in order.
+    pub fn pending_migrations(&self) -> Vec<&dyn Migration> {
+        let current_version = self.current_version();
+        self.migrations
+            .iter()
+            .map(|m| m.as_ref())
+            .filter(|m| m.id() > current_version)
+            .collect()
+    }
+
     pub fn is_upgrade_required(&self) -> Result<bool> {
         if let Some(ultimate_version) = self.ultimate_migration() {
             return Ok(self.manifest.borrow().version.get() < ultimate_version);
@@ -126,8 +147,19 @@ impl Runner {
             let identifier = migration.id();
             let description = migration.description();
             info!(%identifier, %description, "Running migration");
+            let backups = migration
+                .backed_up_files()
+                .iter()
+                .map(|relative_path| self.repo_path.join(relative_path))
+                .filter(|path| path.is_file())
+                .map(|path| BackedUpFile::new(&path))
+                .collect::<Result<Vec<_>>>()
+                .with_context(|| format!("Backing up files for migration {}", identifier))?;
             match migration.upgrade(&self.repo_path, self.app.clone()) {
                 Ok(()) => {
+                    for backup in backups.iter() {
+                        backup.discard();
+                    }
                     self.manifest.borrow().version.replace(identifier);
                 }
                 Err(e) => {
@@ -226,6 +258,28 @@ mod tests {
         }
     }
 
+    const REWRITTEN_FILE_NAME: &str = "rewritten.txt";
+
+    struct FailingMigrationWithBackup;
+    impl Migration for FailingMigrationWithBackup {
+        fn id(&self) -> Identifier {
+            Identifier::Serial(1)
+        }
+
+        fn description(&self) -> &str {
+            "A migration that rewrites a file and then fails for use in tests"
+        }
+
+        fn upgrade(&self, path: &Path, _app: Arc<App>) -> Result<()> {
+            std::fs::write(path.join(REWRITTEN_FILE_NAME), "new")?;
+            bail!("boom")
+        }
+
+        fn backed_up_files(&self) -> Vec<PathBuf> {
+            vec![PathBuf::from(REWRITTEN_FILE_NAME)]
+        }
+    }
+
     #[test]
     fn test_no_migrations() -> Result<()> {
         let app = Arc::from(App::new_for_testing()?);
@@ -296,6 +350,42 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn pending_migrations_are_reported_until_performed() -> Result<()> {
+        let app = Arc::from(App::new_for_testing()?);
+        let fixture = Fixture::new()?;
+        let runner =
+            fixture.new_runner_with_migrations(vec![Box::new(SuccessfulMigration {})], app)?;
+        assert_eq!(runner.current_version(), Identifier::Serial(0));
+        assert_eq!(runner.pending_migrations().len(), 1);
+
+        assert!(runner.perform_pending_migrations()?);
+        assert_eq!(runner.current_version(), Identifier::Serial(1));
+        assert!(runner.pending_migrations().is_empty());
+
+        // Running again is a no-op.
+        assert!(runner.perform_pending_migrations()?);
+        assert_eq!(runner.current_version(), Identifier::Serial(1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn failed_migrations_restore_backed_up_files() -> Result<()> {
+        let app = Arc::from(App::new_for_testing()?);
+        let fixture = Fixture::new()?;
+        let rewritten_file = fixture.repo_dir.join(REWRITTEN_FILE_NAME);
+        std::fs::write(&rewritten_file, "old")?;
+
+        let runner = fixture
+            .new_runner_with_migrations(vec![Box::new(FailingMigrationWithBackup {})], app)?;
+        assert!(runner.perform_pending_migrations().is_err());
+        assert_eq!(std::fs::read_to_string(&rewritten_file)?, "old");
+        assert!(runner.is_upgrade_required()?);
+
+        Ok(())
+    }
+
     #[test]
     fn migrations_are_skipped_when_identifier_is_less_equal_version() -> Result<()> {
         let app = Arc::from(App::new_for_testing()?);
@@ -25,6 +25,31 @@ pub fn perform_pending_migrations(repo_path: &Path, app: Arc<App>) -> Result<boo
     runner_for_repo(repo_path, app).and_then(|runner| runner.perform_pending_migrations())
 }
 
+/// Upgrade the repo's focus metadata to the latest format, reporting each migration that is applied.
+pub fn migrate(repo_path: &Path, app: Arc<App>) -> Result<()> {
+    let runner = runner_for_repo(repo_path, app)?;
+    let pending = runner.pending_migrations();
+    if pending.is_empty() {
+        println!(
+            "Repo is up to date (format version {})",
+            runner.current_version()
+        );
+        return Ok(());
+    }
+
+    println!(
+        "Upgrading repo from format version {}",
+        runner.current_version()
+    );
+    for migration in pending {
+        println!("  {} {}", migration.id(), migration.description());
+    }
+    runner.perform_pending_migrations()?;
+    println!("Repo is now at format version {}", runner.current_version());
+
+    Ok(())
+}
+
 struct HooksMigration;
 impl Migration for HooksMigration {
     fn id(&self) -> Identifier {
@@ -2,11 +2,16 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use anyhow::Context;
+use chrono::{SecondsFormat, Utc};
 use focus_internals::model::repo::Repo;
+use focus_platform::session_state;
 use std::time::Duration;
 use std::{path::PathBuf, sync::Arc};
 
-use focus_util::app::{App, ExitCode};
+use focus_util::{
+    app::{App, ExitCode},
+    interrupt::InterruptGuard,
+};
 
 use crate::sync::{SyncMode, SyncRequest};
 
@@ -29,8 +34,52 @@ pub fn disable(app: Arc<App>, sparse_repo: PathBuf) -> anyhow::Result<ExitCode>
     Ok(ExitCode(0))
 }
 
-pub fn sync(app: Arc<App>, sparse_repo: PathBuf) -> anyhow::Result<ExitCode> {
-    let request = SyncRequest::new(sparse_repo, SyncMode::Preemptive { force: true });
-    super::sync::run(&request, app).context("Running preemptive sync")?;
+/// Run a preemptive sync on request. It is forced unless `ignore_activity` is set, in which case it only runs if
+/// preemptive sync is enabled but does not wait for the machine to be idle.
+pub fn sync(
+    app: Arc<App>,
+    sparse_repo: PathBuf,
+    ignore_activity: bool,
+    idle_threshold: Option<Duration>,
+    verbose: bool,
+) -> anyhow::Result<ExitCode> {
+    let mut request = SyncRequest::new(
+        sparse_repo,
+        SyncMode::Preemptive {
+            force: !ignore_activity,
+            ignore_activity,
+        },
+    );
+    if let Some(idle_threshold) = idle_threshold {
+        request = request.with_idle_threshold(idle_threshold);
+    }
+    let result = super::sync::run(&request, app).context("Running preemptive sync")?;
+    if verbose {
+        if let Some(explanation) = result.status.explanation() {
+            eprintln!("{}", explanation);
+        }
+    }
     Ok(ExitCode(0))
 }
+
+/// Print whether the machine has been idle for `idle_threshold` every `interval`, as preemptive sync would see it,
+/// until interrupted.
+pub fn idle_monitor(interval: Duration, idle_threshold: Duration) -> anyhow::Result<ExitCode> {
+    let interrupt_guard = InterruptGuard::new()?;
+    eprintln!(
+        "Checking whether the machine has been idle for {} every {}; press Ctrl-C to stop.",
+        humantime::format_duration(idle_threshold),
+        humantime::format_duration(interval)
+    );
+    loop {
+        let status = unsafe { session_state::has_session_been_idle_for(idle_threshold) };
+        println!(
+            "{} {:?}",
+            Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            status
+        );
+        if interrupt_guard.sleep(interval) {
+            return Ok(ExitCode(0));
+        }
+    }
+}
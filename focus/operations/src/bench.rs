@@ -0,0 +1,172 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    fmt,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use content_addressed_cache::RocksDBCache;
+use focus_internals::{
+    locking,
+    model::repo::{PatternApplication, Repo},
+};
+use focus_util::app::App;
+use tracing::info;
+
+use crate::{sync::SYNC_LOCK_FILE_NAME, util::perform};
+
+/// Timings collected for one phase of a benchmarked sync.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PhaseTimings {
+    pub name: &'static str,
+    pub samples: Vec<Duration>,
+}
+
+impl PhaseTimings {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            samples: Vec::new(),
+        }
+    }
+
+    fn sorted_samples(&self) -> Vec<Duration> {
+        let mut samples = self.samples.clone();
+        samples.sort();
+        samples
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        self.samples.iter().min().copied()
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.samples.iter().max().copied()
+    }
+
+    /// The median sample. With an even number of samples, this is the mean of the middle two.
+    pub fn median(&self) -> Option<Duration> {
+        let samples = self.sorted_samples();
+        let len = samples.len();
+        match len {
+            0 => None,
+            _ if len % 2 == 1 => Some(samples[len / 2]),
+            _ => Some((samples[len / 2 - 1] + samples[len / 2]) / 2),
+        }
+    }
+}
+
+/// The result of benchmarking the sync computation.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BenchReport {
+    pub iterations: usize,
+    pub phases: Vec<PhaseTimings>,
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<32} {:>12} {:>12} {:>12}",
+            format!("Phase ({} iterations)", self.iterations),
+            "min",
+            "median",
+            "max"
+        )?;
+        for phase in self.phases.iter() {
+            let show = |duration: Option<Duration>| {
+                duration
+                    .map(|duration| format!("{:.3}s", duration.as_secs_f64()))
+                    .unwrap_or_else(|| "-".to_string())
+            };
+            writeln!(
+                f,
+                "{:<32} {:>12} {:>12} {:>12}",
+                phase.name,
+                show(phase.min()),
+                show(phase.median()),
+                show(phase.max())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn timed<T>(timings: &mut PhaseTimings, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    let started_at = Instant::now();
+    let result = perform(timings.name, f)?;
+    timings.samples.push(started_at.elapsed());
+    Ok(result)
+}
+
+/// Repeatedly compute the target set and sparse profile for the current selection at HEAD without applying it to the
+/// working tree, recording how long each phase took.
+pub fn run(sparse_repo: &Path, iterations: usize, app: Arc<App>) -> Result<BenchReport> {
+    if iterations == 0 {
+        bail!("The number of iterations must be at least 1");
+    }
+
+    let repo = Repo::open(sparse_repo, app.clone()).context("Failed to open repo")?;
+    let _lock = locking::hold_lock(sparse_repo, Path::new(SYNC_LOCK_FILE_NAME), app.clone())
+        .context("Failed to obtain synchronization lock")?;
+    let commit_id = repo
+        .get_head_commit()
+        .context("Resolving head commit")?
+        .id();
+    let cache = if repo.get_bazel_oneshot_resolution()? {
+        None
+    } else {
+        Some(RocksDBCache::new(repo.underlying()))
+    };
+
+    let mut target_set_timings = PhaseTimings::new("Computing the target set");
+    let mut profile_timings = PhaseTimings::new("Computing the sparse profile");
+    for iteration in 1..=iterations {
+        info!(iteration, iterations, "Running benchmark iteration");
+        let targets = timed(&mut target_set_timings, || {
+            repo.selection_manager()?.compute_complete_target_set()
+        })?;
+        timed(&mut profile_timings, || {
+            repo.sync(
+                commit_id,
+                &targets,
+                PatternApplication::Skip,
+                app.clone(),
+                cache.as_ref(),
+                None,
+            )
+        })?;
+    }
+
+    Ok(BenchReport {
+        iterations,
+        phases: vec![target_set_timings, profile_timings],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizing_phase_timings() {
+        let mut timings = PhaseTimings::new("phase");
+        assert_eq!(timings.median(), None);
+
+        timings.samples = vec![
+            Duration::from_millis(30),
+            Duration::from_millis(10),
+            Duration::from_millis(20),
+        ];
+        assert_eq!(timings.min(), Some(Duration::from_millis(10)));
+        assert_eq!(timings.median(), Some(Duration::from_millis(20)));
+        assert_eq!(timings.max(), Some(Duration::from_millis(30)));
+
+        timings.samples.push(Duration::from_millis(40));
+        assert_eq!(timings.median(), Some(Duration::from_millis(25)));
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Finding the part of the selection that breaks a build. The selected projects and targets are bisected: subsets of
+//! them are synced and a test command is run against the working tree until the smallest failing subset is found.
+//! Mandatory projects are always part of the synced subset.
+
+use std::{
+    fmt,
+    path::Path,
+    process::{Command, Stdio},
+    sync::Arc,
+};
+
+use anyhow::{bail, Context, Result};
+use focus_internals::{
+    model::{repo::Repo, selection::resolve_targets_for_project},
+    target::TargetSet,
+};
+use focus_util::app::App;
+use tracing::info;
+
+use crate::{
+    selection::{selected_layers, Layer},
+    sync::{SyncMode, SyncRequest},
+};
+
+/// What bisecting the selection found.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BisectOutcome {
+    /// The test command passes with the whole selection, so there is nothing to bisect.
+    NoFailure,
+
+    /// The test command fails with only the mandatory projects, so no selected layer is responsible.
+    MandatoryProjectsFail,
+
+    /// The smallest set of layers found that still fails. More than one layer is reported if the failure needs
+    /// layers from both halves of the remaining candidates.
+    Culprits(Vec<String>),
+}
+
+/// The outcome of bisecting along with how many subsets were tested to find it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BisectReport {
+    pub outcome: BisectOutcome,
+    pub steps: usize,
+}
+
+impl fmt::Display for BisectReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.outcome {
+            BisectOutcome::NoFailure => {
+                writeln!(f, "The test command passes with the whole selection.")
+            }
+            BisectOutcome::MandatoryProjectsFail => writeln!(
+                f,
+                "The test command fails with only the mandatory projects checked out."
+            ),
+            BisectOutcome::Culprits(names) => {
+                writeln!(
+                    f,
+                    "The failure is reproduced by {} after {} steps:",
+                    if names.len() == 1 {
+                        "this layer"
+                    } else {
+                        "these layers together"
+                    },
+                    self.steps
+                )?;
+                for name in names {
+                    writeln!(f, "  {}", name)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Syncs subsets of the selection and runs the test command against them.
+struct Bisector<'a> {
+    sparse_repo: &'a Path,
+    test_command: &'a [String],
+    mandatory_targets: TargetSet,
+    app: Arc<App>,
+    steps: usize,
+}
+
+impl Bisector<'_> {
+    /// Sync the mandatory projects and `layers`, then report whether the test command fails.
+    fn fails_with(&mut self, layers: &[Layer]) -> Result<bool> {
+        self.steps += 1;
+        let mut targets = self.mandatory_targets.clone();
+        for layer in layers {
+            targets.extend(layer.targets.iter().cloned());
+        }
+        let names: Vec<&str> = layers.iter().map(|layer| layer.name.as_str()).collect();
+        info!(step = self.steps, layers = ?names, "Testing a subset of the selection");
+        eprintln!("Step {}: testing {} layers", self.steps, layers.len());
+
+        crate::sync::run(
+            &SyncRequest::new(self.sparse_repo, SyncMode::Incremental).with_targets(targets),
+            self.app.clone(),
+        )
+        .context("Syncing a subset of the selection")?;
+
+        let status = Command::new(&self.test_command[0])
+            .args(&self.test_command[1..])
+            .current_dir(self.sparse_repo)
+            .stdin(Stdio::null())
+            .status()
+            .with_context(|| format!("Running {:?}", self.test_command))?;
+        eprintln!(
+            "Step {}: the test command {}",
+            self.steps,
+            if status.success() { "passed" } else { "failed" }
+        );
+        Ok(!status.success())
+    }
+
+    fn bisect(&mut self, layers: Vec<Layer>) -> Result<BisectOutcome> {
+        if !self.fails_with(&layers)? {
+            return Ok(BisectOutcome::NoFailure);
+        }
+        if self.fails_with(&[])? {
+            return Ok(BisectOutcome::MandatoryProjectsFail);
+        }
+
+        let mut candidates = layers;
+        while candidates.len() > 1 {
+            let right = candidates.split_off(candidates.len() / 2);
+            if self.fails_with(&candidates)? {
+                continue;
+            }
+            if self.fails_with(&right)? {
+                candidates = right;
+                continue;
+            }
+            // Neither half fails alone, so the failure needs layers from both.
+            candidates.extend(right);
+            break;
+        }
+        Ok(BisectOutcome::Culprits(
+            candidates.into_iter().map(|layer| layer.name).collect(),
+        ))
+    }
+}
+
+/// Find the smallest set of selected layers for which `test_command` fails, running it in the root of the working
+/// tree after syncing each subset. The working tree is synced to the whole selection again afterward.
+pub fn run(sparse_repo: &Path, test_command: &[String], app: Arc<App>) -> Result<BisectReport> {
+    if test_command.is_empty() {
+        bail!("No test command was given");
+    }
+
+    let repo = Repo::open(sparse_repo, app.clone()).context("Failed to open repo")?;
+    let selections = repo.selection_manager()?;
+    let layers = selected_layers(&selections)?;
+    if layers.is_empty() {
+        bail!("Nothing is selected, so there is nothing to bisect");
+    }
+    let mandatory_targets = resolve_targets_for_project(
+        selections.mandatory_projects(),
+        &selections.project_catalog().optional_projects.underlying,
+    )
+    .context("Resolving the targets of mandatory projects")?;
+
+    let mut bisector = Bisector {
+        sparse_repo,
+        test_command,
+        mandatory_targets,
+        app: app.clone(),
+        steps: 0,
+    };
+    let outcome = bisector.bisect(layers);
+
+    eprintln!("Restoring the whole selection");
+    crate::sync::run(&SyncRequest::new(sparse_repo, SyncMode::Incremental), app)
+        .context("Syncing the whole selection again")?;
+
+    Ok(BisectReport {
+        outcome: outcome?,
+        steps: bisector.steps,
+    })
+}
@@ -4,18 +4,25 @@
 use crate::event;
 use crate::sync::SyncMode;
 use focus_internals::index::RocksDBMemoizationCacheExt;
-use focus_internals::model::selection::{Operation, OperationAction};
+use focus_internals::model::outlining::PatternSet;
+use focus_internals::model::selection::{store_model, Operation, OperationAction};
 
 use anyhow::{bail, Context, Result};
 use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
 use content_addressed_cache::RocksDBCache;
-use focus_internals::{model::repo::Repo, target::TargetSet, tracker::Tracker};
+use focus_internals::{
+    model::repo::{PatternApplication, Repo},
+    target::TargetSet,
+    tracker::{CloneSpec, Tracker},
+};
 
+use focus_util::interrupt::InterruptGuard;
 use focus_util::sandbox_command::SandboxCommand;
-use focus_util::{self, app::App, git_helper, sandbox_command::SandboxCommandOutput};
+use focus_util::{self, app::App, git_helper, offline, sandbox_command::SandboxCommandOutput};
 use git2::Repository;
 
-use std::collections::HashSet;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
 use std::fs::OpenOptions;
 use std::process::Command;
 use std::{
@@ -28,12 +35,19 @@ use std::{
 use tracing::{debug, error, info, info_span, warn};
 use url::Url;
 
+/// Run `git clone`, logging its progress as it transfers objects. Pressing Ctrl-C stops the transfer promptly and
+/// returns an error, leaving the caller to remove the partial clone.
 pub fn run_clone(mut clone_builder: CloneBuilder, app: Arc<App>) -> Result<()> {
     clone_builder.add_clone_args(vec!["--progress"]);
     let (mut cmd, scmd) = clone_builder.build(app)?;
 
-    scmd.ensure_success_or_log(&mut cmd, SandboxCommandOutput::Stderr)
-        .map(|_| ())
+    let interrupt_guard = InterruptGuard::new()?;
+    scmd.ensure_success_or_log_unless_interrupted(
+        &mut cmd,
+        SandboxCommandOutput::Stderr,
+        &interrupt_guard,
+    )
+    .map(|_| ())
 }
 
 #[derive(Debug, Eq, Hash, PartialEq)]
@@ -290,12 +304,67 @@ impl TryFrom<&str> for Origin {
             Ok(Origin::Remote(url))
         } else {
             let dense_repo_path = PathBuf::from(value);
-            let dense_repo_path = focus_util::paths::expand_tilde(dense_repo_path.as_path())?;
+            let dense_repo_path = focus_util::paths::expand_path(dense_repo_path.as_path())?;
             Ok(Origin::Local(dense_repo_path))
         }
     }
 }
 
+/// The name of the branch checked out in the dense repo, for cloning when no branch is specified.
+pub fn branch_checked_out_in(dense_repo_path: &Path) -> Result<String> {
+    let repo = git2::Repository::open(dense_repo_path)
+        .with_context(|| format!("Opening the dense repo at {}", dense_repo_path.display()))?;
+    let head = repo
+        .head()
+        .with_context(|| format!("Resolving HEAD in {}", dense_repo_path.display()))?;
+    if !head.is_branch() {
+        bail!(
+            "The dense repo at {} has a detached HEAD; specify the branch to clone with --branch",
+            dense_repo_path.display()
+        );
+    }
+    match head.shorthand() {
+        Some(name) => Ok(name.to_owned()),
+        None => bail!(
+            "The name of the branch checked out in {} is not valid UTF-8; specify the branch to clone with --branch",
+            dense_repo_path.display()
+        ),
+    }
+}
+
+/// Exactly what a sparse repo was cloned with: the commit, the selection, the targets it resolved to and the sparse
+/// profile they were resolved into. Cloning from it recreates the same sparse repo without resolving anything, even
+/// if the build graph has changed since.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CloneLock {
+    pub commit: String,
+    pub projects_and_targets: Vec<String>,
+    pub targets: BTreeSet<String>,
+    pub patterns: PatternSet,
+}
+
+impl CloneLock {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Opening the lockfile {}", path.display()))?;
+        serde_json::from_reader(std::io::BufReader::new(file))
+            .with_context(|| format!("Reading the lockfile {}", path.display()))
+    }
+
+    pub fn store(&self, path: &Path) -> Result<()> {
+        store_model(path, self).with_context(|| format!("Writing the lockfile {}", path.display()))
+    }
+
+    fn commit_id(&self) -> Result<git2::Oid> {
+        git2::Oid::from_str(&self.commit).with_context(|| {
+            format!(
+                "The locked commit {:?} is not a valid object ID",
+                self.commit
+            )
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct CloneArgs {
     pub origin: Option<Origin>,
@@ -305,6 +374,15 @@ pub struct CloneArgs {
     pub days_of_history: u64,
     pub do_post_clone_fetch: bool,
     pub sync_mode: SyncMode,
+
+    /// Write the sparse profile without checking out the files it selects.
+    pub no_checkout: bool,
+
+    /// Record what the repo was cloned with in a lockfile at this path.
+    pub write_lock: Option<PathBuf>,
+
+    /// Recreate the repo recorded in this lockfile instead of resolving `projects_and_targets`.
+    pub from_lock: Option<PathBuf>,
 }
 
 impl Default for CloneArgs {
@@ -317,6 +395,9 @@ impl Default for CloneArgs {
             days_of_history: 90,
             do_post_clone_fetch: true,
             sync_mode: SyncMode::Incremental,
+            no_checkout: false,
+            write_lock: None,
+            from_lock: None,
         }
     }
 }
@@ -338,6 +419,9 @@ pub fn run(
         days_of_history,
         do_post_clone_fetch,
         sync_mode,
+        no_checkout,
+        write_lock,
+        from_lock,
     } = clone_args;
 
     let origin = match origin {
@@ -345,6 +429,12 @@ pub fn run(
         None => bail!("Clone does not have a valid origin"),
     };
 
+    let lock = from_lock.as_deref().map(CloneLock::load).transpose()?;
+    let projects_and_targets = match &lock {
+        Some(lock) => lock.projects_and_targets.clone(),
+        None => projects_and_targets,
+    };
+
     if sparse_repo_path.is_dir() {
         bail!("{} already exists", sparse_repo_path.display());
     }
@@ -352,6 +442,10 @@ pub fn run(
     //create the sparse repo dir, so other clones don't use the same name
     std::fs::create_dir_all(&sparse_repo_path).context("Failed to create repo directory")?;
 
+    let clone_spec = CloneSpec {
+        projects_and_targets: projects_and_targets.clone(),
+    };
+
     let mut tmp_sparse_repo_path = PathBuf::from(app.sandbox().path());
     tmp_sparse_repo_path.push("tmp_sparse_repo");
 
@@ -388,15 +482,22 @@ pub fn run(
             }
         };
 
-        set_up_sparse_repo(
+        let written_lock = set_up_sparse_repo(
             &tmp_sparse_repo_path,
             projects_and_targets,
             template,
             sync_mode,
+            no_checkout,
+            lock.as_ref(),
             app.clone(),
         )?;
+        if let Some(write_lock) = &write_lock {
+            written_lock.store(write_lock)?;
+        }
 
-        if do_post_clone_fetch {
+        if do_post_clone_fetch && app.options().offline {
+            info!("Skipping the post clone fetch in offline mode");
+        } else if do_post_clone_fetch {
             fetch_default_remote(&tmp_sparse_repo_path, app.clone())
                 .context("Could not complete post clone fetch")?;
         }
@@ -411,6 +512,10 @@ pub fn run(
         )
         .context("Could not move repo into place")?;
 
+        tracker
+            .record_clone_spec(&sparse_repo_path, &clone_spec, app.clone())
+            .context("Recording what the repo was cloned with")?;
+
         Ok(())
     };
 
@@ -543,6 +648,7 @@ fn clone_remote(
     if sparse_repo_path.is_dir() {
         bail!("Sparse repo directory already exists");
     }
+    offline::ensure_online(&app, &format!("clone {}", dense_repo_url))?;
 
     info!(
         "Cloning {} to {}",
@@ -562,13 +668,17 @@ fn clone_remote(
     .context("Failed to clone the repository")
 }
 
+/// Set up the trees and selection of a freshly cloned repo and sync it, returning a lock recording the result. If
+/// `lock` is given, its commit is checked out and its sparse profile applied instead of resolving the selection.
 fn set_up_sparse_repo(
     sparse_repo_path: &Path,
     projects_and_targets: Vec<String>,
     template: Option<ClonedRepoTemplate>,
     sync_mode: SyncMode,
+    no_checkout: bool,
+    lock: Option<&CloneLock>,
     app: Arc<App>,
-) -> Result<()> {
+) -> Result<CloneLock> {
     {
         let repo = Repo::open(sparse_repo_path, app.clone()).context("Failed to open repo")?;
         // TODO: Parallelize these tree set up processes.
@@ -586,25 +696,55 @@ fn set_up_sparse_repo(
     // before running rest of setup config worktree view to be filtered
     let working_tree = repo.working_tree()?;
     working_tree.set_filter_config(true)?;
+    if let Some(lock) = lock {
+        let commit_id = lock.commit_id()?;
+        repo.underlying().find_commit(commit_id).with_context(|| {
+            format!(
+                "The locked commit {} is not in the clone; it may be older than the days of history cloned",
+                commit_id
+            )
+        })?;
+        info!(%commit_id, "Checking out the locked commit");
+        working_tree.switch_to_commit(commit_id, true, false, app.clone())?;
+    }
     let head_commit = repo.get_head_commit().context("Resolving head commit")?;
     let target_set = compute_and_store_initial_selection(&repo, projects_and_targets, template)?;
     debug!(target_set = ?target_set, "Complete target set");
     repo.set_bazel_oneshot_resolution(sync_mode == SyncMode::OneShot)?;
 
-    let odb = if repo.get_bazel_oneshot_resolution()? {
-        None
+    let targets: BTreeSet<String> = target_set.iter().map(|target| target.to_string()).collect();
+    let patterns = match lock {
+        Some(lock) => {
+            if lock.targets != targets {
+                warn!("The selection resolves to different targets than those locked; using the locked sparse profile");
+            }
+            lock.patterns.clone()
+        }
+        None => {
+            let odb = if repo.get_bazel_oneshot_resolution()? {
+                None
+            } else {
+                Some(RocksDBCache::new(repo.underlying()))
+            };
+            let (patterns, _) = repo
+                .compute_sparse_profile(
+                    head_commit.id(),
+                    &target_set,
+                    app.clone(),
+                    odb.as_ref(),
+                    None,
+                )
+                .context("Sync failed")?;
+            patterns
+        }
+    };
+    let pattern_application = if no_checkout {
+        PatternApplication::WriteOnly
     } else {
-        Some(RocksDBCache::new(repo.underlying()))
+        PatternApplication::Checkout
     };
-    repo.sync(
-        head_commit.id(),
-        &target_set,
-        false,
-        app,
-        odb.as_ref(),
-        None,
-    )
-    .context("Sync failed")?;
+    repo.apply_sparse_profile(patterns.clone(), pattern_application, app)
+        .context("Sync failed")?;
 
     repo.working_tree()?.write_sync_point_ref()?;
 
@@ -614,7 +754,20 @@ fn set_up_sparse_repo(
 
     set_up_bazel_preflight_script(sparse_repo_path)?;
 
-    Ok(())
+    let selection = repo.selection_manager()?.selection()?;
+    let mut projects_and_targets: Vec<String> = selection
+        .projects
+        .iter()
+        .map(|project| project.name.clone())
+        .chain(selection.targets.iter().map(|target| target.to_string()))
+        .collect();
+    projects_and_targets.sort();
+    Ok(CloneLock {
+        commit: head_commit.id().to_string(),
+        projects_and_targets,
+        targets,
+        patterns,
+    })
 }
 
 fn compute_and_store_initial_selection(
@@ -1078,13 +1231,80 @@ impl ClonedRepoTemplate {
 
 #[cfg(test)]
 mod test {
-    use crate::{clone::ClonedRepoTemplate, testing::integration::RepoPairFixture};
-    use focus_internals::target::Target;
+    use crate::{
+        clone::{branch_checked_out_in, CloneArgs, CloneLock, ClonedRepoTemplate, Origin},
+        testing::integration::RepoPairFixture,
+    };
+    use focus_internals::{model::repo::Repo, target::Target};
     use focus_testing::init_logging;
 
     use anyhow::Result;
     use url::Url;
 
+    #[test]
+    fn clone_from_lock_recreates_the_locked_clone() -> Result<()> {
+        init_logging();
+
+        let mut fixture = RepoPairFixture::new()?;
+        fixture
+            .projects_and_targets
+            .push(String::from("directory:library_a"));
+        let lock_path = fixture.dir.path().join("focus.lock");
+        let clone_args = CloneArgs {
+            origin: Some(Origin::Local(fixture.dense_repo_path.clone())),
+            branch: fixture.branch.clone(),
+            projects_and_targets: fixture.projects_and_targets.clone(),
+            do_post_clone_fetch: false,
+            write_lock: Some(lock_path.clone()),
+            ..Default::default()
+        };
+        crate::clone::run(
+            fixture.sparse_repo_path.clone(),
+            clone_args,
+            None,
+            &fixture.tracker,
+            fixture.app.clone(),
+        )?;
+        let lock = CloneLock::load(&lock_path)?;
+        let locked_repo = fixture.sparse_repo()?;
+        assert_eq!(lock.commit, locked_repo.get_head_commit()?.id().to_string());
+        assert_eq!(lock.projects_and_targets, vec!["directory:library_a"]);
+
+        // Move the branch on, then recreate the clone from the lock.
+        fixture
+            .dense_repo
+            .write_and_commit_file("library_a/added.txt", b"added", "Add a file")?;
+        let relocked_path = fixture.dir.path().join("relocked");
+        let clone_args = CloneArgs {
+            origin: Some(Origin::Local(fixture.dense_repo_path.clone())),
+            branch: fixture.branch.clone(),
+            do_post_clone_fetch: false,
+            from_lock: Some(lock_path),
+            ..Default::default()
+        };
+        crate::clone::run(
+            relocked_path.clone(),
+            clone_args,
+            None,
+            &fixture.tracker,
+            fixture.app.clone(),
+        )?;
+
+        let relocked_repo = Repo::open(&relocked_path, fixture.app.clone())?;
+        assert_eq!(
+            relocked_repo.get_head_commit()?.id().to_string(),
+            lock.commit
+        );
+        assert_eq!(
+            std::fs::read_to_string(relocked_repo.working_tree()?.sparse_checkout_path())?,
+            std::fs::read_to_string(locked_repo.working_tree()?.sparse_checkout_path())?
+        );
+        assert!(relocked_path.join("library_a").is_dir());
+        assert!(!relocked_path.join("library_a/added.txt").exists());
+
+        Ok(())
+    }
+
     #[test]
     fn clone_contains_an_initial_layer_set() -> Result<()> {
         init_logging();
@@ -1116,6 +1336,30 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn cloning_the_branch_checked_out_in_the_dense_repo() -> Result<()> {
+        init_logging();
+
+        let fixture = RepoPairFixture::new()?;
+        let original_branch = branch_checked_out_in(&fixture.dense_repo_path)?;
+
+        fixture
+            .dense_repo
+            .create_and_switch_to_branch("feature_branch")?;
+        assert_eq!(
+            branch_checked_out_in(&fixture.dense_repo_path)?,
+            "feature_branch"
+        );
+        assert_ne!(original_branch, "feature_branch");
+
+        let repo = git2::Repository::open(&fixture.dense_repo_path)?;
+        let head_commit_id = repo.head()?.peel_to_commit()?.id();
+        repo.set_head_detached(head_commit_id)?;
+        assert!(branch_checked_out_in(&fixture.dense_repo_path).is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn test_template_from_url() -> Result<()> {
         assert_eq!(
@@ -2,8 +2,10 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use anyhow::{bail, Context, Result};
+use serde_json::json;
 use tracing::{info, warn};
 
+use std::collections::BTreeSet;
 use std::path::{Path, PathBuf};
 
 use std::sync::{mpsc, Arc};
@@ -103,7 +105,48 @@ fn notify(repo: &Repo, _subtitle: &str, message: &str, _persistent: bool) -> Res
     Ok(())
 }
 
-pub fn run(repo_path: &Path, advisory: bool, args: Vec<String>, app: Arc<App>) -> Result<ExitCode> {
+/// Print the changed paths that affect the build graph as JSON, optionally with the selected targets that name the
+/// packages containing them.
+fn print_report(
+    repo: &Repo,
+    committed_changes: &[PathBuf],
+    uncommitted_changes: &[PathBuf],
+    include_affected_targets: bool,
+) -> Result<()> {
+    let mut report = json!({
+        "build_graph_changed": !committed_changes.is_empty() || !uncommitted_changes.is_empty(),
+        "committed_changes": committed_changes,
+        "uncommitted_changes": uncommitted_changes,
+    });
+    if include_affected_targets {
+        let targets = repo
+            .selection_manager()?
+            .compute_complete_target_set()
+            .context("Computing the selected targets")?;
+        let affected_targets: BTreeSet<String> = targets
+            .iter()
+            .filter(|target| {
+                committed_changes
+                    .iter()
+                    .chain(uncommitted_changes.iter())
+                    .any(|path| target.may_be_affected_by(path))
+            })
+            .map(|target| target.to_string())
+            .collect();
+        report["affected_targets"] = json!(affected_targets);
+    }
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+pub fn run(
+    repo_path: &Path,
+    advisory: bool,
+    json: bool,
+    include_affected_targets: bool,
+    args: Vec<String>,
+    app: Arc<App>,
+) -> Result<ExitCode> {
     if let Some(subcommand) = args.get(0) {
         if is_ignored_subcommand(subcommand) {
             return Ok(ExitCode(0));
@@ -165,6 +208,22 @@ pub fn run(repo_path: &Path, advisory: bool, args: Vec<String>, app: Arc<App>) -
     };
 
     let repo = Repo::open(repo_path, app)?;
+    if json {
+        print_report(
+            &repo,
+            &committed_changes,
+            &uncommitted_changes,
+            include_affected_targets,
+        )?;
+        return Ok(
+            if committed_changes.is_empty() && uncommitted_changes.is_empty() {
+                ExitCode(0)
+            } else {
+                failing_exit_code
+            },
+        );
+    }
+
     if !committed_changes.is_empty() && !uncommitted_changes.is_empty() {
         notify(&repo, repo_name, "Committed and uncommitted changes affect the build graph, please commit changes and run `focus sync` to update the sparse checkout!", true)?;
         Ok(failing_exit_code)
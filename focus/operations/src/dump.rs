@@ -0,0 +1,119 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{collections::BTreeSet, path::Path, sync::Arc};
+
+use anyhow::{Context, Result};
+use chrono::SecondsFormat;
+use focus_internals::model::repo::{Repo, WorkingTreeKind};
+use focus_util::app::App;
+use serde_json::{json, Value};
+
+use crate::{effective_config, status};
+
+/// Describe everything focus knows about the repo as one JSON document: its configuration, selection, sync point, and
+/// project catalog. Nothing is changed.
+pub fn repo_model(sparse_repo: &Path, app: Arc<App>) -> Result<Value> {
+    let repo = Repo::open(sparse_repo, app.clone()).context("Failed to open the repo")?;
+    let working_tree = repo.working_tree()?;
+    let config = repo.config();
+
+    let settings: Vec<Value> = effective_config::effective_settings(sparse_repo, app)?
+        .iter()
+        .map(|setting| {
+            json!({
+                "name": setting.name,
+                "value": setting.value,
+                "source": setting.source.to_string(),
+            })
+        })
+        .collect();
+
+    let selections = repo.selection_manager()?;
+    let selection = selections.selection()?;
+    let projects: BTreeSet<&str> = selection
+        .projects
+        .iter()
+        .filter(|project| project.is_selectable())
+        .map(|project| project.name.as_str())
+        .collect();
+    let targets: BTreeSet<String> = selection.targets.iter().map(|t| t.to_string()).collect();
+    let overrides: Vec<Value> = selection
+        .overrides
+        .iter()
+        .map(|(from, to)| json!({ "from": from.to_string(), "to": to.to_string() }))
+        .collect();
+
+    let catalog = selections.project_catalog();
+    let project_set_files: BTreeSet<&String> = catalog
+        .optional_projects
+        .sources
+        .values()
+        .chain(catalog.mandatory_projects.sources.values())
+        .collect();
+    let deprecated_projects = catalog
+        .optional_projects
+        .underlying
+        .values()
+        .filter(|project| project.is_deprecated())
+        .count();
+
+    let oid = |oid: Option<git2::Oid>| oid.map(|oid| oid.to_string());
+    Ok(json!({
+        "path": repo.path(),
+        "git_dir": repo.git_dir(),
+        "head": repo.get_head_commit().ok().map(|commit| commit.id().to_string()),
+        "primary_branch": repo.primary_branch_name().ok(),
+        "config": {
+            "index": config.index,
+            "notification": config.notification,
+            "preemptive_sync": {
+                "enabled": repo.get_preemptive_sync_enabled()?,
+                "idle_threshold_millis": repo.get_preemptive_sync_idle_threshold()?.as_millis() as u64,
+            },
+            "bazel_oneshot_resolution": repo.get_bazel_oneshot_resolution()?,
+            "settings": settings,
+        },
+        "selection": {
+            "name": repo.get_active_selection_name()?,
+            "digest": repo.selection_digest()?,
+            "projects": projects,
+            "targets": targets,
+            "overrides": overrides,
+        },
+        "sync_point": {
+            "sparse": oid(working_tree.read_sparse_sync_point_ref()?),
+            "preemptive": oid(working_tree.read_preemptive_sync_point_ref()?),
+            "fingerprint": repo.read_sync_point_fingerprint()?,
+            "selection_name": repo.read_sync_point_selection_name()?,
+            "last_synced": status::last_sync_completed_at(&repo)?
+                .map(|at| at.to_rfc3339_opts(SecondsFormat::Secs, true)),
+            "needs_sync": Repo::check_sync_needed(sparse_repo).ok(),
+        },
+        "working_tree": {
+            "kind": match working_tree.kind() {
+                WorkingTreeKind::Sparse => "sparse",
+                WorkingTreeKind::Dense => "dense",
+            },
+            "checkout_backend": working_tree.get_checkout_backend()?.to_string(),
+            "sparse_mode": working_tree.get_sparse_mode()?.to_string(),
+            "sparse_patterns": working_tree.sparse_pattern_count()?,
+            "checkout_pending": working_tree.checkout_pending(),
+        },
+        "project_catalog": {
+            "optional_projects": catalog.optional_projects.underlying.len(),
+            "mandatory_projects": catalog.mandatory_projects.underlying.len(),
+            "deprecated_projects": deprecated_projects,
+            "project_set_files": project_set_files,
+        },
+    }))
+}
+
+/// Print the repo model as pretty-printed JSON.
+pub fn run(sparse_repo: &Path, app: Arc<App>) -> Result<()> {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&repo_model(sparse_repo, app)?)?
+    );
+    Ok(())
+}
@@ -0,0 +1,111 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{path::Path, sync::Arc};
+
+use anyhow::{Context, Result};
+use focus_internals::model::{
+    data_paths::DataPaths,
+    repo::{EffectiveSetting, Repo, SettingSource},
+};
+use focus_util::app::App;
+use serde_json::json;
+
+/// Every setting focus uses in the given repo, along with where its value came from.
+pub fn effective_settings(sparse_repo: &Path, app: Arc<App>) -> Result<Vec<EffectiveSetting>> {
+    let repo = Repo::open(sparse_repo, app.clone()).context("Failed to open the repo")?;
+    let mut settings = repo.effective_settings()?;
+
+    let working_tree = repo.working_tree()?;
+    let in_repo_bazel_wrapper = working_tree.work_dir().join("bazel");
+    settings.push(EffectiveSetting::new(
+        "bazel",
+        if in_repo_bazel_wrapper.is_file() {
+            in_repo_bazel_wrapper.display().to_string()
+        } else {
+            String::from("bazel")
+        },
+        SettingSource::Default,
+    ));
+    let paths = DataPaths::from_working_tree(working_tree)?;
+    settings.push(EffectiveSetting::new(
+        "projects-dir",
+        paths.project_dir.display(),
+        SettingSource::Default,
+    ));
+
+    // Global options reach the app already parsed, so a value given through a flag's environment variable is reported
+    // as coming from the flag.
+    let given = |given: bool| {
+        if given {
+            SettingSource::Flag
+        } else {
+            SettingSource::Default
+        }
+    };
+    let options = app.options();
+    settings.push(EffectiveSetting::new(
+        "git",
+        app.git_binary().git_binary_path.display(),
+        given(options.git_binary.is_some()),
+    ));
+    settings.push(EffectiveSetting::new(
+        "sandbox-dir",
+        app.sandbox_dir().display(),
+        given(options.sandbox_dir.is_some()),
+    ));
+    settings.push(EffectiveSetting::new(
+        "offline",
+        options.offline,
+        given(options.offline),
+    ));
+    settings.push(EffectiveSetting::new(
+        "no-deprecated-warnings",
+        options.no_deprecated_warnings,
+        given(options.no_deprecated_warnings),
+    ));
+    settings.push(match &options.data_dir {
+        Some(dir) => EffectiveSetting::new("data-dir", dir.display(), SettingSource::Flag),
+        None => EffectiveSetting::new(
+            "data-dir",
+            "(focus config and cache directories)",
+            SettingSource::Default,
+        ),
+    });
+
+    Ok(settings)
+}
+
+/// Print the effective settings for the repo, either as aligned columns or as a JSON array.
+pub fn run(sparse_repo: &Path, json: bool, app: Arc<App>) -> Result<()> {
+    let settings = effective_settings(sparse_repo, app)?;
+    if json {
+        let settings: Vec<_> = settings
+            .iter()
+            .map(|setting| {
+                json!({
+                    "name": setting.name,
+                    "value": setting.value,
+                    "source": setting.source.to_string(),
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&settings)?);
+    } else {
+        let name_width = settings
+            .iter()
+            .map(|setting| setting.name.len())
+            .max()
+            .unwrap_or(0);
+        for setting in settings.iter() {
+            println!(
+                "{:<name_width$}  {:<10}  {}",
+                setting.name,
+                setting.source.to_string(),
+                setting.value,
+                name_width = name_width
+            );
+        }
+    }
+    Ok(())
+}
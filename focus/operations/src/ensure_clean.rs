@@ -6,23 +6,71 @@ use std::{path::Path, sync::Arc};
 use anyhow::{bail, Context, Result};
 
 use focus_internals::model::repo::Repo;
-use focus_util::app::App;
+use focus_util::app::{App, ExitCode};
+use serde_derive::Serialize;
 
 use super::util::perform;
 
-pub fn run(sparse_repo_path: &Path, app: Arc<App>) -> Result<()> {
+/// The exit code of `focus ensure-clean` when the working tree has changes. Errors exit with 1.
+pub const DIRTY_EXIT_CODE: i32 = 2;
+
+/// A changed or untracked path that keeps the working tree from being clean.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DirtyPath {
+    /// The two-letter status code as shown by `git status --short`, like ` M` or `??`.
+    pub status: String,
+    pub path: String,
+}
+
+pub fn dirty_paths(sparse_repo_path: &Path, app: Arc<App>) -> Result<Vec<DirtyPath>> {
     let repo = Repo::open(sparse_repo_path, app.clone())
         .with_context(|| format!("Opening repo in {}", sparse_repo_path.display()))?;
     let working_tree = repo.working_tree()?;
+    Ok(working_tree
+        .dirty_paths(app)?
+        .into_iter()
+        .map(|(status, path)| DirtyPath { status, path })
+        .collect())
+}
 
-    let clean = perform("Checking that sparse repo is in a clean state", || {
-        working_tree.is_clean(app.clone())
+pub fn run(sparse_repo_path: &Path, app: Arc<App>) -> Result<()> {
+    let dirty_paths = perform("Checking that sparse repo is in a clean state", || {
+        dirty_paths(sparse_repo_path, app.clone())
     })?;
 
-    if !clean {
+    if !dirty_paths.is_empty() {
         eprintln!("The working tree in the sparse repo must be in a clean state. Commit or stash changes and try to run the sync again.");
+        for dirty_path in dirty_paths.iter() {
+            eprintln!("  {} {}", dirty_path.status, dirty_path.path);
+        }
         bail!("Sparse repo working tree is not in a clean state");
     }
 
     Ok(())
 }
+
+/// Report the paths that keep the working tree from being clean, exiting with `DIRTY_EXIT_CODE` if there are any.
+pub fn check(sparse_repo_path: &Path, json: bool, app: Arc<App>) -> Result<ExitCode> {
+    let dirty_paths = dirty_paths(sparse_repo_path, app)?;
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({
+                "clean": dirty_paths.is_empty(),
+                "dirty_paths": dirty_paths,
+            }))?
+        );
+    } else if dirty_paths.is_empty() {
+        println!("The working tree is clean");
+    } else {
+        for dirty_path in dirty_paths.iter() {
+            println!("{} {}", dirty_path.status, dirty_path.path);
+        }
+    }
+
+    Ok(ExitCode(if dirty_paths.is_empty() {
+        0
+    } else {
+        DIRTY_EXIT_CODE
+    }))
+}
@@ -9,7 +9,9 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Context;
-use content_addressed_cache::{Cache, CacheSynchronizer, GitBackedCacheSynchronizer, KeysetID};
+use content_addressed_cache::{
+    AccessStats, Cache, CacheSynchronizer, CompositeKey, GitBackedCacheSynchronizer, KeysetID,
+};
 use focus_util::app::{App, ExitCode};
 use focus_util::git_helper;
 use focus_util::paths::assert_focused_repo;
@@ -21,8 +23,8 @@ use focus_internals::index::{
     FUNCTION_ID,
 };
 use focus_internals::model::configuration::IndexConfig;
-use focus_internals::model::repo::Repo;
-use focus_internals::model::selection::OperationAction;
+use focus_internals::model::repo::{PatternApplication, Repo};
+use focus_internals::model::selection::{resolve_targets_for_project, OperationAction};
 use focus_internals::target::{Target, TargetSet};
 
 const PARENTS_TO_TRY_IN_FETCH: u32 = 100;
@@ -38,6 +40,29 @@ pub fn clear(sparse_repo_path: PathBuf) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Print the most and least frequently read entries of the on-disk cache, `count` of each.
+pub fn print_cache_stats(sparse_repo_path: PathBuf, count: usize) -> anyhow::Result<()> {
+    let repo = git2::Repository::open(sparse_repo_path).context("opening sparse repo")?;
+    let odb = RocksDBCache::new(&repo);
+    let entries = odb.entries_by_access()?;
+    println!("Entries read: {}", entries.len());
+
+    let print_entry = |(key, stats): &(CompositeKey, AccessStats)| {
+        let last_accessed: chrono::DateTime<chrono::Local> = stats.last_accessed.into();
+        println!(
+            "  {} hits={} last-accessed={}",
+            key.to_string(),
+            stats.hits,
+            last_accessed.to_rfc3339()
+        );
+    };
+    println!("Hottest:");
+    entries.iter().take(count).for_each(print_entry);
+    println!("Coldest:");
+    entries.iter().rev().take(count).for_each(print_entry);
+    Ok(())
+}
+
 fn dep_key_to_target(dep_key: &DependencyKey) -> String {
     match dep_key {
         DependencyKey::BazelPackage(label) | DependencyKey::BazelBuildFile(label) => {
@@ -95,15 +120,15 @@ fn resolve_targets(
             }
 
             let repo = Repo::open(repo.path(), app.clone())?;
-            let (pattern_count, _checked_out) = repo.sync(
+            let stats = repo.sync(
                 head_commit.id(),
                 &targets,
-                true,
+                PatternApplication::Skip,
                 app.clone(),
                 Some(borrowed_odb),
                 None,
             )?;
-            println!("Pattern count: {}", pattern_count);
+            println!("Pattern count: {}", stats.pattern_counts.optimized);
 
             match get_files_to_materialize(&ctx, borrowed_odb, dep_keys)? {
                 PathsToMaterializeResult::Ok { seen_keys, paths } => Ok(Ok(ResolveTargetResult {
@@ -165,6 +190,223 @@ pub fn resolve(
     Ok(ExitCode(0))
 }
 
+/// What syncing the current selection at HEAD would find in the index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachePlan {
+    pub commit_id: git2::Oid,
+
+    /// Whether the repo is configured to resolve in one shot, bypassing the index entirely.
+    pub oneshot_resolution: bool,
+
+    /// Dependency keys whose resolutions are in the index.
+    pub cached_keys: usize,
+
+    /// Targets for the dependency keys that are missing from the index. Keys only reachable through missing keys are
+    /// not discovered, so more may be missing.
+    pub missing_targets: Vec<String>,
+
+    /// The Bazel targets in the complete target set, all of which are queried if anything is missing.
+    pub bazel_targets: usize,
+}
+
+impl CachePlan {
+    pub fn fully_cached(&self) -> bool {
+        !self.oneshot_resolution && self.missing_targets.is_empty()
+    }
+
+    /// The number of Bazel queries a sync would run: the incremental resolver queries the dependencies of the whole
+    /// target set and then the rules that define them.
+    pub fn estimated_bazel_queries(&self) -> usize {
+        if self.bazel_targets == 0 {
+            0
+        } else if self.oneshot_resolution {
+            1
+        } else if self.missing_targets.is_empty() {
+            0
+        } else {
+            2
+        }
+    }
+}
+
+impl std::fmt::Display for CachePlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Commit: {}", self.commit_id)?;
+        if self.oneshot_resolution {
+            writeln!(
+                f,
+                "One-shot resolution is enabled, so the index is not used."
+            )?;
+        } else {
+            writeln!(f, "Cached: {} dependency keys", self.cached_keys)?;
+            writeln!(f, "Missing: {} dependency keys", self.missing_targets.len())?;
+            for target in self.missing_targets.iter() {
+                writeln!(f, "  {}", target)?;
+            }
+        }
+        writeln!(
+            f,
+            "A sync would run {} Bazel queries over {} Bazel targets.",
+            self.estimated_bazel_queries(),
+            self.bazel_targets
+        )
+    }
+}
+
+/// Determine which dependency keys of the current selection are missing from the index at HEAD, without resolving
+/// anything.
+pub fn plan(app: Arc<App>, sparse_repo_path: &Path) -> anyhow::Result<CachePlan> {
+    let repo = Repo::open(sparse_repo_path, app)?;
+    let targets = repo
+        .selection_manager()?
+        .compute_complete_target_set()
+        .context("Computing the target set")?;
+    let bazel_targets = targets
+        .iter()
+        .filter(|target| matches!(target, Target::Bazel(_)))
+        .count();
+    let head_commit = repo.get_head_commit()?;
+    let commit_id = head_commit.id();
+    if repo.get_bazel_oneshot_resolution()? {
+        return Ok(CachePlan {
+            commit_id,
+            oneshot_resolution: true,
+            cached_keys: 0,
+            missing_targets: Vec::new(),
+            bazel_targets,
+        });
+    }
+
+    let tree = head_commit.tree().context("Resolving tree")?;
+    let ctx = HashContext::new(repo.underlying(), &tree)?;
+    let odb = RocksDBCache::new(repo.underlying());
+    // Files are included by pattern rather than resolved, so they are never in the index.
+    let dep_keys: HashSet<DependencyKey> = targets
+        .into_iter()
+        .filter(|target| !matches!(target, Target::File(_)))
+        .map(DependencyKey::from)
+        .collect();
+    let (cached_keys, missing_targets) = match get_files_to_materialize(&ctx, &odb, dep_keys)? {
+        PathsToMaterializeResult::Ok { seen_keys, .. } => (seen_keys.len(), Vec::new()),
+        PathsToMaterializeResult::MissingKeys {
+            missing_keys,
+            seen_keys,
+        } => (
+            seen_keys.len().saturating_sub(missing_keys.len()),
+            missing_keys
+                .iter()
+                .map(|(key, _hash)| dep_key_to_target(key))
+                .collect(),
+        ),
+    };
+    Ok(CachePlan {
+        commit_id,
+        oneshot_resolution: false,
+        cached_keys,
+        missing_targets,
+        bazel_targets,
+    })
+}
+
+/// The outcome of warming the index for some projects.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WarmCacheResult {
+    pub commit_id: git2::Oid,
+
+    /// Dependency keys that were already in the index.
+    pub already_cached: usize,
+
+    /// Dependency keys that were resolved and added to the index.
+    pub warmed: usize,
+}
+
+/// Count the dependency keys of `dep_keys` that are in the index and those that are missing from it. Keys only
+/// reachable through missing keys are not discovered, so more may be missing.
+fn count_cached_keys(
+    ctx: &HashContext,
+    odb: &RocksDBCache,
+    dep_keys: HashSet<DependencyKey>,
+) -> anyhow::Result<(usize, usize)> {
+    Ok(match get_files_to_materialize(ctx, odb, dep_keys)? {
+        PathsToMaterializeResult::Ok { seen_keys, .. } => (seen_keys.len(), 0),
+        PathsToMaterializeResult::MissingKeys {
+            seen_keys,
+            missing_keys,
+        } => (
+            seen_keys.len().saturating_sub(missing_keys.len()),
+            missing_keys.len(),
+        ),
+    })
+}
+
+/// Resolve the targets of `projects` and the mandatory projects at HEAD into the index, so that a later sync selecting
+/// them finds their sparse checkout patterns there. Neither the selection nor the working tree is changed.
+pub fn warm_cache(
+    app: Arc<App>,
+    sparse_repo_path: &Path,
+    projects: &[String],
+) -> anyhow::Result<WarmCacheResult> {
+    let repo = Repo::open(sparse_repo_path, app.clone())?;
+    if repo.get_bazel_oneshot_resolution()? {
+        anyhow::bail!("One-shot resolution is enabled, so the index is not used");
+    }
+
+    let selections = repo.selection_manager()?;
+    let catalog = selections.project_catalog();
+    let selected_projects = projects
+        .iter()
+        .map(|name| {
+            catalog
+                .optional_projects
+                .resolve(name)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("Project '{}' is not defined", name))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let mut targets = TargetSet::try_from(&catalog.mandatory_projects)?;
+    targets.extend(resolve_targets_for_project(
+        selected_projects,
+        &catalog.optional_projects.underlying,
+    )?);
+
+    let head_commit = repo.get_head_commit()?;
+    let commit_id = head_commit.id();
+    let tree = head_commit.tree().context("Resolving tree")?;
+    let ctx = HashContext::new(repo.underlying(), &tree)?;
+    let odb = RocksDBCache::new(repo.underlying());
+    // Files are included by pattern rather than resolved, so they are never in the index.
+    let dep_keys: HashSet<DependencyKey> = targets
+        .iter()
+        .filter(|target| !matches!(target, Target::File(_)))
+        .cloned()
+        .map(DependencyKey::from)
+        .collect();
+
+    let (already_cached, missing) = count_cached_keys(&ctx, &odb, dep_keys.clone())?;
+    if missing == 0 {
+        return Ok(WarmCacheResult {
+            commit_id,
+            already_cached,
+            warmed: 0,
+        });
+    }
+
+    repo.compute_sparse_profile(commit_id, &targets, app, Some(&odb), None)
+        .context("Resolving targets")?;
+    let (cached, still_missing) = count_cached_keys(&ctx, &odb, dep_keys)?;
+    if still_missing > 0 {
+        anyhow::bail!(
+            "{} dependency keys are still missing from the index after resolving them",
+            still_missing
+        );
+    }
+    Ok(WarmCacheResult {
+        commit_id,
+        already_cached,
+        warmed: cached.saturating_sub(already_cached),
+    })
+}
+
 pub fn hash(
     _app: Arc<App>,
     sparse_repo_path: &Path,
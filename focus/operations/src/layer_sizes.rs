@@ -0,0 +1,182 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Attributing the size of the working tree to the selected layers. Each layer's sparse profile is computed on its
+//! own, and a layer is charged for the files under the directories that only its profile includes. Directories that
+//! several layers or the mandatory projects include, directly or through a recursively included ancestor, are shared
+//! and charged to no one.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Context, Result};
+use content_addressed_cache::RocksDBCache;
+use focus_internals::{
+    model::{
+        outlining::{Pattern, PatternSet},
+        repo::Repo,
+        selection::resolve_targets_for_project,
+    },
+    target::TargetSet,
+};
+use focus_util::app::{App, ExitCode};
+
+use crate::{selection::selected_layers, util::format_size};
+
+/// The disk usage attributed to one selected layer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LayerSize {
+    pub name: String,
+
+    /// The directories that only this layer includes.
+    pub exclusive_directories: usize,
+
+    /// The total size in bytes of the files in the working tree under those directories.
+    pub bytes: u64,
+}
+
+/// Which directories a sparse profile includes, and whether their subdirectories are included with them.
+fn included_directories(patterns: &PatternSet) -> impl Iterator<Item = (PathBuf, bool)> + '_ {
+    patterns.iter().filter_map(|pattern| match pattern {
+        Pattern::Directory {
+            path, recursive, ..
+        } => Some((path.clone(), *recursive)),
+        Pattern::Verbatim { .. } => None,
+    })
+}
+
+/// The layers whose profiles include `directory`: those including it directly, and those including one of its
+/// ancestors recursively. A layer is only charged for a path if no other layer covers it.
+fn covering_owners(
+    directories: &BTreeMap<PathBuf, (bool, BTreeSet<Option<usize>>)>,
+    directory: &Path,
+) -> BTreeSet<Option<usize>> {
+    let mut owners = BTreeSet::new();
+    for (depth, ancestor) in directory.ancestors().enumerate() {
+        if let Some((recursive, ancestor_owners)) = directories.get(ancestor) {
+            if depth == 0 || *recursive {
+                owners.extend(ancestor_owners.iter().copied());
+            }
+        }
+    }
+    owners
+}
+
+/// Compute the disk usage attributed to each selected layer, sorted from largest to smallest.
+pub fn compute(repo: &Repo, app: Arc<App>) -> Result<Vec<LayerSize>> {
+    let selections = repo.selection_manager()?;
+    let layers = selected_layers(&selections)?;
+    let mandatory_targets = resolve_targets_for_project(
+        selections.mandatory_projects(),
+        &selections.project_catalog().optional_projects.underlying,
+    )
+    .context("Resolving the targets of mandatory projects")?;
+
+    let head_commit = repo.get_head_commit().context("Resolving head commit")?;
+    let odb = if repo.get_bazel_oneshot_resolution()? {
+        None
+    } else {
+        Some(RocksDBCache::new(repo.underlying()))
+    };
+    let profile = |targets: &TargetSet| -> Result<PatternSet> {
+        let (patterns, _) = repo.compute_sparse_profile(
+            head_commit.id(),
+            targets,
+            app.clone(),
+            odb.as_ref(),
+            None,
+        )?;
+        Ok(patterns)
+    };
+
+    // Each directory maps to whether it is included recursively and the layers including it, where `None` stands for
+    // the mandatory projects.
+    let mut directories = BTreeMap::<PathBuf, (bool, BTreeSet<Option<usize>>)>::new();
+    let mut include = |patterns: &PatternSet, owner: Option<usize>| {
+        for (path, recursive) in included_directories(patterns) {
+            let entry = directories
+                .entry(path)
+                .or_insert_with(|| (false, BTreeSet::new()));
+            entry.0 |= recursive;
+            entry.1.insert(owner);
+        }
+    };
+    include(
+        &profile(&mandatory_targets)
+            .context("Computing the sparse profile of mandatory projects")?,
+        None,
+    );
+    for (index, layer) in layers.iter().enumerate() {
+        let patterns = profile(&layer.targets)
+            .with_context(|| format!("Computing the sparse profile of {}", layer.name))?;
+        include(&patterns, Some(index));
+    }
+
+    let mut sizes: Vec<LayerSize> = layers
+        .iter()
+        .map(|layer| LayerSize {
+            name: layer.name.clone(),
+            exclusive_directories: 0,
+            bytes: 0,
+        })
+        .collect();
+    for directory in directories.keys() {
+        if let Some(Some(index)) = exclusive_owner(&covering_owners(&directories, directory)) {
+            sizes[index].exclusive_directories += 1;
+        }
+    }
+
+    let working_tree = repo.working_tree()?;
+    let work_dir = working_tree.work_dir().to_owned();
+    for (path, size) in working_tree.materialized_files()? {
+        let relative_path = match path.strip_prefix(&work_dir) {
+            Ok(relative_path) => relative_path,
+            Err(_) => continue,
+        };
+        let owners = match relative_path.parent() {
+            Some(parent) => covering_owners(&directories, parent),
+            None => continue,
+        };
+        if let Some(Some(index)) = exclusive_owner(&owners) {
+            sizes[index].bytes += size;
+        }
+    }
+
+    sizes.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+    Ok(sizes)
+}
+
+fn exclusive_owner(owners: &BTreeSet<Option<usize>>) -> Option<Option<usize>> {
+    if owners.len() == 1 {
+        owners.iter().next().copied()
+    } else {
+        None
+    }
+}
+
+/// Print the disk usage attributed to each selected layer, largest first.
+pub fn run(sparse_repo: impl AsRef<Path>, app: Arc<App>) -> Result<ExitCode> {
+    let repo = Repo::open(sparse_repo.as_ref(), app.clone()).context("Failed to open repo")?;
+    let sizes = compute(&repo, app)?;
+    if sizes.is_empty() {
+        eprintln!("Nothing is selected beyond the mandatory projects.");
+        return Ok(ExitCode(0));
+    }
+    for size in sizes {
+        println!(
+            "{:>10}  {} ({} exclusive {})",
+            format_size(size.bytes),
+            size.name,
+            size.exclusive_directories,
+            if size.exclusive_directories == 1 {
+                "directory"
+            } else {
+                "directories"
+            }
+        );
+    }
+    Ok(ExitCode(0))
+}
@@ -4,20 +4,31 @@
 #![allow(clippy::too_many_arguments)]
 
 pub mod background;
+pub mod bench;
+pub mod bisect;
 pub mod branch;
 pub mod clone;
 pub mod detect_build_graph_changes;
+pub mod dump;
+pub mod effective_config;
 pub mod ensure_clean;
 pub mod event;
 pub mod filter;
 pub mod index;
+pub mod layer_sizes;
 pub mod maintenance;
+pub mod porcelain;
+pub mod prefetch;
 pub mod project;
 pub mod project_cache;
 pub mod pull;
+pub mod query;
 pub mod refs;
 pub mod repo;
+pub mod sandbox;
 pub mod selection;
+pub mod self_test;
+pub mod serve;
 pub mod status;
 pub mod sync;
 pub(crate) mod testing;
@@ -179,8 +179,8 @@ fn maint_exit_status_metric_helper(exit_status: ExitStatus) -> String {
     }
 }
 
-fn sync_status_metric_helper(sync_status: crate::sync::SyncStatus) -> String {
-    match sync_status {
+pub(crate) fn sync_status_metric_helper(sync_status: &crate::sync::SyncStatus) -> String {
+    match *sync_status {
         crate::sync::SyncStatus::Success => "success".to_string(),
         crate::sync::SyncStatus::SkippedSyncPointUnchanged => "skipped_unchanged".to_string(),
         crate::sync::SyncStatus::SkippedSyncPointDifferenceIrrelevant => {
@@ -191,6 +191,7 @@ fn sync_status_metric_helper(sync_status: crate::sync::SyncStatus) -> String {
             "skipped_activity".to_string()
         }
         crate::sync::SyncStatus::SkippedUnfilterView => "skipped_unfiltered".to_string(),
+        crate::sync::SyncStatus::WrittenWithoutCheckout => "written_without_checkout".to_string(),
     }
 }
 
@@ -278,7 +279,10 @@ impl Runner<'_> {
         let sync_result = crate::sync::run(
             &SyncRequest::new(
                 repo_path,
-                crate::sync::SyncMode::Preemptive { force: false },
+                crate::sync::SyncMode::Preemptive {
+                    force: false,
+                    ignore_activity: false,
+                },
             ),
             self.app.clone(),
         )
@@ -420,7 +424,7 @@ impl Runner<'_> {
                 MaintResult::Success(status) => maint_exit_status_metric_helper(*status),
                 MaintResult::LockFailed => "lock_failed".to_string(),
             },
-            "sync_return_status".to_string() => sync_status_metric_helper(sync_maint_result),
+            "sync_return_status".to_string() => sync_status_metric_helper(&sync_maint_result),
             "git_maint_duration_sec".to_string() => git_maint_runtime.as_secs_f32().to_string(),
             "sync_maint_duration_sec".to_string() => sync_maint_runtime.as_secs_f32().to_string()
         });
@@ -0,0 +1,57 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Stable, script-friendly output enabled by the global `--porcelain` flag.
+//!
+//! Porcelain output begins with a `version` record giving the format version. Every following line is one record: a
+//! record type followed by tab-separated fields. Tabs and newlines inside fields are replaced with spaces. Within a
+//! format version, existing record types and their fields never change; new record types may be added, so scripts
+//! should ignore record types they do not recognize.
+//!
+//! Records in version 1:
+//!
+//! - `status`: `selection\t<active selection set>`, `filter\t<on|off>`, `upstream\t<commits behind>`,
+//!   `files\t<file count>\t<total bytes>`, `patterns\t<sparse pattern count>`, `project\t<name>`, `target\t<target>`
+//! - `projects`: `project\t<name>\t<description>`
+//! - `projects --presets`: `preset\t<name>\t<projects, comma-separated>`
+//! - `repo list`: `repo\t<location>\t<uuid>`
+
+/// The version of the porcelain format.
+pub const VERSION: u32 = 1;
+
+/// Print the record identifying the porcelain format version. This must be the first line of output.
+pub fn print_header() {
+    print_record("version", &[&VERSION.to_string()]);
+}
+
+/// Print a single record.
+pub fn print_record(kind: &str, fields: &[&str]) {
+    println!("{}", format_record(kind, fields));
+}
+
+fn format_record(kind: &str, fields: &[&str]) -> String {
+    let mut line = String::from(kind);
+    for field in fields {
+        line.push('\t');
+        line.extend(field.chars().map(|c| match c {
+            '\t' | '\n' | '\r' => ' ',
+            c => c,
+        }));
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_are_tab_separated_and_sanitized() {
+        assert_eq!(format_record("version", &["1"]), "version\t1");
+        assert_eq!(
+            format_record("project", &["a", "multi\tline\ndescription"]),
+            "project\ta\tmulti line description"
+        );
+        assert_eq!(format_record("filter", &[]), "filter");
+    }
+}
@@ -0,0 +1,43 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{path::Path, sync::Arc};
+
+use anyhow::{Context, Result};
+use focus_internals::model::repo::Repo;
+use focus_util::{app::App, git_helper};
+use tracing::info;
+
+/// Fetch `branch` from `remote` into the prefetch ref that `status` and preemptive sync read, so that it does not depend
+/// on a background job having run. The primary branch is fetched if no branch is given. How far HEAD is behind the
+/// prefetched upstream commit is recorded for shell prompts. Returns the fetched commit.
+pub fn run(
+    sparse_repo: &Path,
+    remote: &str,
+    branch: Option<String>,
+    app: Arc<App>,
+) -> Result<git2::Oid> {
+    let repo = Repo::open(sparse_repo, app.clone()).context("Failed to open the repo")?;
+    let branch = match branch {
+        Some(branch) => branch,
+        None => repo
+            .primary_branch_name()
+            .context("Determining primary branch name")?,
+    };
+    let prefetch_ref_name = Repo::prefetch_ref_name(remote, &branch);
+    info!(%remote, %branch, %prefetch_ref_name, "Prefetching");
+    git_helper::fetch_refs(
+        sparse_repo,
+        [format!("+refs/heads/{}:{}", branch, prefetch_ref_name)].iter(),
+        remote,
+        app,
+        None,
+    )
+    .with_context(|| format!("Fetching {} from {}", branch, remote))?;
+
+    let commit = repo
+        .get_prefetch_head_commit(remote, &branch)?
+        .with_context(|| format!("{} was not created by the fetch", prefetch_ref_name))?;
+    crate::status::record_upstream_drift(&repo)?;
+    Ok(commit.id())
+}
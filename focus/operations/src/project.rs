@@ -1,12 +1,24 @@
 // Copyright 2022 Twitter, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::{path::Path, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Write as _,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 //use anyhow::{Context, Result};
-use anyhow::Result;
-use focus_internals::model::repo::Repo;
+use anyhow::{bail, Context, Result};
+use focus_internals::{
+    model::{
+        repo::Repo,
+        selection::{resolve_targets_for_project, Project},
+    },
+    target::{Target, TargetSet},
+};
 use focus_util::app::{App, ExitCode};
+use serde_json::json;
 
 pub fn lint(sparse_repo: impl AsRef<Path>, app: Arc<App>) -> Result<ExitCode> {
     let repo = Repo::open(sparse_repo.as_ref(), app)?;
@@ -24,16 +36,353 @@ pub fn lint(sparse_repo: impl AsRef<Path>, app: Arc<App>) -> Result<ExitCode> {
     Ok(ExitCode(0))
 }
 
+/// The coordinates a project declares, and optionally the targets it resolves to once included projects are expanded.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProjectCoordinates {
+    pub name: String,
+    pub declared: BTreeSet<String>,
+    pub resolved: Option<BTreeSet<String>>,
+}
+
+pub fn project_coordinates(
+    sparse_repo: impl AsRef<Path>,
+    name: &str,
+    resolve: bool,
+    app: Arc<App>,
+) -> Result<ProjectCoordinates> {
+    let (project, available_projects) = find_project(sparse_repo, name, app)?;
+    let resolved = if resolve {
+        let targets = resolve_targets_for_project(vec![project.clone()], &available_projects)?;
+        Some(targets.iter().map(|target| target.to_string()).collect())
+    } else {
+        None
+    };
+
+    Ok(ProjectCoordinates {
+        name: project.name,
+        declared: project.targets,
+        resolved,
+    })
+}
+
+/// Look up the project named `name`, along with the optional projects it may include.
+fn find_project(
+    sparse_repo: impl AsRef<Path>,
+    name: &str,
+    app: Arc<App>,
+) -> Result<(Project, HashMap<String, Project>)> {
+    let repo = Repo::open(sparse_repo.as_ref(), app)?;
+    find_project_in(&repo, name)
+}
+
+fn find_project_in(repo: &Repo, name: &str) -> Result<(Project, HashMap<String, Project>)> {
+    let selections = repo.selection_manager()?;
+    let catalog = selections.project_catalog();
+    let project = match catalog
+        .optional_projects
+        .underlying
+        .get(name)
+        .or_else(|| catalog.mandatory_projects.underlying.get(name))
+    {
+        Some(project) => project.clone(),
+        None => bail!("Project '{}' is not defined", name),
+    };
+    Ok((project, catalog.optional_projects.underlying.clone()))
+}
+
+pub fn coordinates(
+    sparse_repo: impl AsRef<Path>,
+    name: &str,
+    resolve: bool,
+    json: bool,
+    app: Arc<App>,
+) -> Result<ExitCode> {
+    let coordinates = project_coordinates(sparse_repo, name, resolve, app)?;
+    if json {
+        let mut entry = json!({
+            "name": coordinates.name,
+            "declared": coordinates.declared,
+        });
+        if let Some(resolved) = coordinates.resolved {
+            entry["resolved"] = json!(resolved);
+        }
+        println!("{}", serde_json::to_string_pretty(&entry)?);
+        return Ok(ExitCode(0));
+    }
+
+    println!("Declared coordinates:");
+    for coordinate in coordinates.declared.iter() {
+        println!("  {}", coordinate);
+    }
+    if let Some(resolved) = coordinates.resolved.as_ref() {
+        println!("Resolved targets:");
+        for target in resolved.iter() {
+            println!("  {}", target);
+        }
+    }
+    Ok(ExitCode(0))
+}
+
+/// The directories a project's targets materialize, relative to the repository root, including those of the projects
+/// it includes and of the packages its targets depend on. File targets contribute only the file itself.
+pub fn project_directories(
+    sparse_repo: impl AsRef<Path>,
+    name: &str,
+    app: Arc<App>,
+) -> Result<BTreeSet<PathBuf>> {
+    let repo = Repo::open(sparse_repo.as_ref(), app.clone())?;
+    let (project, available_projects) = find_project_in(&repo, name)?;
+    let (files, targets): (TargetSet, TargetSet) =
+        resolve_targets_for_project(vec![project], &available_projects)?
+            .into_iter()
+            .partition(|target| matches!(target, Target::File(_)));
+    let mut directories = repo
+        .resolve_directories(&targets, app)
+        .with_context(|| format!("Resolving project '{}' failed", name))?;
+    directories.extend(files.into_iter().filter_map(|target| match target {
+        Target::File(path) => Some(PathBuf::from(path)),
+        _ => None,
+    }));
+    Ok(directories)
+}
+
+#[derive(Debug, Default)]
+struct DirectoryTree {
+    children: BTreeMap<String, DirectoryTree>,
+
+    /// Whether this directory was inserted itself, rather than only leading to ones that were.
+    selected: bool,
+}
+
+impl DirectoryTree {
+    fn insert(&mut self, path: &Path) {
+        let mut node = self;
+        for component in path.components() {
+            node = node
+                .children
+                .entry(component.as_os_str().to_string_lossy().into_owned())
+                .or_default();
+        }
+        node.selected = true;
+    }
+
+    fn count_selected(&self) -> usize {
+        self.children
+            .values()
+            .map(|child| usize::from(child.selected) + child.count_selected())
+            .sum()
+    }
+
+    /// Render the children of this directory below `prefix`, `depth` levels deep. A directory that only leads to a
+    /// single subdirectory is shown on the same line as it, like `a/b/c`, and counts as one level.
+    fn render(&self, prefix: &str, depth: Option<usize>, output: &mut String) {
+        for (index, (name, child)) in self.children.iter().enumerate() {
+            let mut name = name.clone();
+            let mut child = child;
+            while !child.selected && child.children.len() == 1 {
+                let (next_name, next) = child.children.iter().next().unwrap();
+                name = format!("{}/{}", name, next_name);
+                child = next;
+            }
+
+            let last = index + 1 == self.children.len();
+            let _ = write!(
+                output,
+                "{}{}{}",
+                prefix,
+                if last { "└── " } else { "├── " },
+                name
+            );
+            let depth = depth.map(|depth| depth.saturating_sub(1));
+            if depth == Some(0) && !child.children.is_empty() {
+                let _ = write!(output, " ({} more below)", child.count_selected());
+            }
+            output.push('\n');
+            if depth != Some(0) {
+                let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+                child.render(&prefix, depth, output);
+            }
+        }
+    }
+}
+
+/// Render `directories` as an indented tree, like `tree`, at most `depth` levels deep.
+fn render_directory_tree(directories: &BTreeSet<PathBuf>, depth: Option<usize>) -> String {
+    let mut tree = DirectoryTree::default();
+    for directory in directories {
+        tree.insert(directory);
+    }
+    let mut output = String::new();
+    if depth != Some(0) {
+        tree.render("", depth, &mut output);
+    }
+    output
+}
+
+pub fn tree(
+    sparse_repo: impl AsRef<Path>,
+    name: &str,
+    depth: Option<usize>,
+    app: Arc<App>,
+) -> Result<ExitCode> {
+    let directories = project_directories(sparse_repo, name, app)?;
+    if directories.contains(Path::new("")) {
+        println!("{} (including the repository root)", name);
+    } else {
+        println!("{}", name);
+    }
+    print!("{}", render_directory_tree(&directories, depth));
+    Ok(ExitCode(0))
+}
+
+/// A project that covers a target, and how much selecting it would add.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProjectCover {
+    pub name: String,
+
+    /// The number of the project's resolved targets that are not already in the selection.
+    pub additional_targets: usize,
+
+    /// The number of targets the project resolves to, including those of the projects it includes.
+    pub total_targets: usize,
+}
+
+/// Find the optional projects whose resolved targets include `target`, with the one adding the fewest targets to the
+/// current selection first.
+pub fn covering_projects(
+    sparse_repo: impl AsRef<Path>,
+    target: &str,
+    app: Arc<App>,
+) -> Result<Vec<ProjectCover>> {
+    let needle =
+        Target::try_from(target).with_context(|| format!("Parsing target '{}' failed", target))?;
+    let repo = Repo::open(sparse_repo.as_ref(), app)?;
+    let selections = repo.selection_manager()?;
+    let selected = selections.compute_complete_target_set()?;
+    let available_projects = &selections.project_catalog().optional_projects.underlying;
+
+    let mut covers = Vec::new();
+    for project in available_projects.values() {
+        let targets = resolve_targets_for_project(vec![project.clone()], available_projects)
+            .with_context(|| format!("Resolving project '{}' failed", project.name))?;
+        if !targets.iter().any(|target| target.covers(&needle)) {
+            continue;
+        }
+        covers.push(ProjectCover {
+            name: project.name.clone(),
+            additional_targets: targets.difference(&selected).count(),
+            total_targets: targets.len(),
+        });
+    }
+    covers.sort_by(|a, b| {
+        (a.additional_targets, a.total_targets, &a.name).cmp(&(
+            b.additional_targets,
+            b.total_targets,
+            &b.name,
+        ))
+    });
+    Ok(covers)
+}
+
+pub fn cover_target(
+    sparse_repo: impl AsRef<Path>,
+    target: &str,
+    app: Arc<App>,
+) -> Result<ExitCode> {
+    let covers = covering_projects(sparse_repo, target, app)?;
+    if covers.is_empty() {
+        eprintln!("No project includes {}", target);
+        return Ok(ExitCode(1));
+    }
+    for cover in covers {
+        println!(
+            "{} (+{} of {} targets)",
+            cover.name, cover.additional_targets, cover.total_targets
+        );
+    }
+    Ok(ExitCode(0))
+}
+
+/// Find coordinates declared directly by more than one project, mapped to the names of those projects. Only selected
+/// projects are considered unless `all` is set, in which case every project in the catalog is.
+pub fn coordinate_overlaps(
+    sparse_repo: impl AsRef<Path>,
+    all: bool,
+    app: Arc<App>,
+) -> Result<BTreeMap<String, BTreeSet<String>>> {
+    let repo = Repo::open(sparse_repo.as_ref(), app)?;
+    let selections = repo.selection_manager()?;
+    let catalog = selections.project_catalog();
+    let projects: Vec<Project> = if all {
+        catalog
+            .optional_projects
+            .underlying
+            .values()
+            .chain(catalog.mandatory_projects.underlying.values())
+            .cloned()
+            .collect()
+    } else {
+        selections.selection()?.projects.into_iter().collect()
+    };
+
+    let mut declarers: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for project in projects.iter() {
+        for coordinate in project.targets.iter() {
+            // Parse the coordinate so that equivalent spellings are counted together.
+            let target = Target::try_from(coordinate.as_str()).with_context(|| {
+                format!(
+                    "Parsing target '{}' of project '{}' failed",
+                    coordinate, project.name
+                )
+            })?;
+            declarers
+                .entry(target.to_string())
+                .or_default()
+                .insert(project.name.clone());
+        }
+    }
+    declarers.retain(|_, names| names.len() > 1);
+    Ok(declarers)
+}
+
+pub fn coordinate_overlap(
+    sparse_repo: impl AsRef<Path>,
+    all: bool,
+    app: Arc<App>,
+) -> Result<ExitCode> {
+    let overlaps = coordinate_overlaps(sparse_repo, all, app)?;
+    if overlaps.is_empty() {
+        eprintln!(
+            "No coordinate is declared by more than one {} project",
+            if all { "defined" } else { "selected" }
+        );
+        return Ok(ExitCode(0));
+    }
+    for (coordinate, names) in overlaps.iter() {
+        println!("{}", coordinate);
+        for name in names {
+            println!("  {}", name);
+        }
+    }
+    Ok(ExitCode(0))
+}
+
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        path::PathBuf,
+        sync::Arc,
+    };
 
     use anyhow::Result;
-    use focus_internals::model::repo::Repo;
+    use focus_internals::model::{repo::Repo, selection::OperationAction};
     use focus_testing::ScratchGitRepo;
     use focus_util::app::{App, ExitCode};
 
-    use crate::project::lint;
+    use crate::project::{
+        coordinate_overlaps, covering_projects, lint, project_coordinates, render_directory_tree,
+    };
     #[test]
     pub fn test_lint() -> Result<()> {
         let temp = tempfile::tempdir()?;
@@ -90,4 +439,196 @@ mod tests {
         assert!(lint_result.is_err());
         Ok(())
     }
+
+    #[test]
+    pub fn test_project_coordinates() -> Result<()> {
+        let temp = tempfile::tempdir()?;
+        let fix = ScratchGitRepo::new_static_fixture(temp.path())?;
+        fix.write_and_commit_file(
+            "focus/projects/nested.projects.json",
+            r#"
+{
+    "projects": [
+        {
+            "name": "outer",
+            "description": "includes another project",
+            "targets": ["directory:outer"],
+            "projects": ["inner"]
+        },
+        {
+            "name": "inner",
+            "description": "is included by another project",
+            "targets": ["directory:inner"]
+        }
+    ]
+}
+        "#,
+            "adding nested projects",
+        )?;
+        let testing_app = Arc::new(App::new_for_testing()?);
+
+        let coordinates = project_coordinates(fix.path(), "outer", false, testing_app.clone())?;
+        assert_eq!(
+            coordinates.declared.into_iter().collect::<Vec<_>>(),
+            vec!["directory:outer".to_string()]
+        );
+        assert_eq!(coordinates.resolved, None);
+
+        let coordinates = project_coordinates(fix.path(), "outer", true, testing_app.clone())?;
+        assert_eq!(
+            coordinates
+                .resolved
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec!["directory:inner".to_string(), "directory:outer".to_string()]
+        );
+
+        assert!(project_coordinates(fix.path(), "undefined", false, testing_app).is_err());
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_covering_projects() -> Result<()> {
+        let temp = tempfile::tempdir()?;
+        let fix = ScratchGitRepo::new_static_fixture(temp.path())?;
+        fix.write_and_commit_file(
+            "focus/projects/covering.projects.json",
+            r#"
+{
+    "projects": [
+        {
+            "name": "broad",
+            "description": "covers the whole tree",
+            "targets": ["directory:search", "directory:tools"]
+        },
+        {
+            "name": "narrow",
+            "description": "covers one package",
+            "targets": ["bazel://search/common/..."]
+        },
+        {
+            "name": "unrelated",
+            "description": "covers something else",
+            "targets": ["directory:docs"]
+        }
+    ]
+}
+        "#,
+            "adding covering projects",
+        )?;
+        let testing_app = Arc::new(App::new_for_testing()?);
+
+        let covers = covering_projects(
+            fix.path(),
+            "bazel://search/common/util:lib",
+            testing_app.clone(),
+        )?;
+        assert_eq!(
+            covers
+                .iter()
+                .map(|cover| (cover.name.as_str(), cover.total_targets))
+                .collect::<Vec<_>>(),
+            vec![("narrow", 1), ("broad", 2)]
+        );
+
+        assert!(
+            covering_projects(fix.path(), "directory:nowhere", testing_app.clone())?.is_empty()
+        );
+        assert!(covering_projects(fix.path(), "nowhere", testing_app).is_err());
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_coordinate_overlaps() -> Result<()> {
+        let temp = tempfile::tempdir()?;
+        let fix = ScratchGitRepo::new_static_fixture(temp.path())?;
+        fix.write_and_commit_file(
+            "focus/projects/overlapping.projects.json",
+            r#"
+{
+    "projects": [
+        {
+            "name": "a",
+            "description": "shares packages with b and docs with c",
+            "targets": ["directory:docs", "bazel://search/common/..."]
+        },
+        {
+            "name": "b",
+            "description": "shares packages with a",
+            "targets": ["bazel://search/common/...", "directory:tools"]
+        },
+        {
+            "name": "c",
+            "description": "shares docs with a",
+            "targets": ["directory:docs"]
+        }
+    ]
+}
+        "#,
+            "adding overlapping projects",
+        )?;
+        let testing_app = Arc::new(App::new_for_testing()?);
+
+        let overlaps = coordinate_overlaps(fix.path(), true, testing_app.clone())?;
+        assert_eq!(
+            overlaps,
+            BTreeMap::from([
+                (
+                    String::from("bazel://search/common/..."),
+                    BTreeSet::from([String::from("a"), String::from("b")])
+                ),
+                (
+                    String::from("directory:docs"),
+                    BTreeSet::from([String::from("a"), String::from("c")])
+                ),
+            ])
+        );
+
+        assert!(coordinate_overlaps(fix.path(), false, testing_app.clone())?.is_empty());
+        let repo = Repo::open(fix.path(), testing_app.clone())?;
+        let mut selections = repo.selection_manager()?;
+        selections.mutate(
+            OperationAction::default_add(),
+            &[String::from("a"), String::from("b")],
+        )?;
+        selections.save()?;
+        let overlaps = coordinate_overlaps(fix.path(), false, testing_app)?;
+        assert_eq!(
+            overlaps.keys().collect::<Vec<_>>(),
+            vec!["bazel://search/common/..."]
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_render_directory_tree() {
+        let directories: BTreeSet<PathBuf> = [
+            "src/main/java/com/example/a",
+            "src/main/java/com/example/b",
+            "src/main/java/com/example/b/c",
+            "tools",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+
+        assert_eq!(
+            render_directory_tree(&directories, None),
+            "\
+├── src/main/java/com/example
+│   ├── a
+│   └── b
+│       └── c
+└── tools
+"
+        );
+        assert_eq!(
+            render_directory_tree(&directories, Some(1)),
+            "\
+├── src/main/java/com/example (3 more below)
+└── tools
+"
+        );
+    }
 }
@@ -0,0 +1,162 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Context, Result};
+use content_addressed_cache::RocksDBCache;
+use focus_internals::{
+    locking,
+    model::{
+        outlining::Pattern,
+        repo::{CoordinateQuery, Repo},
+    },
+    target::{Target, TargetSet},
+};
+use focus_util::app::App;
+
+use crate::sync::SYNC_LOCK_FILE_NAME;
+
+/// Query Bazel for a single coordinate at HEAD, bypassing the index, to see why it checks out the paths it does.
+pub fn coordinate(sparse_repo: &Path, coordinate: &str, app: Arc<App>) -> Result<CoordinateQuery> {
+    let target = Target::try_from(coordinate)
+        .with_context(|| format!("Parsing coordinate '{}'", coordinate))?;
+    let repo = Repo::open(sparse_repo, app.clone()).context("Failed to open the repo")?;
+    // Querying switches the outlining tree, which a concurrent sync would also do.
+    let _lock = locking::hold_lock(sparse_repo, Path::new(SYNC_LOCK_FILE_NAME), app.clone())
+        .context("Failed to obtain synchronization lock")?;
+    repo.query_coordinate(&target, app)
+}
+
+pub fn print_coordinate_query(result: &CoordinateQuery) {
+    println!("Commit: {}", result.commit_id);
+    println!("Query: {}", result.query);
+    println!("Output:");
+    for line in result.output.lines() {
+        println!("  {}", line);
+    }
+    println!("Directories:");
+    for pattern in result.patterns.iter() {
+        match pattern {
+            Pattern::Directory { path, .. } => println!("  {}", path.display()),
+            Pattern::Verbatim { fragment, .. } => println!("  {}", fragment),
+        }
+    }
+}
+
+/// How the selection's complete target set and the directories it resolves to differ between two commits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TargetSetDiff {
+    pub from: git2::Oid,
+    pub to: git2::Oid,
+    pub added_targets: BTreeSet<String>,
+    pub removed_targets: BTreeSet<String>,
+    pub added_directories: BTreeSet<PathBuf>,
+    pub removed_directories: BTreeSet<PathBuf>,
+}
+
+impl TargetSetDiff {
+    /// Whether the selection resolves to the same targets and directories at both commits.
+    pub fn is_empty(&self) -> bool {
+        self.added_targets.is_empty()
+            && self.removed_targets.is_empty()
+            && self.added_directories.is_empty()
+            && self.removed_directories.is_empty()
+    }
+}
+
+/// Resolve the selection at the commits `from` and `to`, using the projects defined at each, and compare the targets
+/// and directories they resolve to.
+pub fn target_set_diff(
+    sparse_repo: &Path,
+    from: &str,
+    to: &str,
+    app: Arc<App>,
+) -> Result<TargetSetDiff> {
+    let repo = Repo::open(sparse_repo, app.clone()).context("Failed to open the repo")?;
+    // Resolving switches the outlining tree, which a concurrent sync would also do.
+    let _lock = locking::hold_lock(sparse_repo, Path::new(SYNC_LOCK_FILE_NAME), app.clone())
+        .context("Failed to obtain synchronization lock")?;
+    let selections = repo.selection_manager()?;
+    let odb = if repo.get_bazel_oneshot_resolution()? {
+        None
+    } else {
+        Some(RocksDBCache::new(repo.underlying()))
+    };
+
+    let resolve = |revision: &str| -> Result<(git2::Oid, BTreeSet<String>, BTreeSet<PathBuf>)> {
+        let commit_id = repo
+            .underlying()
+            .revparse_single(revision)
+            .and_then(|object| object.peel_to_commit())
+            .map(|commit| commit.id())
+            .with_context(|| format!("'{}' does not name a commit in this repository", revision))?;
+        let targets: TargetSet = selections
+            .compute_complete_target_set_at(&repo, commit_id)
+            .with_context(|| format!("Computing the target set at {}", commit_id))?;
+        let (patterns, _) = repo
+            .compute_sparse_profile(commit_id, &targets, app.clone(), odb.as_ref(), None)
+            .with_context(|| format!("Resolving the target set at {}", commit_id))?;
+        let directories = patterns
+            .iter()
+            .filter_map(|pattern| match pattern {
+                Pattern::Directory { path, .. } => Some(path.clone()),
+                Pattern::Verbatim { .. } => None,
+            })
+            .collect();
+        let targets = targets.iter().map(|target| target.to_string()).collect();
+        Ok((commit_id, targets, directories))
+    };
+    let (from, from_targets, from_directories) = resolve(from)?;
+    let (to, to_targets, to_directories) = resolve(to)?;
+
+    Ok(TargetSetDiff {
+        from,
+        to,
+        added_targets: to_targets.difference(&from_targets).cloned().collect(),
+        removed_targets: from_targets.difference(&to_targets).cloned().collect(),
+        added_directories: to_directories
+            .difference(&from_directories)
+            .cloned()
+            .collect(),
+        removed_directories: from_directories
+            .difference(&to_directories)
+            .cloned()
+            .collect(),
+    })
+}
+
+pub fn print_target_set_diff(diff: &TargetSetDiff) {
+    println!("From: {}", diff.from);
+    println!("To: {}", diff.to);
+    if diff.is_empty() {
+        println!("The selection resolves to the same targets and directories at both commits.");
+        return;
+    }
+    for (heading, items) in [
+        ("Targets added:", &diff.added_targets),
+        ("Targets removed:", &diff.removed_targets),
+    ] {
+        if !items.is_empty() {
+            println!("{}", heading);
+            for item in items {
+                println!("  {}", item);
+            }
+        }
+    }
+    for (heading, items) in [
+        ("Directories added:", &diff.added_directories),
+        ("Directories removed:", &diff.removed_directories),
+    ] {
+        if !items.is_empty() {
+            println!("{}", heading);
+            for item in items {
+                println!("  {}", item.display());
+            }
+        }
+    }
+}
@@ -13,6 +13,8 @@ use anyhow::{Context, Result};
 use git2::Repository;
 use tracing::debug;
 
+use crate::util::Removal;
+
 /// Vec of names that should never be expired via this process
 /// TODO: this should probably be in configuration rather than hardcoded here
 const SAFE_BRANCH_NAMES: &[&str] = &[
@@ -166,27 +168,31 @@ fn delete_case_conflict_refs(repo: &Repository, names: Vec<String>) -> Result<Ve
     Ok(ok_names)
 }
 
+/// Delete refs that are older than `cutoff`, once `removal` approves.
 pub fn expire_old_refs(
     repo: &Repository,
     cutoff: FocusTime,
     check_merge_base: bool,
     use_transaction: bool,
+    removal: Removal,
     app: Arc<App>,
 ) -> Result<()> {
+    let PartitionedRefNames {
+        current: _,
+        expired,
+    } = PartitionedRefNames::for_repo(repo, cutoff, check_merge_base)
+        .context("collecting expired ref names")?;
+    if !removal.approve("refs", &expired)? {
+        return Ok(());
+    }
+
     let sandbox = app.sandbox();
 
     let ref_file_path = {
         let (mut ref_file, ref_file_path, _) =
             sandbox.create_file(Some("update-refs"), None, None)?;
 
-        let xs = {
-            let PartitionedRefNames {
-                current: _,
-                expired,
-            } = PartitionedRefNames::for_repo(repo, cutoff, check_merge_base)
-                .context("collecting expired ref names")?;
-            delete_case_conflict_refs(repo, expired)?
-        };
+        let xs = delete_case_conflict_refs(repo, expired)?;
 
         let mut content: Vec<String> = xs
             .iter()
@@ -233,7 +239,7 @@ mod testing {
     use anyhow::Result;
     use focus_util::{git_helper::Ident, time::FocusTime};
 
-    use crate::testing::refs::Fixture;
+    use crate::{testing::refs::Fixture, util::Removal};
 
     const OLD_MERGE_BASE_BRANCH_NAME: &str = "refs/heads/oldmergebase";
     const OLD_TIP_BRANCH_NAME: &str = "refs/heads/oldtip";
@@ -309,7 +315,7 @@ mod testing {
 
         assert!(repo.find_reference(OLD_MERGE_BASE_BRANCH_NAME).is_ok());
 
-        super::expire_old_refs(repo, cutoff, true, false, fix.app())?;
+        super::expire_old_refs(repo, cutoff, true, false, Removal::Yes, fix.app())?;
 
         assert!(repo.find_reference(OLD_MERGE_BASE_BRANCH_NAME).is_err());
         assert!(repo.find_reference(OLD_TIP_BRANCH_NAME).is_err());
@@ -329,7 +335,7 @@ mod testing {
         let repo = fix.repo();
         let cutoff = FocusTime::now() - chrono::Duration::days(90);
 
-        super::expire_old_refs(repo, cutoff, false, false, fix.app())?;
+        super::expire_old_refs(repo, cutoff, false, false, Removal::Yes, fix.app())?;
 
         assert!(repo.find_reference(OLD_TIP_BRANCH_NAME).is_err());
         assert!(repo.find_reference(OLD_MERGE_BASE_BRANCH_NAME).is_ok());
@@ -399,7 +405,7 @@ mod testing {
 
         {
             let repo = fix.repo();
-            super::expire_old_refs(repo, cutoff, false, false, fix.app())?;
+            super::expire_old_refs(repo, cutoff, false, false, Removal::Yes, fix.app())?;
 
             assert!(repo.find_reference(OLD_TIP_BRANCH_NAME).is_err());
             assert!(repo.find_reference(OLD_MERGE_BASE_BRANCH_NAME).is_ok());
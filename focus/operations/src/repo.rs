@@ -1,17 +1,102 @@
 // Copyright 2022 Twitter, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::{path::Path, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 
-use focus_internals::tracker::Tracker;
+use chrono::{DateTime, SecondsFormat, Utc};
+use focus_internals::{
+    model::repo::Repo,
+    tracker::{TrackedRepo, Tracker},
+};
 use focus_util::app::App;
+use glob::{MatchOptions, Pattern};
+use serde_json::json;
+
+use crate::{porcelain, status};
+
+/// Find tracked repositories whose location matches the glob `pattern`. Relative patterns match any
+/// trailing portion of the repository location.
+pub fn find_matching(tracker: &Tracker, pattern: Option<&str>) -> Result<Vec<TrackedRepo>> {
+    let matcher = pattern
+        .map(|pattern| {
+            if pattern.starts_with('/') {
+                Pattern::new(pattern)
+            } else {
+                Pattern::new(&format!("**/{}", pattern))
+            }
+            .with_context(|| format!("Invalid repository pattern {:?}", pattern))
+        })
+        .transpose()?;
+    let match_options = MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
 
-pub fn list(tracker: &Tracker) -> Result<()> {
     let snapshot = tracker.scan().context("scanning repositories")?;
-    for repo in snapshot.repos() {
-        println!("{}", repo)
+    Ok(snapshot
+        .repos()
+        .iter()
+        .filter(|repo| match &matcher {
+            Some(matcher) => matcher.matches_path_with(repo.location(), match_options),
+            None => true,
+        })
+        .cloned()
+        .collect())
+}
+
+/// List tracked repositories, optionally only those matching `pattern`. With `verbose`, also show what each repo was
+/// cloned with; JSON output always includes it.
+pub fn list(
+    tracker: &Tracker,
+    pattern: Option<&str>,
+    json: bool,
+    porcelain: bool,
+    verbose: bool,
+) -> Result<()> {
+    if json && porcelain {
+        bail!("JSON and porcelain output cannot be combined");
+    }
+    let repos = find_matching(tracker, pattern)?;
+
+    if porcelain {
+        porcelain::print_header();
+        for repo in repos {
+            porcelain::print_record(
+                "repo",
+                &[
+                    &repo.location().display().to_string(),
+                    &repo.identifier().to_string(),
+                ],
+            );
+        }
+    } else if json {
+        let mut entries = Vec::new();
+        for repo in repos.iter() {
+            let clone_spec = tracker.clone_spec(repo.identifier())?;
+            entries.push(json!({
+                "location": repo.location(),
+                "uuid": repo.identifier().to_string(),
+                "cloned_with": clone_spec.map(|spec| spec.projects_and_targets),
+            }));
+        }
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else {
+        for repo in repos {
+            if verbose {
+                let cloned_with = match tracker.clone_spec(repo.identifier())? {
+                    Some(spec) => spec.to_string(),
+                    None => String::from("unknown"),
+                };
+                println!("{} (cloned with: {})", repo, cloned_with);
+            } else {
+                println!("{}", repo)
+            }
+        }
     }
 
     Ok(())
@@ -27,3 +112,161 @@ pub fn register(repo_directory: impl AsRef<Path>, tracker: &Tracker, app: Arc<Ap
     tracker.ensure_registered(repo_directory.as_ref(), app)?;
     Ok(())
 }
+
+/// A summary of the state of a tracked repository, as shown by `focus overview`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RepoOverview {
+    pub location: PathBuf,
+    pub selection: String,
+
+    /// The number of projects in the selection.
+    pub projects: usize,
+
+    /// When the last successful sync finished, if any has.
+    pub last_synced: Option<DateTime<Utc>>,
+
+    /// Whether the repo appears to need a sync, or `None` if that cannot be told.
+    pub needs_sync: Option<bool>,
+
+    /// Whether the working tree has no changed or untracked files.
+    pub clean: bool,
+
+    /// The number of sparse checkout patterns.
+    pub patterns: usize,
+}
+
+/// Summarize the state of the repository at `location`.
+pub fn summarize(location: &Path, app: Arc<App>) -> Result<RepoOverview> {
+    let repo = Repo::open(location, app.clone())?;
+    let working_tree = repo.working_tree()?;
+    let projects = repo.selection_manager()?.selection()?.projects.len();
+    Ok(RepoOverview {
+        location: location.to_owned(),
+        selection: repo.get_active_selection_name()?,
+        projects,
+        last_synced: status::last_sync_completed_at(&repo)?,
+        needs_sync: Repo::check_sync_needed(location).ok(),
+        clean: working_tree.is_clean(app)?,
+        patterns: working_tree.sparse_pattern_count()?,
+    })
+}
+
+/// Print a summary of each tracked repository, optionally only those matching `pattern`. Repositories that cannot be
+/// summarized are listed with the reason.
+pub fn overview(tracker: &Tracker, pattern: Option<&str>, json: bool, app: Arc<App>) -> Result<()> {
+    let repos = find_matching(tracker, pattern)?;
+    let summaries: Vec<(&TrackedRepo, Result<RepoOverview>)> = repos
+        .iter()
+        .map(|repo| (repo, summarize(repo.location(), app.clone())))
+        .collect();
+
+    if json {
+        let entries: Vec<_> = summaries
+            .iter()
+            .map(|(repo, summary)| match summary {
+                Ok(summary) => json!({
+                    "location": summary.location,
+                    "selection": summary.selection,
+                    "projects": summary.projects,
+                    "last_synced": summary
+                        .last_synced
+                        .map(|at| at.to_rfc3339_opts(SecondsFormat::Secs, true)),
+                    "needs_sync": summary.needs_sync,
+                    "clean": summary.clean,
+                    "patterns": summary.patterns,
+                }),
+                Err(e) => json!({
+                    "location": repo.location(),
+                    "error": format!("{:#}", e),
+                }),
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
+    let now = Utc::now();
+    let rows: Vec<[String; 6]> = summaries
+        .iter()
+        .map(|(repo, summary)| match summary {
+            Ok(summary) => [
+                summary.location.display().to_string(),
+                format!("{} ({})", summary.selection, summary.projects),
+                summary
+                    .last_synced
+                    .map(|at| status::TimeFormat::RelativeShort.format(at, now))
+                    .unwrap_or_else(|| String::from("never")),
+                String::from(match summary.needs_sync {
+                    Some(true) => "yes",
+                    Some(false) => "no",
+                    None => "unknown",
+                }),
+                String::from(if summary.clean { "clean" } else { "dirty" }),
+                summary.patterns.to_string(),
+            ],
+            Err(e) => [
+                repo.location().display().to_string(),
+                format!("error: {:#}", e),
+                String::new(),
+                String::new(),
+                String::new(),
+                String::new(),
+            ],
+        })
+        .collect();
+    print!("{}", render_table(&OVERVIEW_HEADERS, &rows));
+    Ok(())
+}
+
+const OVERVIEW_HEADERS: [&str; 6] = [
+    "REPO",
+    "SELECTION (PROJECTS)",
+    "SYNCED",
+    "NEEDS SYNC",
+    "TREE",
+    "PATTERNS",
+];
+
+/// Render `rows` below `headers` in left-aligned columns separated by two spaces.
+fn render_table<const N: usize>(headers: &[&str; N], rows: &[[String; N]]) -> String {
+    let mut widths = headers.map(str::len);
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let mut output = String::new();
+    let lines = std::iter::once(headers.map(String::from)).chain(rows.iter().cloned());
+    for line in lines {
+        let cells: Vec<String> = line
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        output.push_str(cells.join("  ").trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rendering_tables() {
+        let rows = [
+            [String::from("/a/long/path"), String::from("1")],
+            [String::from("/b"), String::from("")],
+        ];
+        assert_eq!(
+            render_table(&["REPO", "COUNT"], &rows),
+            "\
+REPO          COUNT
+/a/long/path  1
+/b
+"
+        );
+    }
+}
@@ -0,0 +1,92 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{path::Path, time::Duration};
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use focus_util::sandbox::cleanup;
+
+use crate::util::{format_size, Removal};
+
+/// Print the preserved sandboxes in `sandbox_root` along with when they were created.
+pub fn list(sandbox_root: &Path) -> Result<()> {
+    for entry in cleanup::list(sandbox_root).context("Listing sandboxes")? {
+        println!(
+            "{}  {}",
+            entry
+                .created_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S"),
+            entry.path.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// The age past which sandboxes are removed if no maximum age is given.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Remove sandboxes in `sandbox_root` older than `max_age` that are not in use by a running focus process, once
+/// `removal` approves.
+pub fn clean(sandbox_root: &Path, max_age: Option<Duration>, removal: Removal) -> Result<()> {
+    let max_age = max_age.unwrap_or(DEFAULT_MAX_AGE);
+    let preview = cleanup::remove_older_than(sandbox_root, max_age, true)
+        .context("Finding sandboxes to clean up")?;
+
+    for path in preview.skipped_in_use.iter() {
+        eprintln!("Skipping {} because it is in use", path.display());
+    }
+    let items: Vec<String> = preview
+        .removed
+        .iter()
+        .map(|(path, size)| format!("{}  {}", format_size(*size), path.display()))
+        .collect();
+    if !removal.approve("sandboxes", &items)? {
+        return Ok(());
+    }
+
+    let report = cleanup::remove_older_than(sandbox_root, max_age, false)
+        .context("Cleaning up sandboxes")?;
+    eprintln!(
+        "Removed {} sandboxes, reclaiming {}",
+        report.removed.len(),
+        format_size(report.reclaimed_bytes())
+    );
+
+    Ok(())
+}
+
+/// Report sandboxes left behind by focus processes that did not exit cleanly, and how much space they take up. They
+/// are removed once `removal` approves.
+pub fn audit(sandbox_root: &Path, removal: Removal) -> Result<()> {
+    let preview =
+        cleanup::remove_orphaned(sandbox_root, true).context("Finding orphaned sandboxes")?;
+    let items: Vec<String> = preview
+        .removed
+        .iter()
+        .map(|(path, size)| format!("{}  {}", format_size(*size), path.display()))
+        .collect();
+    if !items.is_empty() {
+        eprintln!(
+            "Found {} orphaned sandboxes in {} using {}",
+            items.len(),
+            sandbox_root.display(),
+            format_size(preview.reclaimed_bytes())
+        );
+    }
+    if !removal.approve("orphaned sandboxes", &items)? {
+        return Ok(());
+    }
+
+    let report =
+        cleanup::remove_orphaned(sandbox_root, false).context("Removing orphaned sandboxes")?;
+    eprintln!(
+        "Removed {} orphaned sandboxes, reclaiming {}",
+        report.removed.len(),
+        format_size(report.reclaimed_bytes())
+    );
+
+    Ok(())
+}
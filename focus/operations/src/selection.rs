@@ -3,33 +3,46 @@
 
 use std::{
     borrow::Cow,
-    collections::HashSet,
+    collections::{BTreeSet, HashMap, HashSet},
     fmt::Display,
+    io::Read,
     path::{Path, PathBuf},
+    str::FromStr,
     sync::Arc,
     thread,
 };
 
 use anyhow::{bail, Context, Result};
-use console::style;
+use console::{style, Term};
 use focus_util::{
     app::App,
     git_helper::{get_changed_paths_between_trees, get_head_commit},
-    paths::is_relevant_to_build_graph,
+    paths::{is_build_definition, is_relevant_to_build_graph},
 };
 use git2::{FileMode, TreeWalkMode, TreeWalkResult};
 use skim::{
     prelude::SkimOptionsBuilder, AnsiString, Skim, SkimItem, SkimItemReceiver, SkimItemSender,
 };
 
-use tracing::info;
+use tracing::{info, warn};
 
 use focus_internals::{
-    model::{repo::Repo, selection::*},
-    target::Target,
+    hashing,
+    model::{
+        repo::{Repo, DEFAULT_SELECTION_MIRROR_PATH},
+        selection::*,
+    },
+    target::{bazel_query_union, Target, TargetSet},
+};
+
+use crate::{
+    porcelain,
+    sync::{SyncMode, SyncRequest},
+    util::recover_orphaned_backup,
 };
 
-use crate::sync::{SyncMode, SyncRequest};
+/// Records the digest of the shared selection the user last declined to adopt, in the focus git dir.
+const DECLINED_SELECTION_MIRROR_FILE_NAME: &str = "declined-selection-mirror";
 
 pub fn save(
     sparse_repo: impl AsRef<Path>,
@@ -71,6 +84,9 @@ pub fn save(
             .into_iter()
             .map(|p| p.name)
             .collect(),
+        deprecated: None,
+        replaced_by: None,
+        aliases: Default::default(),
     };
     selection_manager
         .mut_project_catalog()
@@ -80,26 +96,12 @@ pub fn save(
     Ok(true)
 }
 
-fn mutate(
-    sparse_repo: impl AsRef<Path>,
-    sync_if_changed: bool,
+/// Expand the requested projects and targets according to the options of `action`.
+fn expand_projects_and_targets(
+    selections: &SelectionManager,
     action: OperationAction,
     projects_and_targets: Vec<String>,
-    app: Arc<focus_util::app::App>,
-) -> Result<bool> {
-    let mut synced = false;
-    let repo = Repo::open(sparse_repo.as_ref(), app.clone())?;
-    let mut selections = repo.selection_manager().context("Loading the selection")?;
-    let backup = if sync_if_changed {
-        Some(
-            selections
-                .create_backup()
-                .context("Creating a backup of the current selection")?,
-        )
-    } else {
-        None
-    };
-
+) -> Result<Vec<String>> {
     let mut projects_and_targets = projects_and_targets;
 
     match action {
@@ -153,6 +155,7 @@ fn mutate(
                 .map(|x| match x {
                     Target::Bazel(c) => format!("bazel:{}", c),
                     Target::Directory(c) => format!("bazel:{}", c),
+                    Target::File(c) => format!("file:{}", c),
                 })
                 .collect();
             projects_and_targets = targets;
@@ -160,25 +163,298 @@ fn mutate(
         }
         _ => (),
     }
-    if selections
-        .mutate(action, &projects_and_targets)
-        .context("Updating the selection")?
-    {
+
+    Ok(projects_and_targets)
+}
+
+/// The result of changing the selection with `keep_going`, where projects and targets that could not be applied are
+/// skipped instead of failing the whole change.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PartialMutation {
+    /// Whether the repo was synced after the selection changed.
+    pub synced: bool,
+
+    /// The requested projects and targets that were skipped, sorted.
+    pub skipped: Vec<String>,
+}
+
+impl PartialMutation {
+    /// Were all requested projects and targets applied?
+    pub fn is_complete(&self) -> bool {
+        self.skipped.is_empty()
+    }
+}
+
+/// Expand each requested project and target separately, so that one that cannot be expanded is skipped rather than
+/// failing the rest. Returns the expansion and the skipped names.
+fn expand_each_of_projects_and_targets(
+    selections: &SelectionManager,
+    action: OperationAction,
+    projects_and_targets: Vec<String>,
+) -> Result<(Vec<String>, Vec<String>)> {
+    if let OperationAction::Remove(RemoveOptions { all: true }) = action {
+        return Ok((
+            expand_projects_and_targets(selections, action, projects_and_targets)?,
+            Vec::new(),
+        ));
+    }
+
+    let mut expanded = Vec::new();
+    let mut skipped = Vec::new();
+    for item in projects_and_targets {
+        match expand_projects_and_targets(selections, action, vec![item.clone()]) {
+            Ok(items) => expanded.extend(items),
+            Err(e) => {
+                warn!(%item, error = %e, "Skipping");
+                skipped.push(item);
+            }
+        }
+    }
+    Ok((expanded, skipped))
+}
+
+/// A change to the coordinates the selection overrides.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OverrideChange {
+    /// Replace `from` with `to` wherever the selection resolves to `from`.
+    Set { from: Target, to: Target },
+
+    /// Stop overriding a coordinate.
+    Remove(Target),
+}
+
+/// Interpret a coordinate given on the command line. Bazel labels may omit the `bazel:` scheme, as in `//a:b`.
+fn parse_coordinate(repr: &str) -> Result<Target> {
+    let target = if repr.starts_with("//") || repr.starts_with('@') {
+        Target::try_from(format!("bazel:{}", repr).as_str())
+    } else {
+        Target::try_from(repr)
+    };
+    target.with_context(|| format!("Interpreting coordinate '{}'", repr))
+}
+
+/// Interpret an override given as `FROM=TO`, such as `//a:b=//a:b_local`.
+pub fn parse_override(spec: &str) -> Result<OverrideChange> {
+    match spec.split_once('=') {
+        Some((from, to)) => Ok(OverrideChange::Set {
+            from: parse_coordinate(from)?,
+            to: parse_coordinate(to)?,
+        }),
+        None => bail!("Invalid override '{}': expected FROM=TO", spec),
+    }
+}
+
+/// Interpret the coordinate of an override to remove.
+pub fn parse_override_removal(coordinate: &str) -> Result<OverrideChange> {
+    Ok(OverrideChange::Remove(parse_coordinate(coordinate)?))
+}
+
+fn mutate(
+    sparse_repo: impl AsRef<Path>,
+    sync_if_changed: bool,
+    action: OperationAction,
+    projects_and_targets: Vec<String>,
+    keep_going: bool,
+    app: Arc<focus_util::app::App>,
+) -> Result<PartialMutation> {
+    mutate_with_overrides(
+        sparse_repo,
+        sync_if_changed,
+        action,
+        projects_and_targets,
+        &[],
+        keep_going,
+        app,
+    )
+}
+
+fn mutate_with_overrides(
+    sparse_repo: impl AsRef<Path>,
+    sync_if_changed: bool,
+    action: OperationAction,
+    projects_and_targets: Vec<String>,
+    override_changes: &[OverrideChange],
+    keep_going: bool,
+    app: Arc<focus_util::app::App>,
+) -> Result<PartialMutation> {
+    let repo = Repo::open(sparse_repo.as_ref(), app.clone())?;
+    recover_orphaned_backup(&repo.selection_path()?, "selection")?;
+    let mut selections = repo.selection_manager().context("Loading the selection")?;
+    mutate_loaded(
+        sparse_repo.as_ref(),
+        &mut selections,
+        sync_if_changed,
+        action,
+        projects_and_targets,
+        override_changes,
+        keep_going,
+        app,
+    )
+}
+
+/// Like `add` and `remove`, but for a caller that keeps the selection loaded between changes, such as `serve`, so that
+/// the repo and project catalog are not loaded again. `selections` must reflect the stored selection.
+pub fn mutate_loaded_selection(
+    sparse_repo: &Path,
+    selections: &mut SelectionManager,
+    sync_if_changed: bool,
+    action: OperationAction,
+    projects_and_targets: Vec<String>,
+    app: Arc<App>,
+) -> Result<bool> {
+    Ok(mutate_loaded(
+        sparse_repo,
+        selections,
+        sync_if_changed,
+        action,
+        projects_and_targets,
+        &[],
+        false,
+        app,
+    )?
+    .synced)
+}
+
+fn mutate_loaded(
+    sparse_repo: &Path,
+    selections: &mut SelectionManager,
+    sync_if_changed: bool,
+    action: OperationAction,
+    projects_and_targets: Vec<String>,
+    override_changes: &[OverrideChange],
+    keep_going: bool,
+    app: Arc<focus_util::app::App>,
+) -> Result<PartialMutation> {
+    let mut synced = false;
+    let backup = if sync_if_changed {
+        Some(
+            selections
+                .create_backup()
+                .context("Creating a backup of the current selection")?,
+        )
+    } else {
+        None
+    };
+
+    let (projects_and_targets, mut skipped) = if keep_going {
+        expand_each_of_projects_and_targets(selections, action, projects_and_targets)?
+    } else {
+        (
+            expand_projects_and_targets(selections, action, projects_and_targets)?,
+            Vec::new(),
+        )
+    };
+    let mut result = selections
+        .mutate_with_result(action, &projects_and_targets)
+        .context("Updating the selection")?;
+    if keep_going && !result.is_success() {
+        // Nothing was applied; apply everything except what was not found.
+        let (absent, applicable): (Vec<String>, Vec<String>) =
+            projects_and_targets.into_iter().partition(|item| {
+                result
+                    .absent
+                    .contains(&Operation::new(action, item.clone()).underlying)
+            });
+        result = selections
+            .mutate_with_result(action, &applicable)
+            .context("Updating the selection")?;
+        skipped.extend(absent);
+    }
+    skipped.sort_unstable();
+    skipped.dedup();
+
+    if !app.options().no_deprecated_warnings {
+        for warning in deprecation_warnings(selections, &result) {
+            eprintln!("{} {}", style("Warning:").yellow().bold(), warning);
+        }
+    }
+    let mut overrides_changed = false;
+    for change in override_changes {
+        overrides_changed |= match change {
+            OverrideChange::Set { from, to } => selections.set_override(from.clone(), to.clone()),
+            OverrideChange::Remove(from) => {
+                let removed = selections.remove_override(from);
+                if !removed {
+                    warn!(%from, "Coordinate to stop overriding was not overridden");
+                }
+                removed
+            }
+        };
+    }
+    if result.changed() || overrides_changed {
         selections.save().context("Saving selection")?;
         if sync_if_changed {
             info!("Synchronizing after selection changed");
             // TODO: Use the correct sync mode here. Sync will override for SyncMode::Incremental, but that feels janky.
-            let result = super::sync::run(
-                &SyncRequest::new(sparse_repo.as_ref(), SyncMode::Incremental),
-                app,
-            )
-            .context("Synchronizing changes")?;
+            let result =
+                super::sync::run(&SyncRequest::new(sparse_repo, SyncMode::Incremental), app)
+                    .context("Synchronizing changes")?;
             synced = result.status == super::sync::SyncStatus::Success;
             backup.unwrap().discard();
         }
     }
 
-    Ok(synced)
+    Ok(PartialMutation { synced, skipped })
+}
+
+/// Warnings for the deprecated projects that `result` added to the selection, sorted by project name.
+fn deprecation_warnings(selections: &SelectionManager, result: &OperationResult) -> Vec<String> {
+    let optional_projects = &selections.project_catalog().optional_projects.underlying;
+    let mut warnings: Vec<(&str, String)> = result
+        .added
+        .iter()
+        .filter_map(|underlying| match underlying {
+            Underlying::Project(name) => optional_projects.get(name),
+            Underlying::Target(_) => None,
+        })
+        .filter_map(|project| Some((project.name.as_str(), project.deprecation_warning()?)))
+        .collect();
+    warnings.sort_unstable();
+    warnings.into_iter().map(|(_, warning)| warning).collect()
+}
+
+/// Apply a mutation to an in-memory copy of the selection and print how it would change, without saving or syncing.
+fn preview(
+    sparse_repo: impl AsRef<Path>,
+    action: OperationAction,
+    projects_and_targets: Vec<String>,
+    app: Arc<App>,
+) -> Result<OperationResult> {
+    let repo = Repo::open(sparse_repo.as_ref(), app)?;
+    let mut selections = repo.selection_manager().context("Loading the selection")?;
+    let projects_and_targets =
+        expand_projects_and_targets(&selections, action, projects_and_targets)?;
+    let result = selections
+        .mutate_with_result(action, &projects_and_targets)
+        .context("Updating the selection")?;
+
+    let sorted = |items: &HashSet<Underlying>| {
+        let mut items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+        items.sort_unstable();
+        items
+    };
+    for item in sorted(&result.absent) {
+        eprintln!("Not found: {}", item);
+    }
+    if !result.is_success() {
+        eprintln!(
+            "The selection would not change because some projects or targets were not found."
+        );
+        return Ok(result);
+    }
+    if !result.changed() {
+        eprintln!("The selection would not change.");
+        return Ok(result);
+    }
+    for item in sorted(&result.added) {
+        println!("{} {}", style("+").green(), item);
+    }
+    for item in sorted(&result.removed) {
+        println!("{} {}", style("-").red(), item);
+    }
+    eprintln!("Dry run: the selection was not changed.");
+
+    Ok(result)
 }
 
 pub fn add(
@@ -188,38 +464,928 @@ pub fn add(
     unroll: bool,
     app: Arc<App>,
 ) -> Result<bool> {
+    Ok(mutate(
+        sparse_repo,
+        sync_if_changed,
+        OperationAction::Add(AddOptions { unroll }),
+        projects_and_targets,
+        false,
+        app,
+    )?
+    .synced)
+}
+
+/// Like `add`, also applying `override_changes` to the selection's coordinate overrides. The repo is synced if either
+/// the selection or its overrides changed.
+pub fn add_with_overrides(
+    sparse_repo: impl AsRef<Path>,
+    sync_if_changed: bool,
+    projects_and_targets: Vec<String>,
+    unroll: bool,
+    override_changes: &[OverrideChange],
+    app: Arc<App>,
+) -> Result<bool> {
+    Ok(mutate_with_overrides(
+        sparse_repo,
+        sync_if_changed,
+        OperationAction::Add(AddOptions { unroll }),
+        projects_and_targets,
+        override_changes,
+        false,
+        app,
+    )?
+    .synced)
+}
+
+/// Like `remove`, also applying `override_changes` to the selection's coordinate overrides.
+pub fn remove_with_overrides(
+    sparse_repo: impl AsRef<Path>,
+    sync_if_changed: bool,
+    projects_and_targets: Vec<String>,
+    all: bool,
+    override_changes: &[OverrideChange],
+    app: Arc<App>,
+) -> Result<bool> {
+    Ok(mutate_with_overrides(
+        sparse_repo,
+        sync_if_changed,
+        OperationAction::Remove(RemoveOptions { all }),
+        projects_and_targets,
+        override_changes,
+        false,
+        app,
+    )?
+    .synced)
+}
+
+/// Like `add`, but projects and targets that cannot be added are skipped and reported instead of leaving the
+/// selection unchanged.
+pub fn add_keep_going(
+    sparse_repo: impl AsRef<Path>,
+    sync_if_changed: bool,
+    projects_and_targets: Vec<String>,
+    unroll: bool,
+    app: Arc<App>,
+) -> Result<PartialMutation> {
+    mutate(
+        sparse_repo,
+        sync_if_changed,
+        OperationAction::Add(AddOptions { unroll }),
+        projects_and_targets,
+        true,
+        app,
+    )
+}
+
+pub fn remove(
+    sparse_repo: impl AsRef<Path>,
+    sync_if_changed: bool,
+    projects_and_targets: Vec<String>,
+    all: bool,
+    app: Arc<App>,
+) -> Result<bool> {
+    Ok(mutate(
+        sparse_repo,
+        sync_if_changed,
+        OperationAction::Remove(RemoveOptions { all }),
+        projects_and_targets,
+        false,
+        app,
+    )?
+    .synced)
+}
+
+/// Like `remove`, but projects and targets that cannot be removed are skipped and reported instead of leaving the
+/// selection unchanged.
+pub fn remove_keep_going(
+    sparse_repo: impl AsRef<Path>,
+    sync_if_changed: bool,
+    projects_and_targets: Vec<String>,
+    all: bool,
+    app: Arc<App>,
+) -> Result<PartialMutation> {
     mutate(
         sparse_repo,
-        sync_if_changed,
+        sync_if_changed,
+        OperationAction::Remove(RemoveOptions { all }),
+        projects_and_targets,
+        true,
+        app,
+    )
+}
+
+/// Replace a project in the active selection with another, typically because it was renamed. The selection is saved
+/// but not synced.
+pub fn rename_project(
+    sparse_repo: impl AsRef<Path>,
+    from: &str,
+    to: &str,
+    app: Arc<App>,
+) -> Result<()> {
+    let repo = Repo::open(sparse_repo.as_ref(), app)?;
+    recover_orphaned_backup(&repo.selection_path()?, "selection")?;
+    let mut selections = repo.selection_manager().context("Loading the selection")?;
+    selections
+        .rename_project(from, to)
+        .with_context(|| format!("Renaming project '{}' to '{}'", from, to))?;
+    selections.save().context("Saving selection")?;
+    eprintln!("Replaced '{}' with '{}' in the selection", from, to);
+    Ok(())
+}
+
+/// Print the complete target set of the selection, including mandatory projects, one target per line. With
+/// `emit_query`, print the Bazel targets as a single query expression instead, e.g. for `bazel query "$(...)"`;
+/// directory and file targets have no equivalent and are reported on standard error.
+pub fn print_targets(sparse_repo: impl AsRef<Path>, emit_query: bool, app: Arc<App>) -> Result<()> {
+    let repo = Repo::open(sparse_repo.as_ref(), app)?;
+    let selections = repo.selection_manager().context("Loading the selection")?;
+    let targets = selections
+        .compute_complete_target_set()
+        .context("Computing the target set")?;
+
+    if !emit_query {
+        let mut targets: Vec<String> = targets.iter().map(|target| target.to_string()).collect();
+        targets.sort_unstable();
+        for target in targets {
+            println!("{}", target);
+        }
+        return Ok(());
+    }
+
+    let mut labels = Vec::new();
+    let mut omitted = Vec::new();
+    for target in targets.iter() {
+        match target {
+            Target::Bazel(label) => labels.push(label),
+            Target::Directory(_) | Target::File(_) => omitted.push(target.to_string()),
+        }
+    }
+    omitted.sort_unstable();
+    for target in omitted {
+        eprintln!("Omitted from the query: {}", target);
+    }
+    println!(
+        "{}",
+        bazel_query_union(labels).context("Building the query expression")?
+    );
+    Ok(())
+}
+
+/// Print how many targets each selected project contributes to the complete target set, and which of them the project
+/// lists itself versus pulls in through the projects it includes.
+pub fn print_expansion_report(sparse_repo: impl AsRef<Path>, app: Arc<App>) -> Result<()> {
+    let repo = Repo::open(sparse_repo.as_ref(), app)?;
+    let selections = repo.selection_manager().context("Loading the selection")?;
+    let expansions = selections
+        .expansion_report()
+        .context("Expanding the selected projects")?;
+
+    let mut times_contributed = HashMap::<&Target, usize>::new();
+    for expansion in expansions.iter() {
+        println!(
+            "{}{} ({} {}: {} direct, {} transitive)",
+            style(&expansion.name).bold(),
+            if expansion.mandatory {
+                " [mandatory]"
+            } else {
+                ""
+            },
+            expansion.len(),
+            if expansion.len() == 1 {
+                "target"
+            } else {
+                "targets"
+            },
+            expansion.direct.len(),
+            expansion.transitive.len()
+        );
+        for (kind, targets) in [
+            ("direct", &expansion.direct),
+            ("transitive", &expansion.transitive),
+        ] {
+            for target in targets.iter() {
+                println!("  {:<10}  {}", kind, target);
+                *times_contributed.entry(target).or_default() += 1;
+            }
+        }
+    }
+
+    let selection = selections.selection()?;
+    let mut individual_targets: Vec<String> = selection
+        .targets
+        .iter()
+        .map(|target| target.to_string())
+        .collect();
+    individual_targets.sort_unstable();
+    if !individual_targets.is_empty() {
+        println!(
+            "{} ({})",
+            style("Individually selected targets").bold(),
+            individual_targets.len()
+        );
+        for target in individual_targets {
+            println!("  {}", target);
+        }
+    }
+
+    let complete_target_set = selections
+        .compute_complete_target_set()
+        .context("Computing the target set")?;
+    println!();
+    println!(
+        "The complete target set has {} targets; {} of them are contributed by more than one project.",
+        complete_target_set.len(),
+        times_contributed.values().filter(|count| **count > 1).count()
+    );
+    Ok(())
+}
+
+/// A selected project or an individually selected target, which is treated as a whole when bisecting or measuring the
+/// selection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Layer {
+    pub name: String,
+    pub targets: TargetSet,
+}
+
+/// The layers of the user's selection, sorted by name.
+pub(crate) fn selected_layers(selections: &SelectionManager) -> Result<Vec<Layer>> {
+    let optional_projects = &selections.project_catalog().optional_projects.underlying;
+    let selection = selections.selection()?;
+    let mut layers = Vec::new();
+    for project in selection.projects.iter() {
+        let targets = resolve_targets_for_project(vec![project.clone()], optional_projects)
+            .with_context(|| format!("Resolving the targets of project {}", project.name))?;
+        layers.push(Layer {
+            name: project.name.clone(),
+            targets,
+        });
+    }
+    for target in selection.targets.iter() {
+        layers.push(Layer {
+            name: target.to_string(),
+            targets: TargetSet::from([target.clone()]),
+        });
+    }
+    layers.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    Ok(layers)
+}
+
+/// The shared selection file: the one configured by `focus.selection-mirror`, or `.focus-selection.json` in the
+/// working tree.
+pub fn selection_mirror_path(repo: &Repo) -> Result<PathBuf> {
+    Ok(match repo.get_selection_mirror_path()? {
+        Some(path) => path,
+        None => repo.path().join(DEFAULT_SELECTION_MIRROR_PATH),
+    })
+}
+
+/// Publish the active selection to `path` so that it can be committed and shared.
+pub fn write_selection_mirror(repo: &Repo, path: &Path) -> Result<()> {
+    let selections = repo.selection_manager().context("Loading the selection")?;
+    if selections.write_mirror(path)? {
+        info!(?path, "Updated the selection mirror");
+    }
+    Ok(())
+}
+
+fn declined_selection_mirror_path(repo: &Repo) -> PathBuf {
+    repo.git_focus_dir()
+        .join(DECLINED_SELECTION_MIRROR_FILE_NAME)
+}
+
+/// Remember that the user declined to adopt the shared selection in `path` as it is now, so that they are not asked
+/// again until it changes.
+pub(crate) fn decline_selection_mirror(repo: &Repo, path: &Path) -> Result<()> {
+    let digest = hashing::hash_file(path)?;
+    let declined_path = declined_selection_mirror_path(repo);
+    if let Some(parent) = declined_path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Creating {}", parent.display()))?;
+    }
+    std::fs::write(&declined_path, digest)
+        .with_context(|| format!("Writing {}", declined_path.display()))
+}
+
+/// Whether the user declined to adopt the shared selection in `path` as it is now.
+pub(crate) fn selection_mirror_was_declined(repo: &Repo, path: &Path) -> Result<bool> {
+    let declined = match std::fs::read(declined_selection_mirror_path(repo)) {
+        Ok(declined) => declined,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e).context("Reading the declined selection mirror"),
+    };
+    Ok(declined == hashing::hash_file(path)?)
+}
+
+/// If the shared selection (see `selection_mirror_path`) differs from the active selection, offer to adopt it. Without
+/// a terminal to ask on, the difference is only reported. A declined shared selection is not offered again until it
+/// changes. Returns whether the shared selection was adopted; the caller is responsible for syncing.
+pub fn offer_selection_mirror(sparse_repo: impl AsRef<Path>, app: Arc<App>) -> Result<bool> {
+    let repo = Repo::open(sparse_repo.as_ref(), app)?;
+    let path = selection_mirror_path(&repo)?;
+    let mut selections = repo.selection_manager().context("Loading the selection")?;
+    if !selections.differs_from_mirror(&path)? || selection_mirror_was_declined(&repo, &path)? {
+        return Ok(false);
+    }
+
+    let term = Term::stderr();
+    if !term.is_term() {
+        eprintln!(
+            "The shared selection in {} differs from yours; run focus in a terminal to adopt it.",
+            path.display()
+        );
+        return Ok(false);
+    }
+    loop {
+        term.write_str(&format!(
+            "The shared selection in {} differs from yours. Adopt it? [y/N] ",
+            path.display()
+        ))?;
+        match term.read_line()?.trim().to_ascii_lowercase().as_str() {
+            "y" | "yes" => break,
+            "" | "n" | "no" => {
+                decline_selection_mirror(&repo, &path)?;
+                eprintln!("Keeping your selection; you will be asked again if the shared selection changes.");
+                return Ok(false);
+            }
+            _ => continue,
+        }
+    }
+
+    recover_orphaned_backup(&repo.selection_path()?, "selection")?;
+    selections.adopt_mirror(&path)?;
+    selections.save().context("Saving selection")?;
+    eprintln!("Adopted the shared selection from {}", path.display());
+    Ok(true)
+}
+
+/// Rewrite the active selection file in canonical form without changing the selection. Returns whether the file
+/// changed.
+pub fn compact(sparse_repo: impl AsRef<Path>, app: Arc<App>) -> Result<bool> {
+    let repo = Repo::open(sparse_repo.as_ref(), app)?;
+    let selection_path = repo.selection_path()?;
+    recover_orphaned_backup(&selection_path, "selection")?;
+    let selections = repo.selection_manager().context("Loading the selection")?;
+    let changed = selections.compact().context("Compacting the selection")?;
+    if changed {
+        eprintln!("Compacted {}", selection_path.display());
+    } else {
+        eprintln!("{} is already compact", selection_path.display());
+    }
+    Ok(changed)
+}
+
+/// Add individual files to the selection. Paths may be absolute or relative to the root of the repository, and must
+/// name files that exist at HEAD.
+pub fn add_files(
+    sparse_repo: impl AsRef<Path>,
+    sync_if_changed: bool,
+    paths: Vec<PathBuf>,
+    app: Arc<App>,
+) -> Result<bool> {
+    let sparse_repo = sparse_repo.as_ref();
+    let repo = Repo::open(sparse_repo, app.clone())?;
+    let head_tree = repo
+        .get_head_commit()?
+        .tree()
+        .context("Resolving the HEAD tree")?;
+
+    let mut targets = Vec::new();
+    for path in paths {
+        let relative_path = if path.is_absolute() {
+            path.strip_prefix(sparse_repo)
+                .with_context(|| format!("{} is not within the repository", path.display()))?
+                .to_owned()
+        } else {
+            path
+        };
+        match head_tree.get_path(&relative_path) {
+            Ok(entry) if entry.kind() == Some(git2::ObjectType::Blob) => {}
+            Ok(_) => bail!(
+                "{} is not a file; use `focus add directory:{}` to select a directory",
+                relative_path.display(),
+                relative_path.display()
+            ),
+            Err(_) => bail!("{} does not exist at HEAD", relative_path.display()),
+        }
+        targets.push(Target::File(relative_path.to_string_lossy().into_owned()).to_string());
+    }
+
+    Ok(mutate(
+        sparse_repo,
+        sync_if_changed,
+        OperationAction::default_add(),
+        targets,
+        false,
+        app,
+    )?
+    .synced)
+}
+
+/// The target covering a changed file at `path`: the Bazel package owning it, or the file itself if it is in no
+/// package. Returns `None` for files in no package that do not exist in `tree`, e.g. because they were deleted.
+///
+/// Ownership is decided by the nearest `BUILD` file rather than by querying which rules use the file, since the
+/// outlining tree where Bazel runs holds only build files, so `glob`s in `srcs` match nothing there.
+fn covering_target(
+    repo: &git2::Repository,
+    tree: &git2::Tree,
+    path: &Path,
+) -> Result<Option<Target>> {
+    for package in path.ancestors().skip(1) {
+        let package_tree = if package.as_os_str().is_empty() {
+            tree.clone()
+        } else {
+            match tree.get_path(package) {
+                Ok(entry) if entry.kind() == Some(git2::ObjectType::Tree) => entry
+                    .to_object(repo)
+                    .and_then(|object| object.peel_to_tree())
+                    .with_context(|| format!("Resolving tree {}", package.display()))?,
+                _ => continue,
+            }
+        };
+        let is_package = package_tree.iter().any(|entry| {
+            entry.kind() == Some(git2::ObjectType::Blob)
+                && entry.name().map(is_build_definition).unwrap_or(false)
+        });
+        if is_package {
+            let label = format!("bazel://{}:all", package.display());
+            return Ok(Some(Target::try_from(label.as_str())?));
+        }
+    }
+
+    if tree.get_path(path).is_ok() {
+        Ok(Some(Target::File(path.to_string_lossy().into_owned())))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Add the targets covering the changed files listed in `paths_file` (one path per line, relative to the root of the
+/// repository, or `-` for standard input) to the selection. Returns the targets, sorted.
+pub fn select_for_paths(
+    sparse_repo: impl AsRef<Path>,
+    sync_if_changed: bool,
+    paths_file: &Path,
+    app: Arc<App>,
+) -> Result<Vec<String>> {
+    let sparse_repo = sparse_repo.as_ref();
+    let content = if paths_file == Path::new("-") {
+        let mut content = String::new();
+        std::io::stdin()
+            .read_to_string(&mut content)
+            .context("Reading paths from standard input")?;
+        content
+    } else {
+        std::fs::read_to_string(paths_file)
+            .with_context(|| format!("Reading {}", paths_file.display()))?
+    };
+
+    let repo = Repo::open(sparse_repo, app.clone())?;
+    let head_tree = repo
+        .get_head_commit()?
+        .tree()
+        .context("Resolving the HEAD tree")?;
+    let mut targets = BTreeSet::new();
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let path = Path::new(line);
+        match covering_target(repo.underlying(), &head_tree, path)? {
+            Some(target) => {
+                targets.insert(target.to_string());
+            }
+            None => {
+                warn!(path = %path.display(), "Skipping a path that is in no package and does not exist at HEAD")
+            }
+        }
+    }
+    let targets: Vec<String> = targets.into_iter().collect();
+    if targets.is_empty() {
+        info!("No paths were listed, so the selection is unchanged");
+        return Ok(targets);
+    }
+
+    mutate(
+        sparse_repo,
+        sync_if_changed,
+        OperationAction::default_add(),
+        targets.clone(),
+        false,
+        app,
+    )?;
+    Ok(targets)
+}
+
+/// Add the Bazel targets whose rule kind is exactly `kind` (e.g. `scala_test`) in `scope` and the packages beneath it
+/// to the selection. Returns the targets, sorted.
+pub fn add_by_kind(
+    sparse_repo: impl AsRef<Path>,
+    sync_if_changed: bool,
+    kind: &str,
+    scope: &str,
+    app: Arc<App>,
+) -> Result<Vec<String>> {
+    let sparse_repo = sparse_repo.as_ref();
+    let repo = Repo::open(sparse_repo, app.clone())?;
+    let targets: BTreeSet<String> = repo
+        .query_targets_of_kind(kind, scope, app.clone())?
+        .iter()
+        .map(|target| target.to_string())
+        .collect();
+    let targets: Vec<String> = targets.into_iter().collect();
+    if targets.is_empty() {
+        info!(%kind, %scope, "No targets of this kind were found, so the selection is unchanged");
+        return Ok(targets);
+    }
+
+    mutate(
+        sparse_repo,
+        sync_if_changed,
+        OperationAction::default_add(),
+        targets.clone(),
+        false,
+        app,
+    )?;
+    Ok(targets)
+}
+
+/// Print how adding the given projects and targets would change the selection, without changing it.
+pub fn add_dry_run(
+    sparse_repo: impl AsRef<Path>,
+    projects_and_targets: Vec<String>,
+    unroll: bool,
+    app: Arc<App>,
+) -> Result<OperationResult> {
+    preview(
+        sparse_repo,
         OperationAction::Add(AddOptions { unroll }),
         projects_and_targets,
         app,
     )
 }
 
-pub fn remove(
+/// Print how removing the given projects and targets would change the selection, without changing it.
+pub fn remove_dry_run(
     sparse_repo: impl AsRef<Path>,
-    sync_if_changed: bool,
     projects_and_targets: Vec<String>,
     all: bool,
     app: Arc<App>,
-) -> Result<bool> {
-    mutate(
+) -> Result<OperationResult> {
+    preview(
         sparse_repo,
-        sync_if_changed,
         OperationAction::Remove(RemoveOptions { all }),
         projects_and_targets,
         app,
     )
 }
 
-pub fn list_projects(sparse_repo: impl AsRef<Path>, app: Arc<App>) -> Result<()> {
+/// Switch the active selection set to `name`, creating it empty if it does not exist, and sync if requested.
+pub fn use_selection(
+    sparse_repo: impl AsRef<Path>,
+    name: &str,
+    sync: bool,
+    app: Arc<App>,
+) -> Result<bool> {
+    let repo = Repo::open(sparse_repo.as_ref(), app.clone())?;
+    let previous_name = repo.get_active_selection_name()?;
+    if previous_name == name {
+        eprintln!("Selection set '{}' is already active", name);
+        return Ok(false);
+    }
+    repo.set_active_selection_name(name)
+        .context("Switching the active selection set")?;
+    eprintln!(
+        "Switched from selection set '{}' to '{}'",
+        previous_name, name
+    );
+
+    if !sync {
+        return Ok(false);
+    }
+    let result = super::sync::run(
+        &SyncRequest::new(sparse_repo.as_ref(), SyncMode::Incremental),
+        app,
+    )
+    .context("Synchronizing the new selection set")?;
+    Ok(result.status == super::sync::SyncStatus::Success)
+}
+
+/// Replace the projects and targets in the selection with the projects of the preset `name` from the project catalog,
+/// syncing if the selection changed and `sync` is set. Coordinate overrides are kept. Returns whether a sync happened.
+pub fn apply_preset(
+    sparse_repo: impl AsRef<Path>,
+    name: &str,
+    sync: bool,
+    app: Arc<App>,
+) -> Result<bool> {
+    let repo = Repo::open(sparse_repo.as_ref(), app.clone())?;
+    recover_orphaned_backup(&repo.selection_path()?, "selection")?;
+    let mut selections = repo.selection_manager().context("Loading the selection")?;
+    let preset = match selections
+        .project_catalog()
+        .optional_projects
+        .presets
+        .get(name)
+    {
+        Some(preset) => preset.clone(),
+        None => bail!("Preset '{}' is not defined", name),
+    };
+    let backup = if sync {
+        Some(
+            selections
+                .create_backup()
+                .context("Creating a backup of the current selection")?,
+        )
+    } else {
+        None
+    };
+
+    let remove = |underlying: Underlying| Operation {
+        action: OperationAction::default_remove(),
+        underlying,
+    };
+    let selection = selections.selection()?;
+    let mut operations: Vec<Operation> = selection
+        .projects
+        .iter()
+        .filter(|project| project.is_selectable() && !preset.contains(&project.name))
+        .map(|project| remove(Underlying::Project(project.name.clone())))
+        .chain(
+            selection
+                .targets
+                .iter()
+                .map(|target| remove(Underlying::Target(target.clone()))),
+        )
+        .chain(
+            selections
+                .missing_projects()
+                .iter()
+                .map(|name| remove(Underlying::Project(name.clone()))),
+        )
+        .collect();
+    operations.extend(preset.iter().map(|project| Operation {
+        action: OperationAction::default_add(),
+        underlying: Underlying::Project(project.clone()),
+    }));
+
+    let result = selections
+        .process(&operations)
+        .context("Updating the selection")?;
+    if !result.is_success() {
+        let mut absent: Vec<String> = result.absent.iter().map(|item| item.to_string()).collect();
+        absent.sort_unstable();
+        bail!(
+            "Preset '{}' selects projects that are not defined: {}",
+            name,
+            absent.join(", ")
+        );
+    }
+    if !app.options().no_deprecated_warnings {
+        for warning in deprecation_warnings(&selections, &result) {
+            eprintln!("{} {}", style("Warning:").yellow().bold(), warning);
+        }
+    }
+    if !result.changed() {
+        eprintln!("The selection already matches preset '{}'", name);
+        return Ok(false);
+    }
+
+    selections.save().context("Saving selection")?;
+    eprintln!(
+        "Applied preset '{}' ({} added, {} removed)",
+        name,
+        result.added.len(),
+        result.removed.len()
+    );
+    if !sync {
+        return Ok(false);
+    }
+    let result = super::sync::run(
+        &SyncRequest::new(sparse_repo.as_ref(), SyncMode::Incremental),
+        app,
+    )
+    .context("Synchronizing the preset")?;
+    backup.unwrap().discard();
+    Ok(result.status == super::sync::SyncStatus::Success)
+}
+
+/// Print the previous versions of the selection recorded in the history, most recent first.
+pub fn history(sparse_repo: impl AsRef<Path>, app: Arc<App>) -> Result<()> {
+    let repo = Repo::open(sparse_repo.as_ref(), app)?;
+    let selections = repo.selection_manager().context("Loading the selection")?;
+    let entries = selections.history()?;
+    if entries.is_empty() {
+        eprintln!("The selection history is empty");
+        return Ok(());
+    }
+    for entry in entries.iter().rev() {
+        println!(
+            "{:>6}  {}  {} projects, {} targets",
+            entry.serial,
+            chrono::DateTime::<chrono::Local>::from(entry.recorded_at).format("%Y-%m-%d %H:%M:%S"),
+            entry.projects.len(),
+            entry.targets.len()
+        );
+    }
+    Ok(())
+}
+
+/// Replace the selection with the most recent version recorded in the history, syncing if `sync` is set. The entry is
+/// removed from the history once the selection has been applied. Returns whether a sync happened.
+pub fn undo(sparse_repo: impl AsRef<Path>, sync: bool, app: Arc<App>) -> Result<bool> {
+    let repo = Repo::open(sparse_repo.as_ref(), app.clone())?;
+    recover_orphaned_backup(&repo.selection_path()?, "selection")?;
+    let mut selections = repo.selection_manager().context("Loading the selection")?;
+    let entry = match selections.history()?.pop() {
+        Some(entry) => entry,
+        None => {
+            eprintln!("There is no earlier selection to go back to");
+            return Ok(false);
+        }
+    };
+    let backup = if sync {
+        Some(
+            selections
+                .create_backup()
+                .context("Creating a backup of the current selection")?,
+        )
+    } else {
+        None
+    };
+
+    selections
+        .restore(&entry)
+        .context("Restoring the previous selection")?;
+    eprintln!(
+        "Restored the selection from history entry {} ({} projects, {} targets)",
+        entry.serial,
+        entry.projects.len(),
+        entry.targets.len()
+    );
+    if !sync {
+        selections.discard_history_entry(&entry)?;
+        return Ok(false);
+    }
+    let result = super::sync::run(
+        &SyncRequest::new(sparse_repo.as_ref(), SyncMode::Incremental),
+        app,
+    )
+    .context("Synchronizing the restored selection")?;
+    backup.unwrap().discard();
+    selections.discard_history_entry(&entry)?;
+    Ok(result.status == super::sync::SyncStatus::Success)
+}
+
+/// Print the presets defined in the project catalog and the projects each selects.
+pub fn list_presets(
+    sparse_repo: impl AsRef<Path>,
+    app: Arc<App>,
+    format: ProjectListFormat,
+    porcelain: bool,
+) -> Result<()> {
+    let repo = Repo::open(sparse_repo.as_ref(), app)?;
+    let selections = repo.selection_manager()?;
+    let presets = &selections.project_catalog().optional_projects.presets;
+
+    if porcelain {
+        if format != ProjectListFormat::Text {
+            bail!("--porcelain cannot be combined with --format");
+        }
+        porcelain::print_header();
+        for (name, projects) in presets.iter() {
+            porcelain::print_record("preset", &[name.as_str(), &projects.join(",")]);
+        }
+        return Ok(());
+    }
+
+    match format {
+        ProjectListFormat::Text => {
+            let name_width = presets.keys().map(|name| name.len()).max().unwrap_or(0);
+            for (name, projects) in presets.iter() {
+                println!(
+                    "{:<name_width$}   {}",
+                    name,
+                    projects.join(", "),
+                    name_width = name_width
+                );
+            }
+        }
+        ProjectListFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(presets).context("Serializing presets")?
+        ),
+        ProjectListFormat::Csv => {
+            print!("name,projects\r\n");
+            for (name, projects) in presets.iter() {
+                print!("{},{}\r\n", csv_field(name), csv_field(&projects.join(" ")));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// How `list_projects` prints the project catalog.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProjectListFormat {
+    /// A human-readable table of optional projects.
+    Text,
+
+    /// A JSON array of all project definitions.
+    Json,
+
+    /// RFC 4180 CSV with a header row, covering all projects.
+    Csv,
+}
+
+impl FromStr for ProjectListFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(ProjectListFormat::Text),
+            "json" => Ok(ProjectListFormat::Json),
+            "csv" => Ok(ProjectListFormat::Csv),
+            _ => bail!("Unknown format '{}' (expected text, json, or csv)", s),
+        }
+    }
+}
+
+pub fn list_projects(
+    sparse_repo: impl AsRef<Path>,
+    app: Arc<App>,
+    format: ProjectListFormat,
+    porcelain: bool,
+) -> Result<()> {
     let repo = Repo::open(sparse_repo.as_ref(), app)?;
     let selections = repo.selection_manager()?;
-    println!("{}", selections.project_catalog().optional_projects);
+    let catalog = selections.project_catalog();
+    let optional_projects = &catalog.optional_projects;
+    let all_projects = || {
+        let mut projects: Vec<&Project> = optional_projects
+            .underlying
+            .values()
+            .chain(catalog.mandatory_projects.underlying.values())
+            .collect();
+        projects.sort_unstable_by_key(|project| project.name.as_str());
+        projects
+    };
+
+    if porcelain {
+        if format != ProjectListFormat::Text {
+            bail!("--porcelain cannot be combined with --format");
+        }
+        let mut projects: Vec<_> = optional_projects.underlying.values().collect();
+        projects.sort_unstable_by_key(|project| project.name.as_str());
+        porcelain::print_header();
+        for project in projects {
+            porcelain::print_record("project", &[&project.name, &project.description]);
+        }
+        return Ok(());
+    }
+
+    match format {
+        ProjectListFormat::Text => println!("{}", optional_projects),
+        ProjectListFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&all_projects()).context("Serializing projects")?
+        ),
+        ProjectListFormat::Csv => print!("{}", format_projects_as_csv(&all_projects())),
+    }
     Ok(())
 }
 
+/// Render projects as RFC 4180 CSV, including a header row.
+fn format_projects_as_csv(projects: &[&Project]) -> String {
+    const LINE_ENDING: &str = "\r\n";
+
+    let mut csv = String::from("name,description,mandatory,target_count,project_count,deprecated");
+    csv.push_str(LINE_ENDING);
+    for project in projects {
+        let fields = [
+            csv_field(&project.name),
+            csv_field(&project.description),
+            Cow::from(project.mandatory.to_string()),
+            Cow::from(project.targets.len().to_string()),
+            Cow::from(project.projects.len().to_string()),
+            csv_field(project.deprecated.as_deref().unwrap_or_default()),
+        ];
+        csv.push_str(&fields.join(","));
+        csv.push_str(LINE_ENDING);
+    }
+    csv
+}
+
+/// Quote a CSV field if it contains a delimiter, quote, or line break, doubling any quotes within it.
+fn csv_field(value: &str) -> Cow<str> {
+    if value.contains(|c| matches!(c, ',' | '"' | '\r' | '\n')) {
+        Cow::from(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::from(value)
+    }
+}
+
 #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 enum SkimSource {
     Project,
@@ -664,6 +1830,7 @@ mod tests {
     use std::fs;
 
     use anyhow::Result;
+    use focus_internals::target::Target;
 
     use crate::testing::integration::RepoPairFixture;
 
@@ -709,6 +1876,49 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn selection_overrides_replace_project_coordinates() -> Result<()> {
+        let fixture = RepoPairFixture::new()?;
+        fixture.perform_clone()?;
+
+        let project_target = Target::try_from("bazel://project_b/...")?;
+        let local_target = Target::try_from("directory:library_a")?;
+        let overrides = vec![crate::selection::parse_override(
+            "//project_b/...=directory:library_a",
+        )?];
+        crate::selection::add_with_overrides(
+            &fixture.sparse_repo_path,
+            false,
+            vec![String::from("team_zissou/project_c")],
+            false,
+            &overrides,
+            fixture.app.clone(),
+        )?;
+        let target_set = fixture
+            .sparse_repo()?
+            .selection_manager()?
+            .compute_complete_target_set()?;
+        assert!(target_set.contains(&local_target));
+        assert!(!target_set.contains(&project_target));
+
+        crate::selection::remove_with_overrides(
+            &fixture.sparse_repo_path,
+            false,
+            Vec::new(),
+            false,
+            &[crate::selection::parse_override_removal("//project_b/...")?],
+            fixture.app.clone(),
+        )?;
+        let selections = fixture.sparse_repo()?.selection_manager()?;
+        assert!(selections.selection()?.overrides.is_empty());
+        assert!(selections
+            .compute_complete_target_set()?
+            .contains(&project_target));
+
+        assert!(crate::selection::parse_override("//project_b/...").is_err());
+        Ok(())
+    }
+
     #[test]
     fn selection_remove_all() -> Result<()> {
         let fixture = RepoPairFixture::new()?;
@@ -958,4 +2168,243 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn formatting_projects_as_csv() {
+        use focus_internals::model::selection::Project;
+
+        use super::{csv_field, format_projects_as_csv};
+
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+
+        let project = Project {
+            name: "team/app".to_string(),
+            description: "The app, \"v2\"".to_string(),
+            mandatory: false,
+            targets: ["bazel://a/...", "directory:b"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            ..Default::default()
+        };
+        assert_eq!(
+            format_projects_as_csv(&[&project]),
+            "name,description,mandatory,target_count,project_count,deprecated\r\nteam/app,\"The app, \"\"v2\"\"\",false,2,0,\r\n"
+        );
+    }
+
+    #[test]
+    fn compacting_the_selection() -> Result<()> {
+        let fixture = RepoPairFixture::new()?;
+        fixture.perform_clone()?;
+
+        let selection_path = fixture.sparse_repo()?.selection_path()?;
+        fs::write(
+            &selection_path,
+            r#"{"targets": ["directory:w_dir", "bazel://library_b/...", "directory:w_dir"], "projects": ["team_banzai/project_a"]}"#,
+        )?;
+        let selection_before = fixture
+            .sparse_repo()?
+            .selection_manager()?
+            .computed_selection()?;
+
+        assert!(super::compact(
+            &fixture.sparse_repo_path,
+            fixture.app.clone()
+        )?);
+        assert_eq!(
+            fs::read_to_string(&selection_path)?,
+            r#"{
+    "projects": [
+        "team_banzai/project_a"
+    ],
+    "targets": [
+        "bazel://library_b/...",
+        "directory:w_dir"
+    ]
+}"#
+        );
+        assert_eq!(
+            fixture
+                .sparse_repo()?
+                .selection_manager()?
+                .computed_selection()?,
+            selection_before
+        );
+
+        // Compacting again has no effect.
+        assert!(!super::compact(
+            &fixture.sparse_repo_path,
+            fixture.app.clone()
+        )?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn renaming_a_project_in_the_selection() -> Result<()> {
+        let fixture = RepoPairFixture::new()?;
+        fixture.perform_clone()?;
+
+        let selection_path = fixture.sparse_repo()?.selection_path()?;
+        fs::write(
+            &selection_path,
+            r#"{"targets": [], "projects": ["team_banzai/old_name"]}"#,
+        )?;
+
+        super::rename_project(
+            &fixture.sparse_repo_path,
+            "team_banzai/old_name",
+            "team_banzai/project_a",
+            fixture.app.clone(),
+        )?;
+        let selections = fixture.sparse_repo()?.selection_manager()?;
+        assert!(selections.missing_projects().is_empty());
+        let selected: Vec<String> = selections
+            .selection()?
+            .projects
+            .into_iter()
+            .map(|project| project.name)
+            .collect();
+        assert_eq!(selected, vec!["team_banzai/project_a".to_string()]);
+
+        assert!(super::rename_project(
+            &fixture.sparse_repo_path,
+            "team_banzai/not_selected",
+            "team_banzai/project_a",
+            fixture.app.clone(),
+        )
+        .is_err());
+        assert!(super::rename_project(
+            &fixture.sparse_repo_path,
+            "team_banzai/project_a",
+            "team_banzai/undefined",
+            fixture.app.clone(),
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn keep_going_skips_what_cannot_be_applied() -> Result<()> {
+        let fixture = RepoPairFixture::new()?;
+        fixture.perform_clone()?;
+
+        let selected_projects = || -> Result<Vec<String>> {
+            let mut names: Vec<String> = fixture
+                .sparse_repo()?
+                .selection_manager()?
+                .selection()?
+                .projects
+                .into_iter()
+                .map(|project| project.name)
+                .collect();
+            names.sort_unstable();
+            Ok(names)
+        };
+        let requested = vec![
+            String::from("team_banzai/project_a"),
+            String::from("team_banzai/stale"),
+            String::from("team_zissou/project_b"),
+        ];
+
+        // Without keep-going, nothing is applied.
+        crate::selection::add(
+            &fixture.sparse_repo_path,
+            false,
+            requested.clone(),
+            false,
+            fixture.app.clone(),
+        )?;
+        assert!(selected_projects()?.is_empty());
+
+        let outcome = super::add_keep_going(
+            &fixture.sparse_repo_path,
+            false,
+            requested,
+            false,
+            fixture.app.clone(),
+        )?;
+        assert!(!outcome.is_complete());
+        assert_eq!(outcome.skipped, vec![String::from("team_banzai/stale")]);
+        assert_eq!(
+            selected_projects()?,
+            vec![
+                String::from("team_banzai/project_a"),
+                String::from("team_zissou/project_b")
+            ]
+        );
+
+        let outcome = super::remove_keep_going(
+            &fixture.sparse_repo_path,
+            false,
+            vec![
+                String::from("team_zissou/project_b"),
+                String::from("team_banzai/stale"),
+            ],
+            false,
+            fixture.app.clone(),
+        )?;
+        assert_eq!(outcome.skipped, vec![String::from("team_banzai/stale")]);
+        assert_eq!(
+            selected_projects()?,
+            vec![String::from("team_banzai/project_a")]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn apply_preset_replaces_selection() -> Result<()> {
+        let fixture = RepoPairFixture::new()?;
+        fixture.perform_clone()?;
+        fs::write(
+            fixture
+                .sparse_repo_path
+                .join("focus/projects/presets.projects.json"),
+            r#"{ "projects": [], "presets": { "starter": ["team_zissou/project_c"] } }"#,
+        )?;
+        crate::selection::add(
+            &fixture.sparse_repo_path,
+            false,
+            vec![
+                String::from("team_banzai/project_a"),
+                String::from("bazel://library_b/..."),
+            ],
+            false,
+            fixture.app.clone(),
+        )?;
+
+        crate::selection::apply_preset(
+            &fixture.sparse_repo_path,
+            "starter",
+            false,
+            fixture.app.clone(),
+        )?;
+        let selection = fixture.sparse_repo()?.selection_manager()?.selection()?;
+        let project_names: HashSet<String> = selection
+            .projects
+            .into_iter()
+            .filter(|project| project.is_selectable())
+            .map(|project| project.name)
+            .collect();
+        assert_eq!(
+            project_names,
+            HashSet::from(["team_zissou/project_c".to_string()])
+        );
+        assert!(selection.targets.is_empty());
+
+        assert!(crate::selection::apply_preset(
+            &fixture.sparse_repo_path,
+            "nonexistent",
+            false,
+            fixture.app.clone(),
+        )
+        .is_err());
+        Ok(())
+    }
 }
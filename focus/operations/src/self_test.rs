@@ -0,0 +1,238 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! An end-to-end check that focus works on this machine: a tiny dense repo is created in a temporary directory, a
+//! sparse repo is cloned from it, and the working tree is checked after cloning and after changing the selection.
+//! Only directory targets are used, so Bazel is not needed.
+
+use std::{fmt, path::Path, sync::Arc};
+
+use anyhow::{bail, Context, Result};
+use console::style;
+use focus_internals::tracker::Tracker;
+use focus_util::app::App;
+use tracing::info;
+
+use crate::{
+    clone::{CloneArgs, Origin},
+    sync::SyncMode,
+};
+
+const BRANCH_NAME: &str = "master";
+const ALPHA_PROJECT_NAME: &str = "self_test/alpha";
+const BETA_PROJECT_NAME: &str = "self_test/beta";
+
+const MANDATORY_PROJECTS: &str = r#"{
+    "projects": [
+        {
+            "name": "self_test/mandatory",
+            "description": "Always checked out",
+            "mandatory": true,
+            "targets": ["directory:shared"]
+        }
+    ]
+}
+"#;
+
+const OPTIONAL_PROJECTS: &str = r#"{
+    "projects": [
+        {
+            "name": "self_test/alpha",
+            "description": "The first optional project",
+            "targets": ["directory:alpha"]
+        },
+        {
+            "name": "self_test/beta",
+            "description": "The second optional project",
+            "targets": ["directory:beta"]
+        }
+    ]
+}
+"#;
+
+/// The outcome of one stage of the self-test.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StageResult {
+    pub name: &'static str,
+
+    /// Why the stage failed, if it did.
+    pub error: Option<String>,
+}
+
+/// The outcomes of the stages that ran. Stages after a failed one are not run.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SelfTestReport {
+    pub stages: Vec<StageResult>,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.stages.iter().all(|stage| stage.error.is_none())
+    }
+
+    /// Run `f` as the stage `name` unless an earlier stage failed.
+    fn stage(&mut self, name: &'static str, f: impl FnOnce() -> Result<()>) {
+        if !self.passed() {
+            return;
+        }
+        info!(stage = name, "Running self-test stage");
+        let error = f().err().map(|e| format!("{:#}", e));
+        self.stages.push(StageResult { name, error });
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for stage in self.stages.iter() {
+            match &stage.error {
+                None => writeln!(f, "{} {}", style("PASS").green().bold(), stage.name)?,
+                Some(error) => {
+                    writeln!(f, "{} {}", style("FAIL").red().bold(), stage.name)?;
+                    writeln!(f, "     {}", error)?;
+                }
+            }
+        }
+        if self.passed() {
+            writeln!(f, "Focus is working.")
+        } else {
+            writeln!(f, "Focus is not working; see the failed stage above.")
+        }
+    }
+}
+
+/// Run every stage of the self-test in a temporary directory, which is removed afterward.
+pub fn run(app: Arc<App>) -> Result<SelfTestReport> {
+    let dir = tempfile::Builder::new()
+        .prefix("focus-self-test")
+        .tempdir()
+        .context("Creating a temporary directory")?;
+    let dense_repo_path = dir.path().join("dense");
+    let sparse_repo_path = dir.path().join("sparse");
+    let tracker = Tracker::new(&dir.path().join("tracker")).context("Creating a tracker")?;
+
+    let mut report = SelfTestReport::default();
+    report.stage("Create a dense repo", || {
+        create_dense_repo(&dense_repo_path)
+    });
+    report.stage("Clone a sparse repo", || {
+        let clone_args = CloneArgs {
+            origin: Some(Origin::Local(dense_repo_path.clone())),
+            branch: String::from(BRANCH_NAME),
+            projects_and_targets: vec![String::from(ALPHA_PROJECT_NAME)],
+            copy_branches: false,
+            days_of_history: 90,
+            do_post_clone_fetch: false,
+            sync_mode: SyncMode::Incremental,
+            no_checkout: false,
+            write_lock: None,
+            from_lock: None,
+        };
+        crate::clone::run(
+            sparse_repo_path.clone(),
+            clone_args,
+            None,
+            &tracker,
+            app.clone(),
+        )
+    });
+    report.stage("Check out the selected project after cloning", || {
+        expect_checked_out(&sparse_repo_path, &["alpha", "shared"], &["beta", "gamma"])
+    });
+    report.stage("Add a project and sync", || {
+        if !crate::selection::add(
+            &sparse_repo_path,
+            true,
+            vec![String::from(BETA_PROJECT_NAME)],
+            false,
+            app.clone(),
+        )? {
+            bail!("The sync did not complete");
+        }
+        Ok(())
+    });
+    report.stage("Check out the added project after syncing", || {
+        expect_checked_out(&sparse_repo_path, &["alpha", "beta", "shared"], &["gamma"])
+    });
+
+    dir.close().context("Removing the temporary directory")?;
+    Ok(report)
+}
+
+fn create_dense_repo(path: &Path) -> Result<()> {
+    let files = [
+        ("focus/mandatory.projects.json", MANDATORY_PROJECTS),
+        ("focus/projects/self_test.projects.json", OPTIONAL_PROJECTS),
+        ("alpha/README", "alpha\n"),
+        ("beta/README", "beta\n"),
+        ("gamma/README", "gamma\n"),
+        ("shared/README", "shared\n"),
+        ("README", "A repo created by `focus self-test`\n"),
+    ];
+    for (name, contents) in files {
+        let file_path = path.join(name);
+        if let Some(parent) = file_path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Creating {}", parent.display()))?;
+        }
+        std::fs::write(&file_path, contents)
+            .with_context(|| format!("Writing {}", file_path.display()))?;
+    }
+
+    let mut init_options = git2::RepositoryInitOptions::new();
+    init_options.initial_head(BRANCH_NAME);
+    let repo = git2::Repository::init_opts(path, &init_options)
+        .with_context(|| format!("Initializing a repo in {}", path.display()))?;
+    let mut index = repo.index().context("Opening the index")?;
+    index
+        .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+        .context("Staging files")?;
+    index.write().context("Writing the index")?;
+    let tree = repo
+        .find_tree(index.write_tree().context("Writing the tree")?)
+        .context("Finding the tree")?;
+    let signature = git2::Signature::now("Focus Self-Test", "focus-self-test@localhost")
+        .context("Creating a signature")?;
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        "Initial commit",
+        &tree,
+        &[],
+    )
+    .context("Committing")?;
+    Ok(())
+}
+
+fn expect_checked_out(sparse_repo: &Path, present: &[&str], absent: &[&str]) -> Result<()> {
+    for dir in present {
+        if !sparse_repo.join(dir).join("README").is_file() {
+            bail!("Expected '{}' to be checked out, but it is not", dir);
+        }
+    }
+    for dir in absent {
+        if sparse_repo.join(dir).exists() {
+            bail!("Expected '{}' not to be checked out, but it is", dir);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use focus_testing::init_logging;
+
+    #[test]
+    fn self_test_passes() -> Result<()> {
+        init_logging();
+
+        let app = Arc::new(App::new_for_testing()?);
+        let report = run(app)?;
+        assert!(report.passed(), "{}", report);
+        assert_eq!(report.stages.len(), 5);
+
+        Ok(())
+    }
+}
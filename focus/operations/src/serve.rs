@@ -0,0 +1,318 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! A long-lived server that accepts JSON-RPC 2.0 requests on standard input, one per line, and writes one response per
+//! line to standard output. This lets editors drive focus without starting a new process for every call.
+//!
+//! The methods are `status`, `selected-layers` (also accepted as `selection`), `add`, `remove`, and `sync`.
+
+use std::{
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Context, Result};
+use focus_internals::{
+    locking,
+    model::{
+        repo::Repo,
+        selection::{OperationAction, SelectionManager},
+    },
+};
+use focus_util::app::App;
+use git2::Oid;
+use serde_json::{json, Value};
+use tracing::{debug, info};
+
+use crate::{
+    status::compute_drift,
+    sync::{SyncMode, SyncRequest},
+    util::recover_orphaned_backup,
+};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const OPERATION_FAILED: i64 = -32000;
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::new(OPERATION_FAILED, format!("{:#}", e))
+    }
+}
+
+/// Serve requests from standard input until it is closed.
+pub fn run(sparse_repo: &Path, app: Arc<App>) -> Result<()> {
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    serve(sparse_repo, stdin.lock(), stdout.lock(), app)
+}
+
+fn serve(
+    sparse_repo: &Path,
+    input: impl BufRead,
+    mut output: impl Write,
+    app: Arc<App>,
+) -> Result<()> {
+    info!(?sparse_repo, "Serving requests");
+    let mut server = Server::new(sparse_repo, app)?;
+    for line in input.lines() {
+        let line = line.context("Reading a request")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = server.handle_request(&line);
+        writeln!(output, "{}", response).context("Writing a response")?;
+        output.flush().context("Flushing the response")?;
+    }
+    info!("Input closed; no longer serving requests");
+    Ok(())
+}
+
+/// State kept between requests so that each one does not open the repo and load the project catalog again.
+struct Server {
+    sparse_repo: PathBuf,
+    app: Arc<App>,
+    repo: Repo,
+    /// The selection manager, along with the HEAD commit and selection name its project catalog was loaded for.
+    selections: Option<(Oid, String, SelectionManager)>,
+}
+
+impl Server {
+    fn new(sparse_repo: &Path, app: Arc<App>) -> Result<Self> {
+        let repo = Repo::open(sparse_repo, app.clone()).context("Failed to open the repo")?;
+        Ok(Self {
+            sparse_repo: sparse_repo.to_owned(),
+            app,
+            repo,
+            selections: None,
+        })
+    }
+
+    fn handle_request(&mut self, line: &str) -> Value {
+        let request: Value = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(e) => {
+                return error_response(
+                    Value::Null,
+                    RpcError::new(PARSE_ERROR, format!("Invalid JSON: {}", e)),
+                )
+            }
+        };
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let method = match request.get("method").and_then(Value::as_str) {
+            Some(method) => method,
+            None => {
+                return error_response(
+                    id,
+                    RpcError::new(INVALID_REQUEST, "The request has no method"),
+                )
+            }
+        };
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+        debug!(?id, %method, ?params, "Handling request");
+
+        match self.dispatch(method, &params) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(e) => error_response(id, e),
+        }
+    }
+
+    fn dispatch(&mut self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        let sparse_repo = self.sparse_repo.clone();
+        let app = self.app.clone();
+        match method {
+            "status" => Ok(self.status()?),
+            // `selection` was the original name of the method.
+            "selected-layers" | "selection" => Ok(self.selected_layers()?),
+            "add" | "remove" => {
+                let projects_and_targets = string_list_param(params, "projects_and_targets")?;
+                let sync = bool_param(params, "sync", true)?;
+                let action = if method == "add" {
+                    OperationAction::default_add()
+                } else {
+                    OperationAction::default_remove()
+                };
+                let _lock = locking::hold_repo_lock(&sparse_repo)?;
+                recover_orphaned_backup(&self.repo.selection_path()?, "selection")?;
+                let selections = self.selection_manager()?;
+                let synced = crate::selection::mutate_loaded_selection(
+                    &sparse_repo,
+                    selections,
+                    sync,
+                    action,
+                    projects_and_targets,
+                    app,
+                )?;
+                Ok(json!({ "synced": synced }))
+            }
+            "sync" => {
+                let _lock = locking::hold_repo_lock(&sparse_repo)?;
+                let result =
+                    crate::sync::run(&SyncRequest::new(&sparse_repo, SyncMode::Incremental), app)?;
+                Ok(json!({
+                    "status": format!("{:?}", result.status),
+                    "explanation": result.status.explanation(),
+                    "checked_out": result.checked_out,
+                    "commit_id": result.commit_id.map(|id| id.to_string()),
+                    "mechanism": result.mechanism.to_string(),
+                }))
+            }
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Unknown method '{}'", method),
+            )),
+        }
+    }
+
+    /// The selection manager with the stored selection freshly loaded. Its project catalog is only loaded again when
+    /// HEAD or the active selection changes, since the projects are defined in the working tree.
+    fn selection_manager(&mut self) -> Result<&mut SelectionManager> {
+        let head = self.repo.get_head_commit()?.id();
+        let selection_name = self.repo.get_active_selection_name()?;
+        let reusable = matches!(
+            &self.selections,
+            Some((cached_head, cached_name, _)) if *cached_head == head && *cached_name == selection_name
+        );
+        if reusable {
+            let (_, _, selections) = self.selections.as_mut().unwrap();
+            selections.reload()?;
+        } else {
+            debug!(?head, %selection_name, "Loading the project catalog");
+            self.selections = Some((head, selection_name, self.repo.selection_manager()?));
+        }
+        Ok(&mut self.selections.as_mut().unwrap().2)
+    }
+
+    fn status(&self) -> Result<Value> {
+        let drift = compute_drift(&self.repo)?;
+        Ok(json!({
+            "sync_point": drift.sync_point.map(|id| id.to_string()),
+            "head_ahead_behind": drift.head_ahead_behind,
+            "selection_changed": drift.selection_changed,
+            "synced_selection_name": drift.synced_selection_name,
+            "sync_recommended": drift.sync_recommended(),
+        }))
+    }
+
+    fn selected_layers(&mut self) -> Result<Value> {
+        let selection = self.selection_manager()?.selection()?;
+        let mut projects: Vec<String> = selection
+            .projects
+            .iter()
+            .map(|project| project.name.clone())
+            .collect();
+        projects.sort_unstable();
+        let mut targets: Vec<String> = selection
+            .targets
+            .iter()
+            .map(|target| target.to_string())
+            .collect();
+        targets.sort_unstable();
+        Ok(json!({ "projects": projects, "targets": targets }))
+    }
+}
+
+fn error_response(id: Value, error: RpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": error.code, "message": error.message },
+    })
+}
+
+fn string_list_param(params: &Value, name: &str) -> Result<Vec<String>, RpcError> {
+    let invalid = || {
+        RpcError::new(
+            INVALID_PARAMS,
+            format!("'{}' must be a list of strings", name),
+        )
+    };
+    params
+        .get(name)
+        .and_then(Value::as_array)
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|value| value.as_str().map(String::from).ok_or_else(invalid))
+        .collect()
+}
+
+fn bool_param(params: &Value, name: &str, default: bool) -> Result<bool, RpcError> {
+    match params.get(name) {
+        None | Some(Value::Null) => Ok(default),
+        Some(Value::Bool(value)) => Ok(*value),
+        Some(_) => Err(RpcError::new(
+            INVALID_PARAMS,
+            format!("'{}' must be a boolean", name),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::Result;
+
+    use crate::testing::integration::RepoPairFixture;
+
+    #[test]
+    fn serving_requests() -> Result<()> {
+        let fixture = RepoPairFixture::new()?;
+        fixture.perform_clone()?;
+
+        let input = [
+            r#"{"jsonrpc": "2.0", "id": 0, "method": "selected-layers"}"#,
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "add", "params": {"projects_and_targets": ["team_banzai/project_a"], "sync": false}}"#,
+            r#"{"jsonrpc": "2.0", "id": 2, "method": "selection"}"#,
+            "",
+            r#"{"jsonrpc": "2.0", "id": 3, "method": "explode"}"#,
+            r#"{"jsonrpc": "2.0", "id": 4, "method": "remove", "params": {"projects_and_targets": "team_banzai/project_a"}}"#,
+            "not json",
+        ]
+        .join("\n");
+        let mut output = Vec::new();
+        serve(
+            &fixture.sparse_repo_path,
+            input.as_bytes(),
+            &mut output,
+            fixture.app.clone(),
+        )?;
+
+        let responses: Vec<Value> = String::from_utf8(output)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(responses.len(), 6);
+        assert_eq!(responses[0]["result"]["projects"], json!([]));
+        assert_eq!(responses[1]["result"], json!({ "synced": false }));
+        // The cached selection manager picks up the change made by the add.
+        assert_eq!(
+            responses[2]["result"]["projects"],
+            json!(["team_banzai/project_a"])
+        );
+        assert_eq!(responses[3]["id"], json!(3));
+        assert_eq!(responses[3]["error"]["code"], json!(METHOD_NOT_FOUND));
+        assert_eq!(responses[4]["error"]["code"], json!(INVALID_PARAMS));
+        assert_eq!(responses[5]["id"], Value::Null);
+        assert_eq!(responses[5]["error"]["code"], json!(PARSE_ERROR));
+
+        Ok(())
+    }
+}
@@ -1,30 +1,211 @@
 // Copyright 2022 Twitter, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use anyhow::Result;
-use focus_internals::{model::repo::Repo, target::TargetTypes};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use focus_internals::{
+    hashing, locking,
+    model::{
+        repo::{Repo, WorkingTree},
+        selection::SelectionManager,
+    },
+    target::{Target, TargetTypes},
+};
 use focus_util::app::{App, ExitCode};
-use std::{collections::HashSet, path::Path, sync::Arc};
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    porcelain,
+    sync::{parse_sync_point_fingerprint, COMPLETION_MARKER_FILE_NAME, SYNC_LOCK_FILE_NAME},
+    util::format_size,
+};
+
+/// Records how far HEAD was behind the prefetched upstream commit when last computed, for `prompt_status`.
+const UPSTREAM_DRIFT_FILE_NAME: &str = "upstream-drift";
+
+/// How `run` renders the upstream commit time and the time of the last sync.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeFormat {
+    /// How long ago, to the second, such as `2h 5m 12s ago`.
+    Relative,
+
+    /// How long ago, in the largest whole unit, such as `2h ago`.
+    RelativeShort,
+
+    /// An RFC 3339 timestamp in UTC.
+    Absolute,
+}
+
+impl TimeFormat {
+    /// Render `at` as seen from `now`. Times in the future are treated as happening now.
+    pub fn format(&self, at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+        let age = Duration::from_secs((now - at).num_seconds().max(0) as u64);
+        match self {
+            TimeFormat::Relative => format!("{} ago", humantime::format_duration(age)),
+            TimeFormat::RelativeShort => {
+                const UNITS: [(u64, &str); 4] =
+                    [(24 * 60 * 60, "d"), (60 * 60, "h"), (60, "m"), (1, "s")];
+                let seconds = age.as_secs();
+                let (size, suffix) = UNITS
+                    .iter()
+                    .find(|(size, _)| seconds >= *size)
+                    .unwrap_or(&(1, "s"));
+                format!("{}{} ago", seconds / size, suffix)
+            }
+            TimeFormat::Absolute => at.to_rfc3339_opts(SecondsFormat::Secs, true),
+        }
+    }
+}
+
+impl FromStr for TimeFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "relative" => Ok(TimeFormat::Relative),
+            "relative-short" => Ok(TimeFormat::RelativeShort),
+            "absolute" => Ok(TimeFormat::Absolute),
+            _ => bail!(
+                "Unknown time format '{}' (expected relative, relative-short, or absolute)",
+                s
+            ),
+        }
+    }
+}
 
 pub fn run(
     sparse_repo: impl AsRef<Path>,
     app: Arc<App>,
     targets_flag: bool,
     target_types: Vec<TargetTypes>,
+    upstream: bool,
+    count_files: bool,
+    porcelain: bool,
+    time_format: TimeFormat,
+    lock_timeout: Option<Duration>,
+    verbose: bool,
 ) -> Result<ExitCode> {
     let target_types = HashSet::<TargetTypes>::from_iter(target_types.iter().cloned());
+    // Hold a shared lock so that a concurrent sync cannot change the selection while it is being read.
+    let _lock = locking::hold_read_lock(
+        sparse_repo.as_ref(),
+        Path::new(SYNC_LOCK_FILE_NAME),
+        lock_timeout,
+        app.clone(),
+    )
+    .context("Failed to obtain a read lock; is a sync in progress?")?;
     let repo = Repo::open(sparse_repo.as_ref(), app)?;
     let selections = repo.selection_manager()?;
     let selection = selections.selection()?;
     let working_tree = repo.working_tree()?;
     let is_filter_view = working_tree.get_filter_config()?;
+    let selection_name = repo.get_active_selection_name()?;
+
+    if porcelain {
+        porcelain::print_header();
+        porcelain::print_record("selection", &[&selection_name]);
+        porcelain::print_record("filter", &[if is_filter_view { "on" } else { "off" }]);
+        if upstream {
+            if let Some(behind) = upstream_drift(&repo)? {
+                porcelain::print_record("upstream", &[&behind.to_string()]);
+            }
+        }
+        if count_files {
+            let (count, size) = working_tree.count_materialized_files()?;
+            porcelain::print_record("files", &[&count.to_string(), &size.to_string()]);
+            porcelain::print_record(
+                "patterns",
+                &[&working_tree.sparse_pattern_count()?.to_string()],
+            );
+        }
+
+        let includes_type = |target: &Target| match target {
+            Target::Bazel(_) => {
+                target_types.is_empty() || target_types.contains(&TargetTypes::Bazel)
+            }
+            Target::Directory(_) => {
+                target_types.is_empty() || target_types.contains(&TargetTypes::Directory)
+            }
+            Target::File(_) => target_types.is_empty() || target_types.contains(&TargetTypes::File),
+        };
+        let target_set = if targets_flag {
+            selections.compute_complete_target_set()?
+        } else {
+            let mut project_names: Vec<&str> =
+                selection.projects.iter().map(|p| p.name.as_str()).collect();
+            project_names.sort_unstable();
+            for name in project_names {
+                porcelain::print_record("project", &[name]);
+            }
+            selection.targets
+        };
+        let mut targets: Vec<String> = target_set
+            .iter()
+            .filter(|target| includes_type(target))
+            .map(|target| target.to_string())
+            .collect();
+        targets.sort_unstable();
+        for target in targets {
+            porcelain::print_record("target", &[&target]);
+        }
+
+        return Ok(ExitCode(0));
+    }
 
     eprintln!();
+    eprintln!("Active selection set: {}", selection_name);
+    if let Some(synced_selection_name) = repo.read_sync_point_selection_name()? {
+        if synced_selection_name != selection_name {
+            eprintln!(
+                "The working tree was last synced with the '{}' selection set; run `focus sync` to update it.",
+                synced_selection_name
+            );
+        }
+    }
     if is_filter_view {
         eprintln!("Only selections are present in the worktree.");
     } else {
         eprintln!("All repo contents are present in the worktree.")
     }
+    if let Some(synced_at) = last_sync_completed_at(&repo)? {
+        eprintln!("Last synced: {}", time_format.format(synced_at, Utc::now()));
+    }
+    if verbose && !selection.overrides.is_empty() {
+        eprintln!("Coordinate overrides:");
+        for (from, to) in selection.overrides.iter() {
+            eprintln!("  {} -> {}", from, to);
+        }
+    } else if !selection.overrides.is_empty() {
+        eprintln!(
+            "{} {} overridden; run `focus status --verbose` to list the overrides.",
+            selection.overrides.len(),
+            if selection.overrides.len() == 1 {
+                "coordinate is"
+            } else {
+                "coordinates are"
+            }
+        );
+    }
+    if upstream {
+        report_upstream_drift(&repo, time_format)?;
+    }
+    if count_files {
+        let (count, size) = working_tree.count_materialized_files()?;
+        eprintln!(
+            "The working tree contains {} {} ({}) from {} sparse patterns.",
+            count,
+            if count == 1 { "file" } else { "files" },
+            format_size(size),
+            working_tree.sparse_pattern_count()?
+        );
+    }
     eprintln!();
 
     if target_types.is_empty() && !targets_flag {
@@ -51,9 +232,466 @@ pub fn run(
                         println!("{}", target);
                     }
                 }
+                focus_internals::target::Target::File(_) => {
+                    if target_types.contains(&TargetTypes::File) {
+                        println!("{}", target);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(ExitCode(0))
+}
+
+/// Determine how many commits HEAD is behind the most recently prefetched upstream commit, if anything has been
+/// prefetched. Nothing is written, so this is safe for read-only commands; see `record_upstream_drift`.
+pub(crate) fn upstream_drift(repo: &Repo) -> Result<Option<usize>> {
+    Ok(compare_with_upstream(repo)?.map(|(_, _, behind)| behind))
+}
+
+/// Like `upstream_drift`, also recording the result for `prompt_status`.
+pub(crate) fn record_upstream_drift(repo: &Repo) -> Result<Option<usize>> {
+    let (head_id, prefetch_id, behind) = match compare_with_upstream(repo)? {
+        Some(comparison) => comparison,
+        None => return Ok(None),
+    };
+    let path = upstream_drift_path(repo.git_dir());
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Creating {}", parent.display()))?;
+    }
+    std::fs::write(&path, format!("{} {} {}", head_id, prefetch_id, behind))
+        .with_context(|| format!("Writing {}", path.display()))?;
+    Ok(Some(behind))
+}
+
+/// The HEAD commit, the most recently prefetched upstream commit, and how many commits the former is behind the latter.
+fn compare_with_upstream(repo: &Repo) -> Result<Option<(git2::Oid, git2::Oid, usize)>> {
+    let head_commit = repo.get_head_commit().context("Resolving head commit")?;
+    let primary_branch_name = repo
+        .primary_branch_name()
+        .context("Determining primary branch name")?;
+    let prefetch_commit = match repo
+        .get_prefetch_head_commit("origin", primary_branch_name.as_str())
+        .context("Resolving prefetch head commit")?
+    {
+        Some(commit) => commit,
+        None => return Ok(None),
+    };
+
+    let (_ahead, behind) = repo
+        .underlying()
+        .graph_ahead_behind(head_commit.id(), prefetch_commit.id())
+        .context("Comparing HEAD with the prefetched upstream commit")?;
+    Ok(Some((head_commit.id(), prefetch_commit.id(), behind)))
+}
+
+pub(crate) fn upstream_drift_path(git_dir: &Path) -> PathBuf {
+    Repo::focus_git_dir_path(git_dir).join(UPSTREAM_DRIFT_FILE_NAME)
+}
+
+/// How many commits `head_id` is behind `prefetch_id` according to the last `record_upstream_drift`, if it compared
+/// them.
+fn recorded_upstream_drift(
+    git_dir: &Path,
+    head_id: git2::Oid,
+    prefetch_id: git2::Oid,
+) -> Option<usize> {
+    let content = std::fs::read_to_string(upstream_drift_path(git_dir)).ok()?;
+    let mut fields = content.split_whitespace();
+    let recorded_head_id = git2::Oid::from_str(fields.next()?).ok()?;
+    let recorded_prefetch_id = git2::Oid::from_str(fields.next()?).ok()?;
+    if (recorded_head_id, recorded_prefetch_id) != (head_id, prefetch_id) {
+        return None;
+    }
+    fields.next()?.parse().ok()
+}
+
+/// A compact summary of a focused repo for shell prompts, rendered like `focus:12L±!`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PromptStatus {
+    /// The number of projects in the selection.
+    pub projects: usize,
+
+    /// Whether HEAD is behind the most recently prefetched upstream commit, as last computed by a prefetch or
+    /// `focus status`.
+    pub behind_upstream: bool,
+
+    /// Whether the repo appears to need a sync.
+    pub needs_sync: bool,
+}
+
+impl Display for PromptStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "focus:{}L{}{}",
+            self.projects,
+            if self.behind_upstream { "±" } else { "" },
+            if self.needs_sync { "!" } else { "" }
+        )
+    }
+}
+
+/// Summarize the focused repo containing `path` for a shell prompt. Prompts are drawn constantly, so this only reads
+/// refs, the Git config, and files recorded by earlier commands, like `Repo::check_sync_needed`; in particular, the
+/// commit graph is not walked, so HEAD is only reported as behind upstream if that was recorded for the current HEAD
+/// and prefetched commit. Fails if `path` is not within a focused repo.
+pub fn prompt_status(path: &Path) -> Result<PromptStatus> {
+    Repo::check_focused(path)?;
+    let working_tree = WorkingTree::new(
+        git2::Repository::discover(path)
+            .with_context(|| format!("{} is not in a Git repo", path.display()))?,
+    )?;
+    let projects =
+        SelectionManager::count_persisted_projects(&working_tree.active_selection_path())?;
+
+    let repo = working_tree.git_repo();
+    let behind_upstream = working_tree
+        .primary_branch_name()
+        .ok()
+        .and_then(|branch| {
+            repo.refname_to_id(&Repo::prefetch_ref_name("origin", &branch))
+                .ok()
+        })
+        .and_then(|prefetch_id| {
+            let head_id = working_tree.get_head_commit().ok()?.id();
+            recorded_upstream_drift(working_tree.git_dir(), head_id, prefetch_id)
+        })
+        .map(|behind| behind > 0)
+        .unwrap_or(false);
+
+    Ok(PromptStatus {
+        projects,
+        behind_upstream,
+        // A sync that has not recorded its completion cannot be judged, and is not worth alarming a prompt about.
+        needs_sync: Repo::check_sync_needed(path).unwrap_or(false),
+    })
+}
+
+/// When the last successful sync finished, according to its completion marker.
+pub(crate) fn last_sync_completed_at(repo: &Repo) -> Result<Option<DateTime<Utc>>> {
+    let path = Repo::focus_git_dir_path(repo.git_dir()).join(COMPLETION_MARKER_FILE_NAME);
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Reading {}", path.display())),
+    };
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("completed_at="))
+        .map(|value| {
+            DateTime::parse_from_rfc3339(value)
+                .map(|time| time.with_timezone(&Utc))
+                .with_context(|| format!("Parsing the completion time in {}", path.display()))
+        })
+        .transpose()
+}
+
+/// Print how far behind the most recently prefetched upstream commit HEAD is, and when that commit was made.
+fn report_upstream_drift(repo: &Repo, time_format: TimeFormat) -> Result<()> {
+    let behind = match upstream_drift(repo)? {
+        Some(behind) => behind,
+        None => {
+            eprintln!("Upstream status is unavailable because nothing has been prefetched yet.");
+            return Ok(());
+        }
+    };
+
+    if let Some(problem) = prefetch_problem(repo)? {
+        eprintln!("Warning: {}", problem);
+    }
+
+    if behind == 0 {
+        eprintln!("HEAD is up to date with upstream.");
+    } else {
+        eprintln!(
+            "HEAD is {} {} older than upstream.",
+            behind,
+            if behind == 1 { "commit" } else { "commits" }
+        );
+    }
+
+    let primary_branch_name = repo
+        .primary_branch_name()
+        .context("Determining primary branch name")?;
+    if let Some(commit) = repo
+        .get_prefetch_head_commit("origin", primary_branch_name.as_str())
+        .context("Resolving prefetch head commit")?
+    {
+        if let Some(committed_at) = Utc.timestamp_opt(commit.time().seconds(), 0).single() {
+            eprintln!(
+                "Upstream commit time: {}",
+                time_format.format(committed_at, Utc::now())
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Prefetches older than this suggest that the prefetch job has stopped running.
+const PREFETCH_STALENESS_THRESHOLD: Duration = Duration::from_secs(3 * 24 * 60 * 60);
+
+/// When the prefetch ref was last updated. This is the newest reflog entry if the ref is logged, otherwise the
+/// modification time of the loose ref, otherwise that of `FETCH_HEAD`. Returns `None` if none of these are available.
+fn prefetched_at(repo: &Repo, ref_name: &str) -> Result<Option<SystemTime>> {
+    if let Ok(reflog) = repo.underlying().reflog(ref_name) {
+        if let Some(entry) = reflog.get(0) {
+            let seconds = entry.committer().when().seconds().max(0) as u64;
+            return Ok(Some(UNIX_EPOCH + Duration::from_secs(seconds)));
+        }
+    }
+
+    let git_dir = repo.underlying().path();
+    for path in [git_dir.join(ref_name), git_dir.join("FETCH_HEAD")] {
+        match std::fs::metadata(&path) {
+            Ok(metadata) => {
+                return Ok(Some(metadata.modified().with_context(|| {
+                    format!("Reading the modification time of {}", path.display())
+                })?))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                return Err(e).with_context(|| format!("Reading metadata of {}", path.display()))
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Describe why the prefetch ref looks broken, or return `None` if it exists and is reasonably fresh.
+fn prefetch_problem(repo: &Repo) -> Result<Option<String>> {
+    let primary_branch_name = repo
+        .primary_branch_name()
+        .context("Determining primary branch name")?;
+    let prefetch_commit = match repo
+        .get_prefetch_head_commit("origin", primary_branch_name.as_str())
+        .context("Resolving prefetch head commit")?
+    {
+        Some(commit) => commit,
+        None => {
+            return Ok(Some(format!(
+                "There is no prefetch ref for origin/{}; background prefetching may not be set up",
+                primary_branch_name
+            )))
+        }
+    };
+
+    // The prefetch ref should never lag behind what was fetched explicitly.
+    let remote_ref_name = format!("refs/remotes/origin/{}", primary_branch_name);
+    if let Ok(remote_ref) = repo.underlying().find_reference(&remote_ref_name) {
+        let remote_commit = remote_ref
+            .peel_to_commit()
+            .with_context(|| format!("Resolving {}", remote_ref_name))?;
+        let (ahead, behind) = repo
+            .underlying()
+            .graph_ahead_behind(remote_commit.id(), prefetch_commit.id())
+            .context("Comparing the remote-tracking ref with the prefetch ref")?;
+        if ahead > 0 && behind == 0 {
+            return Ok(Some(format!(
+                "The prefetched commit is {} {} older than origin/{}; prefetch may have stopped running",
+                ahead,
+                if ahead == 1 { "commit" } else { "commits" },
+                primary_branch_name
+            )));
+        }
+    }
+
+    // Nor should it lag behind what is checked out.
+    let head_commit = repo.get_head_commit()?;
+    let (ahead, behind) = repo
+        .underlying()
+        .graph_ahead_behind(head_commit.id(), prefetch_commit.id())
+        .context("Comparing HEAD with the prefetch ref")?;
+    if ahead > 0 && behind == 0 {
+        return Ok(Some(format!(
+            "The prefetched commit is {} {} older than HEAD; prefetch may have stopped running",
+            ahead,
+            if ahead == 1 { "commit" } else { "commits" },
+        )));
+    }
+
+    let ref_name = Repo::prefetch_ref_name("origin", primary_branch_name.as_str());
+    let fetched_at = match prefetched_at(repo, &ref_name)? {
+        Some(fetched_at) => fetched_at,
+        None => UNIX_EPOCH + Duration::from_secs(prefetch_commit.time().seconds().max(0) as u64),
+    };
+    let age = SystemTime::now()
+        .duration_since(fetched_at)
+        .unwrap_or_default();
+    if age > PREFETCH_STALENESS_THRESHOLD {
+        return Ok(Some(format!(
+            "The last prefetch was {} days ago; prefetch may have stopped running",
+            age.as_secs() / (24 * 60 * 60)
+        )));
+    }
+
+    Ok(None)
+}
+
+/// Verify that the prefetch ref exists and is reasonably fresh, exiting unsuccessfully if it is not.
+pub fn check_prefetch(sparse_repo: impl AsRef<Path>, app: Arc<App>) -> Result<ExitCode> {
+    let repo = Repo::open(sparse_repo.as_ref(), app)?;
+    match prefetch_problem(&repo)? {
+        Some(problem) => {
+            eprintln!("{}", problem);
+            Ok(ExitCode(1))
+        }
+        None => {
+            eprintln!("Prefetch is up to date.");
+            Ok(ExitCode(0))
+        }
+    }
+}
+
+/// How the repository has changed since it was last synced.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Drift {
+    /// The commit the working tree was last synced to, if it has ever been synced.
+    pub sync_point: Option<git2::Oid>,
+
+    /// The number of commits HEAD has moved ahead of and behind the sync point.
+    pub head_ahead_behind: Option<(usize, usize)>,
+
+    /// Whether the targets selected now differ from those that were synced. This is `None` if the sync point predates
+    /// focus recording what was synced.
+    pub selection_changed: Option<bool>,
+
+    /// The selection set that was synced, if it differs from the active one.
+    pub synced_selection_name: Option<String>,
+}
+
+impl Drift {
+    /// Whether running `focus sync` would change the working tree.
+    pub fn sync_recommended(&self) -> bool {
+        self.sync_point.is_none()
+            || self.head_ahead_behind != Some((0, 0))
+            || self.selection_changed != Some(false)
+            || self.synced_selection_name.is_some()
+    }
+}
+
+/// Compare HEAD and the selection with what was recorded when the repository was last synced.
+pub fn compute_drift(repo: &Repo) -> Result<Drift> {
+    let working_tree = repo.working_tree()?;
+    let sync_point = match working_tree.read_sparse_sync_point_ref()? {
+        Some(sync_point) => sync_point,
+        None => return Ok(Drift::default()),
+    };
+
+    let head_commit = repo.get_head_commit().context("Resolving head commit")?;
+    let head_ahead_behind = repo
+        .underlying()
+        .graph_ahead_behind(head_commit.id(), sync_point)
+        .context("Comparing HEAD with the sync point")?;
+
+    let targets = repo.selection_manager()?.compute_complete_target_set()?;
+    let selection_changed = repo
+        .read_sync_point_fingerprint()?
+        .and_then(|fingerprint| {
+            parse_sync_point_fingerprint(&fingerprint).map(|(_, hash)| hash.to_owned())
+        })
+        .map(|hash| hash != hashing::hash_target_set(&targets));
+
+    let active_selection_name = repo.get_active_selection_name()?;
+    let synced_selection_name = repo
+        .read_sync_point_selection_name()?
+        .filter(|name| *name != active_selection_name);
+
+    Ok(Drift {
+        sync_point: Some(sync_point),
+        head_ahead_behind: Some(head_ahead_behind),
+        selection_changed,
+        synced_selection_name,
+    })
+}
+
+/// Report how the repository has changed since it was last synced and whether a sync is recommended.
+pub fn drift(sparse_repo: impl AsRef<Path>, app: Arc<App>) -> Result<ExitCode> {
+    let repo = Repo::open(sparse_repo.as_ref(), app)?;
+    let drift = compute_drift(&repo)?;
+
+    match (drift.sync_point, drift.head_ahead_behind) {
+        (None, _) | (_, None) => {
+            println!("The repository has never been synced.");
+        }
+        (Some(sync_point), Some((ahead, behind))) => {
+            println!("Sync point: {}", sync_point);
+            if ahead == 0 && behind == 0 {
+                println!("HEAD has not moved since the last sync.");
+            } else {
+                println!(
+                    "HEAD has moved since the last sync ({} ahead, {} behind).",
+                    ahead, behind
+                );
             }
         }
     }
+    match drift.selection_changed {
+        Some(true) => println!("The selection has changed since the last sync."),
+        Some(false) => println!("The selection has not changed since the last sync."),
+        None if drift.sync_point.is_some() => {
+            println!(
+                "Whether the selection changed is unknown because the last sync did not record it."
+            )
+        }
+        None => {}
+    }
+    if let Some(name) = &drift.synced_selection_name {
+        println!(
+            "The last sync used the '{}' selection set, which is no longer active.",
+            name
+        );
+    }
+    if drift.sync_recommended() {
+        println!("A sync is recommended; run `focus sync`.");
+    } else {
+        println!("The working tree is up to date; no sync is needed.");
+    }
 
     Ok(ExitCode(0))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formatting_times() {
+        let now = Utc.ymd(2022, 6, 1).and_hms(12, 0, 0);
+        let at = Utc.ymd(2022, 6, 1).and_hms(9, 54, 30);
+
+        assert_eq!(TimeFormat::Relative.format(at, now), "2h 5m 30s ago");
+        assert_eq!(TimeFormat::RelativeShort.format(at, now), "2h ago");
+        assert_eq!(TimeFormat::Absolute.format(at, now), "2022-06-01T09:54:30Z");
+        assert_eq!(TimeFormat::RelativeShort.format(now, now), "0s ago");
+        assert_eq!(TimeFormat::Relative.format(now, at), "0s ago");
+        assert_eq!(
+            "relative-short".parse::<TimeFormat>().unwrap(),
+            TimeFormat::RelativeShort
+        );
+        assert!("sideways".parse::<TimeFormat>().is_err());
+    }
+
+    #[test]
+    fn formatting_prompt_status() {
+        let status = PromptStatus {
+            projects: 12,
+            behind_upstream: false,
+            needs_sync: false,
+        };
+        assert_eq!(status.to_string(), "focus:12L");
+        assert_eq!(
+            PromptStatus {
+                behind_upstream: true,
+                needs_sync: true,
+                ..status
+            }
+            .to_string(),
+            "focus:12L±!"
+        );
+    }
+}
@@ -4,15 +4,25 @@
 use core::fmt;
 use core::sync::atomic::AtomicBool;
 use focus_internals::index::RocksDBMemoizationCacheExt;
-use focus_internals::{locking, model::repo::Repo};
+use focus_internals::{
+    hashing, locking,
+    model::{
+        repo::{PatternApplication, Repo, SyncStats, WorkingTree},
+        verification,
+    },
+    target::{Target, TargetSet},
+};
 use focus_util::git;
 
-use crate::util::perform;
+use crate::util::{format_size, perform, recover_orphaned_backup};
 use content_addressed_cache::RocksDBCache;
 use focus_util::app::App;
 use focus_util::backed_up_file::BackedUpFile;
+use focus_util::interrupt::InterruptGuard;
+use focus_util::metrics;
 use tracing::{debug, info, warn};
 
+use std::collections::BTreeSet;
 use std::path::{Path, PathBuf};
 
 use std::sync::Arc;
@@ -24,6 +34,15 @@ use lazy_static::lazy_static;
 const PREEMPTIVE_SYNC_MAX_WAIT_MILLIS: u64 = 30000;
 const TEST_ONLY_PREEMPTIVE_SYNC_MAX_WAIT_MILLIS_UNDER_TEST: u64 = 300;
 const PREEMPTIVE_SYNC_POLL_INTERVAL_MILLIS: u64 = 100;
+pub(crate) const SYNC_LOCK_FILE_NAME: &str = "sync.lock";
+
+/// The file written to the focus Git directory when a non-preemptive sync leaves the working tree in sync. It is
+/// removed when such a sync starts, so its presence reliably indicates that the last sync completed.
+pub const COMPLETION_MARKER_FILE_NAME: &str = "last_sync_ok";
+/// The commit about to be synced, as seen by pre-sync hooks.
+pub const PRE_SYNC_HOOK_COMMIT_ENV_VAR: &str = "FOCUS_SYNC_COMMIT";
+/// The hash of the target set about to be synced, as seen by pre-sync hooks.
+pub const PRE_SYNC_HOOK_SELECTION_HASH_ENV_VAR: &str = "FOCUS_SELECTION_HASH";
 pub(crate) const SYNC_FROM_PROJECT_CACHE_REQUIRED_ERROR_MESSAGE: &str =
     "Sync from project cache was required but not possible";
 
@@ -52,6 +71,9 @@ pub enum SyncMode {
     Preemptive {
         /// Whether to skip enablement and machine idleness checks
         force: bool,
+
+        /// Whether to skip the machine idleness check while still honoring enablement
+        ignore_activity: bool,
     },
 
     // Peform a one-shot sync, not using the cache
@@ -81,6 +103,37 @@ pub enum SyncStatus {
 
     /// Syncing was cancelled because repo unfiltered view
     SkippedUnfilterView,
+
+    /// The sparse profile and sync point were written, but the working tree was deliberately not updated to match.
+    WrittenWithoutCheckout,
+}
+
+impl SyncStatus {
+    /// A human-readable explanation of why the sync was skipped, or `None` if it was performed.
+    pub fn explanation(&self) -> Option<String> {
+        match self {
+            SyncStatus::Success => None,
+            SyncStatus::SkippedSyncPointUnchanged => Some(String::from(
+                "Nothing to do: the sync point and selection have not changed since the last sync",
+            )),
+            SyncStatus::SkippedSyncPointDifferenceIrrelevant => Some(String::from(
+                "Nothing to do: the changes since the last sync do not affect the build graph",
+            )),
+            SyncStatus::SkippedPreemptiveSyncDisabled => Some(String::from(
+                "Preemptive sync is disabled; enable it with `focus background enable`",
+            )),
+            SyncStatus::SkippedPreemptiveSyncCancelledByActivity => Some(format!(
+                "Preemptive sync was cancelled because the machine was active for the whole {}s window",
+                PREEMPTIVE_SYNC_MAX_WAIT_MILLIS / 1000
+            )),
+            SyncStatus::SkippedUnfilterView => Some(String::from(
+                "Sync does not run while the focus filter is off; turn it back on with `focus filter on`",
+            )),
+            SyncStatus::WrittenWithoutCheckout => Some(String::from(
+                "Wrote the sparse profile without updating the working tree; it is out of sync until you run `git checkout`",
+            )),
+        }
+    }
 }
 
 /// An enumeration capturing which mechanism was used to perform the sync.
@@ -94,6 +147,9 @@ pub enum SyncMechanism {
 
     /// The sync was peformed by consulting the project cache.
     ProjectCache,
+
+    /// No sync was performed because the recorded sync point was already up to date.
+    Skipped,
 }
 
 impl fmt::Display for SyncMechanism {
@@ -102,6 +158,7 @@ impl fmt::Display for SyncMechanism {
             SyncMechanism::IncrementalOutline => write!(f, "outline"),
             SyncMechanism::OneShotOutline => write!(f, "one-shot-outline"),
             SyncMechanism::ProjectCache => write!(f, "project-cache"),
+            SyncMechanism::Skipped => write!(f, "skipped"),
         }
     }
 }
@@ -112,6 +169,42 @@ pub struct SyncRequest {
 
     /// Which sync mechanism to use.
     mode: SyncMode,
+
+    /// Targets to sync instead of those computed from the selection.
+    targets: Option<TargetSet>,
+
+    /// How long the machine must be idle before a preemptive sync proceeds, overriding the configured threshold.
+    idle_threshold: Option<Duration>,
+
+    /// Whether a non-preemptive sync should use the prefetch commit instead of HEAD.
+    to_prefetch: bool,
+
+    /// How many times to retry Bazel queries that fail transiently, overriding the configured value.
+    query_retries: Option<usize>,
+
+    /// Where to write the completion marker instead of the default location in the focus Git directory.
+    completion_marker_path: Option<PathBuf>,
+
+    /// Whether to write the sparse profile without checking out the working tree.
+    no_checkout: bool,
+
+    /// Whether to write the sparse profile without checking out the working tree, reporting that it is out of sync.
+    write_only: bool,
+
+    /// Where to mirror the selection after syncing instead of the location configured by `focus.selection-mirror`.
+    selection_mirror_path: Option<PathBuf>,
+
+    /// Whether to fail rather than warn if selected projects are no longer defined.
+    strict: bool,
+
+    /// Whether to recompute sparse checkout patterns found in the index rather than trusting them.
+    strict_cache: bool,
+
+    /// Whether to fail if Bazel reports warnings or errors while resolving.
+    strict_resolution: bool,
+
+    /// Whether to skip the configured pre-sync hooks.
+    no_hooks: bool,
 }
 
 impl SyncRequest {
@@ -119,9 +212,148 @@ impl SyncRequest {
         Self {
             sparse_repo: sparse_repo.as_ref().to_owned(),
             mode,
+            targets: None,
+            idle_threshold: None,
+            to_prefetch: false,
+            query_retries: None,
+            completion_marker_path: None,
+            no_checkout: false,
+            write_only: false,
+            selection_mirror_path: None,
+            strict: false,
+            strict_cache: false,
+            strict_resolution: false,
+            no_hooks: false,
         }
     }
 
+    /// Sync exactly the given targets rather than computing them from the selection.
+    pub fn with_targets(mut self, targets: TargetSet) -> Self {
+        self.targets = Some(targets);
+        self
+    }
+
+    pub fn targets(&self) -> Option<&TargetSet> {
+        self.targets.as_ref()
+    }
+
+    /// Override the configured idle threshold for a preemptive sync. The machine is checked for idleness even when
+    /// the sync is forced.
+    pub fn with_idle_threshold(mut self, idle_threshold: Duration) -> Self {
+        self.idle_threshold = Some(idle_threshold);
+        self
+    }
+
+    pub fn idle_threshold(&self) -> Option<Duration> {
+        self.idle_threshold
+    }
+
+    /// Sync to the prefetch commit of the primary branch rather than HEAD, in preparation for the next pull. The sync
+    /// point is recorded at the prefetch commit, so syncing after pulling it is skipped.
+    pub fn with_prefetch_commit(mut self) -> Self {
+        self.to_prefetch = true;
+        self
+    }
+
+    pub fn to_prefetch(&self) -> bool {
+        self.to_prefetch
+    }
+
+    /// Override the configured number of times Bazel queries that fail transiently are retried.
+    pub fn with_query_retries(mut self, query_retries: usize) -> Self {
+        self.query_retries = Some(query_retries);
+        self
+    }
+
+    pub fn query_retries(&self) -> Option<usize> {
+        self.query_retries
+    }
+
+    /// Write the completion marker to `path` rather than `.git/focus/last_sync_ok`.
+    pub fn with_completion_marker_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.completion_marker_path = Some(path.as_ref().to_owned());
+        self
+    }
+
+    pub fn completion_marker_path(&self) -> Option<&Path> {
+        self.completion_marker_path.as_deref()
+    }
+
+    /// Write the sparse profile and sync point without checking out the working tree, for callers that consume the
+    /// patterns rather than the files. The working tree is updated by the next checkout.
+    pub fn with_no_checkout(mut self) -> Self {
+        self.no_checkout = true;
+        self
+    }
+
+    pub fn no_checkout(&self) -> bool {
+        self.no_checkout
+    }
+
+    /// Like `with_no_checkout`, but for callers that run the checkout themselves at a time of their choosing: the
+    /// result has `SyncStatus::WrittenWithoutCheckout`, since the working tree is out of sync until they run
+    /// `git checkout`.
+    pub fn with_write_only(mut self) -> Self {
+        self.write_only = true;
+        self
+    }
+
+    pub fn write_only(&self) -> bool {
+        self.write_only
+    }
+
+    /// After syncing, write a canonical copy of the selection to `path` so that it can be committed and shared.
+    pub fn with_selection_mirror_path(mut self, path: PathBuf) -> Self {
+        self.selection_mirror_path = Some(path);
+        self
+    }
+
+    pub fn selection_mirror_path(&self) -> Option<&Path> {
+        self.selection_mirror_path.as_deref()
+    }
+
+    /// Fail if the selection includes projects that are no longer defined instead of ignoring them with a warning.
+    pub fn with_strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Skip the commands configured in `focus.hooks.pre-sync`, which could otherwise veto the sync.
+    pub fn with_no_hooks(mut self) -> Self {
+        self.no_hooks = true;
+        self
+    }
+
+    pub fn no_hooks(&self) -> bool {
+        self.no_hooks
+    }
+
+    /// Treat index entries as misses and recompute them, trading speed for certainty that the profile reflects the
+    /// commit being synced. Has no effect on one-shot syncs, which do not use the index.
+    pub fn with_strict_cache(mut self) -> Self {
+        self.strict_cache = true;
+        self
+    }
+
+    pub fn strict_cache(&self) -> bool {
+        self.strict_cache
+    }
+
+    /// Fail if Bazel reports warnings or errors while resolving, since a query that succeeds despite them may have
+    /// skipped some of the requested targets.
+    pub fn with_strict_resolution(mut self) -> Self {
+        self.strict_resolution = true;
+        self
+    }
+
+    pub fn strict_resolution(&self) -> bool {
+        self.strict_resolution
+    }
+
     pub fn sparse_repo_path(&self) -> &Path {
         self.sparse_repo.as_path()
     }
@@ -144,12 +376,282 @@ pub struct SyncResult {
 
     /// The mechanism used to perform the sync
     pub mechanism: SyncMechanism,
+
+    /// The sparse profile that was computed and how checking it out changed the working tree, if a new sparse
+    /// profile was computed
+    pub stats: Option<SyncStats>,
+}
+
+/// How many included and excluded directories are sampled when verifying the checkout.
+const VERIFY_SAMPLE_SIZE: usize = 100;
+
+/// Check a sample of the working tree against the sparse checkout patterns, printing any discrepancies. Returns
+/// whether the working tree matched.
+pub fn verify_checkout(sparse_repo: &Path, app: Arc<App>) -> Result<bool> {
+    let repo = Repo::open(sparse_repo, app).context("Failed to open the repo")?;
+    let working_tree = repo.working_tree()?;
+    if !working_tree.get_filter_config()? {
+        eprintln!("Skipping checkout verification because the focus filter is off");
+        return Ok(true);
+    }
+
+    let discrepancies = verification::verify_working_tree(&working_tree, VERIFY_SAMPLE_SIZE)
+        .context("Verifying the checkout")?;
+    if discrepancies.is_empty() {
+        eprintln!("The working tree matches the sparse checkout patterns");
+        return Ok(true);
+    }
+
+    eprintln!("The working tree does not match the sparse checkout patterns:");
+    for discrepancy in discrepancies.iter() {
+        eprintln!("  {}", discrepancy);
+    }
+    Ok(false)
+}
+
+/// Read a newline-delimited list of targets, ignoring blank lines and `#` comments.
+pub fn read_targets_file(path: &Path) -> Result<TargetSet> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Reading targets file {}", path.display()))?;
+    let mut targets = TargetSet::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let target = Target::try_from(line).with_context(|| {
+            format!(
+                "Parsing target '{}' on line {} of {}",
+                line,
+                index + 1,
+                path.display()
+            )
+        })?;
+        targets.insert(target);
+    }
+    Ok(targets)
+}
+
+/// Clear sync point refs that name commits missing from the object database so that the next sync starts over
+/// instead of failing. Returns the names of the cleared refs.
+pub fn verify_sync_point(sparse_repo: &Path, app: Arc<App>) -> Result<Vec<String>> {
+    let repo = Repo::open(sparse_repo, app.clone()).context("Failed to open the repo")?;
+    let _lock = locking::hold_lock(sparse_repo, Path::new(SYNC_LOCK_FILE_NAME), app)
+        .context("Failed to obtain synchronization lock")?;
+    repo.working_tree()?
+        .clear_dangling_sync_point_refs()
+        .context("Verifying the sync point refs")
+}
+
+/// Point the sparse sync ref at `commit` (any revision that names a commit) and record the current selection as the
+/// one synced there, without computing or applying a sparse profile. This is for recovering a repository whose sync
+/// point is known to be wrong; the working tree is left alone. Returns the commit that was recorded.
+pub fn set_sync_point(sparse_repo: &Path, commit: &str, app: Arc<App>) -> Result<git2::Oid> {
+    let repo = Repo::open(sparse_repo, app.clone()).context("Failed to open the repo")?;
+    let _lock = locking::hold_lock(sparse_repo, Path::new(SYNC_LOCK_FILE_NAME), app)
+        .context("Failed to obtain synchronization lock")?;
+    let commit_id = repo
+        .underlying()
+        .revparse_single(commit)
+        .and_then(|object| object.peel_to_commit())
+        .map(|commit| commit.id())
+        .with_context(|| format!("'{}' does not name a commit in this repository", commit))?;
+
+    let targets = repo.selection_manager()?.compute_complete_target_set()?;
+    let working_tree = repo.working_tree()?;
+    let fingerprint =
+        sync_point_fingerprint(working_tree, commit_id, &hashing::hash_target_set(&targets))?;
+    perform("Updating the sync point", || {
+        working_tree.write_sync_point_ref_to(commit_id)?;
+        record_sync_point(&repo, &fingerprint, true)
+    })?;
+    warn!(%commit_id, "Set the sync point without syncing");
+    Ok(commit_id)
+}
+
+/// Identifies a sync point commit together with the target set it was computed from and the settings that determine how
+/// the profile is written and checked out, so that changing any of them makes the next sync do the work again. Keep
+/// `parse_sync_point_fingerprint` in step with the layout.
+fn sync_point_fingerprint(
+    working_tree: &WorkingTree,
+    commit_id: git2::Oid,
+    selection_hash: &str,
+) -> Result<String> {
+    Ok(format!(
+        "{} {} {} {}",
+        commit_id,
+        selection_hash,
+        working_tree.get_sparse_mode()?,
+        working_tree.get_checkout_backend()?
+    ))
+}
+
+/// Read the commit and the target set hash back out of a fingerprint written by `sync_point_fingerprint`. The settings
+/// that follow them are only ever compared as part of the whole fingerprint. Returns `None` if the fingerprint is
+/// malformed.
+pub(crate) fn parse_sync_point_fingerprint(fingerprint: &str) -> Option<(git2::Oid, &str)> {
+    let mut fields = fingerprint.split(' ');
+    let commit_id = git2::Oid::from_str(fields.next()?).ok()?;
+    let selection_hash = fields.next().filter(|hash| !hash.is_empty())?;
+    Some((commit_id, selection_hash))
+}
+
+/// Record the fingerprint of the sync point along with the name and digest of the selection set it was synced from.
+/// When the sync used provided targets rather than the selection, no digest is recorded, so the selection still counts
+/// as needing a sync.
+fn record_sync_point(repo: &Repo, fingerprint: &str, synced_selection: bool) -> Result<()> {
+    repo.write_sync_point_fingerprint(fingerprint)?;
+    repo.write_sync_point_selection_name(&repo.get_active_selection_name()?)?;
+    if synced_selection {
+        repo.write_sync_point_selection_digest(&repo.selection_digest()?)
+    } else {
+        repo.clear_sync_point_selection_digest()
+    }
+}
+
+/// Run the commands configured in `focus.hooks.pre-sync` in the root of the working tree, failing with a hook's output
+/// if it exits unsuccessfully. Hooks are told what is about to be synced through the `FOCUS_SYNC_COMMIT` and
+/// `FOCUS_SELECTION_HASH` environment variables.
+fn run_pre_sync_hooks(repo: &Repo, commit_id: git2::Oid, selection_hash: &str) -> Result<()> {
+    for hook in repo.get_pre_sync_hooks()? {
+        info!(%hook, "Running pre-sync hook");
+        let output = std::process::Command::new("/bin/sh")
+            .arg("-c")
+            .arg(&hook)
+            .current_dir(repo.working_tree()?.work_dir())
+            .env(PRE_SYNC_HOOK_COMMIT_ENV_VAR, commit_id.to_string())
+            .env(PRE_SYNC_HOOK_SELECTION_HASH_ENV_VAR, selection_hash)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .with_context(|| format!("Running pre-sync hook '{}'", hook))?;
+        if !output.status.success() {
+            let hook_output: Vec<String> = [&output.stdout, &output.stderr]
+                .iter()
+                .map(|stream| String::from_utf8_lossy(stream).trim_end().to_owned())
+                .filter(|text| !text.is_empty())
+                .collect();
+            if hook_output.is_empty() {
+                bail!("The pre-sync hook '{}' refused the sync", hook);
+            }
+            bail!(
+                "The pre-sync hook '{}' refused the sync:\n{}",
+                hook,
+                hook_output.join("\n")
+            );
+        }
+    }
+    Ok(())
 }
 
 /// Synchronize the sparse repo's contents with the build graph. Returns a SyncResult indicating what happened.
 pub fn run(request: &SyncRequest, app: Arc<App>) -> Result<SyncResult> {
-    let repo =
+    let mut repo =
         Repo::open(request.sparse_repo_path(), app.clone()).context("Failed to open the repo")?;
+    if let Some(query_retries) = request.query_retries() {
+        repo.override_bazel_query_retries(query_retries);
+    }
+    if request.strict_cache() {
+        repo.require_strict_cache();
+    }
+    if request.strict_resolution() {
+        repo.require_strict_resolution();
+    }
+    // Taken before the selection is read, so a selection changed by another process during the sync is detected
+    // before the stale profile is written.
+    repo.expect_selection_digest(repo.selection_digest()?);
+
+    // Preemptive syncs do not change the working tree, so they leave the completion marker alone.
+    let preemptive = matches!(request.mode(), SyncMode::Preemptive { .. });
+    let completion_marker_path = match request.completion_marker_path() {
+        Some(path) => path.to_owned(),
+        None => Repo::focus_git_dir_path(repo.git_dir()).join(COMPLETION_MARKER_FILE_NAME),
+    };
+    if !preemptive {
+        remove_completion_marker(&completion_marker_path)?;
+    }
+
+    let result = run_with_repo(request, &repo, app.clone())?;
+    let mechanism = result.mechanism.to_string();
+    let status = crate::maintenance::sync_status_metric_helper(&result.status);
+    app.metrics().increment(
+        &metrics::SYNC_RUNS_TOTAL,
+        &[("status", &status), ("mechanism", &mechanism)],
+    );
+    if let Some(stats) = result.stats {
+        let pattern_counts = stats.pattern_counts;
+        for (stage, count) in [
+            ("computed", pattern_counts.computed),
+            ("optimized", pattern_counts.optimized),
+        ] {
+            app.metrics()
+                .set(&metrics::SYNC_PATTERNS, &[("stage", stage)], count as f64);
+        }
+        if stats.checked_out {
+            record_checkout_metrics(&app, &stats);
+        }
+    }
+    if !preemptive
+        && matches!(
+            result.status,
+            SyncStatus::Success | SyncStatus::SkippedSyncPointUnchanged
+        )
+    {
+        if let Some(commit_id) = result.commit_id {
+            write_completion_marker(&completion_marker_path, commit_id)?;
+        }
+    }
+    if let Some(selection_mirror_path) = request.selection_mirror_path() {
+        if !preemptive
+            && matches!(
+                result.status,
+                SyncStatus::Success
+                    | SyncStatus::SkippedSyncPointUnchanged
+                    | SyncStatus::WrittenWithoutCheckout
+            )
+        {
+            crate::selection::write_selection_mirror(&repo, selection_mirror_path)
+                .context("Mirroring the selection")?;
+        }
+    }
+    Ok(result)
+}
+
+/// A one-line summary of how a checkout changed the working tree.
+pub fn checkout_summary(stats: &SyncStats) -> String {
+    format!(
+        "Checked out {} files ({}) and removed {} ({}) in {:.1}s",
+        stats.files_added,
+        format_size(stats.bytes_added),
+        stats.files_removed,
+        format_size(stats.bytes_removed),
+        stats.checkout_duration.as_secs_f64()
+    )
+}
+
+fn record_checkout_metrics(app: &App, stats: &SyncStats) {
+    for (change, count, bytes) in [
+        ("added", stats.files_added, stats.bytes_added),
+        ("removed", stats.files_removed, stats.bytes_removed),
+    ] {
+        app.metrics().set(
+            &metrics::SYNC_CHECKOUT_FILES,
+            &[("change", change)],
+            count as f64,
+        );
+        app.metrics().set(
+            &metrics::SYNC_CHECKOUT_BYTES,
+            &[("change", change)],
+            bytes as f64,
+        );
+    }
+    app.metrics().set(
+        &metrics::SYNC_CHECKOUT_DURATION_SECONDS,
+        &[],
+        stats.checkout_duration.as_secs_f64(),
+    );
+}
+
+fn run_with_repo(request: &SyncRequest, repo: &Repo, app: Arc<App>) -> Result<SyncResult> {
     let working_tree = repo.working_tree()?;
     if !working_tree.get_filter_config()? {
         info!("Sync does not run when focus filter is off. Run \"focus filter on\" to turn filter back on.");
@@ -158,31 +660,46 @@ pub fn run(request: &SyncRequest, app: Arc<App>) -> Result<SyncResult> {
             commit_id: None,
             status: SyncStatus::SkippedUnfilterView,
             mechanism: SyncMechanism::IncrementalOutline,
+            stats: None,
         });
     }
 
-    let (preemptive, force) = match request.mode {
-        SyncMode::Preemptive { force: forced } => (true, forced),
-        _ => (false, false),
+    let (preemptive, force, ignore_activity) = match request.mode {
+        SyncMode::Preemptive {
+            force,
+            ignore_activity,
+        } => (true, force, ignore_activity),
+        _ => (false, false, false),
     };
 
-    if preemptive && !force {
-        if !repo.get_preemptive_sync_enabled()? {
-            return Ok(SyncResult {
-                checked_out: false,
-                commit_id: None,
-                status: SyncStatus::SkippedPreemptiveSyncDisabled,
-                mechanism: SyncMechanism::IncrementalOutline,
-            });
-        }
+    let max_wait = Duration::from_millis(if cfg!(test) {
+        TEST_ONLY_PREEMPTIVE_SYNC_MAX_WAIT_MILLIS_UNDER_TEST
+    } else {
+        PREEMPTIVE_SYNC_MAX_WAIT_MILLIS
+    });
+    let poll_interval = Duration::from_millis(PREEMPTIVE_SYNC_POLL_INTERVAL_MILLIS);
+    if let Some(idle_threshold) = request.idle_threshold() {
+        validate_idle_wait(idle_threshold, max_wait, poll_interval)
+            .context("Invalid idle threshold")?;
+    }
 
-        let idle_duration = repo.get_preemptive_sync_idle_threshold()?;
-        let max_wait = Duration::from_millis(if cfg!(test) {
-            TEST_ONLY_PREEMPTIVE_SYNC_MAX_WAIT_MILLIS_UNDER_TEST
-        } else {
-            PREEMPTIVE_SYNC_MAX_WAIT_MILLIS
+    if preemptive && !force && !repo.get_preemptive_sync_enabled()? {
+        return Ok(SyncResult {
+            checked_out: false,
+            commit_id: None,
+            status: SyncStatus::SkippedPreemptiveSyncDisabled,
+            mechanism: SyncMechanism::IncrementalOutline,
+            stats: None,
         });
-        let poll_interval = Duration::from_millis(PREEMPTIVE_SYNC_POLL_INTERVAL_MILLIS);
+    }
+
+    if preemptive && ignore_activity {
+        info!("Ignoring machine activity for this preemptive sync");
+    } else if preemptive && (!force || request.idle_threshold().is_some()) {
+        let idle_duration = match request.idle_threshold() {
+            Some(idle_threshold) => idle_threshold,
+            None => repo.get_preemptive_sync_idle_threshold()?,
+        };
         info!(
             ?idle_duration,
             ?max_wait,
@@ -200,21 +717,25 @@ pub fn run(request: &SyncRequest, app: Arc<App>) -> Result<SyncResult> {
                 commit_id: None,
                 status: SyncStatus::SkippedPreemptiveSyncCancelledByActivity,
                 mechanism: SyncMechanism::IncrementalOutline,
+                stats: None,
             });
         }
     }
 
     let _lock = locking::hold_lock(
         request.sparse_repo_path(),
-        Path::new("sync.lock"),
+        Path::new(SYNC_LOCK_FILE_NAME),
         app.clone(),
     )
     .context("Failed to obtain synchronization lock")?;
 
-    let sparse_profile_path = repo.git_dir().join("info").join("sparse-checkout");
+    let sparse_profile_path = working_tree.sparse_checkout_path();
     if !sparse_profile_path.is_file() {
         bail!("This does not appear to be a focused repo -- it is missing a sparse checkout file");
     }
+    if !preemptive {
+        recover_orphaned_backup(&sparse_profile_path, "sparse checkout profile")?;
+    }
 
     // Take a snapshot of the sparse repo state.
     let snapshot =
@@ -228,8 +749,17 @@ pub fn run(request: &SyncRequest, app: Arc<App>) -> Result<SyncResult> {
         git::snapshot::ReapplyGuard::new(request.sparse_repo_path(), snapshot.clone(), app.clone());
 
     let selections = repo.selection_manager()?;
+    if !preemptive {
+        check_for_missing_projects(selections.missing_projects(), request.strict())?;
+    }
     let selection = selections.computed_selection()?;
-    let targets = selections.compute_complete_target_set()?;
+    let targets = match request.targets() {
+        Some(targets) => {
+            info!(count = targets.len(), "Using the provided target set");
+            targets.clone()
+        }
+        None => selections.compute_complete_target_set()?,
+    };
 
     let mut mechanism = SyncMechanism::IncrementalOutline;
 
@@ -251,6 +781,8 @@ pub fn run(request: &SyncRequest, app: Arc<App>) -> Result<SyncResult> {
         ti_client
             .get_context()
             .add_to_custom_map("total_target_count", targets.len().to_string());
+        app.metrics()
+            .set(&metrics::SYNC_TARGETS, &[], targets.len() as f64);
         ti_client.get_context().add_to_custom_map(
             "user_selected_project_count",
             selection.projects.len().to_string(),
@@ -284,19 +816,26 @@ pub fn run(request: &SyncRequest, app: Arc<App>) -> Result<SyncResult> {
         .primary_branch_name()
         .context("Determining primary branch name")?;
 
-    let commit = if preemptive {
+    let commit = if preemptive || request.to_prefetch() {
         if let Some(prefetch_commit) = repo
             .get_prefetch_head_commit("origin", primary_branch_name.as_str())
             .context("Resolving prefetch head commit")?
         {
             prefetch_commit
-        } else {
+        } else if preemptive {
             bail!("No prefetch commit found for preemptive sync");
+        } else {
+            bail!(
+                "No prefetch commit found; run `git maintenance run --task=prefetch` to fetch one"
+            );
         }
     } else {
         head_commit
     };
 
+    let selection_hash = hashing::hash_target_set(&targets);
+    let fingerprint = sync_point_fingerprint(working_tree, commit.id(), &selection_hash)?;
+
     if preemptive {
         if let Ok(working_tree) = repo.working_tree() {
             if let Ok(Some(sync_point)) = working_tree.read_sparse_sync_point_ref() {
@@ -308,6 +847,7 @@ pub fn run(request: &SyncRequest, app: Arc<App>) -> Result<SyncResult> {
                         commit_id: Some(commit.id()),
                         status: SyncStatus::SkippedSyncPointUnchanged,
                         mechanism,
+                        stats: None,
                     });
                 }
             } else if let Ok(Some(sync_point)) = working_tree.read_preemptive_sync_point_ref() {
@@ -319,19 +859,62 @@ pub fn run(request: &SyncRequest, app: Arc<App>) -> Result<SyncResult> {
                         commit_id: Some(commit.id()),
                         status: SyncStatus::SkippedSyncPointUnchanged,
                         mechanism,
+                        stats: None,
                     });
                 }
             }
         }
         // TODO: Skip outlining if there are no changes to the build graph between the last and new prospective sync point
+    } else if !request.strict_cache()
+        && !request.strict_resolution()
+        && working_tree.read_sparse_sync_point_ref()? == Some(commit.id())
+        && repo.read_sync_point_fingerprint()?.as_deref() == Some(fingerprint.as_str())
+        && !working_tree.checkout_pending()
+    {
+        // Neither the sync point nor the selection changed since the last sync, so the profile is already up to date.
+        // Strict syncs are never skipped since their point is to check the resolution again.
+        info!("Skipping synchronization because the sync point and selection are unchanged");
+        perform("Updating the sync point", || {
+            record_sync_point(repo, &fingerprint, request.targets().is_none())
+        })?;
+        if let Some(backed_up_sparse_profile) = backed_up_sparse_profile {
+            backed_up_sparse_profile.set_restore(false);
+        }
+        return Ok(SyncResult {
+            checked_out: false,
+            commit_id: Some(commit.id()),
+            status: SyncStatus::SkippedSyncPointUnchanged,
+            mechanism: SyncMechanism::Skipped,
+            stats: None,
+        });
     }
 
+    if !preemptive && !request.no_hooks() {
+        run_pre_sync_hooks(repo, commit.id(), &selection_hash)?;
+    }
+
+    let pattern_application = if preemptive {
+        PatternApplication::Skip
+    } else if request.no_checkout() || request.write_only() {
+        PatternApplication::WriteOnly
+    } else {
+        PatternApplication::Checkout
+    };
+
     // If only projects are selected (no ad-hoc targets) we try to use the project cache to sync. Otherwise we fall back to regular syncing.
-    let (pattern_count, checked_out) = perform("Computing the new sparse profile", || {
-        // Try to use the project cache
-        let project_cache_result = repo
-            .sync_using_project_cache(commit.id(), &selection, snapshot.clone())
-            .context("Syncing from project cache failed");
+    let stats = perform("Computing the new sparse profile", || {
+        // Try to use the project cache, unless the caller provided the targets or resolution must be checked
+        let project_cache_result = if request.targets().is_some() || request.strict_resolution() {
+            Ok(None)
+        } else {
+            repo.sync_using_project_cache(
+                commit.id(),
+                &selection,
+                pattern_application,
+                snapshot.clone(),
+            )
+            .context("Syncing from project cache failed")
+        };
 
         match project_cache_result {
             // Answered from project cache optionally
@@ -370,7 +953,7 @@ pub fn run(request: &SyncRequest, app: Arc<App>) -> Result<SyncResult> {
                 repo.sync(
                     commit.id(),
                     &targets,
-                    preemptive,
+                    pattern_application,
                     app.clone(),
                     cache.as_ref(),
                     snapshot.clone(),
@@ -387,7 +970,7 @@ pub fn run(request: &SyncRequest, app: Arc<App>) -> Result<SyncResult> {
     } else {
         ti_client
             .get_context()
-            .add_to_custom_map("pattern_count", pattern_count.to_string());
+            .add_to_custom_map("pattern_count", stats.pattern_counts.optimized.to_string());
         ti_client
             .get_context()
             .add_to_custom_map("sync_mechanism", mechanism.to_string());
@@ -395,7 +978,12 @@ pub fn run(request: &SyncRequest, app: Arc<App>) -> Result<SyncResult> {
             .get_context()
             .add_to_custom_map("sync_commit_id", commit.id().to_string());
         perform("Updating the sync point", || {
-            working_tree.write_sync_point_ref()
+            if request.to_prefetch() {
+                working_tree.write_sync_point_ref_to(commit.id())?;
+            } else {
+                working_tree.write_sync_point_ref()?;
+            }
+            record_sync_point(repo, &fingerprint, request.targets().is_none())
         })?;
 
         // The profile was successfully applied, so do not restore the backup.
@@ -403,27 +991,94 @@ pub fn run(request: &SyncRequest, app: Arc<App>) -> Result<SyncResult> {
     }
 
     Ok(SyncResult {
-        checked_out,
+        checked_out: stats.checked_out,
         commit_id: Some(commit.id()),
-        status: SyncStatus::Success,
+        status: if request.write_only() {
+            SyncStatus::WrittenWithoutCheckout
+        } else {
+            SyncStatus::Success
+        },
         mechanism,
+        stats: Some(stats),
     })
 }
 
-/// Wait for the machine to be idle for a given time period, waiting up to some maximum, and polling at a given interval.
-fn wait_for_machine_to_be_idle(
+/// Warn about selected projects that are no longer defined, or fail if `strict` is set.
+fn check_for_missing_projects(missing_projects: &BTreeSet<String>, strict: bool) -> Result<()> {
+    if missing_projects.is_empty() {
+        return Ok(());
+    }
+    let names: Vec<&str> = missing_projects.iter().map(String::as_str).collect();
+    let remedy = format!("focus remove {}", names.join(" "));
+    if strict {
+        bail!(
+            "The selection includes projects that are no longer defined: {}. Remove them with `{}`",
+            names.join(", "),
+            remedy
+        );
+    }
+    warn!(
+        projects = ?names,
+        "The selection includes projects that are no longer defined, which will be ignored. Remove them with `{}`",
+        remedy
+    );
+    Ok(())
+}
+
+fn remove_completion_marker(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => {
+            Err(e).with_context(|| format!("Removing the completion marker {}", path.display()))
+        }
+    }
+}
+
+/// Atomically write the completion marker, recording the synced commit and when the sync finished.
+fn write_completion_marker(path: &Path, commit_id: git2::Oid) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Creating directory {}", parent.display()))?;
+    }
+    let temporary_path = path.with_extension("tmp");
+    let content = format!(
+        "commit={}\ncompleted_at={}\n",
+        commit_id,
+        chrono::Utc::now().to_rfc3339()
+    );
+    std::fs::write(&temporary_path, content)
+        .with_context(|| format!("Writing {}", temporary_path.display()))?;
+    std::fs::rename(&temporary_path, path)
+        .with_context(|| format!("Writing the completion marker {}", path.display()))
+}
+
+/// Check that waiting for `idle_duration` of idleness, polling every `poll_interval`, can finish within `max_wait`.
+fn validate_idle_wait(
     idle_duration: Duration,
     max_wait: Duration,
     poll_interval: Duration,
-) -> Result<bool> {
-    use focus_platform::session_state;
-
+) -> Result<()> {
     if max_wait < idle_duration {
         bail!("max_wait must be greater than idle_duration")
     } else if poll_interval > max_wait {
         bail!("poll_interval must be less than max_wait")
     }
+    Ok(())
+}
 
+/// Wait for the machine to be idle for a given time period, waiting up to some maximum, and polling at a given interval.
+/// Fails promptly if SIGINT is received while waiting.
+fn wait_for_machine_to_be_idle(
+    idle_duration: Duration,
+    max_wait: Duration,
+    poll_interval: Duration,
+) -> Result<bool> {
+    use focus_platform::session_state;
+
+    validate_idle_wait(idle_duration, max_wait, poll_interval)?;
+
+    let interrupt_guard = InterruptGuard::new()?;
     let started_at = SystemTime::now();
     loop {
         let elapsed = started_at
@@ -450,7 +1105,9 @@ fn wait_for_machine_to_be_idle(
 
         match state {
             session_state::SessionStatus::Active => {
-                std::thread::sleep(poll_interval);
+                if interrupt_guard.sleep(poll_interval) {
+                    bail!("Interrupted while waiting for the machine to become idle");
+                }
             }
             _ => {
                 // Note: If we can't determine whether the session is idle, just go ahead.
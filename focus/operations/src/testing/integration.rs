@@ -115,6 +115,9 @@ impl RepoPairFixture {
             days_of_history: 90,
             do_post_clone_fetch: false,
             sync_mode: self.sync_mode.get(),
+            no_checkout: false,
+            write_lock: None,
+            from_lock: None,
         };
 
         crate::clone::run(
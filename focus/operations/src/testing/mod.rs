@@ -10,6 +10,9 @@ pub mod refs;
 #[cfg(test)]
 pub mod repo;
 
+#[cfg(test)]
+pub mod status;
+
 #[cfg(test)]
 pub mod sync;
 
@@ -1,13 +1,152 @@
 // Copyright 2022 Twitter, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use focus_internals::tracker::Tracker;
+use focus_internals::{
+    model::repo::{Repo, WorkingTree},
+    tracker::Tracker,
+};
 
 use anyhow::Result;
 
 use focus_testing::init_logging;
+use focus_util::app::ExitCode;
 
-use crate::testing::integration::RepoPairFixture;
+use crate::{
+    ensure_clean::{DirtyPath, DIRTY_EXIT_CODE},
+    testing::integration::RepoPairFixture,
+};
+
+#[test]
+fn disabled_tracker_does_not_record_clones() -> Result<()> {
+    init_logging();
+
+    let mut fixture = RepoPairFixture::new()?;
+    fixture.tracker = Tracker::disabled();
+    fixture.tracker.ensure_directories_exist()?;
+    fixture.perform_clone()?;
+
+    assert!(fixture.tracker.scan()?.repos().is_empty());
+    assert!(fixture
+        .sparse_repo()?
+        .working_tree()?
+        .read_uuid()?
+        .is_none());
+
+    Ok(())
+}
+
+#[test]
+fn ensure_clean_reports_dirty_paths() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+
+    let exit_code =
+        crate::ensure_clean::check(&fixture.sparse_repo_path, false, fixture.app.clone())?;
+    assert_eq!(exit_code, ExitCode(0));
+
+    std::fs::write(
+        fixture.sparse_repo_path.join("untracked file.txt"),
+        "Howdy!\n",
+    )?;
+    let mandatory_projects = fixture
+        .sparse_repo_path
+        .join("focus/mandatory.projects.json");
+    let mut content = std::fs::read_to_string(&mandatory_projects)?;
+    content.push('\n');
+    std::fs::write(&mandatory_projects, content)?;
+
+    let dirty_paths =
+        crate::ensure_clean::dirty_paths(&fixture.sparse_repo_path, fixture.app.clone())?;
+    assert_eq!(
+        dirty_paths,
+        vec![
+            DirtyPath {
+                status: String::from(" M"),
+                path: String::from("focus/mandatory.projects.json"),
+            },
+            DirtyPath {
+                status: String::from("??"),
+                path: String::from("untracked file.txt"),
+            },
+        ]
+    );
+    let exit_code =
+        crate::ensure_clean::check(&fixture.sparse_repo_path, true, fixture.app.clone())?;
+    assert_eq!(exit_code, ExitCode(DIRTY_EXIT_CODE));
+    assert!(crate::ensure_clean::run(&fixture.sparse_repo_path, fixture.app.clone()).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn overview_summarizes_tracked_repos() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    crate::selection::add(
+        &fixture.sparse_repo_path,
+        true,
+        vec![String::from("team_banzai/project_a")],
+        false,
+        fixture.app.clone(),
+    )?;
+
+    let summary = crate::repo::summarize(&fixture.sparse_repo_path, fixture.app.clone())?;
+    assert_eq!(summary.projects, 1);
+    assert!(summary.last_synced.is_some());
+    assert_eq!(summary.needs_sync, Some(false));
+    assert!(summary.clean);
+    assert!(summary.patterns > 0);
+
+    std::fs::write(fixture.sparse_repo_path.join("untracked.txt"), "Howdy!\n")?;
+    let summary = crate::repo::summarize(&fixture.sparse_repo_path, fixture.app.clone())?;
+    assert!(!summary.clean);
+
+    Ok(())
+}
+
+#[test]
+fn dump_describes_repo_model() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    crate::selection::add(
+        &fixture.sparse_repo_path,
+        true,
+        vec![String::from("team_banzai/project_a")],
+        false,
+        fixture.app.clone(),
+    )?;
+    let selection_path = fixture.sparse_repo()?.selection_path()?;
+    let selection_before = std::fs::read_to_string(&selection_path)?;
+
+    let model = crate::dump::repo_model(&fixture.sparse_repo_path, fixture.app.clone())?;
+    let sync_point = fixture
+        .sparse_repo()?
+        .working_tree()?
+        .read_sparse_sync_point_ref()?
+        .unwrap();
+    assert_eq!(model["primary_branch"], "main");
+    assert_eq!(model["selection"]["projects"][0], "team_banzai/project_a");
+    assert_eq!(model["sync_point"]["sparse"], sync_point.to_string());
+    assert_eq!(model["sync_point"]["needs_sync"], false);
+    assert_eq!(model["working_tree"]["kind"], "sparse");
+    assert!(
+        model["project_catalog"]["optional_projects"]
+            .as_u64()
+            .unwrap()
+            > 0
+    );
+    assert!(!model["config"]["settings"].as_array().unwrap().is_empty());
+
+    assert_eq!(std::fs::read_to_string(&selection_path)?, selection_before);
+
+    Ok(())
+}
 
 #[test]
 fn repo_register_after_move() -> Result<()> {
@@ -53,3 +192,242 @@ fn repo_register_after_move() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn repo_find_matching_filters_by_pattern() -> Result<()> {
+    init_logging();
+
+    let tracker = Tracker::for_testing()?;
+    tracker.ensure_directories_exist()?;
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    tracker.ensure_registered(&fixture.sparse_repo_path, fixture.app.clone())?;
+
+    let name = fixture
+        .sparse_repo_path
+        .file_name()
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_owned();
+
+    assert_eq!(crate::repo::find_matching(&tracker, None)?.len(), 1);
+    assert_eq!(crate::repo::find_matching(&tracker, Some(&name))?.len(), 1);
+    assert!(crate::repo::find_matching(&tracker, Some("nothing/*"))?.is_empty());
+    crate::repo::list(&tracker, Some("nothing/*"), true, false, false)?;
+    assert!(crate::repo::find_matching(&tracker, Some("[unterminated")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn repo_is_focused() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+
+    assert!(Repo::is_focused(&fixture.sparse_repo_path));
+    assert!(Repo::is_focused(&fixture.sparse_repo_path.join("focus")));
+    assert!(!Repo::is_focused(&fixture.dense_repo_path));
+    assert!(!Repo::is_focused(fixture.dir.path()));
+
+    let working_tree = fixture.sparse_repo()?.working_tree()?;
+    std::fs::remove_file(working_tree.sparse_checkout_path())?;
+    assert!(Repo::check_focused(&fixture.sparse_repo_path).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn sparse_checkout_path_is_where_git_keeps_it() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+
+    let repo = fixture.sparse_repo()?;
+    let working_tree = repo.working_tree()?;
+    assert_eq!(
+        working_tree.sparse_checkout_path().canonicalize()?,
+        repo.git_dir()
+            .join("info")
+            .join("sparse-checkout")
+            .canonicalize()?
+    );
+
+    // Linked worktrees keep their own sparse checkout file in their own git dir.
+    let linked_path = fixture.dir.path().join("linked");
+    let status = fixture
+        .app
+        .git_binary()
+        .command()
+        .args(["worktree", "add", "--detach"])
+        .arg(&linked_path)
+        .current_dir(&fixture.sparse_repo_path)
+        .status()?;
+    assert!(status.success());
+    let linked = WorkingTree::new(git2::Repository::open(&linked_path)?)?;
+    assert!(linked
+        .sparse_checkout_path()
+        .ends_with("worktrees/linked/info/sparse-checkout"));
+
+    Ok(())
+}
+
+#[test]
+fn concurrent_tracker_directory_creation() -> Result<()> {
+    init_logging();
+
+    let tracker = std::sync::Arc::new(Tracker::for_testing()?);
+    let threads: Vec<_> = (0..16)
+        .map(|_| {
+            let tracker = tracker.clone();
+            std::thread::spawn(move || tracker.ensure_directories_exist())
+        })
+        .collect();
+    for thread in threads {
+        thread.join().expect("thread panicked")?;
+    }
+    assert!(tracker.scan()?.repos().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn effective_settings_report_their_source() -> Result<()> {
+    use focus_internals::model::repo::{SettingSource, BAZEL_QUERY_RETRIES_CONFIG_KEY};
+
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+
+    let find = |name: &str| -> Result<(String, SettingSource)> {
+        let settings = crate::effective_config::effective_settings(
+            &fixture.sparse_repo_path,
+            fixture.app.clone(),
+        )?;
+        let setting = settings
+            .into_iter()
+            .find(|setting| setting.name == name)
+            .expect("setting missing");
+        Ok((setting.value, setting.source))
+    };
+
+    assert_eq!(
+        find(BAZEL_QUERY_RETRIES_CONFIG_KEY)?,
+        (String::from("2"), SettingSource::Default)
+    );
+
+    fixture.sparse_repo()?.set_bazel_query_retries(5)?;
+    assert_eq!(
+        find(BAZEL_QUERY_RETRIES_CONFIG_KEY)?,
+        (String::from("5"), SettingSource::GitConfig)
+    );
+
+    let mut repo = fixture.sparse_repo()?;
+    repo.override_bazel_query_retries(0);
+    let overridden = repo
+        .effective_settings()?
+        .into_iter()
+        .find(|setting| setting.name == BAZEL_QUERY_RETRIES_CONFIG_KEY)
+        .expect("setting missing");
+    assert_eq!(overridden.value, "0");
+    assert_eq!(overridden.source, SettingSource::Flag);
+
+    Ok(())
+}
+
+#[test]
+fn effective_settings_cover_every_setting() -> Result<()> {
+    use focus_internals::model::repo::{
+        SettingSource, RESOLUTION_CACHE_TTL_CONFIG_KEY, SETTING_CONFIG_KEYS,
+    };
+
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    fixture
+        .sparse_repo()?
+        .underlying()
+        .config()?
+        .set_str(RESOLUTION_CACHE_TTL_CONFIG_KEY, "12h")?;
+
+    let settings = fixture.sparse_repo()?.effective_settings()?;
+    let names: Vec<&str> = settings
+        .iter()
+        .map(|setting| setting.name.as_str())
+        .collect();
+    let mut expected = SETTING_CONFIG_KEYS.to_vec();
+    expected.sort_unstable();
+    assert_eq!(names, expected);
+
+    let ttl = settings
+        .iter()
+        .find(|setting| setting.name == RESOLUTION_CACHE_TTL_CONFIG_KEY)
+        .expect("setting missing");
+    assert_eq!(ttl.value, "12h");
+    assert_eq!(ttl.source, SettingSource::GitConfig);
+
+    Ok(())
+}
+
+#[test]
+fn clone_records_what_the_repo_was_cloned_with() -> Result<()> {
+    use focus_internals::tracker::{CloneSpec, TrackedRepo};
+
+    init_logging();
+
+    let mut fixture = RepoPairFixture::new()?;
+    fixture.projects_and_targets = vec![String::from("team_banzai/project_a")];
+    fixture.perform_clone()?;
+
+    let identifier =
+        TrackedRepo::get_or_generate_uuid(&fixture.sparse_repo_path, fixture.app.clone())?;
+    let spec = fixture.tracker.clone_spec(&identifier)?;
+    assert_eq!(
+        spec,
+        Some(CloneSpec {
+            projects_and_targets: vec![String::from("team_banzai/project_a")],
+        })
+    );
+    assert_eq!(spec.unwrap().to_string(), "team_banzai/project_a");
+
+    // Repos registered without being cloned, e.g. before specs were recorded, have none.
+    let tracker = Tracker::for_testing()?;
+    tracker.ensure_directories_exist()?;
+    tracker.ensure_registered(&fixture.sparse_repo_path, fixture.app.clone())?;
+    assert_eq!(tracker.clone_spec(&identifier)?, None);
+
+    Ok(())
+}
+
+#[test]
+fn repos_refuse_binaries_older_than_their_minimum_version() -> Result<()> {
+    use focus_internals::model::repo::MIN_VERSION_CONFIG_KEY;
+
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let mut config = git2::Repository::open(&fixture.sparse_repo_path)?.config()?;
+
+    config.set_str(MIN_VERSION_CONFIG_KEY, "0.1")?;
+    Repo::open(&fixture.sparse_repo_path, fixture.app.clone())?;
+    config.set_str(MIN_VERSION_CONFIG_KEY, env!("CARGO_PKG_VERSION"))?;
+    Repo::open(&fixture.sparse_repo_path, fixture.app.clone())?;
+
+    config.set_str(MIN_VERSION_CONFIG_KEY, "999.0.0-beta")?;
+    let error = Repo::open(&fixture.sparse_repo_path, fixture.app.clone())
+        .err()
+        .expect("an old binary opened the repo");
+    assert!(format!("{:#}", error).contains("please upgrade focus"));
+
+    config.set_str(MIN_VERSION_CONFIG_KEY, "latest")?;
+    assert!(Repo::open(&fixture.sparse_repo_path, fixture.app.clone()).is_err());
+
+    Ok(())
+}
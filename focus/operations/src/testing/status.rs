@@ -0,0 +1,404 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::path::Path;
+
+use anyhow::Result;
+
+use focus_internals::model::repo::Repo;
+use focus_testing::init_logging;
+use focus_util::app::ExitCode;
+
+use crate::testing::integration::{RepoDisposition, RepoPairFixture};
+
+const PREFETCH_REF_NAME: &str = "refs/prefetch/remotes/origin/main";
+
+#[test]
+fn check_prefetch_detects_missing_and_orphaned_refs() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let path = fixture.sparse_repo_path.clone();
+
+    // Nothing has been prefetched.
+    assert_eq!(
+        crate::status::check_prefetch(&path, fixture.app.clone())?,
+        ExitCode(1)
+    );
+
+    // A prefetch ref at HEAD is healthy.
+    let repo = fixture.sparse_repo()?;
+    let head_id = repo.get_head_commit()?.id();
+    repo.underlying()
+        .reference(PREFETCH_REF_NAME, head_id, true, "Emulated prefetch ref")?;
+    assert_eq!(
+        crate::status::check_prefetch(&path, fixture.app.clone())?,
+        ExitCode(0)
+    );
+
+    // Freshness is judged by when the ref was fetched, not by when its commit was made.
+    let head_commit = repo.get_head_commit()?;
+    let signature =
+        git2::Signature::new("Test", "test@example.com", &git2::Time::new(946684800, 0))?;
+    let old_commit_id = repo.underlying().commit(
+        None,
+        &signature,
+        &signature,
+        "An old commit",
+        &head_commit.tree()?,
+        &[&head_commit],
+    )?;
+    repo.underlying().reference(
+        PREFETCH_REF_NAME,
+        old_commit_id,
+        true,
+        "Emulated prefetch ref",
+    )?;
+    assert_eq!(
+        crate::status::check_prefetch(&path, fixture.app.clone())?,
+        ExitCode(0)
+    );
+
+    // A prefetch ref behind HEAD has stopped moving.
+    repo.underlying().commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        "A local commit",
+        &head_commit.tree()?,
+        &[&head_commit],
+    )?;
+    repo.underlying()
+        .reference(PREFETCH_REF_NAME, head_id, true, "Emulated prefetch ref")?;
+    assert_eq!(
+        crate::status::check_prefetch(&path, fixture.app.clone())?,
+        ExitCode(1)
+    );
+    repo.underlying()
+        .reset(head_commit.as_object(), git2::ResetType::Soft, None)?;
+
+    // Fetching explicitly without the prefetch ref moving leaves it orphaned.
+    fixture.dense_repo.write_and_commit_file(
+        Path::new("x/catz.txt"),
+        b"The Naming of Cats is a difficult matter",
+        "Add catz",
+    )?;
+    fixture.perform_fetch(RepoDisposition::Sparse, "origin")?;
+    assert_eq!(
+        crate::status::check_prefetch(&path, fixture.app.clone())?,
+        ExitCode(1)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn counting_materialized_files() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+
+    let working_tree = fixture.sparse_repo()?.working_tree()?;
+    let (count, size) = working_tree.count_materialized_files()?;
+    assert!(count > 0);
+    assert!(size > 0);
+    assert!(working_tree.sparse_pattern_count()? > 0);
+
+    std::fs::write(fixture.sparse_repo_path.join("extra.txt"), b"12345")?;
+    assert_eq!(
+        working_tree.count_materialized_files()?,
+        (count + 1, size + 5)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn layer_sizes_count_exclusive_directories() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let path = fixture.sparse_repo_path.clone();
+    let targets = vec![
+        String::from("directory:library_1"),
+        String::from("directory:library_2"),
+        String::from("directory:library_a"),
+        // Nested in `library_a`, so removing it would not shrink the checkout.
+        String::from("directory:library_a/src"),
+    ];
+    crate::selection::add(&path, true, targets, false, fixture.app.clone())?;
+
+    let directory_size = |name: &str| -> Result<u64> {
+        let mut size = 0;
+        for entry in walkdir::WalkDir::new(path.join(name)) {
+            let entry = entry?;
+            if entry.file_type().is_file() {
+                size += entry.metadata()?.len();
+            }
+        }
+        Ok(size)
+    };
+    std::fs::write(path.join("library_2").join("padding.txt"), vec![b'x'; 4096])?;
+
+    let repo = fixture.sparse_repo()?;
+    let sizes = crate::layer_sizes::compute(&repo, fixture.app.clone())?;
+    let size_of = |name: &str| sizes.iter().find(|size| size.name == name).unwrap();
+    assert_eq!(sizes[0].name, "directory:library_2");
+    assert_eq!(sizes[0].bytes, directory_size("library_2")?);
+    assert_eq!(
+        size_of("directory:library_1").bytes,
+        directory_size("library_1")?
+    );
+    assert!(size_of("directory:library_1").exclusive_directories > 0);
+    assert!(size_of("directory:library_2").exclusive_directories > 0);
+    assert_eq!(
+        size_of("directory:library_a").bytes,
+        directory_size("library_a")? - directory_size("library_a/src")?
+    );
+    assert!(size_of("directory:library_a").exclusive_directories > 0);
+    assert_eq!(size_of("directory:library_a/src").bytes, 0);
+    assert_eq!(size_of("directory:library_a/src").exclusive_directories, 0);
+
+    Ok(())
+}
+
+#[test]
+fn drift_reflects_selection_and_head_changes() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    fixture.perform_sync()?;
+    let path = fixture.sparse_repo_path.clone();
+
+    let drift = crate::status::compute_drift(&fixture.sparse_repo()?)?;
+    assert_eq!(drift.head_ahead_behind, Some((0, 0)));
+    assert_eq!(drift.selection_changed, Some(false));
+    assert!(!drift.sync_recommended());
+
+    crate::selection::add(
+        &path,
+        false,
+        vec![String::from("bazel://library_b/...")],
+        false,
+        fixture.app.clone(),
+    )?;
+    let drift = crate::status::compute_drift(&fixture.sparse_repo()?)?;
+    assert_eq!(drift.selection_changed, Some(true));
+    assert!(drift.sync_recommended());
+
+    fixture.dense_repo.write_and_commit_file(
+        Path::new("x/catz.txt"),
+        b"The Naming of Cats is a difficult matter",
+        "Add catz",
+    )?;
+    fixture.perform_pull(RepoDisposition::Sparse, "origin", "main")?;
+    let drift = crate::status::compute_drift(&fixture.sparse_repo()?)?;
+    assert_eq!(drift.head_ahead_behind, Some((1, 0)));
+
+    Ok(())
+}
+
+#[test]
+fn check_sync_needed_uses_head_and_selection_file() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    fixture.perform_sync()?;
+    let path = fixture.sparse_repo_path.clone();
+    assert!(!Repo::check_sync_needed(&path)?);
+
+    crate::selection::add(
+        &path,
+        false,
+        vec![String::from("bazel://library_b/...")],
+        false,
+        fixture.app.clone(),
+    )?;
+    assert!(Repo::check_sync_needed(&path)?);
+    fixture.perform_sync()?;
+    assert!(!Repo::check_sync_needed(&path)?);
+
+    // Rewriting the selection file without changing it does not call for a sync.
+    let selection_path = fixture.sparse_repo()?.selection_path()?;
+    std::fs::write(&selection_path, std::fs::read(&selection_path)?)?;
+    assert!(!Repo::check_sync_needed(&path)?);
+
+    fixture.dense_repo.write_and_commit_file(
+        Path::new("x/catz.txt"),
+        b"The Naming of Cats is a difficult matter",
+        "Add catz",
+    )?;
+    fixture.perform_pull(RepoDisposition::Sparse, "origin", "main")?;
+    assert!(Repo::check_sync_needed(&path)?);
+
+    // The dense repo is not focused.
+    assert!(Repo::check_sync_needed(fixture.dense_repo.path()).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn prompt_status_summarizes_cheaply() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    fixture.perform_sync()?;
+    let path = fixture.sparse_repo_path.clone();
+    assert_eq!(crate::status::prompt_status(&path)?.to_string(), "focus:0L");
+
+    crate::selection::add(
+        &path,
+        false,
+        vec![String::from("team_banzai/project_a")],
+        false,
+        fixture.app.clone(),
+    )?;
+    assert_eq!(
+        crate::status::prompt_status(&path)?.to_string(),
+        "focus:1L!"
+    );
+    fixture.perform_sync()?;
+
+    fixture.dense_repo.write_and_commit_file(
+        Path::new("x/catz.txt"),
+        b"The Naming of Cats is a difficult matter",
+        "Add catz",
+    )?;
+    crate::prefetch::run(&path, "origin", None, fixture.app.clone())?;
+    assert_eq!(
+        crate::status::prompt_status(&path)?.to_string(),
+        "focus:1L±"
+    );
+
+    // Drift recorded for an earlier HEAD is not shown.
+    fixture.perform_pull(RepoDisposition::Sparse, "origin", "main")?;
+    assert_eq!(
+        crate::status::prompt_status(&path)?.to_string(),
+        "focus:1L!"
+    );
+
+    // The dense repo is not focused.
+    assert!(crate::status::prompt_status(fixture.dense_repo.path()).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn prefetch_updates_the_prefetch_ref() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let commit_id = fixture.dense_repo.write_and_commit_file(
+        Path::new("x/catz.txt"),
+        b"The Naming of Cats is a difficult matter",
+        "Add catz",
+    )?;
+
+    let fetched = crate::prefetch::run(
+        &fixture.sparse_repo_path,
+        "origin",
+        None,
+        fixture.app.clone(),
+    )?;
+    assert_eq!(fetched, commit_id);
+    let repo = fixture.sparse_repo()?;
+    let prefetch_commit = repo
+        .underlying()
+        .find_reference(PREFETCH_REF_NAME)?
+        .peel_to_commit()?;
+    assert_eq!(prefetch_commit.id(), commit_id);
+    assert_eq!(
+        crate::status::check_prefetch(&fixture.sparse_repo_path, fixture.app.clone())?,
+        ExitCode(0)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn upstream_drift_counts_commits_behind_the_prefetched_commit() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    fixture.perform_sync()?;
+    assert_eq!(
+        crate::status::upstream_drift(&fixture.sparse_repo()?)?,
+        None
+    );
+
+    for (name, content) in [("x/catz.txt", "Catz"), ("x/dogz.txt", "Dogz")] {
+        fixture
+            .dense_repo
+            .write_and_commit_file(Path::new(name), content.as_bytes(), name)?;
+    }
+    crate::prefetch::run(
+        &fixture.sparse_repo_path,
+        "origin",
+        None,
+        fixture.app.clone(),
+    )?;
+    assert_eq!(
+        crate::status::upstream_drift(&fixture.sparse_repo()?)?,
+        Some(2)
+    );
+
+    fixture.perform_pull(RepoDisposition::Sparse, "origin", "main")?;
+    assert_eq!(
+        crate::status::upstream_drift(&fixture.sparse_repo()?)?,
+        Some(0)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn status_does_not_record_upstream_drift() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    fixture.perform_sync()?;
+    fixture.dense_repo.write_and_commit_file(
+        Path::new("x/catz.txt"),
+        b"The Naming of Cats is a difficult matter",
+        "Add catz",
+    )?;
+    crate::prefetch::run(
+        &fixture.sparse_repo_path,
+        "origin",
+        None,
+        fixture.app.clone(),
+    )?;
+    let drift_path = crate::status::upstream_drift_path(fixture.sparse_repo()?.git_dir());
+    std::fs::remove_file(&drift_path)?;
+
+    let run_status = |upstream: bool| {
+        crate::status::run(
+            &fixture.sparse_repo_path,
+            fixture.app.clone(),
+            false,
+            vec![],
+            upstream,
+            false,
+            false,
+            crate::status::TimeFormat::Relative,
+            None,
+            false,
+        )
+    };
+    // Status is read-only whether or not it compares with upstream; only prefetching records the drift.
+    assert_eq!(run_status(true)?, ExitCode(0));
+    assert!(!drift_path.exists());
+    assert_eq!(run_status(false)?, ExitCode(0));
+    assert!(!drift_path.exists());
+
+    Ok(())
+}
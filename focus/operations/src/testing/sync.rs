@@ -1,7 +1,10 @@
 // Copyright 2022 Twitter, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use focus_internals::{model::repo::Repo, target::Target};
+use focus_internals::{
+    model::{repo::Repo, selection::Underlying},
+    target::Target,
+};
 use focus_testing::ScratchGitRepo;
 use insta::assert_snapshot;
 use std::{
@@ -100,6 +103,8 @@ fn sync_upstream_changes_internal(sync_mode: SyncMode) -> Result<SyncMechanism>
         crate::detect_build_graph_changes::run(
             &fixture.sparse_repo_path,
             false,
+            false,
+            false,
             vec![],
             fixture.app.clone(),
         )?,
@@ -147,12 +152,27 @@ fn sync_detect_graph_changes_advisory() -> Result<()> {
         crate::detect_build_graph_changes::run(
             &fixture.sparse_repo_path,
             true,
+            false,
+            false,
             vec![],
             fixture.app.clone(),
         )?,
         app::ExitCode(0)
     );
 
+    // The JSON report carries the same exit code signal
+    assert_eq!(
+        crate::detect_build_graph_changes::run(
+            &fixture.sparse_repo_path,
+            false,
+            true,
+            true,
+            vec![],
+            fixture.app.clone(),
+        )?,
+        app::ExitCode(1)
+    );
+
     Ok(())
 }
 
@@ -372,6 +392,94 @@ fn sync_adhoc_manipulation_internal(sync_mode: SyncMode) -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn sync_with_external_resolver() -> Result<()> {
+    use std::os::unix::prelude::PermissionsExt;
+
+    init_logging();
+
+    let fixture = RepoPairFixture::with_sync_mode(SyncMode::OneShot)?;
+    fixture.perform_clone()?;
+
+    // The resolver records its request and always asks for library_a.
+    let resolver_path = fixture.dir.path().join("resolver");
+    let request_path = fixture.dir.path().join("resolver-request.json");
+    std::fs::write(
+        &resolver_path,
+        format!(
+            "#!/bin/sh\ncat > '{}'\necho '{{\"paths\": [\"library_a\"]}}'\n",
+            request_path.display()
+        ),
+    )?;
+    std::fs::set_permissions(&resolver_path, std::fs::Permissions::from_mode(0o755))?;
+    fixture
+        .sparse_repo()?
+        .set_resolver_command(&resolver_path)?;
+
+    crate::selection::add(
+        &fixture.sparse_repo_path,
+        true,
+        vec![String::from("bazel://library_b/...")],
+        false,
+        fixture.app.clone(),
+    )?;
+
+    assert!(fixture.sparse_repo_path.join("library_a").is_dir());
+    assert!(!fixture.sparse_repo_path.join("library_b").is_dir());
+    let request: serde_json::Value = serde_json::from_slice(&std::fs::read(&request_path)?)?;
+    assert_eq!(
+        request["coordinates"],
+        serde_json::json!(["bazel://library_b/..."])
+    );
+    let head_commit_id = fixture.sparse_repo()?.get_head_commit()?.id().to_string();
+    assert_eq!(request["commit"].as_str(), Some(head_commit_id.as_str()));
+
+    Ok(())
+}
+
+#[test]
+fn sync_with_individual_files() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+
+    let path = fixture.sparse_repo_path.clone();
+    crate::selection::add_files(
+        &path,
+        true,
+        vec![std::path::PathBuf::from("library_a/BUILD")],
+        fixture.app.clone(),
+    )?;
+    assert!(path.join("library_a/BUILD").is_file());
+    assert!(!path.join("library_a/src").is_dir());
+
+    // The file is included by itself rather than by way of its directory.
+    let working_tree = fixture.sparse_repo()?.working_tree()?;
+    let profile = std::fs::read_to_string(working_tree.sparse_checkout_path())?;
+    assert!(profile.lines().any(|line| line == "/library_a/BUILD"));
+    assert!(!profile.lines().any(|line| line == "/library_a/"));
+
+    let selection = fixture.sparse_repo()?.selection_manager()?.selection()?;
+    assert_eq!(
+        selection.targets,
+        hashset! {Target::File(String::from("library_a/BUILD"))}
+    );
+    assert!(selection
+        .to_string()
+        .contains("--- Files ---\nlibrary_a/BUILD\n"));
+
+    assert!(crate::selection::add_files(
+        &path,
+        true,
+        vec![std::path::PathBuf::from("library_a/src")],
+        fixture.app.clone(),
+    )
+    .is_err());
+
+    Ok(())
+}
+
 #[test]
 fn failed_selection_mutations_are_reverted() -> Result<()> {
     init_logging();
@@ -396,6 +504,46 @@ fn failed_selection_mutations_are_reverted() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn dry_run_mutations_leave_the_selection_unchanged() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+
+    let path = fixture.sparse_repo_path.clone();
+    let targets = vec![String::from("bazel://library_b/...")];
+    let result = crate::selection::add_dry_run(&path, targets.clone(), false, fixture.app.clone())?;
+    assert_eq!(
+        result.added,
+        hashset! {Underlying::Target(Target::try_from("bazel://library_b/...")?)}
+    );
+    assert!(result.removed.is_empty());
+    assert!(fixture
+        .sparse_repo()?
+        .selection_manager()?
+        .selection()?
+        .targets
+        .is_empty());
+    assert!(!path.join("library_b").is_dir());
+
+    crate::selection::add(&path, true, targets.clone(), false, fixture.app.clone())?;
+    let result = crate::selection::remove_dry_run(&path, targets, false, fixture.app.clone())?;
+    assert_eq!(result.removed.len(), 1);
+    assert!(path.join("library_b").is_dir());
+    assert_eq!(
+        fixture
+            .sparse_repo()?
+            .selection_manager()?
+            .selection()?
+            .targets
+            .len(),
+        1
+    );
+
+    Ok(())
+}
+
 #[test]
 fn clone_contains_top_level_with_incremental_sync() -> Result<()> {
     clone_contains_top_level_internal(SyncMode::Incremental)
@@ -497,6 +645,14 @@ fn sync_skips_checkout_with_unchanged_profile_internal(
     assert_snapshot!(snapshot_label.next(), first_sync_profile_contents);
     assert!(sync_result.checked_out);
 
+    // Forget the recorded sync point so that the subsequent sync computes the profile again instead of being skipped.
+    std::fs::remove_file(
+        fixture
+            .sparse_repo()?
+            .git_focus_dir()
+            .join("sync-point-fingerprint"),
+    )?;
+
     // Subsequent sync does not perform a checkout.
     let sync_result = crate::sync::run(&SyncRequest::new(&path, sync_mode), fixture.app.clone())?;
     let subsequent_sync_profile_contents = std::fs::read_to_string(&profile_path)?;
@@ -509,6 +665,71 @@ fn sync_skips_checkout_with_unchanged_profile_internal(
     Ok(sync_result.mechanism)
 }
 
+#[test]
+fn sync_is_skipped_when_run_twice_back_to_back() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let path = fixture.sparse_repo_path.clone();
+
+    crate::selection::add(
+        &path,
+        false,
+        vec![String::from("bazel://library_b/...")],
+        false,
+        fixture.app.clone(),
+    )?;
+
+    let first = crate::sync::run(
+        &SyncRequest::new(&path, SyncMode::Incremental),
+        fixture.app.clone(),
+    )?;
+    assert_eq!(first.status, SyncStatus::Success);
+
+    // Remove the index database; resolving targets in the second sync would recreate it.
+    let index_dir = fixture
+        .sparse_repo()?
+        .git_focus_dir()
+        .join("focus-index-rocks-db");
+    if index_dir.exists() {
+        std::fs::remove_dir_all(&index_dir)?;
+    }
+
+    let second = crate::sync::run(
+        &SyncRequest::new(&path, SyncMode::Incremental),
+        fixture.app.clone(),
+    )?;
+    assert_eq!(second.status, SyncStatus::SkippedSyncPointUnchanged);
+    assert_eq!(second.mechanism, SyncMechanism::Skipped);
+    assert!(!second.checked_out);
+    assert_eq!(second.commit_id, first.commit_id);
+    assert!(!index_dir.exists());
+
+    // Strict syncs check the resolution again instead of being skipped.
+    let strict = crate::sync::run(
+        &SyncRequest::new(&path, SyncMode::Incremental).with_strict_cache(),
+        fixture.app.clone(),
+    )?;
+    assert_eq!(strict.status, SyncStatus::Success);
+
+    // Changing the selection makes the next sync do work again.
+    crate::selection::add(
+        &path,
+        false,
+        vec![String::from("bazel://library_a/...")],
+        false,
+        fixture.app.clone(),
+    )?;
+    let third = crate::sync::run(
+        &SyncRequest::new(&path, SyncMode::Incremental),
+        fixture.app.clone(),
+    )?;
+    assert_eq!(third.status, SyncStatus::Success);
+
+    Ok(())
+}
+
 #[cfg(feature = "twttr")]
 #[test]
 fn sync_sets_ti_client_correctly() -> Result<()> {
@@ -725,117 +946,1480 @@ impl PreemptiveSyncFixture {
 }
 
 #[test]
-#[ignore] // these must be run single-threaded
-fn preemptive_sync_single_threaded_test() -> Result<()> {
+fn sync_writes_completion_marker() -> Result<()> {
     init_logging();
 
-    let fixture = PreemptiveSyncFixture::new()?;
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let marker_path = Repo::focus_git_dir_path(fixture.sparse_repo()?.git_dir())
+        .join(crate::sync::COMPLETION_MARKER_FILE_NAME);
+    let head_commit_id = fixture.sparse_repo()?.get_head_commit()?.id();
 
-    // Sync preemptively
-    fixture.repo.set_preemptive_sync_enabled(true)?;
-    fixture
-        .repo
-        .set_preemptive_sync_idle_threshold(Duration::from_millis(150))?;
-    crate::sync::test_only_set_preemptive_sync_machine_is_active(false);
-    let result = crate::sync::run(
-        &SyncRequest::new(
-            &fixture.underlying.sparse_repo_path,
-            SyncMode::Preemptive { force: false },
-        ),
-        fixture.underlying.app.clone(),
+    crate::sync::run(
+        &SyncRequest::new(&fixture.sparse_repo_path, SyncMode::Incremental),
+        fixture.app.clone(),
     )?;
-    assert!(!result.checked_out);
-    assert_eq!(result.status, SyncStatus::Success);
+    let marker = std::fs::read_to_string(&marker_path)?;
+    assert!(marker.starts_with(&format!("commit={}\n", head_commit_id)));
+    assert!(marker.contains("completed_at="));
 
-    assert_eq!(result.commit_id.unwrap(), fixture.commit_id);
+    // A failed sync removes the marker.
+    assert!(crate::sync::run(
+        &SyncRequest::new(&fixture.sparse_repo_path, SyncMode::RequireProjectCache),
+        fixture.app.clone(),
+    )
+    .is_err());
+    assert!(!marker_path.exists());
+
+    // The marker can be written elsewhere.
+    let custom_marker_path = fixture.dir.path().join("markers").join("sync_ok");
+    crate::sync::run(
+        &SyncRequest::new(&fixture.sparse_repo_path, SyncMode::Incremental)
+            .with_completion_marker_path(&custom_marker_path),
+        fixture.app.clone(),
+    )?;
+    assert!(custom_marker_path.is_file());
+    assert!(!marker_path.exists());
 
     Ok(())
 }
 
 #[test]
-#[ignore] // these must be run single-threaded
-fn preemptive_sync_skips_if_presync_ref_is_at_commit_single_threaded_test() -> Result<()> {
+fn sync_without_checkout() -> Result<()> {
     init_logging();
 
-    let fixture = PreemptiveSyncFixture::new()?;
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    crate::selection::add(
+        &fixture.sparse_repo_path,
+        false,
+        vec![String::from("directory:library_a")],
+        false,
+        fixture.app.clone(),
+    )?;
 
-    // Sync preemptively
-    fixture
-        .repo
-        .set_preemptive_sync_idle_threshold(Duration::from_millis(150))?;
-    crate::sync::test_only_set_preemptive_sync_machine_is_active(false);
     let result = crate::sync::run(
-        &SyncRequest::new(
-            &fixture.underlying.sparse_repo_path,
-            SyncMode::Preemptive { force: false },
-        ),
-        fixture.underlying.app.clone(),
+        &SyncRequest::new(&fixture.sparse_repo_path, SyncMode::Incremental).with_no_checkout(),
+        fixture.app.clone(),
     )?;
     assert_eq!(result.status, SyncStatus::Success);
-    assert_eq!(result.commit_id.unwrap(), fixture.commit_id);
+    assert!(!result.checked_out);
 
-    // Subsequent preemptive syncs are skipped
+    // The profile names the directory, but it has not been checked out.
+    let working_tree = fixture.sparse_repo()?.working_tree()?;
+    let profile = std::fs::read_to_string(working_tree.sparse_checkout_path())?;
+    assert!(profile.lines().any(|line| line == "/library_a/"));
+    assert!(!fixture.sparse_repo_path.join("library_a").exists());
+    assert_eq!(
+        working_tree.read_sparse_sync_point_ref()?,
+        Some(fixture.sparse_repo()?.get_head_commit()?.id())
+    );
+
+    // The next sync checks out the profile although neither it nor the sync point changed.
     let result = crate::sync::run(
-        &SyncRequest::new(
-            &fixture.underlying.sparse_repo_path,
-            SyncMode::Preemptive { force: false },
-        ),
-        fixture.underlying.app.clone(),
+        &SyncRequest::new(&fixture.sparse_repo_path, SyncMode::Incremental),
+        fixture.app.clone(),
     )?;
-    assert_eq!(result.status, SyncStatus::SkippedSyncPointUnchanged);
-    assert_eq!(result.commit_id.unwrap(), fixture.commit_id);
+    assert_eq!(result.status, SyncStatus::Success);
+    assert!(result.checked_out);
+    assert!(fixture.sparse_repo_path.join("library_a").is_dir());
+    assert!(!working_tree.checkout_pending());
 
     Ok(())
 }
 
 #[test]
-#[ignore] // these must be run single-threaded
-fn preemptive_sync_skips_if_disabled_single_threaded_test() -> Result<()> {
+fn sync_write_only_reports_that_the_working_tree_is_out_of_sync() -> Result<()> {
     init_logging();
 
-    let fixture = PreemptiveSyncFixture::new()?;
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    crate::selection::add(
+        &fixture.sparse_repo_path,
+        false,
+        vec![String::from("directory:library_a")],
+        false,
+        fixture.app.clone(),
+    )?;
 
-    fixture.repo.set_preemptive_sync_enabled(false)?;
-    fixture
-        .repo
-        .set_preemptive_sync_idle_threshold(Duration::from_millis(150))?;
-    crate::sync::test_only_set_preemptive_sync_machine_is_active(false);
     let result = crate::sync::run(
-        &SyncRequest::new(
-            &fixture.underlying.sparse_repo_path,
-            SyncMode::Preemptive { force: false },
-        ),
-        fixture.underlying.app.clone(),
+        &SyncRequest::new(&fixture.sparse_repo_path, SyncMode::Incremental).with_write_only(),
+        fixture.app.clone(),
     )?;
-    assert_eq!(result.status, SyncStatus::SkippedPreemptiveSyncDisabled);
+    assert_eq!(result.status, SyncStatus::WrittenWithoutCheckout);
+    assert!(!result.checked_out);
+    assert!(result.status.explanation().is_some());
+
+    let working_tree = fixture.sparse_repo()?.working_tree()?;
+    let profile = std::fs::read_to_string(working_tree.sparse_checkout_path())?;
+    assert!(profile.lines().any(|line| line == "/library_a/"));
+    assert!(!fixture.sparse_repo_path.join("library_a").exists());
+    assert_eq!(
+        working_tree.read_sparse_sync_point_ref()?,
+        Some(fixture.sparse_repo()?.get_head_commit()?.id())
+    );
 
     Ok(())
 }
 
 #[test]
-#[ignore] // these must be run single-threaded
-fn preemptive_sync_skips_if_machine_is_in_active_use_single_threaded_test() -> Result<()> {
+fn sync_with_missing_projects() -> Result<()> {
     init_logging();
 
-    let fixture = PreemptiveSyncFixture::new()?;
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let missing_project = String::from("team_gone/project_x");
+    std::fs::write(
+        fixture.sparse_repo()?.selection_path()?,
+        format!(r#"{{"projects": ["{}"], "targets": []}}"#, missing_project),
+    )?;
 
-    fixture.repo.set_preemptive_sync_enabled(true)?;
-    fixture
-        .repo
-        .set_preemptive_sync_idle_threshold(Duration::from_millis(150))?;
-    crate::sync::test_only_set_preemptive_sync_machine_is_active(true);
-    let result = crate::sync::run(
-        &SyncRequest::new(
-            &fixture.underlying.sparse_repo_path,
-            SyncMode::Preemptive { force: false },
-        ),
-        fixture.underlying.app.clone(),
+    // Missing projects are ignored, and kept in the selection across changes until removed.
+    crate::selection::add(
+        &fixture.sparse_repo_path,
+        false,
+        vec![String::from("directory:library_a")],
+        false,
+        fixture.app.clone(),
     )?;
     assert_eq!(
-        result.status,
-        SyncStatus::SkippedPreemptiveSyncCancelledByActivity
+        fixture
+            .sparse_repo()?
+            .selection_manager()?
+            .missing_projects()
+            .iter()
+            .collect::<Vec<_>>(),
+        vec![&missing_project]
     );
-    crate::sync::test_only_set_preemptive_sync_machine_is_active(false);
+    let result = crate::sync::run(
+        &SyncRequest::new(&fixture.sparse_repo_path, SyncMode::Incremental),
+        fixture.app.clone(),
+    )?;
+    assert_eq!(result.status, SyncStatus::Success);
+
+    let error = crate::sync::run(
+        &SyncRequest::new(&fixture.sparse_repo_path, SyncMode::Incremental).with_strict(),
+        fixture.app.clone(),
+    )
+    .err()
+    .expect("Strict sync should fail");
+    assert!(error.to_string().contains(&missing_project));
+
+    crate::selection::remove(
+        &fixture.sparse_repo_path,
+        false,
+        vec![missing_project],
+        false,
+        fixture.app.clone(),
+    )?;
+    assert!(fixture
+        .sparse_repo()?
+        .selection_manager()?
+        .missing_projects()
+        .is_empty());
+    crate::sync::run(
+        &SyncRequest::new(&fixture.sparse_repo_path, SyncMode::Incremental).with_strict(),
+        fixture.app.clone(),
+    )?;
+
+    Ok(())
+}
+
+#[test]
+fn sync_to_prefetch_commit() -> Result<()> {
+    init_logging();
+
+    let fixture = PreemptiveSyncFixture::new()?;
+    let sparse_repo_path = &fixture.underlying.sparse_repo_path;
+    let head_commit_id = fixture.repo.get_head_commit()?.id();
+    assert_ne!(head_commit_id, fixture.commit_id);
+
+    let result = crate::sync::run(
+        &SyncRequest::new(sparse_repo_path, SyncMode::Incremental).with_prefetch_commit(),
+        fixture.underlying.app.clone(),
+    )?;
+    assert_eq!(result.status, SyncStatus::Success);
+    assert_eq!(result.commit_id.unwrap(), fixture.commit_id);
+
+    // The sync point is recorded at the prefetch commit while HEAD is unchanged.
+    let working_tree = fixture.repo.working_tree()?;
+    assert_eq!(
+        working_tree.read_sparse_sync_point_ref()?,
+        Some(fixture.commit_id)
+    );
+    assert_eq!(fixture.repo.get_head_commit()?.id(), head_commit_id);
+
+    // Once the prefetched commit is pulled, there is nothing left to sync.
+    fixture
+        .underlying
+        .perform_pull(RepoDisposition::Sparse, "origin", "main")?;
+    let result = crate::sync::run(
+        &SyncRequest::new(sparse_repo_path, SyncMode::Incremental),
+        fixture.underlying.app.clone(),
+    )?;
+    assert_eq!(result.status, SyncStatus::SkippedSyncPointUnchanged);
+
+    Ok(())
+}
+
+#[test]
+#[ignore] // these must be run single-threaded
+fn preemptive_sync_single_threaded_test() -> Result<()> {
+    init_logging();
+
+    let fixture = PreemptiveSyncFixture::new()?;
+
+    // Sync preemptively
+    fixture.repo.set_preemptive_sync_enabled(true)?;
+    fixture
+        .repo
+        .set_preemptive_sync_idle_threshold(Duration::from_millis(150))?;
+    crate::sync::test_only_set_preemptive_sync_machine_is_active(false);
+    let result = crate::sync::run(
+        &SyncRequest::new(
+            &fixture.underlying.sparse_repo_path,
+            SyncMode::Preemptive {
+                force: false,
+                ignore_activity: false,
+            },
+        ),
+        fixture.underlying.app.clone(),
+    )?;
+    assert!(!result.checked_out);
+    assert_eq!(result.status, SyncStatus::Success);
+
+    assert_eq!(result.commit_id.unwrap(), fixture.commit_id);
+
+    Ok(())
+}
+
+#[test]
+#[ignore] // these must be run single-threaded
+fn preemptive_sync_skips_if_presync_ref_is_at_commit_single_threaded_test() -> Result<()> {
+    init_logging();
+
+    let fixture = PreemptiveSyncFixture::new()?;
+
+    // Sync preemptively
+    fixture
+        .repo
+        .set_preemptive_sync_idle_threshold(Duration::from_millis(150))?;
+    crate::sync::test_only_set_preemptive_sync_machine_is_active(false);
+    let result = crate::sync::run(
+        &SyncRequest::new(
+            &fixture.underlying.sparse_repo_path,
+            SyncMode::Preemptive {
+                force: false,
+                ignore_activity: false,
+            },
+        ),
+        fixture.underlying.app.clone(),
+    )?;
+    assert_eq!(result.status, SyncStatus::Success);
+    assert_eq!(result.commit_id.unwrap(), fixture.commit_id);
+
+    // Subsequent preemptive syncs are skipped
+    let result = crate::sync::run(
+        &SyncRequest::new(
+            &fixture.underlying.sparse_repo_path,
+            SyncMode::Preemptive {
+                force: false,
+                ignore_activity: false,
+            },
+        ),
+        fixture.underlying.app.clone(),
+    )?;
+    assert_eq!(result.status, SyncStatus::SkippedSyncPointUnchanged);
+    assert_eq!(result.commit_id.unwrap(), fixture.commit_id);
+
+    Ok(())
+}
+
+#[test]
+#[ignore] // these must be run single-threaded
+fn preemptive_sync_skips_if_disabled_single_threaded_test() -> Result<()> {
+    init_logging();
+
+    let fixture = PreemptiveSyncFixture::new()?;
+
+    fixture.repo.set_preemptive_sync_enabled(false)?;
+    fixture
+        .repo
+        .set_preemptive_sync_idle_threshold(Duration::from_millis(150))?;
+    crate::sync::test_only_set_preemptive_sync_machine_is_active(false);
+    let result = crate::sync::run(
+        &SyncRequest::new(
+            &fixture.underlying.sparse_repo_path,
+            SyncMode::Preemptive {
+                force: false,
+                ignore_activity: false,
+            },
+        ),
+        fixture.underlying.app.clone(),
+    )?;
+    assert_eq!(result.status, SyncStatus::SkippedPreemptiveSyncDisabled);
+
+    Ok(())
+}
+
+#[test]
+#[ignore] // these must be run single-threaded
+fn preemptive_sync_skips_if_machine_is_in_active_use_single_threaded_test() -> Result<()> {
+    init_logging();
+
+    let fixture = PreemptiveSyncFixture::new()?;
+
+    fixture.repo.set_preemptive_sync_enabled(true)?;
+    fixture
+        .repo
+        .set_preemptive_sync_idle_threshold(Duration::from_millis(150))?;
+    crate::sync::test_only_set_preemptive_sync_machine_is_active(true);
+    let result = crate::sync::run(
+        &SyncRequest::new(
+            &fixture.underlying.sparse_repo_path,
+            SyncMode::Preemptive {
+                force: false,
+                ignore_activity: false,
+            },
+        ),
+        fixture.underlying.app.clone(),
+    )?;
+    assert_eq!(
+        result.status,
+        SyncStatus::SkippedPreemptiveSyncCancelledByActivity
+    );
+    crate::sync::test_only_set_preemptive_sync_machine_is_active(false);
+
+    Ok(())
+}
+
+#[test]
+#[ignore] // these must be run single-threaded
+fn preemptive_sync_ignoring_activity_still_honors_enablement_single_threaded_test() -> Result<()> {
+    init_logging();
+
+    let fixture = PreemptiveSyncFixture::new()?;
+    let mode = SyncMode::Preemptive {
+        force: false,
+        ignore_activity: true,
+    };
+
+    crate::sync::test_only_set_preemptive_sync_machine_is_active(true);
+    fixture.repo.set_preemptive_sync_enabled(false)?;
+    let result = crate::sync::run(
+        &SyncRequest::new(&fixture.underlying.sparse_repo_path, mode),
+        fixture.underlying.app.clone(),
+    )?;
+    assert_eq!(result.status, SyncStatus::SkippedPreemptiveSyncDisabled);
+
+    fixture.repo.set_preemptive_sync_enabled(true)?;
+    fixture
+        .repo
+        .set_preemptive_sync_idle_threshold(Duration::from_millis(150))?;
+    let result = crate::sync::run(
+        &SyncRequest::new(&fixture.underlying.sparse_repo_path, mode),
+        fixture.underlying.app.clone(),
+    );
+    crate::sync::test_only_set_preemptive_sync_machine_is_active(false);
+    assert_eq!(result?.status, SyncStatus::Success);
+
+    Ok(())
+}
+
+#[test]
+fn preemptive_sync_explains_why_it_was_skipped() -> Result<()> {
+    init_logging();
+
+    let fixture = PreemptiveSyncFixture::new()?;
+
+    fixture.repo.set_preemptive_sync_enabled(false)?;
+    let result = crate::sync::run(
+        &SyncRequest::new(
+            &fixture.underlying.sparse_repo_path,
+            SyncMode::Preemptive {
+                force: false,
+                ignore_activity: false,
+            },
+        ),
+        fixture.underlying.app.clone(),
+    )?;
+    assert_eq!(result.status, SyncStatus::SkippedPreemptiveSyncDisabled);
+    assert!(result
+        .status
+        .explanation()
+        .unwrap()
+        .contains("focus background enable"));
+    assert_eq!(SyncStatus::Success.explanation(), None);
+
+    Ok(())
+}
+
+#[test]
+fn sync_with_provided_targets() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    fixture.perform_sync()?;
+    let path = fixture.sparse_repo_path.clone();
+    let library_b_dir = path.join("library_b");
+    assert!(!library_b_dir.is_dir());
+    assert!(!Repo::check_sync_needed(&path)?);
+
+    let targets_file = fixture.dir.path().join("targets.txt");
+    std::fs::write(&targets_file, "# Computed by CI\nbazel://library_b/...\n\n")?;
+    let targets = crate::sync::read_targets_file(&targets_file)?;
+    assert_eq!(
+        targets,
+        hashset! {Target::try_from("bazel://library_b/...")?}
+    );
+
+    let result = crate::sync::run(
+        &SyncRequest::new(&path, SyncMode::Incremental).with_targets(targets),
+        fixture.app.clone(),
+    )?;
+    assert_eq!(result.status, SyncStatus::Success);
+    assert!(library_b_dir.is_dir());
+
+    // The selection itself is untouched.
+    let selection = fixture.sparse_repo()?.computed_selection()?;
+    assert!(selection.targets.is_empty());
+
+    // The selection was not what got synced, so it still needs a sync.
+    assert!(Repo::check_sync_needed(&path)?);
+    fixture.perform_sync()?;
+    assert!(!Repo::check_sync_needed(&path)?);
+
+    std::fs::write(&targets_file, "not a target\n")?;
+    assert!(crate::sync::read_targets_file(&targets_file).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn sync_follows_the_active_selection_set() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let path = fixture.sparse_repo_path.clone();
+    let library_b_dir = path.join("library_b");
+    let repo = fixture.sparse_repo()?;
+    assert_eq!(repo.get_active_selection_name()?, "default");
+
+    // A new selection set starts out empty and is modified independently of the default one.
+    crate::selection::use_selection(&path, "release", false, fixture.app.clone())?;
+    crate::selection::add(
+        &path,
+        true,
+        vec![String::from("bazel://library_b/...")],
+        false,
+        fixture.app.clone(),
+    )?;
+    assert!(library_b_dir.is_dir());
+    assert_eq!(repo.read_sync_point_selection_name()?.unwrap(), "release");
+
+    // Switching back to the default set syncs it, which does not include library_b.
+    crate::selection::use_selection(&path, "default", true, fixture.app.clone())?;
+    assert!(!library_b_dir.is_dir());
+    assert!(fixture
+        .sparse_repo()?
+        .computed_selection()?
+        .targets
+        .is_empty());
+    assert_eq!(repo.read_sync_point_selection_name()?.unwrap(), "default");
+
+    // Switching to a set with the same contents skips the sync but still records which set was synced.
+    crate::selection::use_selection(&path, "scratch", true, fixture.app.clone())?;
+    assert_eq!(repo.read_sync_point_selection_name()?.unwrap(), "scratch");
+
+    assert!(
+        crate::selection::use_selection(&path, "../escape", false, fixture.app.clone()).is_err()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn verify_checkout_detects_discrepancies() -> Result<()> {
+    use focus_internals::model::verification::{verify_working_tree, Discrepancy};
+
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let path = fixture.sparse_repo_path.clone();
+    crate::selection::add(
+        &path,
+        true,
+        vec![String::from("bazel://library_b/...")],
+        false,
+        fixture.app.clone(),
+    )?;
+    assert!(crate::sync::verify_checkout(&path, fixture.app.clone())?);
+
+    std::fs::remove_dir_all(path.join("library_b"))?;
+    let repo = fixture.sparse_repo()?;
+    let discrepancies = verify_working_tree(&repo.working_tree()?, 100)?;
+    assert_eq!(
+        discrepancies,
+        vec![Discrepancy::MissingIncludedDirectory("library_b".into())]
+    );
+    assert!(!crate::sync::verify_checkout(&path, fixture.app.clone())?);
+
+    Ok(())
+}
+
+#[test]
+fn preemptive_sync_rejects_idle_threshold_longer_than_max_wait() -> Result<()> {
+    init_logging();
+
+    let fixture = PreemptiveSyncFixture::new()?;
+    let result = crate::sync::run(
+        &SyncRequest::new(
+            &fixture.underlying.sparse_repo_path,
+            SyncMode::Preemptive {
+                force: true,
+                ignore_activity: false,
+            },
+        )
+        .with_idle_threshold(Duration::from_secs(3600)),
+        fixture.underlying.app.clone(),
+    );
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[test]
+#[ignore] // these must be run single-threaded
+fn forced_preemptive_sync_waits_for_overridden_idle_threshold_single_threaded_test() -> Result<()> {
+    init_logging();
+
+    let fixture = PreemptiveSyncFixture::new()?;
+    fixture.repo.set_preemptive_sync_enabled(false)?;
+
+    crate::sync::test_only_set_preemptive_sync_machine_is_active(true);
+    let result = crate::sync::run(
+        &SyncRequest::new(
+            &fixture.underlying.sparse_repo_path,
+            SyncMode::Preemptive {
+                force: true,
+                ignore_activity: false,
+            },
+        )
+        .with_idle_threshold(Duration::from_millis(150)),
+        fixture.underlying.app.clone(),
+    )?;
+    crate::sync::test_only_set_preemptive_sync_machine_is_active(false);
+    assert_eq!(
+        result.status,
+        SyncStatus::SkippedPreemptiveSyncCancelledByActivity
+    );
+
+    Ok(())
+}
+
+#[test]
+fn sync_refuses_to_write_profile_for_changed_selection() -> Result<()> {
+    use focus_internals::model::repo::PatternApplication;
+
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let path = fixture.sparse_repo_path.clone();
+
+    let mut repo = fixture.sparse_repo()?;
+    repo.expect_selection_digest(repo.selection_digest()?);
+    let profile_before = std::fs::read_to_string(repo.working_tree()?.sparse_checkout_path())?;
+
+    // Another process changes the selection while the profile is being computed.
+    crate::selection::add(
+        &path,
+        false,
+        vec![String::from("bazel://library_b/...")],
+        false,
+        fixture.app.clone(),
+    )?;
+
+    let commit_id = repo.get_head_commit()?.id();
+    let error = repo
+        .sync(
+            commit_id,
+            &HashSet::new(),
+            PatternApplication::WriteOnly,
+            fixture.app.clone(),
+            None,
+            None,
+        )
+        .unwrap_err();
+    assert!(format!("{:#}", error).contains("re-run sync"));
+    assert_eq!(
+        std::fs::read_to_string(repo.working_tree()?.sparse_checkout_path())?,
+        profile_before
+    );
+
+    // Computing the profile without writing it is still allowed.
+    repo.sync(
+        commit_id,
+        &HashSet::new(),
+        PatternApplication::Skip,
+        fixture.app.clone(),
+        None,
+        None,
+    )?;
+
+    Ok(())
+}
+
+#[test]
+fn sync_with_read_tree_checkout_backend() -> Result<()> {
+    use focus_internals::model::repo::CheckoutBackend;
+
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let path = fixture.sparse_repo_path.clone();
+
+    let working_tree = fixture.sparse_repo()?.working_tree()?;
+    assert_eq!(
+        working_tree.get_checkout_backend()?,
+        CheckoutBackend::SparseCheckout
+    );
+    working_tree.set_checkout_backend(CheckoutBackend::ReadTree)?;
+    assert_eq!(
+        working_tree.get_checkout_backend()?,
+        CheckoutBackend::ReadTree
+    );
+
+    assert!(!path.join("library_b").is_dir());
+    crate::selection::add(
+        &path,
+        true,
+        vec![String::from("bazel://library_b/...")],
+        false,
+        fixture.app.clone(),
+    )?;
+    assert!(path.join("library_b").is_dir());
+
+    assert!("checkout".parse::<CheckoutBackend>().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn sync_with_pattern_sparse_mode() -> Result<()> {
+    use focus_internals::model::{
+        outlining::{Pattern, PatternSet},
+        repo::SparseMode,
+    };
+
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let path = fixture.sparse_repo_path.clone();
+
+    let repo = fixture.sparse_repo()?;
+    let working_tree = repo.working_tree()?;
+    assert_eq!(working_tree.get_sparse_mode()?, SparseMode::Auto);
+    assert!(repo
+        .underlying()
+        .config()?
+        .get_bool("core.sparseCheckoutCone")?);
+
+    working_tree.set_sparse_mode(SparseMode::Pattern)?;
+    crate::selection::add(
+        &path,
+        true,
+        vec![String::from("bazel://library_b/...")],
+        false,
+        fixture.app.clone(),
+    )?;
+    assert!(path.join("library_b").is_dir());
+    assert!(!repo
+        .underlying()
+        .config()?
+        .get_bool("core.sparseCheckoutCone")?);
+
+    assert!("sparse".parse::<SparseMode>().is_err());
+
+    // Cone mode cannot express verbatim patterns, so auto mode avoids it and cone mode refuses them.
+    let mut patterns = PatternSet::new();
+    patterns.insert(Pattern::Directory {
+        precedence: 0,
+        path: std::path::PathBuf::from("library_a"),
+        recursive: true,
+    });
+    assert!(SparseMode::Auto.uses_cone(&patterns)?);
+    assert!(SparseMode::Cone.uses_cone(&patterns)?);
+    patterns.insert(Pattern::Verbatim {
+        precedence: 1,
+        fragment: String::from("/library_a/*.txt"),
+    });
+    assert!(!SparseMode::Auto.uses_cone(&patterns)?);
+    assert!(SparseMode::Cone.uses_cone(&patterns).is_err());
+    assert!(!SparseMode::Pattern.uses_cone(&patterns)?);
+
+    Ok(())
+}
+
+#[test]
+fn set_sync_point_records_the_commit_without_syncing() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let path = fixture.sparse_repo_path.clone();
+    let upstream_id = fixture.dense_repo.write_and_commit_file(
+        Path::new("x/catz.txt"),
+        b"The Naming of Cats is a difficult matter",
+        "Add catz",
+    )?;
+    fixture.perform_fetch(RepoDisposition::Sparse, "origin")?;
+
+    assert!(crate::sync::set_sync_point(&path, "not-a-commit", fixture.app.clone()).is_err());
+
+    let commit_id = crate::sync::set_sync_point(
+        &path,
+        &format!("refs/remotes/origin/{}", fixture.branch),
+        fixture.app.clone(),
+    )?;
+    assert_eq!(commit_id, upstream_id);
+    let repo = fixture.sparse_repo()?;
+    assert_eq!(
+        repo.working_tree()?.read_sparse_sync_point_ref()?,
+        Some(upstream_id)
+    );
+    let fingerprint = repo.read_sync_point_fingerprint()?.unwrap();
+    assert!(fingerprint.starts_with(&upstream_id.to_string()));
+    // HEAD was left behind, so the repository should be synced.
+    assert!(crate::status::compute_drift(&repo)?.sync_recommended());
+
+    Ok(())
+}
+
+#[test]
+fn pre_sync_hooks_can_veto_a_sync() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let path = fixture.sparse_repo_path.clone();
+    let commit_file = fixture.dir.path().join("hook-commit");
+    let veto_file = fixture.dir.path().join("veto");
+    let hook = format!(
+        "echo \"$FOCUS_SYNC_COMMIT\" > {}; if [ -e {} ]; then echo 'Migrations are pending'; exit 1; fi",
+        commit_file.display(),
+        veto_file.display()
+    );
+    fixture.sparse_repo()?.underlying().config()?.set_str(
+        focus_internals::model::repo::PRE_SYNC_HOOKS_CONFIG_KEY,
+        &hook,
+    )?;
+    let head_id = fixture.sparse_repo()?.get_head_commit()?.id();
+
+    crate::selection::add(
+        &path,
+        false,
+        vec![String::from("directory:library_1")],
+        false,
+        fixture.app.clone(),
+    )?;
+    crate::sync::run(
+        &SyncRequest::new(&path, SyncMode::Incremental),
+        fixture.app.clone(),
+    )?;
+    assert_eq!(
+        std::fs::read_to_string(&commit_file)?.trim(),
+        head_id.to_string()
+    );
+    assert!(path.join("library_1").is_dir());
+
+    std::fs::write(&veto_file, b"")?;
+    crate::selection::add(
+        &path,
+        false,
+        vec![String::from("directory:library_2")],
+        false,
+        fixture.app.clone(),
+    )?;
+    let error = crate::sync::run(
+        &SyncRequest::new(&path, SyncMode::Incremental),
+        fixture.app.clone(),
+    )
+    .unwrap_err();
+    assert!(format!("{:#}", error).contains("Migrations are pending"));
+    assert!(!path.join("library_2").exists());
+
+    crate::sync::run(
+        &SyncRequest::new(&path, SyncMode::Incremental).with_no_hooks(),
+        fixture.app.clone(),
+    )?;
+    assert!(path.join("library_2").is_dir());
+
+    Ok(())
+}
+
+#[test]
+fn verify_sync_point_clears_dangling_refs() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let path = fixture.sparse_repo_path.clone();
+
+    assert!(crate::sync::verify_sync_point(&path, fixture.app.clone())?.is_empty());
+    assert!(fixture
+        .sparse_repo()?
+        .working_tree()?
+        .read_sparse_sync_point_ref()?
+        .is_some());
+
+    // Emulate the synced commit having been garbage collected.
+    std::fs::write(
+        path.join(".git/refs/focus/sync"),
+        "0123456789abcdef0123456789abcdef01234567\n",
+    )?;
+    assert_eq!(
+        crate::sync::verify_sync_point(&path, fixture.app.clone())?,
+        vec![String::from("refs/focus/sync")]
+    );
+    assert!(fixture
+        .sparse_repo()?
+        .working_tree()?
+        .read_sparse_sync_point_ref()?
+        .is_none());
+
+    crate::sync::run(
+        &SyncRequest::new(&path, SyncMode::Incremental),
+        fixture.app.clone(),
+    )?;
+    assert!(fixture
+        .sparse_repo()?
+        .working_tree()?
+        .read_sparse_sync_point_ref()?
+        .is_some());
+
+    Ok(())
+}
+
+#[test]
+fn cache_plan_reports_missing_keys_until_synced() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let path = fixture.sparse_repo_path.clone();
+
+    let plan = crate::index::plan(fixture.app.clone(), &path)?;
+    assert!(plan.fully_cached(), "{}", plan);
+    assert_eq!(plan.estimated_bazel_queries(), 0);
+
+    // Files are selected by pattern, so they are never missing from the index.
+    crate::selection::add(
+        &path,
+        false,
+        vec![
+            String::from("bazel://library_b/..."),
+            String::from("file:WORKSPACE"),
+        ],
+        false,
+        fixture.app.clone(),
+    )?;
+    let plan = crate::index::plan(fixture.app.clone(), &path)?;
+    assert!(!plan.fully_cached());
+    assert!(!plan.missing_targets.is_empty());
+    assert!(!plan
+        .missing_targets
+        .iter()
+        .any(|target| target.starts_with("file:")));
+    assert_eq!(plan.estimated_bazel_queries(), 2);
+
+    crate::sync::run(
+        &SyncRequest::new(&path, SyncMode::Incremental),
+        fixture.app.clone(),
+    )?;
+    let plan = crate::index::plan(fixture.app.clone(), &path)?;
+    assert!(plan.fully_cached(), "{}", plan);
+
+    Ok(())
+}
+
+#[test]
+fn warm_cache_populates_index_without_selecting() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let path = fixture.sparse_repo_path.clone();
+    let selection_before = fixture
+        .sparse_repo()?
+        .selection_manager()?
+        .computed_selection()?;
+
+    let projects = vec![String::from("team_zissou/project_c")];
+    let result = crate::index::warm_cache(fixture.app.clone(), &path, &projects)?;
+    assert!(result.warmed > 0);
+
+    let result = crate::index::warm_cache(fixture.app.clone(), &path, &projects)?;
+    assert_eq!(result.warmed, 0);
+    assert!(result.already_cached > 0);
+
+    let selection_after = fixture
+        .sparse_repo()?
+        .selection_manager()?
+        .computed_selection()?;
+    assert_eq!(selection_before, selection_after);
+
+    Ok(())
+}
+
+#[test]
+fn strict_resolution_checks_resolutions_found_in_the_index() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let path = fixture.sparse_repo_path.clone();
+
+    add_updated_content(&fixture.dense_repo)?;
+    fixture.dense_repo.write_and_commit_file(
+        Path::new("broken/BUILD.bazel"),
+        br#"filegroup(name = "broken", srcs = ["//missing:file"])"#,
+        "Add a package depending on a missing one",
+    )?;
+    fixture.perform_pull(RepoDisposition::Sparse, "origin", "main")?;
+
+    crate::selection::add(
+        &path,
+        false,
+        vec![String::from("bazel://x/...")],
+        false,
+        fixture.app.clone(),
+    )?;
+    let first = crate::sync::run(
+        &SyncRequest::new(&path, SyncMode::Incremental),
+        fixture.app.clone(),
+    )?;
+    assert_eq!(first.status, SyncStatus::Success);
+
+    // The resolution is now in the index and the sync point is unchanged, but a strict sync resolves it again.
+    let strict = crate::sync::run(
+        &SyncRequest::new(&path, SyncMode::Incremental).with_strict_resolution(),
+        fixture.app.clone(),
+    )?;
+    assert_eq!(strict.status, SyncStatus::Success);
+    assert_ne!(strict.mechanism, SyncMechanism::Skipped);
+
+    // A requested target that Bazel cannot resolve fails the sync, leaving the profile alone.
+    crate::selection::add(
+        &path,
+        false,
+        vec![String::from("bazel://broken:broken")],
+        false,
+        fixture.app.clone(),
+    )?;
+    let working_tree = fixture.sparse_repo()?.working_tree()?;
+    let profile_contents = std::fs::read_to_string(working_tree.sparse_checkout_path())?;
+    assert!(crate::sync::run(
+        &SyncRequest::new(&path, SyncMode::Incremental).with_strict_resolution(),
+        fixture.app.clone(),
+    )
+    .is_err());
+    assert_eq!(
+        std::fs::read_to_string(working_tree.sparse_checkout_path())?,
+        profile_contents
+    );
+    assert!(!path.join("broken").exists());
+
+    Ok(())
+}
+
+#[test]
+fn strict_cache_recomputes_cached_patterns() -> Result<()> {
+    use content_addressed_cache::RocksDBCache;
+    use focus_internals::model::repo::PatternApplication;
+
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+
+    // Each resolution that misses the index lists the missing keys in the sandbox.
+    let count_misses = || -> Result<usize> {
+        Ok(std::fs::read_dir(fixture.app.sandbox().path())?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with("missing-keys")
+            })
+            .count())
+    };
+
+    let mut repo = fixture.sparse_repo()?;
+    let commit_id = repo.get_head_commit()?.id();
+    let targets = repo.selection_manager()?.compute_complete_target_set()?;
+    let sync = |repo: &Repo| {
+        let cache = RocksDBCache::new(repo.underlying());
+        repo.sync(
+            commit_id,
+            &targets,
+            PatternApplication::Skip,
+            fixture.app.clone(),
+            Some(&cache),
+            None,
+        )
+    };
+
+    let misses = count_misses()?;
+    let cached_counts = sync(&repo)?.pattern_counts;
+    assert_eq!(count_misses()?, misses);
+
+    repo.require_strict_cache();
+    let strict_counts = sync(&repo)?.pattern_counts;
+    assert_eq!(count_misses()?, misses + 1);
+    assert_eq!(strict_counts, cached_counts);
+
+    Ok(())
+}
+
+#[test]
+fn pattern_sparse_mode_checks_out_what_the_optimized_cone_profile_does() -> Result<()> {
+    use content_addressed_cache::RocksDBCache;
+    use focus_internals::model::repo::{PatternApplication, SparseMode};
+    use std::{collections::BTreeSet, path::PathBuf};
+
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let path = fixture.sparse_repo_path.clone();
+    // Nested in `library_a`, so optimization removes its pattern in cone mode.
+    let targets = vec![
+        String::from("directory:library_a"),
+        String::from("directory:library_a/src"),
+    ];
+    crate::selection::add(&path, true, targets, false, fixture.app.clone())?;
+
+    let materialized_files = || -> Result<BTreeSet<PathBuf>> {
+        let mut files = BTreeSet::new();
+        for entry in walkdir::WalkDir::new(&path)
+            .into_iter()
+            .filter_entry(|entry| entry.file_name() != ".git")
+        {
+            let entry = entry?;
+            if entry.file_type().is_file() {
+                files.insert(entry.path().strip_prefix(&path)?.to_owned());
+            }
+        }
+        Ok(files)
+    };
+
+    let repo = fixture.sparse_repo()?;
+    let commit_id = repo.get_head_commit()?.id();
+    let targets = repo.selection_manager()?.compute_complete_target_set()?;
+    let sync = |repo: &Repo| {
+        let cache = RocksDBCache::new(repo.underlying());
+        repo.sync(
+            commit_id,
+            &targets,
+            PatternApplication::Checkout,
+            fixture.app.clone(),
+            Some(&cache),
+            None,
+        )
+    };
+
+    let cone_counts = sync(&repo)?.pattern_counts;
+    assert!(cone_counts.optimized < cone_counts.computed);
+    let cone_files = materialized_files()?;
+
+    // Removing patterns only preserves what the profile matches in cone mode, so pattern mode applies them all.
+    repo.working_tree()?.set_sparse_mode(SparseMode::Pattern)?;
+    let pattern_counts = sync(&repo)?.pattern_counts;
+    assert_eq!(pattern_counts.computed, cone_counts.computed);
+    assert_eq!(pattern_counts.optimized, pattern_counts.computed);
+    assert_eq!(materialized_files()?, cone_files);
+
+    Ok(())
+}
+
+#[test]
+fn query_coordinate_shows_raw_output_and_directories() -> Result<()> {
+    use focus_internals::model::outlining::Pattern;
+
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+
+    let result = crate::query::coordinate(
+        &fixture.sparse_repo_path,
+        "bazel://library_b/...",
+        fixture.app.clone(),
+    )?;
+    assert_eq!(
+        result.commit_id,
+        fixture.sparse_repo()?.get_head_commit()?.id()
+    );
+    assert!(result.output.lines().any(|line| line == "library_b"));
+    assert!(result.patterns.iter().any(|pattern| matches!(
+        pattern,
+        Pattern::Directory { path, .. } if path == Path::new("library_b")
+    )));
+
+    assert!(crate::query::coordinate(
+        &fixture.sparse_repo_path,
+        "directory:library_b",
+        fixture.app.clone()
+    )
+    .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn project_directories_include_dependencies() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+
+    let directories = crate::project::project_directories(
+        &fixture.sparse_repo_path,
+        "team_banzai/project_a",
+        fixture.app.clone(),
+    )?;
+    assert!(directories.contains(Path::new("project_a")));
+    // The project's binary depends on `//library_a:a`.
+    assert!(directories.contains(Path::new("library_a")));
+    assert!(!directories.contains(Path::new("library_b")));
+
+    assert!(crate::project::project_directories(
+        &fixture.sparse_repo_path,
+        "undefined",
+        fixture.app.clone()
+    )
+    .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn target_set_diff_uses_the_projects_defined_at_each_commit() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let path = fixture.sparse_repo_path.clone();
+    crate::selection::add(
+        &path,
+        true,
+        vec![String::from("team_zissou/project_c")],
+        false,
+        fixture.app.clone(),
+    )?;
+
+    let diff = crate::query::target_set_diff(&path, "HEAD", "HEAD", fixture.app.clone())?;
+    assert!(diff.is_empty());
+
+    fixture.dense_repo.write_and_commit_file(
+        Path::new("focus/projects/project_c.projects.json"),
+        r#"{
+            "projects": [
+                {
+                    "name": "team_zissou/project_c",
+                    "description": "Stuff relating to project C",
+                    "targets": ["bazel://project_b/...", "directory:library_1"],
+                    "projects": ["team_banzai/project_a"]
+                }
+            ]
+        }"#,
+        "Add library_1 to project C",
+    )?;
+    fixture.perform_fetch(RepoDisposition::Sparse, "origin")?;
+
+    let upstream = format!("refs/remotes/origin/{}", fixture.branch);
+    let diff = crate::query::target_set_diff(&path, "HEAD", &upstream, fixture.app.clone())?;
+    assert_eq!(
+        diff.added_targets.iter().collect::<Vec<_>>(),
+        vec!["directory:library_1"]
+    );
+    assert!(diff.removed_targets.is_empty());
+    assert!(diff.added_directories.contains(Path::new("library_1")));
+    assert!(diff.removed_directories.is_empty());
+
+    // The working tree's project definitions are unchanged.
+    let targets = fixture
+        .sparse_repo()?
+        .selection_manager()?
+        .compute_complete_target_set()?;
+    assert!(!targets.contains(&Target::try_from("directory:library_1")?));
+
+    Ok(())
+}
+
+#[test]
+fn sync_resumes_a_checkout_that_did_not_complete() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let path = fixture.sparse_repo_path.clone();
+
+    crate::selection::add(
+        &path,
+        false,
+        vec![String::from("bazel://library_b/...")],
+        false,
+        fixture.app.clone(),
+    )?;
+    crate::sync::run(
+        &SyncRequest::new(&path, SyncMode::Incremental).with_no_checkout(),
+        fixture.app.clone(),
+    )?;
+    assert!(!path.join("library_b").is_dir());
+
+    // The checkout of the written profile is left pending, as if it had failed partway.
+    let working_tree = fixture.sparse_repo()?.working_tree()?;
+    assert!(working_tree.checkout_pending());
+
+    let result = crate::sync::run(
+        &SyncRequest::new(&path, SyncMode::Incremental),
+        fixture.app.clone(),
+    )?;
+    assert_eq!(result.status, SyncStatus::Success);
+    assert!(result.checked_out);
+    assert!(path.join("library_b").is_dir());
+    assert!(!working_tree.checkout_pending());
+
+    Ok(())
+}
+
+#[test]
+fn sync_reports_checkout_stats() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let path = fixture.sparse_repo_path.clone();
+    let targets = vec![String::from("directory:library_a")];
+
+    crate::selection::add(&path, false, targets.clone(), false, fixture.app.clone())?;
+    let result = crate::sync::run(
+        &SyncRequest::new(&path, SyncMode::Incremental),
+        fixture.app.clone(),
+    )?;
+    let stats = result.stats.unwrap();
+    assert!(stats.checked_out);
+    assert!(stats.files_added > 0);
+    assert_eq!(stats.files_removed, 0);
+    assert!(stats.bytes_added > 0);
+    assert_eq!(stats.bytes_removed, 0);
+
+    crate::selection::remove(&path, false, targets, false, fixture.app.clone())?;
+    let result = crate::sync::run(
+        &SyncRequest::new(&path, SyncMode::Incremental),
+        fixture.app.clone(),
+    )?;
+    let removed_stats = result.stats.unwrap();
+    assert_eq!(removed_stats.files_added, 0);
+    assert!(removed_stats.files_removed > 0);
+    assert_eq!(removed_stats.files_removed, stats.files_added);
+    assert_eq!(removed_stats.bytes_removed, stats.bytes_added);
+
+    Ok(())
+}
+
+#[test]
+fn sync_mirrors_the_selection() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let path = fixture.sparse_repo_path.clone();
+    let library_a = Target::Directory(String::from("library_a"));
+
+    crate::selection::add(
+        &path,
+        false,
+        vec![library_a.to_string()],
+        false,
+        fixture.app.clone(),
+    )?;
+    let mirror_path = path.join(".focus-selection.json");
+    crate::sync::run(
+        &SyncRequest::new(&path, SyncMode::Incremental)
+            .with_selection_mirror_path(mirror_path.clone()),
+        fixture.app.clone(),
+    )?;
+    assert!(std::fs::read_to_string(&mirror_path)?.contains("directory:library_a"));
+    let repo = fixture.sparse_repo()?;
+    assert!(!repo
+        .selection_manager()?
+        .differs_from_mirror(&mirror_path)?);
+
+    // Without a configured mirror, changing the selection leaves the mirror behind, so it can be adopted.
+    crate::selection::remove(
+        &path,
+        false,
+        vec![library_a.to_string()],
+        false,
+        fixture.app.clone(),
+    )?;
+    let mut selections = repo.selection_manager()?;
+    assert!(selections.differs_from_mirror(&mirror_path)?);
+    selections.adopt_mirror(&mirror_path)?;
+    assert_eq!(
+        selections.selection()?.targets,
+        hashset! {library_a.clone()}
+    );
+
+    // Declining the shared selection is remembered until it changes.
+    assert!(!crate::selection::selection_mirror_was_declined(
+        &repo,
+        &mirror_path
+    )?);
+    crate::selection::decline_selection_mirror(&repo, &mirror_path)?;
+    assert!(crate::selection::selection_mirror_was_declined(
+        &repo,
+        &mirror_path
+    )?);
+    assert!(!crate::selection::offer_selection_mirror(
+        &path,
+        fixture.app.clone()
+    )?);
+
+    // Even with a configured mirror, changing the selection leaves it alone until the selection is published.
+    repo.set_selection_mirror_path(Path::new("shared/selection.json"))?;
+    let shared_path = crate::selection::selection_mirror_path(&repo)?;
+    assert_eq!(shared_path, path.join("shared/selection.json"));
+    crate::selection::add(
+        &path,
+        false,
+        vec![String::from("bazel://library_b/...")],
+        false,
+        fixture.app.clone(),
+    )?;
+    crate::sync::run(
+        &SyncRequest::new(&path, SyncMode::Incremental),
+        fixture.app.clone(),
+    )?;
+    assert!(!shared_path.exists());
+    crate::sync::run(
+        &SyncRequest::new(&path, SyncMode::Incremental)
+            .with_selection_mirror_path(shared_path.clone()),
+        fixture.app.clone(),
+    )?;
+    assert!(std::fs::read_to_string(&shared_path)?.contains("bazel://library_b/..."));
+
+    Ok(())
+}
+
+#[test]
+fn select_for_paths_selects_owning_packages() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let path = fixture.sparse_repo_path.clone();
+
+    let paths_file = fixture.dir.path().join("changed-paths.txt");
+    std::fs::write(
+        &paths_file,
+        "library_b/src/main/java/com/example/lib_b/LibraryB.java\nlibrary_b/BUILD\nWORKSPACE\nremoved.txt\n",
+    )?;
+    let targets =
+        crate::selection::select_for_paths(&path, true, &paths_file, fixture.app.clone())?;
+    assert_eq!(
+        targets,
+        vec![
+            String::from("bazel://library_b:all"),
+            String::from("file:WORKSPACE")
+        ]
+    );
+
+    let selection = fixture.sparse_repo()?.selection_manager()?.selection()?;
+    assert!(selection
+        .targets
+        .contains(&Target::try_from("bazel://library_b:all")?));
+    assert!(path
+        .join("library_b/src/main/java/com/example/lib_b/LibraryB.java")
+        .is_file());
+
+    Ok(())
+}
+
+#[test]
+fn sync_records_metrics() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let path = fixture.sparse_repo_path.clone();
+
+    crate::selection::add(
+        &path,
+        true,
+        vec![String::from("bazel://library_b/...")],
+        false,
+        fixture.app.clone(),
+    )?;
+    crate::sync::run(
+        &SyncRequest::new(&path, SyncMode::Incremental),
+        fixture.app.clone(),
+    )?;
+
+    let metrics = fixture.app.metrics().render("");
+    assert!(
+        metrics.contains("focus_sync_runs_total{status=\"success\",mechanism=\"outline\"} 1\n"),
+        "{}",
+        metrics
+    );
+    assert!(metrics
+        .contains("focus_sync_runs_total{status=\"skipped_unchanged\",mechanism=\"outline\"} 1\n"));
+    assert!(metrics.contains("focus_sync_patterns{stage=\"optimized\"}"));
+    assert!(metrics.contains("focus_sync_index_hit_ratio"));
+
+    Ok(())
+}
+
+#[test]
+fn bisect_selection_finds_the_failing_layer() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+    let path = fixture.sparse_repo_path.clone();
+    let targets: Vec<String> = ["library_1", "library_2", "library_3", "library_a"]
+        .iter()
+        .map(|dir| format!("directory:{}", dir))
+        .collect();
+    crate::selection::add(&path, true, targets, false, fixture.app.clone())?;
+
+    let test_command: Vec<String> = ["test", "!", "-d", "library_3"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+    let report = crate::bisect::run(&path, &test_command, fixture.app.clone())?;
+    assert_eq!(
+        report.outcome,
+        crate::bisect::BisectOutcome::Culprits(vec![String::from("directory:library_3")])
+    );
+
+    // The whole selection is checked out again.
+    assert!(path.join("library_1").is_dir());
+    assert!(path.join("library_3").is_dir());
+
+    let passing_command: Vec<String> = vec![String::from("true")];
+    let report = crate::bisect::run(&path, &passing_command, fixture.app.clone())?;
+    assert_eq!(report.outcome, crate::bisect::BisectOutcome::NoFailure);
+    assert_eq!(report.steps, 1);
 
     Ok(())
 }
@@ -1,9 +1,12 @@
 // Copyright 2022 Twitter, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use anyhow::{bail, Result};
+use std::path::Path;
 
-use tracing::error;
+use anyhow::{bail, Context, Result};
+use console::Term;
+use focus_util::backed_up_file::{BackedUpFile, OrphanedBackupAction};
+use tracing::{error, warn};
 
 pub fn perform<F, J>(description: &str, f: F) -> Result<J>
 where
@@ -17,3 +20,153 @@ where
 
     result
 }
+
+/// Whether a command that deletes things goes ahead. Commands that remove sandboxes, refs, and the like share this so
+/// that they all follow the same `--dry-run`/`--yes` contract.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Removal {
+    /// Only show what would be removed.
+    DryRun,
+
+    /// Show what would be removed and ask before removing it. Without a terminal to ask on, nothing is removed.
+    Confirm,
+
+    /// Remove without asking.
+    Yes,
+}
+
+impl Removal {
+    /// Interpret the `--dry-run` and `--yes` flags; `--dry-run` wins if both are given.
+    pub fn from_flags(dry_run: bool, yes: bool) -> Self {
+        if dry_run {
+            Removal::DryRun
+        } else if yes {
+            Removal::Yes
+        } else {
+            Removal::Confirm
+        }
+    }
+
+    /// Print `items`, which are described by the plural `noun`, and decide whether to remove them.
+    pub fn approve(&self, noun: &str, items: &[String]) -> Result<bool> {
+        if items.is_empty() {
+            eprintln!("There are no {} to remove.", noun);
+            return Ok(false);
+        }
+        for item in items {
+            println!("{}", item);
+        }
+
+        match self {
+            Removal::DryRun => {
+                eprintln!("Would remove {} {}.", items.len(), noun);
+                Ok(false)
+            }
+            Removal::Yes => Ok(true),
+            Removal::Confirm => {
+                let term = Term::stderr();
+                if !term.is_term() {
+                    eprintln!(
+                        "Would remove {} {}. Pass --yes to remove them.",
+                        items.len(),
+                        noun
+                    );
+                    return Ok(false);
+                }
+                loop {
+                    term.write_str(&format!("Remove {} {}? [y/N] ", items.len(), noun))?;
+                    match term.read_line()?.trim().to_ascii_lowercase().as_str() {
+                        "y" | "yes" => return Ok(true),
+                        "" | "n" | "no" => return Ok(false),
+                        _ => continue,
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Deal with a backup of `path` left behind by a focus process that was killed before it could restore or discard
+/// it. When attached to a terminal, the user chooses whether to restore the backup; otherwise it is restored, since
+/// the backup reflects the last state that focus completed successfully.
+pub fn recover_orphaned_backup(path: &Path, description: &str) -> Result<()> {
+    let backup_path = match BackedUpFile::find_orphaned_backup(path) {
+        Some(backup_path) => backup_path,
+        None => return Ok(()),
+    };
+
+    warn!(
+        ?backup_path,
+        "Found a backup of the {} left behind by an interrupted focus process", description
+    );
+    let term = Term::stderr();
+    let action = if term.is_term() {
+        loop {
+            term.write_str(&format!(
+                "A previous focus process was interrupted while changing the {} ({}). Restore the backup taken before it started? [Y/n] ",
+                description,
+                path.display()
+            ))?;
+            match term.read_line()?.trim().to_ascii_lowercase().as_str() {
+                "" | "y" | "yes" => break OrphanedBackupAction::Restore,
+                "n" | "no" => break OrphanedBackupAction::Discard,
+                _ => continue,
+            }
+        }
+    } else {
+        OrphanedBackupAction::Restore
+    };
+
+    BackedUpFile::resolve_orphaned_backup(path, action)
+        .with_context(|| format!("Recovering the {}", description))?;
+    match action {
+        OrphanedBackupAction::Restore => eprintln!("Restored the {} from its backup.", description),
+        OrphanedBackupAction::Discard => eprintln!("Discarded the backup of the {}.", description),
+    }
+    Ok(())
+}
+
+/// Format a size in bytes using binary units, like `1.5 KiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for next_unit in UNITS {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next_unit;
+    }
+    format!("{:.1} {}", size, unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formatting_sizes() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024 * 1024), "5.0 GiB");
+    }
+
+    #[test]
+    fn removal_follows_the_flags() -> Result<()> {
+        assert_eq!(Removal::from_flags(false, false), Removal::Confirm);
+        assert_eq!(Removal::from_flags(false, true), Removal::Yes);
+        assert_eq!(Removal::from_flags(true, true), Removal::DryRun);
+
+        let items = vec![String::from("a"), String::from("b")];
+        assert!(!Removal::DryRun.approve("things", &items)?);
+        assert!(Removal::Yes.approve("things", &items)?);
+        assert!(!Removal::Yes.approve("things", &[])?);
+
+        Ok(())
+    }
+}
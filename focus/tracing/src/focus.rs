@@ -33,15 +33,41 @@ pub struct TracingOpts {
     pub is_tty: bool,
     pub no_color: bool,
     pub log_dir: Option<PathBuf>,
+
+    /// A filter in the syntax of `RUST_LOG`, e.g. `info,focus_internals::coordinate_resolver=trace`, which takes
+    /// precedence over `RUST_LOG`.
+    pub log_filter: Option<String>,
 }
 
 const LOG_FILE_NAME: &str = "focus.log";
 
+/// The log filter corresponding to the number of times `--debug` is given, if any. Each occurrence logs one level
+/// more than the default, which is info.
+pub fn log_filter_for_verbosity(verbosity: u64) -> Option<&'static str> {
+    match verbosity {
+        0 => None,
+        1 => Some("debug"),
+        _ => Some("trace"),
+    }
+}
+
+fn env_filter(log_filter: Option<&str>) -> Result<EnvFilter> {
+    match log_filter {
+        Some(spec) => {
+            EnvFilter::try_new(spec).with_context(|| format!("Parsing log filter '{}'", spec))
+        }
+        None => Ok(EnvFilter::new(
+            std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string()),
+        )),
+    }
+}
+
 pub fn init_tracing(opts: TracingOpts) -> Result<Guard> {
     let TracingOpts {
         is_tty,
         no_color,
         log_dir,
+        log_filter,
     } = opts;
 
     let use_color = is_tty && !no_color;
@@ -74,12 +100,10 @@ pub fn init_tracing(opts: TracingOpts) -> Result<Guard> {
         .with_thread_names(false)
         .compact();
 
-    tracing_subscriber::registry()
-        .with(ErrorLayer::default())
-        .with(EnvFilter::new(
-            std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string()),
-        ))
-        .with(
+    // Noisy modules are quieted unless a filter is given explicitly, since it may ask for them.
+    let quiet_targets = match log_filter {
+        Some(_) => None,
+        None => Some(
             Targets::new()
                 .with_targets(vec![
                     ("serde_xml_rs", LevelFilter::INFO),
@@ -89,7 +113,13 @@ pub fn init_tracing(opts: TracingOpts) -> Result<Guard> {
                     ),
                 ])
                 .with_default(LevelFilter::TRACE),
-        )
+        ),
+    };
+
+    tracing_subscriber::registry()
+        .with(ErrorLayer::default())
+        .with(env_filter(log_filter.as_deref())?)
+        .with(quiet_targets)
         .with(
             tracing_subscriber::fmt::layer()
                 .with_target(true)
@@ -113,3 +143,22 @@ pub fn init_tracing(opts: TracingOpts) -> Result<Guard> {
         ],
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verbosity_maps_to_log_filters() {
+        assert_eq!(log_filter_for_verbosity(0), None);
+        assert_eq!(log_filter_for_verbosity(1), Some("debug"));
+        assert_eq!(log_filter_for_verbosity(2), Some("trace"));
+        assert_eq!(log_filter_for_verbosity(7), Some("trace"));
+    }
+
+    #[test]
+    fn explicit_log_filters_are_validated() {
+        assert!(env_filter(Some("info,focus_internals::coordinate_resolver=trace")).is_ok());
+        assert!(env_filter(Some("focus_internals=notalevel")).is_err());
+    }
+}
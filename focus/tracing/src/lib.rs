@@ -7,7 +7,7 @@ pub mod git_trace2;
 
 use std::path::{Path, PathBuf};
 
-pub use crate::focus::{init_tracing, Guard, TracingOpts};
+pub use crate::focus::{init_tracing, log_filter_for_verbosity, Guard, TracingOpts};
 pub use chrome::Trace;
 
 use anyhow::Result;
@@ -2,9 +2,9 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::sync::Arc;
-use std::{borrow::Borrow, fmt::Debug};
+use std::{borrow::Borrow, fmt::Debug, path::PathBuf};
 
-use crate::sandbox::Sandbox;
+use crate::{metrics::Metrics, paths, sandbox::Sandbox, sandbox_command::RunningCommands};
 use anyhow::{Context, Result};
 use focus_testing::GitBinary;
 use std::time::SystemTime;
@@ -18,11 +18,34 @@ use tool_insights_client::Client;
 #[derive(Debug, PartialEq, Eq)]
 pub struct ExitCode(pub i32);
 
+/// Global options, usually given on the command line, that change where and how operations run.
+#[derive(Clone, Debug, Default)]
+pub struct AppOptions {
+    /// Run this Git binary instead of the first `git` on the `PATH`.
+    pub git_binary: Option<PathBuf>,
+
+    /// Create sandboxes in this directory instead of the default location.
+    pub sandbox_dir: Option<PathBuf>,
+
+    /// Keep the registry of repos and the resolution cache in this directory instead of the focus config and cache
+    /// directories.
+    pub data_dir: Option<PathBuf>,
+
+    /// Fail operations that need the network instead of attempting them.
+    pub offline: bool,
+
+    /// Do not warn when selecting deprecated projects.
+    pub no_deprecated_warnings: bool,
+}
+
 #[derive(Clone)]
 pub struct App {
+    options: AppOptions,
     git_binary: GitBinary,
     sandbox: Arc<Sandbox>,
     tool_insights_client: Client,
+    metrics: Arc<Metrics>,
+    running_commands: Arc<RunningCommands>,
 }
 
 impl Debug for App {
@@ -33,7 +56,7 @@ impl Debug for App {
 
 impl App {
     pub fn new_for_testing() -> Result<Self> {
-        let mut app = Self::new(false, None, None, None)?;
+        let mut app = Self::new(false, None, None, None, AppOptions::default())?;
         app.git_binary = GitBinary::for_testing()?;
         Ok(app)
     }
@@ -42,10 +65,19 @@ impl App {
         with_cmd_prefix: Option<&str>,
         app_name: Option<String>,
         app_version: Option<String>,
+        options: AppOptions,
     ) -> Result<Self> {
-        let git_binary = GitBinary::from_env()?;
+        let git_binary = match &options.git_binary {
+            Some(path) => GitBinary::from_binary_path(path.clone())
+                .with_context(|| format!("Using the Git binary at {}", path.display()))?,
+            None => GitBinary::from_env()?,
+        };
+        let sandbox_dir = options
+            .sandbox_dir
+            .clone()
+            .unwrap_or_else(paths::focus_sandbox_dir);
         let sandbox = Arc::from(
-            Sandbox::new(preserve_sandbox_contents, with_cmd_prefix)
+            Sandbox::new_in(&sandbox_dir, preserve_sandbox_contents, with_cmd_prefix)
                 .context("Failed to create sandbox")?,
         );
         let tool_insights_client = Client::new(
@@ -54,9 +86,12 @@ impl App {
             SystemTime::now(),
         );
         Ok(Self {
+            options,
             git_binary,
             sandbox,
             tool_insights_client,
+            metrics: Default::default(),
+            running_commands: Default::default(),
         })
     }
 
@@ -65,6 +100,19 @@ impl App {
         &self.git_binary
     }
 
+    /// The global options the app was created with.
+    pub fn options(&self) -> &AppOptions {
+        &self.options
+    }
+
+    /// The directory sandboxes are created in.
+    pub fn sandbox_dir(&self) -> PathBuf {
+        self.options
+            .sandbox_dir
+            .clone()
+            .unwrap_or_else(paths::focus_sandbox_dir)
+    }
+
     /// Get a reference to the app's sandbox.
     pub fn sandbox(&self) -> Arc<Sandbox> {
         self.sandbox.clone()
@@ -74,4 +122,14 @@ impl App {
     pub fn tool_insights_client(&self) -> &Client {
         self.tool_insights_client.borrow()
     }
+
+    /// Get a reference to the metrics recorded for `--metrics-file`.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Get a reference to the commands currently running in the app's sandbox.
+    pub fn running_commands(&self) -> Arc<RunningCommands> {
+        self.running_commands.clone()
+    }
 }
@@ -15,15 +15,19 @@ pub struct BackedUpFile {
     restore: Cell<bool>,
 }
 
+/// What to do with a backup left behind by a process that exited without restoring or discarding it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OrphanedBackupAction {
+    /// Replace the file with the backup.
+    Restore,
+
+    /// Keep the file as it is and delete the backup.
+    Discard,
+}
+
 impl BackedUpFile {
     pub fn new(path: &Path) -> Result<Self> {
-        let mut backup_path = path.to_owned();
-        let mut name = backup_path
-            .file_name()
-            .expect("Backup file with no file name")
-            .to_owned();
-        name.push(".backup");
-        backup_path.set_file_name(name);
+        let backup_path = Self::backup_path_for(path);
 
         std::fs::copy(path, &backup_path).with_context(|| {
             format!(
@@ -51,6 +55,51 @@ impl BackedUpFile {
     pub fn discard(&self) {
         self.set_restore(false);
     }
+
+    /// The location of the backup of the file at `path`.
+    pub fn backup_path_for(path: &Path) -> PathBuf {
+        let mut backup_path = path.to_owned();
+        let mut name = backup_path
+            .file_name()
+            .expect("Backup file with no file name")
+            .to_owned();
+        name.push(".backup");
+        backup_path.set_file_name(name);
+        backup_path
+    }
+
+    /// Find a backup of the file at `path` that a previous process created but never restored or discarded, most
+    /// likely because it crashed or was killed. Callers must hold whatever lock guards backups of this file.
+    pub fn find_orphaned_backup(path: &Path) -> Option<PathBuf> {
+        let backup_path = Self::backup_path_for(path);
+        if backup_path.is_file() {
+            Some(backup_path)
+        } else {
+            None
+        }
+    }
+
+    /// Restore or discard an orphaned backup of the file at `path`.
+    pub fn resolve_orphaned_backup(path: &Path, action: OrphanedBackupAction) -> Result<()> {
+        let backup_path = Self::backup_path_for(path);
+        match action {
+            OrphanedBackupAction::Restore => {
+                info!(?backup_path, ?path, "Restoring orphaned backup file");
+                std::fs::rename(&backup_path, path).with_context(|| {
+                    format!(
+                        "Restoring {} from the backup file {}",
+                        path.display(),
+                        backup_path.display()
+                    )
+                })
+            }
+            OrphanedBackupAction::Discard => {
+                info!(?backup_path, "Removing orphaned backup file");
+                std::fs::remove_file(&backup_path)
+                    .with_context(|| format!("Removing the backup file {}", backup_path.display()))
+            }
+        }
+    }
 }
 
 impl Drop for BackedUpFile {
@@ -69,3 +118,35 @@ impl Drop for BackedUpFile {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orphaned_backups_can_be_restored_or_discarded() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("sparse-checkout");
+        std::fs::write(&path, "original")?;
+        assert_eq!(BackedUpFile::find_orphaned_backup(&path), None);
+
+        // Simulate a crash by leaking the backup.
+        std::mem::forget(BackedUpFile::new(&path)?);
+        std::fs::write(&path, "partially written")?;
+        assert_eq!(
+            BackedUpFile::find_orphaned_backup(&path),
+            Some(dir.path().join("sparse-checkout.backup"))
+        );
+        BackedUpFile::resolve_orphaned_backup(&path, OrphanedBackupAction::Restore)?;
+        assert_eq!(std::fs::read_to_string(&path)?, "original");
+        assert_eq!(BackedUpFile::find_orphaned_backup(&path), None);
+
+        std::mem::forget(BackedUpFile::new(&path)?);
+        std::fs::write(&path, "updated")?;
+        BackedUpFile::resolve_orphaned_backup(&path, OrphanedBackupAction::Discard)?;
+        assert_eq!(std::fs::read_to_string(&path)?, "updated");
+        assert_eq!(BackedUpFile::find_orphaned_backup(&path), None);
+
+        Ok(())
+    }
+}
@@ -20,7 +20,7 @@ use tracing::{error, warn};
 
 use crate::{
     app::App,
-    process,
+    offline, process,
     sandbox_command::{SandboxCommand, SandboxCommandOutput},
     time::GitIdentTime,
 };
@@ -77,6 +77,7 @@ pub fn fetch_refs<P: AsRef<Path>>(
     app: Arc<App>,
     depth: Option<u64>,
 ) -> Result<()> {
+    offline::ensure_online(&app, &format!("fetch from {}", remote))?;
     let (mut cmd, scmd) = git_command(app)?;
     cmd.current_dir(repo_path).arg("fetch").arg("--force");
     if let Some(d) = depth {
@@ -98,6 +99,7 @@ pub fn pull<P: AsRef<Path>>(
     depth: Option<u64>,
     rebase: Option<bool>,
 ) -> Result<()> {
+    offline::ensure_online(&app, &format!("pull from {}", remote))?;
     let (mut cmd, scmd) = git_command(app)?;
     cmd.current_dir(repo_path).arg("pull").arg("--force");
     if let Some(d) = depth {
@@ -126,6 +128,7 @@ pub fn fetch_all_tags<P: AsRef<Path>>(
     app: Arc<App>,
     depth: Option<u64>,
 ) -> Result<String> {
+    offline::ensure_online(&app, &format!("fetch tags from {}", remote))?;
     let mut args = vec![
         String::from("fetch"),
         remote.to_owned(),
@@ -142,6 +145,7 @@ pub fn fetch_all_tags<P: AsRef<Path>>(
 }
 
 pub fn ls_remote(remote: &str, app: Arc<App>) -> Result<String> {
+    offline::ensure_online(&app, &format!("list tags in {}", remote))?;
     run_consuming_stdout(
         std::env::current_dir().unwrap(),
         vec!["ls-remote", "--tags", remote],
@@ -155,6 +159,7 @@ pub fn push_refs<P: AsRef<Path>>(
     remote: &str,
     app: Arc<App>,
 ) -> Result<()> {
+    offline::ensure_online(&app, &format!("push to {}", remote))?;
     let mut args = vec![String::from("push"), remote.to_owned()];
     args.extend(refspecs);
     let (mut cmd, scmd) = git_command(app)?;
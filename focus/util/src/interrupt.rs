@@ -0,0 +1,135 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Noticing SIGINT during long waits so that they can be abandoned promptly rather than running to completion.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use once_cell::sync::Lazy;
+use tracing::debug;
+
+/// The longest a `sleep` goes without checking whether it was interrupted.
+const CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// The number of live guards and the SIGINT disposition the outermost one replaced.
+static INSTALLED: Lazy<Mutex<(usize, Option<SigAction>)>> = Lazy::new(|| Mutex::new((0, None)));
+
+extern "C" fn handle_sigint(_: nix::libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// While held, SIGINT sets a flag instead of terminating the process. Guards nest: the handler is installed by the
+/// outermost guard and the previous disposition is restored when the last guard is dropped, so guards should only be
+/// held around waits that check `interrupted`.
+pub struct InterruptGuard {
+    _private: (),
+}
+
+impl InterruptGuard {
+    pub fn new() -> Result<Self> {
+        let mut installed = INSTALLED.lock().unwrap();
+        if installed.0 == 0 {
+            INTERRUPTED.store(false, Ordering::SeqCst);
+            let action = SigAction::new(
+                SigHandler::Handler(handle_sigint),
+                SaFlags::empty(),
+                SigSet::empty(),
+            );
+            let previous = unsafe { sigaction(Signal::SIGINT, &action) }
+                .context("Installing the SIGINT handler")?;
+            installed.1 = Some(previous);
+        }
+        installed.0 += 1;
+        Ok(Self { _private: () })
+    }
+
+    /// Whether SIGINT was received since the outermost guard was created.
+    pub fn interrupted(&self) -> bool {
+        INTERRUPTED.load(Ordering::SeqCst)
+    }
+
+    /// Sleep for `duration`, returning early if SIGINT is received. Returns whether the sleep was interrupted.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        loop {
+            if self.interrupted() {
+                return true;
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            std::thread::sleep(remaining.min(CHECK_INTERVAL));
+        }
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        let mut installed = INSTALLED.lock().unwrap();
+        installed.0 -= 1;
+        if installed.0 > 0 {
+            return;
+        }
+        if let Some(previous) = installed.1.take() {
+            if let Err(e) = unsafe { sigaction(Signal::SIGINT, &previous) } {
+                debug!(?e, "Failed to restore the previous SIGINT handler");
+            }
+        }
+    }
+}
+
+/// Tests that raise SIGINT hold this so that they don't observe each other's signals.
+#[cfg(test)]
+pub(crate) static SIGNAL_TEST_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sleep_returns_promptly_when_interrupted() -> Result<()> {
+        let _lock = SIGNAL_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let guard = InterruptGuard::new()?;
+        let started_at = Instant::now();
+        assert!(!guard.sleep(Duration::from_millis(10)));
+        assert!(!guard.interrupted());
+
+        nix::sys::signal::raise(Signal::SIGINT)?;
+        assert!(guard.interrupted());
+        assert!(guard.sleep(Duration::from_secs(30)));
+        assert!(started_at.elapsed() < Duration::from_secs(5));
+
+        Ok(())
+    }
+
+    #[test]
+    fn nested_guards_share_the_handler() -> Result<()> {
+        let _lock = SIGNAL_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let outer = InterruptGuard::new()?;
+        {
+            let inner = InterruptGuard::new()?;
+            nix::sys::signal::raise(Signal::SIGINT)?;
+            assert!(inner.interrupted());
+        }
+
+        // Dropping the inner guard neither forgets the interruption nor restores the default disposition.
+        assert!(outer.interrupted());
+        let _again = InterruptGuard::new()?;
+        assert!(outer.interrupted());
+        nix::sys::signal::raise(Signal::SIGINT)?;
+        assert!(outer.interrupted());
+
+        Ok(())
+    }
+}
@@ -6,10 +6,14 @@ pub mod backed_up_file;
 pub mod files;
 pub mod git;
 pub mod git_helper;
+pub mod interrupt;
 pub mod lock_file;
+pub mod metrics;
+pub mod offline;
 pub mod paths;
 pub mod periodic;
 pub mod process;
+pub mod profile;
 pub mod sandbox;
 pub mod sandbox_command;
 pub mod stubs;
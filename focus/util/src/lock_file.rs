@@ -2,20 +2,113 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use anyhow::{bail, Context, Result};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
 use std::fs::{File, OpenOptions};
 use std::io::{BufWriter, Seek, SeekFrom, Write};
 use std::os::unix::prelude::RawFd;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use tracing::{error, warn};
 
+/// How often `wait_shared` checks whether an exclusive holder has released the lock when waiting with a timeout.
+const WAIT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The locks this process holds, by file descriptor, so that `release_all` can release them without their owners.
+static HELD_LOCKS: Lazy<Mutex<HashMap<RawFd, (PathBuf, bool)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
 pub struct LockFile {
     path: PathBuf,
     fd: i32,
+    exclusive: bool,
 }
 
 impl LockFile {
     // Try to obtain an exclusively locked file at the given path
     pub fn new(path: &Path) -> Result<Self> {
+        Self::open(path, true)
+    }
+
+    /// Try to obtain a shared lock on the file at the given path. Any number of shared locks can be held at once, but
+    /// not while an exclusive lock is held.
+    pub fn new_shared(path: &Path) -> Result<Self> {
+        Self::open(path, false)
+    }
+
+    /// Obtain a shared lock on the file at the given path, waiting for an exclusive holder to release it. If `timeout`
+    /// is given, give up once it elapses.
+    pub fn wait_shared(path: &Path, timeout: Option<Duration>) -> Result<Self> {
+        Self::wait_for(path, false, timeout)
+    }
+
+    /// Obtain an exclusive lock on the file at the given path, waiting for any other holders to release it. If
+    /// `timeout` is given, give up once it elapses.
+    pub fn wait(path: &Path, timeout: Option<Duration>) -> Result<Self> {
+        Self::wait_for(path, true, timeout)
+    }
+
+    fn wait_for(path: &Path, exclusive: bool, timeout: Option<Duration>) -> Result<Self> {
+        use nix::libc;
+        use std::os::unix::prelude::*;
+
+        let kind = if exclusive { "exclusive" } else { "shared" };
+        let lock = if exclusive {
+            libc::LOCK_EX
+        } else {
+            libc::LOCK_SH
+        };
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .read(true)
+                .write(true)
+                .open(path)
+                .with_context(|| format!("Creating lock file {} failed", path.display()))?;
+            let op = if deadline.is_some() {
+                lock | libc::LOCK_NB
+            } else {
+                lock
+            };
+            match Self::flock(file.as_raw_fd(), op) {
+                Ok(()) => {
+                    // The exclusive holder removes the file when it releases the lock, so a lock taken on the removed
+                    // file excludes nobody; start over with whatever is at the path now.
+                    let current = std::fs::metadata(path).ok().map(|metadata| metadata.ino());
+                    if current == Some(file.metadata()?.ino()) {
+                        if exclusive {
+                            Self::write_process_description(&mut file)?;
+                        }
+                        return Ok(Self::held(path, file.into_raw_fd(), exclusive));
+                    }
+                }
+                Err(e) if deadline.is_none() => {
+                    bail!(
+                        "Acquiring {} advisory lock on {} failed: {}",
+                        kind,
+                        path.display(),
+                        e
+                    );
+                }
+                Err(_) => {
+                    if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+                        bail!(
+                            "Timed out acquiring {} advisory lock on {}; it is held by {}",
+                            kind,
+                            path.display(),
+                            std::fs::read_to_string(path).unwrap_or_default().trim()
+                        );
+                    }
+                    std::thread::sleep(WAIT_INTERVAL);
+                }
+            }
+        }
+    }
+
+    fn open(path: &Path, exclusive: bool) -> Result<Self> {
+        use nix::libc;
         use std::os::unix::prelude::*;
 
         let res = OpenOptions::new()
@@ -23,30 +116,36 @@ impl LockFile {
             .read(true)
             .write(true)
             .open(path);
+        let kind = if exclusive { "exclusive" } else { "shared" };
 
         match res {
             Ok(mut file) => {
-                // acquire the exclusive lock
-                if let Err(e) = Self::acqrel_lock(file.as_raw_fd(), true) {
+                // acquire the lock
+                let op = if exclusive {
+                    libc::LOCK_EX
+                } else {
+                    libc::LOCK_SH
+                };
+                if let Err(e) = Self::flock(file.as_raw_fd(), op | libc::LOCK_NB) {
                     error!("Another process is holding a lock on {}", path.display());
                     error!(
                         "The lock is held by {}",
                         std::fs::read_to_string(path).context("Failed reading lockfile")?
                     );
                     bail!(
-                        "Acquiring exclusive advisory lock on {} failed: {}",
+                        "Acquiring {} advisory lock on {} failed: {}",
+                        kind,
                         path.display(),
                         e
                     );
                 }
 
-                // if we acquired the lock, write our process info to the lock
-                Self::write_process_description(&mut file)?;
+                // if we acquired the lock exclusively, write our process info to the lock
+                if exclusive {
+                    Self::write_process_description(&mut file)?;
+                }
 
-                Ok(Self {
-                    path: path.to_owned(),
-                    fd: file.into_raw_fd(),
-                })
+                Ok(Self::held(path, file.into_raw_fd(), exclusive))
             }
             Err(e) => {
                 bail!("Creating lock file {} failed: {:?}", path.display(), e);
@@ -54,6 +153,57 @@ impl LockFile {
         }
     }
 
+    fn held(path: &Path, fd: RawFd, exclusive: bool) -> Self {
+        HELD_LOCKS
+            .lock()
+            .unwrap()
+            .insert(fd, (path.to_owned(), exclusive));
+        Self {
+            path: path.to_owned(),
+            fd,
+            exclusive,
+        }
+    }
+
+    /// Release every lock this process holds, as their owners would when dropped. This is for a process that is about
+    /// to exit without unwinding, such as when a command is abandoned on another thread. The owners are left with
+    /// released locks, and dropping them afterwards does nothing.
+    pub fn release_all() {
+        let held: Vec<_> = HELD_LOCKS.lock().unwrap().drain().collect();
+        for (fd, (path, exclusive)) in held {
+            Self::release(fd, &path, exclusive);
+        }
+    }
+
+    fn release(fd: RawFd, path: &Path, exclusive: bool) {
+        // Other readers may still hold a shared lock on the file, so only the exclusive holder removes it.
+        if exclusive {
+            if let Err(e) = std::fs::remove_file(path) {
+                warn!(?path, ?e, "Removing lock file failed");
+            }
+        }
+        if let Err(e) = Self::acqrel_lock(fd, false) {
+            warn!(?path, ?e, "Releasing advisory lock on file failed");
+        }
+    }
+
+    /// Determine whether some process currently holds the lock at the given path. A missing lock file is not held.
+    pub fn is_locked(path: &Path) -> Result<bool> {
+        use std::os::unix::prelude::*;
+
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => bail!("Opening lock file {} failed: {:?}", path.display(), e),
+        };
+        let fd = file.as_raw_fd();
+        if Self::acqrel_lock(fd, true).is_err() {
+            return Ok(true);
+        }
+        Self::acqrel_lock(fd, false)?;
+        Ok(false)
+    }
+
     // we must own the exclusive lock before writing the file!
     fn write_process_description(file: &mut File) -> Result<()> {
         file.seek(SeekFrom::Start(0))?;
@@ -83,7 +233,11 @@ impl LockFile {
             libc::LOCK_UN
         };
 
-        let ret = unsafe { libc::flock(fd, op) };
+        Self::flock(fd, op)
+    }
+
+    fn flock(fd: RawFd, op: i32) -> Result<()> {
+        let ret = unsafe { nix::libc::flock(fd, op) };
         if ret < 0 {
             bail!(std::io::Error::last_os_error())
         } else {
@@ -94,15 +248,9 @@ impl LockFile {
 
 impl Drop for LockFile {
     fn drop(&mut self) {
-        if let Err(e) = std::fs::remove_file(self.path.as_path()) {
-            warn!(?self.path, ?e, "Removing lock file failed");
-        }
-        if let Err(e) = Self::acqrel_lock(self.fd, false) {
-            warn!(
-                ?self.path,
-                ?e,
-                "Releasing advisory lock on file failed",
-            );
+        // The lock is no longer held if `release_all` released it.
+        if HELD_LOCKS.lock().unwrap().remove(&self.fd).is_some() {
+            Self::release(self.fd, &self.path, self.exclusive);
         }
     }
 }
@@ -168,6 +316,90 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn detecting_whether_a_lock_is_held() -> Result<()> {
+        testing::init_logging();
+        let dir = tempdir()?;
+        let path = dir.path().join("lockfile");
+
+        assert!(!LockFile::is_locked(&path)?);
+        {
+            let _a = LockFile::new(&path).expect("should have acquired lock");
+            assert!(LockFile::is_locked(&path)?);
+        }
+        std::fs::write(&path, "stale")?;
+        assert!(!LockFile::is_locked(&path)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn shared_locks_coexist_but_exclude_exclusive_locks() -> Result<()> {
+        testing::init_logging();
+        let dir = tempdir()?;
+        let path = dir.path().join("lockfile");
+
+        {
+            let _a = LockFile::new_shared(&path).expect("should have acquired shared lock");
+            let _b = LockFile::new_shared(&path).expect("should have acquired second shared lock");
+            LockFile::new(&path)
+                .err()
+                .expect("exclusive lock should have failed");
+        }
+
+        let _c = LockFile::new(&path).expect("should have acquired exclusive lock");
+        LockFile::new_shared(&path)
+            .err()
+            .expect("shared lock should have failed");
+
+        Ok(())
+    }
+
+    #[test]
+    fn waiting_for_a_shared_lock() -> Result<()> {
+        testing::init_logging();
+        let dir = tempdir()?;
+        let path = dir.path().join("lockfile");
+
+        let exclusive = LockFile::new(&path).expect("should have acquired exclusive lock");
+        LockFile::wait_shared(&path, Some(Duration::from_millis(200)))
+            .err()
+            .expect("waiting should have timed out");
+
+        let waiter = {
+            let path = path.clone();
+            std::thread::spawn(move || LockFile::wait_shared(&path, None).map(|_| ()))
+        };
+        std::thread::sleep(Duration::from_millis(200));
+        drop(exclusive);
+        waiter.join().unwrap()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn waiting_for_an_exclusive_lock() -> Result<()> {
+        testing::init_logging();
+        let dir = tempdir()?;
+        let path = dir.path().join("lockfile");
+
+        let shared = LockFile::new_shared(&path).expect("should have acquired shared lock");
+        LockFile::wait(&path, Some(Duration::from_millis(200)))
+            .err()
+            .expect("waiting should have timed out");
+
+        let waiter = {
+            let path = path.clone();
+            std::thread::spawn(move || LockFile::wait(&path, None).map(|_| ()))
+        };
+        std::thread::sleep(Duration::from_millis(200));
+        drop(shared);
+        waiter.join().unwrap()?;
+        assert!(!path.exists());
+
+        Ok(())
+    }
+
     #[test]
     fn failing_to_create_a_lock_in_an_inextant_directory() -> Result<()> {
         testing::init_logging();
@@ -0,0 +1,348 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Metrics about an invocation, written with `--metrics-file` in the Prometheus text exposition format so that
+//! node-exporter's textfile collector can scrape them. The names and labels below are stable; dashboards depend on
+//! them, so add new metrics rather than changing existing ones.
+//!
+//! | Metric                                | Type    | Labels                | Meaning                                           |
+//! |---------------------------------------|---------|-----------------------|---------------------------------------------------|
+//! | `focus_command_duration_seconds`      | gauge   | `command`             | How long the last invocation of a command took.   |
+//! | `focus_command_success`               | gauge   | `command`             | 1 if the last invocation succeeded, otherwise 0.  |
+//! | `focus_command_runs_total`            | counter | `command`, `result`   | Invocations, by `result` (`success`/`failure`).  |
+//! | `focus_sync_runs_total`               | counter | `status`, `mechanism` | Syncs, by outcome (e.g. `skipped_unchanged`). |
+//! | `focus_sync_patterns`                 | gauge   | `stage`               | Patterns in the last profile, `computed` and `optimized`. |
+//! | `focus_sync_targets`                  | gauge   |                       | Targets in the last synced target set.            |
+//! | `focus_sync_index_keys`               | gauge   | `result`              | Dependency keys found (`hit`) or not (`miss`) in the index by the last sync. |
+//! | `focus_sync_index_hit_ratio`          | gauge   |                       | The fraction of those keys that were hits.        |
+//! | `focus_sync_checkout_files`           | gauge   | `change`              | Files `added` or `removed` by the last checkout.  |
+//! | `focus_sync_checkout_bytes`           | gauge   | `change`              | The size of the files `added` or `removed` by the last checkout. |
+//! | `focus_sync_checkout_duration_seconds`| gauge   |                       | How long the last checkout took.                  |
+//!
+//! Gauges describe the last invocation that set them. Counters accumulate across invocations writing the same file.
+//! Both are kept when an invocation that does not record them writes the file.
+
+use std::{
+    collections::BTreeMap, fmt::Write as _, io::Write as _, path::Path, sync::Mutex, time::Duration,
+};
+
+use anyhow::{Context, Result};
+
+use crate::lock_file::LockFile;
+
+/// How long to wait for another invocation to finish writing the metrics file.
+const WRITE_LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetricKind {
+    Gauge,
+    Counter,
+}
+
+#[derive(Debug)]
+pub struct MetricDefinition {
+    pub name: &'static str,
+    pub kind: MetricKind,
+    pub help: &'static str,
+}
+
+pub const COMMAND_DURATION_SECONDS: MetricDefinition = MetricDefinition {
+    name: "focus_command_duration_seconds",
+    kind: MetricKind::Gauge,
+    help: "How long the last invocation of the command took.",
+};
+
+pub const COMMAND_SUCCESS: MetricDefinition = MetricDefinition {
+    name: "focus_command_success",
+    kind: MetricKind::Gauge,
+    help: "Whether the last invocation of the command succeeded.",
+};
+
+pub const COMMAND_RUNS_TOTAL: MetricDefinition = MetricDefinition {
+    name: "focus_command_runs_total",
+    kind: MetricKind::Counter,
+    help: "Invocations of the command, by result.",
+};
+
+pub const SYNC_RUNS_TOTAL: MetricDefinition = MetricDefinition {
+    name: "focus_sync_runs_total",
+    kind: MetricKind::Counter,
+    help: "Syncs, by outcome and mechanism.",
+};
+
+pub const SYNC_PATTERNS: MetricDefinition = MetricDefinition {
+    name: "focus_sync_patterns",
+    kind: MetricKind::Gauge,
+    help: "Patterns in the last computed sparse profile, before and after optimization.",
+};
+
+pub const SYNC_TARGETS: MetricDefinition = MetricDefinition {
+    name: "focus_sync_targets",
+    kind: MetricKind::Gauge,
+    help: "Targets in the last synced target set.",
+};
+
+pub const SYNC_INDEX_KEYS: MetricDefinition = MetricDefinition {
+    name: "focus_sync_index_keys",
+    kind: MetricKind::Gauge,
+    help: "Dependency keys found or missing in the index during the last sync.",
+};
+
+pub const SYNC_INDEX_HIT_RATIO: MetricDefinition = MetricDefinition {
+    name: "focus_sync_index_hit_ratio",
+    kind: MetricKind::Gauge,
+    help: "The fraction of dependency keys found in the index during the last sync.",
+};
+
+pub const SYNC_CHECKOUT_FILES: MetricDefinition = MetricDefinition {
+    name: "focus_sync_checkout_files",
+    kind: MetricKind::Gauge,
+    help: "Files added or removed by the last checkout.",
+};
+
+pub const SYNC_CHECKOUT_BYTES: MetricDefinition = MetricDefinition {
+    name: "focus_sync_checkout_bytes",
+    kind: MetricKind::Gauge,
+    help: "The total size of the files added or removed by the last checkout.",
+};
+
+pub const SYNC_CHECKOUT_DURATION_SECONDS: MetricDefinition = MetricDefinition {
+    name: "focus_sync_checkout_duration_seconds",
+    kind: MetricKind::Gauge,
+    help: "How long the last checkout took.",
+};
+
+/// Every metric, in the order they are written.
+const DEFINITIONS: &[&MetricDefinition] = &[
+    &COMMAND_DURATION_SECONDS,
+    &COMMAND_SUCCESS,
+    &COMMAND_RUNS_TOTAL,
+    &SYNC_RUNS_TOTAL,
+    &SYNC_PATTERNS,
+    &SYNC_TARGETS,
+    &SYNC_INDEX_KEYS,
+    &SYNC_INDEX_HIT_RATIO,
+    &SYNC_CHECKOUT_FILES,
+    &SYNC_CHECKOUT_BYTES,
+    &SYNC_CHECKOUT_DURATION_SECONDS,
+];
+
+/// A metric name together with its rendered labels, e.g. `focus_sync_patterns{stage="computed"}`.
+type Series = String;
+
+/// Samples recorded during an invocation.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    samples: Mutex<BTreeMap<Series, f64>>,
+}
+
+impl Metrics {
+    /// Set a gauge.
+    pub fn set(&self, metric: &MetricDefinition, labels: &[(&str, &str)], value: f64) {
+        debug_assert_eq!(metric.kind, MetricKind::Gauge);
+        self.samples
+            .lock()
+            .unwrap()
+            .insert(series(metric, labels), value);
+    }
+
+    /// Add one to a counter.
+    pub fn increment(&self, metric: &MetricDefinition, labels: &[(&str, &str)]) {
+        debug_assert_eq!(metric.kind, MetricKind::Counter);
+        *self
+            .samples
+            .lock()
+            .unwrap()
+            .entry(series(metric, labels))
+            .or_default() += 1.0;
+    }
+
+    /// Render the recorded samples on top of `previous`, the earlier contents of the file: counters are added to their
+    /// previous values, and gauges that were not set keep them.
+    pub fn render(&self, previous: &str) -> String {
+        let mut samples = self.samples.lock().unwrap().clone();
+        for (series, kind, value) in parse_samples(previous) {
+            match kind {
+                MetricKind::Gauge => {
+                    samples.entry(series).or_insert(value);
+                }
+                MetricKind::Counter => *samples.entry(series).or_default() += value,
+            }
+        }
+
+        let mut output = String::new();
+        for metric in DEFINITIONS {
+            let matching: Vec<(&Series, &f64)> = samples
+                .iter()
+                .filter(|(series, _)| series_name(series) == metric.name)
+                .collect();
+            if matching.is_empty() {
+                continue;
+            }
+            let kind = match metric.kind {
+                MetricKind::Gauge => "gauge",
+                MetricKind::Counter => "counter",
+            };
+            let _ = writeln!(output, "# HELP {} {}", metric.name, metric.help);
+            let _ = writeln!(output, "# TYPE {} {}", metric.name, kind);
+            for (series, value) in matching {
+                let _ = writeln!(output, "{} {}", series, value);
+            }
+        }
+        output
+    }
+
+    /// Write the samples to `path`, replacing it atomically so that a scrape never sees a partial file. Invocations
+    /// writing the same file take turns so that none of their counts are lost.
+    pub fn write_to_file(&self, path: &Path) -> Result<()> {
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let _lock = LockFile::wait(Path::new(&lock_path), Some(WRITE_LOCK_TIMEOUT))
+            .context("Waiting for other invocations to finish writing metrics")?;
+
+        let previous = match std::fs::read_to_string(path) {
+            Ok(previous) => previous,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("Reading {}", path.display())),
+        };
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let mut temporary_file = tempfile::NamedTempFile::new_in(directory)
+            .with_context(|| format!("Creating a temporary file in {}", directory.display()))?;
+        temporary_file
+            .write_all(self.render(&previous).as_bytes())
+            .with_context(|| format!("Writing {}", temporary_file.path().display()))?;
+        temporary_file
+            .persist(path)
+            .with_context(|| format!("Moving metrics into place at {}", path.display()))?;
+        Ok(())
+    }
+}
+
+fn series(metric: &MetricDefinition, labels: &[(&str, &str)]) -> Series {
+    if labels.is_empty() {
+        return metric.name.to_owned();
+    }
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape_label_value(value)))
+        .collect();
+    format!("{}{{{}}}", metric.name, labels.join(","))
+}
+
+fn series_name(series: &str) -> &str {
+    series.split('{').next().unwrap_or(series)
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// The samples of known metrics in previously written metrics. Anything unrecognized is dropped.
+fn parse_samples(previous: &str) -> Vec<(Series, MetricKind, f64)> {
+    previous
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let (series, value) = line.rsplit_once(' ')?;
+            let metric = DEFINITIONS
+                .iter()
+                .find(|metric| metric.name == series_name(series))?;
+            Some((series.to_owned(), metric.kind, value.parse().ok()?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rendering_and_accumulating_counters() {
+        let metrics = Metrics::default();
+        metrics.set(&COMMAND_DURATION_SECONDS, &[("command", "sync")], 1.5);
+        metrics.increment(
+            &COMMAND_RUNS_TOTAL,
+            &[("command", "sync"), ("result", "success")],
+        );
+        metrics.set(&SYNC_TARGETS, &[], 3.0);
+
+        let first = metrics.render("");
+        assert_eq!(
+            first,
+            "# HELP focus_command_duration_seconds How long the last invocation of the command took.\n\
+             # TYPE focus_command_duration_seconds gauge\n\
+             focus_command_duration_seconds{command=\"sync\"} 1.5\n\
+             # HELP focus_command_runs_total Invocations of the command, by result.\n\
+             # TYPE focus_command_runs_total counter\n\
+             focus_command_runs_total{command=\"sync\",result=\"success\"} 1\n\
+             # HELP focus_sync_targets Targets in the last synced target set.\n\
+             # TYPE focus_sync_targets gauge\n\
+             focus_sync_targets 3\n"
+        );
+
+        let second = Metrics::default();
+        second.set(&COMMAND_DURATION_SECONDS, &[("command", "sync")], 2.0);
+        second.increment(
+            &COMMAND_RUNS_TOTAL,
+            &[("command", "sync"), ("result", "success")],
+        );
+        second.increment(
+            &COMMAND_RUNS_TOTAL,
+            &[("command", "clone"), ("result", "failure")],
+        );
+        let rendered = second.render(&first);
+        assert!(
+            rendered.contains("focus_command_runs_total{command=\"sync\",result=\"success\"} 2\n")
+        );
+        assert!(
+            rendered.contains("focus_command_runs_total{command=\"clone\",result=\"failure\"} 1\n")
+        );
+        assert!(rendered.contains("focus_command_duration_seconds{command=\"sync\"} 2\n"));
+        assert!(rendered.contains("focus_sync_targets 3\n"));
+    }
+
+    #[test]
+    fn concurrent_writes_keep_every_increment() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("focus.prom");
+        let writers: Vec<_> = (0..8)
+            .map(|_| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    let metrics = Metrics::default();
+                    metrics.increment(
+                        &COMMAND_RUNS_TOTAL,
+                        &[("command", "sync"), ("result", "success")],
+                    );
+                    metrics.write_to_file(&path)
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap()?;
+        }
+
+        assert!(std::fs::read_to_string(&path)?
+            .contains("focus_command_runs_total{command=\"sync\",result=\"success\"} 8\n"));
+        let leftovers: Vec<_> = std::fs::read_dir(dir.path())?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<std::io::Result<_>>()?;
+        assert_eq!(leftovers, vec![std::ffi::OsString::from("focus.prom")]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(
+            series(&SYNC_PATTERNS, &[("stage", "a \"b\"\\c")]),
+            "focus_sync_patterns{stage=\"a \\\"b\\\"\\\\c\"}"
+        );
+    }
+}
@@ -0,0 +1,20 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Offline mode, enabled by the global `--offline` flag, disables network access. Operations that require the network
+//! fail immediately instead of waiting for a connection to time out.
+
+use anyhow::{bail, Result};
+
+use crate::app::App;
+
+/// Fail if offline mode is enabled. `operation` describes what needs the network, e.g. "fetch from origin".
+pub fn ensure_online(app: &App, operation: &str) -> Result<()> {
+    if app.options().offline {
+        bail!(
+            "Cannot {} in offline mode; omit --offline to allow network access",
+            operation
+        );
+    }
+    Ok(())
+}
@@ -1,8 +1,9 @@
 // Copyright 2022 Twitter, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use lazy_static::lazy_static;
+use regex::Regex;
 use std::{
     ffi::OsString,
     path::{Path, PathBuf},
@@ -36,6 +37,26 @@ pub fn focus_config_dir() -> PathBuf {
         .join("focus")
 }
 
+/// The directory the tracker keeps its registry of repos in: `data_dir` if given, otherwise the focus config
+/// directory.
+pub fn focus_tracker_dir(data_dir: Option<&Path>) -> PathBuf {
+    data_dir
+        .map(Path::to_owned)
+        .unwrap_or_else(focus_config_dir)
+}
+
+/// The directory resolution results are cached in: `cache` within `data_dir` if given, otherwise within the platform
+/// cache directory.
+pub fn focus_cache_dir(data_dir: Option<&Path>) -> Result<PathBuf> {
+    match data_dir {
+        Some(dir) => Ok(dir.join("cache")),
+        None => Ok(dirs::cache_dir()
+            .context("failed to determine cache dir")?
+            .join("focus")
+            .join("cache")),
+    }
+}
+
 #[cfg(not(target_os = "macos"))]
 pub fn focus_sandbox_dir() -> PathBuf {
     dirs::data_dir()
@@ -115,6 +136,79 @@ pub fn expand_tilde<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
     }
 }
 
+lazy_static! {
+    static ref ENV_VAR_REGEX: Regex =
+        Regex::new(r"\$(?:\{([A-Za-z_][A-Za-z0-9_]*)\}|([A-Za-z_][A-Za-z0-9_]*))").unwrap();
+}
+
+/// Expand environment variables (`$VAR` or `${VAR}`) and a leading tilde in the given path.
+/// Referencing a variable that is not set is an error.
+pub fn expand_path<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
+    expand_path_with(path, |name| std::env::var(name).ok())
+}
+
+/// Like `expand_path`, but looks variables up with `lookup` instead of in the environment.
+fn expand_path_with<P: AsRef<Path>>(
+    path: P,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<PathBuf> {
+    let p = path.as_ref();
+    let s = match p.to_str() {
+        Some(s) => s,
+        None => bail!("Path '{}' is not valid UTF-8", p.display()),
+    };
+
+    let mut expanded = String::with_capacity(s.len());
+    let mut last_end = 0;
+    for captures in ENV_VAR_REGEX.captures_iter(s) {
+        let whole = captures.get(0).unwrap();
+        let name = captures
+            .get(1)
+            .or_else(|| captures.get(2))
+            .unwrap()
+            .as_str();
+        let value = lookup(name).with_context(|| {
+            format!(
+                "Failed to expand environment variable '{}' in path '{}'",
+                name, s
+            )
+        })?;
+        expanded.push_str(&s[last_end..whole.start()]);
+        expanded.push_str(&value);
+        last_end = whole.end();
+    }
+    expanded.push_str(&s[last_end..]);
+
+    expand_tilde(expanded)
+}
+
+/// Create the directory at `path` and any missing parents. Unlike `std::fs::create_dir_all`, a directory that another
+/// process creates concurrently at any level is not an error, so processes starting at the same time can all call this.
+pub fn create_dir_all_race_safe(path: &Path) -> Result<()> {
+    if path.is_dir() {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            create_dir_all_race_safe(parent)?;
+        }
+    }
+    match std::fs::create_dir(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && path.is_dir() => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("Creating directory {}", path.display())),
+    }
+}
+
+/// Create the directory at `path` if necessary and check that files can be written in it.
+pub fn ensure_writable_directory(path: &Path) -> Result<()> {
+    std::fs::create_dir_all(path)
+        .with_context(|| format!("Creating directory {}", path.display()))?;
+    tempfile::tempfile_in(path)
+        .with_context(|| format!("Directory {} is not writable", path.display()))?;
+    Ok(())
+}
+
 /// Determine if the `subject` is under `ancestor`.
 pub fn has_ancestor<P: AsRef<Path>>(subject: P, ancestor: P) -> Result<bool> {
     let subject = subject.as_ref();
@@ -159,6 +253,58 @@ mod tests {
         assert!(!is_build_definition(Path::new("bar.c")));
     }
 
+    #[test]
+    fn test_data_dir_override() -> Result<()> {
+        let data_dir = Path::new("/srv/ci/focus");
+        assert_eq!(focus_tracker_dir(Some(data_dir)), data_dir);
+        assert_eq!(focus_cache_dir(Some(data_dir))?, data_dir.join("cache"));
+        assert_eq!(focus_tracker_dir(None), focus_config_dir());
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_path_home_variable() -> Result<()> {
+        let home = std::env::var("HOME")?;
+        assert_eq!(expand_path("$HOME/repo")?, Path::new(&home).join("repo"));
+        assert_eq!(expand_path("${HOME}/repo")?, Path::new(&home).join("repo"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_path_unset_variable_is_an_error() {
+        let lookup = |_: &str| None;
+        assert!(expand_path_with("/tmp/${PROJECT}/repo", lookup).is_err());
+        assert!(expand_path_with("/tmp/$PROJECT/repo", lookup).is_err());
+    }
+
+    #[test]
+    fn test_expand_path_tilde_and_variable() -> Result<()> {
+        let lookup = |name: &str| (name == "PROJECT").then(|| String::from("project_a"));
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(
+            expand_path_with("~/$PROJECT", lookup)?,
+            home.join("project_a")
+        );
+        assert_eq!(
+            expand_path("/no/variables")?,
+            PathBuf::from("/no/variables")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_ensure_writable_directory() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let nested = temp_dir.path().join("a").join("b");
+        ensure_writable_directory(&nested)?;
+        assert!(nested.is_dir());
+
+        let file = temp_dir.path().join("file");
+        std::fs::write(&file, "")?;
+        assert!(ensure_writable_directory(&file).is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_find_repo_root_from() -> Result<()> {
         let app = Arc::new(App::new_for_testing()?);
@@ -0,0 +1,100 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Named presets of global options, selected with `--profile` or `FOCUS_PROFILE`. Profiles are read from
+//! `config.json` in the focus config directory, for example:
+//!
+//! ```json
+//! {
+//!     "profiles": {
+//!         "ci": { "offline": true, "resolution-threads": 8, "sandbox-dir": "/tmp/focus-sandboxes" }
+//!     }
+//! }
+//! ```
+//!
+//! A profile only supplies the options that were not given, so explicitly passed flags and their environment
+//! variables take precedence over it.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde_derive::Deserialize;
+
+/// The name of the file in the focus config directory that profiles are read from.
+pub const CONFIG_FILE_NAME: &str = "config.json";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    profiles: BTreeMap<String, Profile>,
+}
+
+/// Values for global options. Options that are not given keep their usual defaults.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Profile {
+    pub resolution_threads: Option<usize>,
+    pub sandbox_dir: Option<PathBuf>,
+    pub data_dir: Option<PathBuf>,
+    pub preserve_sandbox: Option<bool>,
+    pub preserve_sandbox_on_failure: Option<bool>,
+    pub offline: Option<bool>,
+    pub porcelain: Option<bool>,
+    pub no_color: Option<bool>,
+    pub no_deprecated_warnings: Option<bool>,
+    pub git: Option<PathBuf>,
+}
+
+impl Profile {
+    /// Read the profile named `name` from `config_file`.
+    pub fn load(config_file: &Path, name: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(config_file)
+            .with_context(|| format!("Reading {}", config_file.display()))?;
+        let mut config: ConfigFile = serde_json::from_str(&content)
+            .with_context(|| format!("Parsing {}", config_file.display()))?;
+        config.profiles.remove(name).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Profile '{}' is not defined in {}",
+                name,
+                config_file.display()
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loading_profiles() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config_file = dir.path().join(CONFIG_FILE_NAME);
+        std::fs::write(
+            &config_file,
+            r#"{"profiles": {"ci": {"offline": true, "resolution-threads": 8, "porcelain": false}}}"#,
+        )?;
+
+        let profile = Profile::load(&config_file, "ci")?;
+        assert_eq!(
+            profile,
+            Profile {
+                resolution_threads: Some(8),
+                offline: Some(true),
+                porcelain: Some(false),
+                ..Default::default()
+            }
+        );
+
+        assert!(Profile::load(&config_file, "laptop").is_err());
+
+        std::fs::write(&config_file, r#"{"profiles": {"ci": {"jobs": 8}}}"#)?;
+        assert!(Profile::load(&config_file, "ci").is_err());
+
+        Ok(())
+    }
+}
@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use super::*;
-use crate::{git_helper::ConfigExt, paths};
+use crate::{git_helper::ConfigExt, lock_file::LockFile, paths};
 use chrono::{DateTime, Utc};
 use tracing::{debug, warn};
 use walkdir::{DirEntry, WalkDir};
@@ -115,9 +115,155 @@ impl TryFrom<DirEntry> for DirEnt {
     }
 }
 
+/// Enumerate the sandbox directories directly under `sb_root`.
+fn sandbox_dirents(sb_root: &Path) -> Vec<DirEnt> {
+    let walker = WalkDir::new(sb_root)
+        .follow_links(false)
+        .max_depth(1)
+        .min_depth(1)
+        .same_file_system(true)
+        .sort_by_file_name();
+
+    walker
+        .into_iter()
+        .filter_entry(|dirent| {
+            !dirent.path_is_symlink()
+                && dirent.file_type().is_dir()
+                && dirent
+                    .file_name()
+                    .to_str()
+                    .map(|s| s.starts_with(DEFAULT_NAME_PREFIX))
+                    .unwrap_or(false)
+        })
+        .filter_map(|d| d.ok())
+        .filter_map(|d| DirEnt::try_from(d).ok())
+        .collect()
+}
+
+/// A sandbox directory found on disk.
+#[derive(Debug, Clone)]
+pub struct SandboxEntry {
+    pub path: PathBuf,
+    pub created_at: DateTime<Utc>,
+}
+
+/// List the sandboxes under `sandbox_root`, oldest first.
+pub fn list(sandbox_root: &Path) -> Result<Vec<SandboxEntry>> {
+    if !sandbox_root.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut entries: Vec<SandboxEntry> = sandbox_dirents(sandbox_root)
+        .into_iter()
+        .map(|dirent| {
+            let created_at = dirent
+                .entry
+                .metadata()
+                .ok()
+                .and_then(|md| md.created().ok())
+                .map(DateTime::<Utc>::from)
+                .unwrap_or(dirent.mtime);
+            SandboxEntry {
+                path: dirent.entry.into_path(),
+                created_at,
+            }
+        })
+        .collect();
+    entries.sort_by_key(|entry| entry.created_at);
+    Ok(entries)
+}
+
+/// Whether a running focus process is still using the sandbox at `path`.
+pub fn is_in_use(path: &Path) -> bool {
+    LockFile::is_locked(&path.join(IN_USE_LOCK_FILE_NAME)).unwrap_or_else(|e| {
+        warn!(
+            ?path,
+            ?e,
+            "Could not determine whether sandbox is in use, assuming it is"
+        );
+        true
+    })
+}
+
+/// The outcome of removing old sandboxes.
+#[derive(Debug, Default, Clone)]
+pub struct RemovalReport {
+    /// Sandboxes that were (or, in a dry run, would have been) removed along with their sizes in bytes.
+    pub removed: Vec<(PathBuf, u64)>,
+    /// Sandboxes that were old enough to be removed but are in use by a running process.
+    pub skipped_in_use: Vec<PathBuf>,
+}
+
+impl RemovalReport {
+    pub fn reclaimed_bytes(&self) -> u64 {
+        self.removed.iter().map(|(_, size)| size).sum()
+    }
+}
+
+fn disk_usage(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .follow_links(false)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Remove sandboxes under `sandbox_root` created longer than `max_age` ago, skipping any that are in use. If
+/// `dry_run` is set, only report what would be removed.
+pub fn remove_older_than(
+    sandbox_root: &Path,
+    max_age: std::time::Duration,
+    dry_run: bool,
+) -> Result<RemovalReport> {
+    let cutoff = Utc::now() - chrono::Duration::from_std(max_age)?;
+    let mut report = RemovalReport::default();
+    for entry in list(sandbox_root)? {
+        if entry.created_at >= cutoff {
+            continue;
+        }
+        if is_in_use(&entry.path) {
+            report.skipped_in_use.push(entry.path);
+            continue;
+        }
+        let size = disk_usage(&entry.path);
+        if !dry_run {
+            safe_delete_all(sandbox_root, &entry.path);
+        }
+        report.removed.push((entry.path, size));
+    }
+    Ok(report)
+}
+
+/// Remove sandboxes under `sandbox_root` that were left behind: they are not in use by a running process, and the
+/// process that created them exited without either cleaning them up or preserving them. If `dry_run` is set, only
+/// report what would be removed.
+pub fn remove_orphaned(sandbox_root: &Path, dry_run: bool) -> Result<RemovalReport> {
+    let mut report = RemovalReport::default();
+    for entry in list(sandbox_root)? {
+        if entry.path.join(PRESERVED_MARKER_FILE_NAME).exists() || is_in_use(&entry.path) {
+            continue;
+        }
+        let size = disk_usage(&entry.path);
+        if !dry_run {
+            safe_delete_all(sandbox_root, &entry.path);
+        }
+        report.removed.push((entry.path, size));
+    }
+    Ok(report)
+}
+
 /// Run the cleanup using the config stored in gitconfig (or defaults)
-pub fn run_with_default() -> Result<()> {
-    Config::try_from_git_default().and_then(|config| run(&config))
+/// Clean up sandboxes in `sandbox_root` as configured in the global Git config.
+pub fn run_with_default(sandbox_root: &Path) -> Result<()> {
+    Config::try_from_git_default().and_then(|config| {
+        run(&Config {
+            sandbox_root: Some(sandbox_root.to_owned()),
+            ..config
+        })
+    })
 }
 
 pub fn run(config: &Config) -> Result<()> {
@@ -141,27 +287,7 @@ pub fn run(config: &Config) -> Result<()> {
         None => std::env::temp_dir(),
     };
 
-    let walker = WalkDir::new(&sb_root)
-        .follow_links(false)
-        .max_depth(1)
-        .min_depth(1)
-        .same_file_system(true)
-        .sort_by_file_name();
-
-    let dirents: Vec<DirEnt> = walker
-        .into_iter()
-        .filter_entry(|dirent| {
-            !dirent.path_is_symlink()
-                && dirent.file_type().is_dir()
-                && dirent
-                    .file_name()
-                    .to_str()
-                    .map(|s| s.starts_with(DEFAULT_NAME_PREFIX))
-                    .unwrap_or(false)
-        })
-        .filter_map(|d| d.ok())
-        .filter_map(|d| DirEnt::try_from(d).ok())
-        .collect();
+    let dirents = sandbox_dirents(&sb_root);
 
     let (time_expired, mut unexpired): (Vec<DirEnt>, Vec<DirEnt>) = if preserve_hours == 0 {
         (vec![], dirents)
@@ -225,6 +351,7 @@ mod tests {
     use super::*;
     use anyhow::Result;
     use filetime::FileTime;
+    use std::time::Duration;
 
     fn assert_cleanup_config(content: impl AsRef<str>, expect: cleanup::Config) -> Result<()> {
         use std::io::prelude::*;
@@ -387,6 +514,75 @@ cleanup = 72
         Ok(())
     }
 
+    #[test]
+    fn list_and_remove_older_than() -> Result<()> {
+        focus_testing::init_logging();
+
+        let SandboxFixture { sb_root, sandboxes } = SandboxFixture::run(Config {
+            cleanup_enabled: false,
+            ..Default::default()
+        })?;
+        std::fs::create_dir(sb_root.path().join("not_a_sandbox"))?;
+        std::fs::write(sandboxes[0].join("log"), "0123456789")?;
+
+        let listed: Vec<PathBuf> = list(sb_root.path())?
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect();
+        assert_eq!(listed.len(), sandboxes.len());
+        for sb in sandboxes.iter() {
+            assert!(listed.contains(sb), "expected {:?} to be listed", sb);
+        }
+
+        // Nothing is old enough to be removed.
+        let report = remove_older_than(sb_root.path(), Duration::from_secs(7 * 24 * 3600), false)?;
+        assert!(report.removed.is_empty());
+
+        // A dry run removes nothing but reports everything.
+        let _in_use = LockFile::new(&sandboxes[1].join(IN_USE_LOCK_FILE_NAME))?;
+        let report = remove_older_than(sb_root.path(), Duration::ZERO, true)?;
+        assert_eq!(report.removed.len(), sandboxes.len() - 1);
+        assert_eq!(report.skipped_in_use, vec![sandboxes[1].clone()]);
+        assert_eq!(report.reclaimed_bytes(), 10);
+        for sb in sandboxes.iter() {
+            assert!(sb.exists(), "expected path {:?} to exist", sb)
+        }
+
+        let report = remove_older_than(sb_root.path(), Duration::ZERO, false)?;
+        assert_eq!(report.removed.len(), sandboxes.len() - 1);
+        assert!(sandboxes[1].exists());
+        assert_eq!(list(sb_root.path())?.len(), 1);
+        assert!(sb_root.path().join("not_a_sandbox").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn remove_orphaned_keeps_preserved_and_in_use_sandboxes() -> Result<()> {
+        focus_testing::init_logging();
+
+        let SandboxFixture { sb_root, sandboxes } = SandboxFixture::run(Config {
+            cleanup_enabled: false,
+            ..Default::default()
+        })?;
+        std::fs::write(sandboxes[0].join("log"), "0123456789")?;
+        std::fs::write(sandboxes[1].join(PRESERVED_MARKER_FILE_NAME), "")?;
+        let _in_use = LockFile::new(&sandboxes[2].join(IN_USE_LOCK_FILE_NAME))?;
+
+        let report = remove_orphaned(sb_root.path(), true)?;
+        assert_eq!(report.removed.len(), sandboxes.len() - 2);
+        assert_eq!(report.reclaimed_bytes(), 10);
+        assert!(sandboxes[0].exists());
+
+        remove_orphaned(sb_root.path(), false)?;
+        assert!(!sandboxes[0].exists());
+        assert!(sandboxes[1].exists());
+        assert!(sandboxes[2].exists());
+        assert_eq!(list(sb_root.path())?.len(), 2);
+
+        Ok(())
+    }
+
     #[test]
     #[should_panic]
     fn safe_delete_all_panics_if_path_is_not_under_sb_root() {
@@ -8,32 +8,51 @@ use std::fs;
 use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
 use tracing::{info, warn};
 
 use tempfile::TempDir;
 
-use crate::{paths, process};
+use crate::{lock_file::LockFile, paths, process};
 
 pub struct Sandbox {
+    // Declared before `temp_dir` so that the lock is released before the directory is removed.
     #[allow(dead_code)]
-    temp_dir: Option<tempfile::TempDir>,
+    in_use_lock: Option<LockFile>,
+    temp_dir: Mutex<Option<tempfile::TempDir>>,
     path: PathBuf,
+    prefix: String,
     serial_sequence: AtomicUsize,
 }
 
 const DEFAULT_NAME_PREFIX: &str = "focus_sandbox_";
 
+/// Name of the lock file held in a sandbox for as long as the process that created it is using it.
+const IN_USE_LOCK_FILE_NAME: &str = "in-use.lock";
+
+/// Name of the file marking a sandbox that was deliberately kept after its process exited. Sandboxes without it that
+/// are not in use were left behind by a process that did not exit cleanly.
+const PRESERVED_MARKER_FILE_NAME: &str = "preserved";
+
 impl Sandbox {
     pub fn new(preserve_contents: bool, name_prefix: Option<&str>) -> Result<Self> {
-        let sandbox_root = paths::focus_sandbox_dir();
-        std::fs::create_dir_all(&sandbox_root)
+        Self::new_in(&paths::focus_sandbox_dir(), preserve_contents, name_prefix)
+    }
+
+    /// Create a sandbox in `sandbox_root` instead of the default location.
+    pub fn new_in(
+        sandbox_root: &Path,
+        preserve_contents: bool,
+        name_prefix: Option<&str>,
+    ) -> Result<Self> {
+        std::fs::create_dir_all(sandbox_root)
             .with_context(|| format!("creating sandbox root {}", sandbox_root.display()))?;
         let prefix = name_prefix
             .map(|prefix| DEFAULT_NAME_PREFIX.to_string() + prefix + "_")
             .unwrap_or_else(|| DEFAULT_NAME_PREFIX.to_string());
         let underlying: TempDir = tempfile::Builder::new()
             .prefix(&prefix)
-            .tempdir_in(&sandbox_root)
+            .tempdir_in(sandbox_root)
             .context("creating a temporary directory to house the sandbox")?;
 
         let path = underlying.path().to_owned();
@@ -48,17 +67,23 @@ impl Sandbox {
             );
 
             // Create a symlink since we are preserving the sandbox
-            Self::create_latest_symlink(&path, &sandbox_root, &prefix);
+            Self::create_latest_symlink(&path, sandbox_root, &prefix);
+            Self::mark_preserved(&path);
 
             None
         } else {
             Some(underlying)
         };
 
+        let in_use_lock =
+            LockFile::new(&path.join(IN_USE_LOCK_FILE_NAME)).context("Locking the sandbox")?;
+
         let serial_sequence = AtomicUsize::new(0);
         let instance = Self {
-            temp_dir,
+            in_use_lock: Some(in_use_lock),
+            temp_dir: Mutex::new(temp_dir),
             path,
+            prefix,
             serial_sequence,
         };
 
@@ -71,6 +96,40 @@ impl Sandbox {
         Ok(instance)
     }
 
+    /// Keep the contents of the sandbox after it is dropped. Returns whether the sandbox would otherwise have been
+    /// cleaned up.
+    pub fn preserve(&self) -> bool {
+        let temp_dir = self.temp_dir.lock().unwrap().take();
+        match temp_dir {
+            Some(temp_dir) => {
+                let path = temp_dir.into_path();
+                if let Some(sandbox_root) = path.parent() {
+                    Self::create_latest_symlink(&path, sandbox_root, &self.prefix);
+                }
+                Self::mark_preserved(&path);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove the sandbox and its contents now rather than when it is dropped, unless it is being preserved. This is
+    /// for when the process must exit while other threads still hold references to the sandbox.
+    pub fn discard(&self) -> Result<()> {
+        if let Some(temp_dir) = self.temp_dir.lock().unwrap().take() {
+            temp_dir
+                .close()
+                .with_context(|| format!("Removing sandbox {}", self.path.display()))?;
+        }
+        Ok(())
+    }
+
+    fn mark_preserved(path: &Path) {
+        if let Err(e) = std::fs::write(path.join(PRESERVED_MARKER_FILE_NAME), b"") {
+            warn!(?path, ?e, "Failed to mark the sandbox as preserved");
+        }
+    }
+
     pub fn command_description_path(&self) -> PathBuf {
         self.path.join("cmd")
     }
@@ -156,8 +215,10 @@ impl Clone for Sandbox {
         }
 
         Self {
-            temp_dir: None,
+            in_use_lock: None,
+            temp_dir: Mutex::new(None),
             path,
+            prefix: self.prefix.clone(),
             serial_sequence: AtomicUsize::new(0),
         }
     }
@@ -181,6 +242,14 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn sandbox_in_a_given_root() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let sandbox = Sandbox::new_in(root.path(), false, None)?;
+        assert_eq!(sandbox.path().parent(), Some(root.path()));
+        Ok(())
+    }
+
     #[test]
     fn sandbox_preservation() -> Result<()> {
         let sandbox = Sandbox::new(true, None)?;
@@ -200,6 +269,36 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn sandbox_preservation_after_creation() -> Result<()> {
+        let sandbox = Sandbox::new(false, Some("preserve_later"))?;
+        let path = sandbox.path().to_owned();
+        assert!(sandbox.preserve());
+        assert!(!sandbox.preserve());
+        drop(sandbox);
+        assert!(fs::metadata(&path)?.is_dir());
+
+        let latest_link_path = path
+            .parent()
+            .unwrap()
+            .join("focus_sandbox_preserve_later.latest");
+        assert!(std::fs::symlink_metadata(&latest_link_path)?.is_symlink());
+        fs::remove_file(&latest_link_path)?;
+        fs::remove_dir_all(&path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn sandbox_discard() -> Result<()> {
+        let sandbox = Sandbox::new(false, None)?;
+        let path = sandbox.path().to_owned();
+        sandbox.discard()?;
+        assert!(fs::metadata(&path).is_err());
+        assert!(!sandbox.preserve());
+        Ok(())
+    }
+
     #[test]
     fn sandbox_name_prefix_is_present() -> Result<()> {
         let unnamed_sandbox = Sandbox::new(false, None)?;
@@ -1,9 +1,14 @@
 // Copyright 2022 Twitter, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{app::App, process};
+use crate::{app::App, interrupt::InterruptGuard, process};
 use anyhow::{bail, Context, Result};
+use nix::{
+    sys::signal::{kill, Signal},
+    unistd::Pid,
+};
 use std::{
+    collections::HashSet,
     ffi::OsStr,
     fs::{File, OpenOptions},
     io::{BufRead, BufReader, Write},
@@ -11,9 +16,9 @@ use std::{
     process::{Command, ExitStatus, Stdio},
     sync::{
         atomic::{AtomicBool, Ordering},
-        mpsc, Arc,
+        mpsc, Arc, Mutex,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tracing::{debug, debug_span, error, info, info_span, warn};
 
@@ -34,6 +39,79 @@ fn exhibit_file(file: &Path, title: &str) -> Result<()> {
     Ok(())
 }
 
+/// The processes started by `SandboxCommand`s that have not yet been waited for, so that they can be stopped when the
+/// work that started them is abandoned.
+#[derive(Debug, Default)]
+pub struct RunningCommands {
+    pids: Mutex<HashSet<u32>>,
+    killed: AtomicBool,
+}
+
+impl RunningCommands {
+    fn register(&self, pid: u32) -> RunningCommand<'_> {
+        let mut pids = self.pids.lock().unwrap();
+        pids.insert(pid);
+        // Commands started by abandoned work after `kill_all` are stopped as soon as they appear.
+        if self.killed.load(Ordering::SeqCst) {
+            Self::kill(pid);
+        }
+        RunningCommand {
+            commands: self,
+            pid,
+        }
+    }
+
+    fn kill(pid: u32) {
+        if let Err(e) = kill(Pid::from_raw(pid as i32), Signal::SIGKILL) {
+            debug!(?e, pid, "Failed to kill a running command");
+        }
+    }
+
+    /// The number of commands still running.
+    pub fn len(&self) -> usize {
+        self.pids.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Kill every running command, and any started afterward, then wait up to `timeout` for the threads running them to
+    /// reap them. Returns whether they all exited in time.
+    pub fn kill_all(&self, timeout: Duration) -> bool {
+        {
+            let pids = self.pids.lock().unwrap();
+            self.killed.store(true, Ordering::SeqCst);
+            pids.iter().copied().for_each(Self::kill);
+        }
+
+        let deadline = Instant::now() + timeout;
+        while !self.is_empty() {
+            if Instant::now() >= deadline {
+                warn!(
+                    remaining = self.len(),
+                    "Commands are still running after being killed"
+                );
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        true
+    }
+}
+
+/// Removes a command from `RunningCommands` once it has been waited for.
+struct RunningCommand<'a> {
+    commands: &'a RunningCommands,
+    pid: u32,
+}
+
+impl Drop for RunningCommand<'_> {
+    fn drop(&mut self) {
+        self.commands.pids.lock().unwrap().remove(&self.pid);
+    }
+}
+
 // SandboxCommandRunner is a command that captures stdout and stderr into sandbox logs unless other destinations are specified.
 #[derive(Debug, Clone)]
 pub struct SandboxCommand {
@@ -41,6 +119,7 @@ pub struct SandboxCommand {
     stderr_path: PathBuf,
     git_trace2_path: PathBuf,
     description_path: PathBuf,
+    running_commands: Arc<RunningCommands>,
 }
 
 #[derive(Debug)]
@@ -123,6 +202,7 @@ impl SandboxCommand {
             stderr_path,
             git_trace2_path,
             description_path,
+            running_commands: app.running_commands(),
         })
     }
 
@@ -223,6 +303,27 @@ impl SandboxCommand {
         cmd: &mut Command,
         output: SandboxCommandOutput,
         successful_status_codes: &[i32],
+    ) -> Result<ExitStatus> {
+        self.run_and_check(cmd, output, successful_status_codes, None)
+    }
+
+    /// Like `ensure_success_or_log`, but if SIGINT is received while the command runs, it is killed if it has not
+    /// already exited and an error is returned promptly, so that the caller can clean up after it.
+    pub fn ensure_success_or_log_unless_interrupted(
+        &self,
+        cmd: &mut Command,
+        output: SandboxCommandOutput,
+        interrupt_guard: &InterruptGuard,
+    ) -> Result<ExitStatus> {
+        self.run_and_check(cmd, output, &[0], Some(interrupt_guard))
+    }
+
+    fn run_and_check(
+        &self,
+        cmd: &mut Command,
+        output: SandboxCommandOutput,
+        successful_status_codes: &[i32],
+        interrupt_guard: Option<&InterruptGuard>,
     ) -> Result<ExitStatus> {
         let command_description = process::pretty_print_command(cmd);
         let span = debug_span!("Running command", description = %command_description);
@@ -236,13 +337,35 @@ impl SandboxCommand {
         let mut launch = cmd
             .spawn()
             .with_context(|| format!("Failed to spawn command {}", &command_description))?;
+        let _running = self.running_commands.register(launch.id());
 
         let tailer = Self::tail(&command_description, &self.stderr_path)
             .context("Could not create log tailer");
 
-        let status = launch
-            .wait()
-            .with_context(|| format!("Failed to wait for command {}", &command_description))?;
+        let status = match interrupt_guard {
+            None => launch.wait(),
+            Some(interrupt_guard) => loop {
+                match launch.try_wait() {
+                    Ok(Some(status)) if !interrupt_guard.interrupted() => break Ok(status),
+                    Ok(None) if !interrupt_guard.interrupted() => {
+                        std::thread::sleep(Duration::from_millis(50))
+                    }
+                    Ok(_) => {
+                        warn!(command = %command_description, "Interrupted; stopping the command");
+                        if let Err(e) = launch.kill() {
+                            debug!(?e, "Failed to kill the interrupted command");
+                        }
+                        let _ = launch.wait();
+                        if let Ok(tailer) = &tailer {
+                            tailer.stop();
+                        }
+                        bail!("Interrupted while running {}", command_description);
+                    }
+                    Err(e) => break Err(e),
+                }
+            },
+        }
+        .with_context(|| format!("Failed to wait for command {}", &command_description))?;
         if let Ok(tailer) = tailer {
             tailer.stop();
         }
@@ -384,4 +507,57 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn interrupted_command_is_stopped_promptly() -> Result<()> {
+        init_logging();
+
+        let _lock = crate::interrupt::SIGNAL_TEST_LOCK
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let app = Arc::from(App::new_for_testing()?);
+        let interrupt_guard = InterruptGuard::new()?;
+        let (mut cmd, scmd) = SandboxCommand::new("sleep", app)?;
+        cmd.arg("30");
+        let interrupter = std::thread::spawn(|| {
+            std::thread::sleep(Duration::from_millis(200));
+            nix::sys::signal::raise(nix::sys::signal::Signal::SIGINT)
+        });
+
+        let started_at = std::time::Instant::now();
+        let result = scmd.ensure_success_or_log_unless_interrupted(
+            &mut cmd,
+            SandboxCommandOutput::Stderr,
+            &interrupt_guard,
+        );
+        interrupter.join().unwrap()?;
+        assert!(result.is_err());
+        assert!(started_at.elapsed() < Duration::from_secs(10));
+
+        Ok(())
+    }
+
+    #[test]
+    fn running_commands_are_killed() -> Result<()> {
+        init_logging();
+
+        let app = Arc::from(App::new_for_testing()?);
+        let running_commands = app.running_commands();
+        let (mut cmd, scmd) = SandboxCommand::new("sleep", app)?;
+        cmd.arg("30");
+        let started_at = std::time::Instant::now();
+        let runner = std::thread::spawn(move || {
+            scmd.ensure_success_or_log(&mut cmd, SandboxCommandOutput::Stderr)
+        });
+        while running_commands.is_empty() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        assert!(running_commands.kill_all(Duration::from_secs(10)));
+        assert!(running_commands.is_empty());
+        assert!(runner.join().unwrap().is_err());
+        assert!(started_at.elapsed() < Duration::from_secs(10));
+
+        Ok(())
+    }
 }